@@ -0,0 +1,65 @@
+#import bevy_pbr::forward_io::VertexOutput
+#import bevy_pbr::mesh_view_bindings::{globals, view}
+
+// toward the sun in xyz, how bright the day is in w
+@group(2) @binding(0) var<uniform> sun: vec4<f32>;
+
+const DAY_ZENITH: vec3<f32> = vec3<f32>(0.22, 0.45, 0.85);
+const DAY_HORIZON: vec3<f32> = vec3<f32>(0.68, 0.8, 0.95);
+const NIGHT_ZENITH: vec3<f32> = vec3<f32>(0.005, 0.008, 0.03);
+const NIGHT_HORIZON: vec3<f32> = vec3<f32>(0.03, 0.04, 0.09);
+const SUNSET: vec3<f32> = vec3<f32>(1.0, 0.45, 0.15);
+const SUN_COLOR: vec3<f32> = vec3<f32>(1.0, 0.95, 0.8);
+// under the horizon, what the void past the map fades to
+const GROUND: vec3<f32> = vec3<f32>(0.12, 0.11, 0.1);
+// cells of the star grid around the sky, and the share holding a star
+const STAR_CELLS: f32 = 160.0;
+const STAR_CHANCE: f32 = 0.004;
+
+fn hash(p: vec3<f32>) -> f32 {
+    let q = fract(p * vec3<f32>(0.1031, 0.1030, 0.0973));
+    let r = q + dot(q, q.yzx + 33.33);
+    return fract((r.x + r.y) * r.z);
+}
+
+fn stars(dir: vec3<f32>, t: f32) -> f32 {
+    let cell = floor(dir * STAR_CELLS);
+    let h = hash(cell);
+    if (h > STAR_CHANCE) {
+        return 0.0;
+    }
+    // each star in the middle of its cell, twinkling at its own pace
+    let center = (cell + 0.5) / STAR_CELLS;
+    let spot = 1.0 - smoothstep(0.0, 0.6 / STAR_CELLS, distance(dir, normalize(center)));
+    let twinkle = 0.75 + 0.25 * sin(t * (1.0 + h * 900.0) + h * 6283.0);
+    return spot * twinkle;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(mesh.world_position.xyz - view.world_position);
+    let sun_dir = normalize(sun.xyz);
+    let day = sun.w;
+
+    // blends from the horizon up to the zenith
+    let up = clamp(dir.y, 0.0, 1.0);
+    let zenith = mix(NIGHT_ZENITH, DAY_ZENITH, day);
+    let horizon = mix(NIGHT_HORIZON, DAY_HORIZON, day);
+    var color = mix(horizon, zenith, pow(up, 0.5));
+
+    // the glow around a low sun, strongest on its side of the sky
+    let toward_sun = max(dot(dir, sun_dir), 0.0);
+    let low_sun = 1.0 - smoothstep(0.0, 0.35, abs(sun_dir.y));
+    let glow = low_sun * pow(toward_sun, 4.0) * (1.0 - up);
+    color = mix(color, SUNSET, glow * 0.8);
+
+    // the disc and its halo, hidden once it's set
+    let above = smoothstep(-0.05, 0.05, sun_dir.y);
+    color += SUN_COLOR * (pow(toward_sun, 800.0) * 4.0 + pow(toward_sun, 24.0) * 0.15) * above;
+
+    let night = 1.0 - smoothstep(0.0, 0.3, day);
+    color += vec3<f32>(stars(dir, globals.time) * night * smoothstep(0.0, 0.1, dir.y));
+
+    color = mix(color, GROUND * (0.15 + 0.85 * day), 1.0 - smoothstep(-0.08, 0.0, dir.y));
+    return vec4<f32>(color, 1.0);
+}
@@ -1,87 +1,424 @@
-#import bevy_pbr::mesh_functions::{get_model_matrix, mesh_position_local_to_clip}
+#import bevy_pbr::mesh_functions::{get_model_matrix, mesh_position_local_to_clip, mesh_position_local_to_world}
 
 @group(2) @binding(0) var texture: texture_2d<f32>;
 @group(2) @binding(1) var texture_sampler: sampler;
 @group(2) @binding(2) var<uniform> color: vec4<f32>;
 @group(2) @binding(3) var<uniform> texture_count: u32;
 @group(2) @binding(4) var<uniform> terrain_slice_y: u32;
+// one texel a block, full where it's filled, 0 strength while turned off
+@group(2) @binding(6) var occlusion: texture_3d<f32>;
+@group(2) @binding(7) var occlusion_sampler: sampler;
+@group(2) @binding(8) var<uniform> occlusion_strength: f32;
+// 1 or 2 to draw the sky or block light the faces were meshed with instead
+@group(2) @binding(9) var<uniform> light_debug: u32;
+// one texel a block, red where it's to be dug out and green where one is to
+// be built
+@group(2) @binding(10) var designations: texture_3d<f32>;
+// a normal map a tile, layered in atlas order, 0 strength without one
+@group(2) @binding(11) var normal_map: texture_2d_array<f32>;
+@group(2) @binding(12) var normal_sampler: sampler;
+@group(2) @binding(13) var<uniform> normal_strength: f32;
+// how dark the edges of the slice's cut are, 0 while turned off, their width
+// in pixels, and how much of their darkness the seams between two cut blocks
+// get
+@group(2) @binding(14) var<uniform> outline: vec4<f32>;
+// the depths under the slice unlit faces start and finish fading to black
+// between, the light level up to which they count as partly unlit, and how
+// strongly, 0 while turned off
+@group(2) @binding(15) var<uniform> dimming: vec4<f32>;
 
-struct Vertex {
-    @builtin(instance_index) instance_index: u32,
-    @location(0) position: vec3<f32>,
-    @location(1) packed_block: u32,
-}
+// how much brighter than their texture glowing blocks are drawn
+const EMISSIVE: f32 = 1.4;
+// brightest light level, and how much dimmer each level down is drawn
+const MAX_LIGHT: f32 = 15.0;
+const LIGHT_FALLOFF: f32 = 0.8;
+// how bright a face without any light is, so caves aren't pitch black
+const MIN_BRIGHTNESS: f32 = 0.06;
+// what full light looks like in the sky and block light debug views
+const DEBUG_SKY: vec3<f32> = vec3<f32>(0.35, 0.7, 1.0);
+const DEBUG_BLOCK: vec3<f32> = vec3<f32>(1.0, 0.6, 0.15);
+// the tints of designated blocks, and how far faces are pulled toward them
+const DIG_TINT: vec3<f32> = vec3<f32>(1.0, 0.3, 0.2);
+const BUILD_TINT: vec3<f32> = vec3<f32>(1.0, 0.7, 0.25);
+const DESIGNATION_STRENGTH: f32 = 0.5;
+// where the relief of normal maps is lit from, high and a little east and
+// south the way the faces are shaded
+const RELIEF_LIGHT: vec3<f32> = vec3<f32>(0.37, 0.86, 0.35);
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) packed_block: u32,
     @location(1) position: vec3<f32>,
+    // from 0 to MAX_LIGHT, blended across the face from its corners
+    @location(2) light: f32,
+    // the smooth mesher's sloped normal, zero on cube faces
+    @location(3) normal: vec3<f32>,
 };
 
-@vertex 
+#ifdef VERTEX_PULLING
+// two words a face: the chunk-local cell, then the quad's height and width
+// less one, five bits each, and the packed block with the darkness of each
+// corner in its top sixteen bits
+@group(2) @binding(5) var<storage, read> faces: array<u32>;
+
+var<private> AXES: array<vec3<f32>, 3> = array<vec3<f32>, 3>(
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+);
+
+// corner of the quad for each of a face's six vertices, and the same wound
+// the other way for the faces whose plane axes turn clockwise
+var<private> QUAD: array<u32, 6> = array<u32, 6>(0u, 1u, 2u, 2u, 3u, 0u);
+var<private> QUAD_FLIPPED: array<u32, 6> = array<u32, 6>(0u, 3u, 2u, 2u, 1u, 0u);
+
+@vertex
+fn vertex(
+    @builtin(instance_index) instance_index: u32,
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    let face = vertex_index / 6u;
+    if (face * 2u + 1u >= arrayLength(&faces)) {
+        // counted before the faces it belongs to arrived
+        var out: VertexOutput;
+        out.clip_position = vec4<f32>(0.0);
+        return out;
+    }
+
+    let shape = faces[face * 2u];
+    let packed_block = faces[face * 2u + 1u];
+    let dir = (packed_block >> 6u) & 7u;
+    let axis = dir / 2u;
+
+    // the plane's axes in the order the mesher merges along them
+    var u_axis = 0u;
+    var v_axis = 1u;
+    switch axis {
+        case 0u: { u_axis = 1u; v_axis = 2u; }
+        case 1u: { u_axis = 0u; v_axis = 2u; }
+        default: {}
+    }
+
+    var corner = QUAD[vertex_index % 6u];
+    // NegX, PosY, NegZ
+    if (dir == 1u || dir == 2u || dir == 5u) {
+        corner = QUAD_FLIPPED[vertex_index % 6u];
+    }
+
+    let cell = vec3<u32>(shape & 31u, (shape >> 5u) & 31u, (shape >> 10u) & 31u);
+    let height = f32(((shape >> 15u) & 31u) + 1u);
+    let width = f32(((shape >> 20u) & 31u) + 1u);
+
+    var local = vec3<f32>(cell);
+    if (dir % 2u == 0u) {
+        local += AXES[axis];
+    }
+    if (corner == 1u || corner == 2u) {
+        local += AXES[u_axis] * height;
+    }
+    if (corner == 2u || corner == 3u) {
+        local += AXES[v_axis] * width;
+    }
+
+    let darkness = (packed_block >> (16u + corner * 4u)) & 15u;
+    return place(instance_index, local, packed_block & 65535u, darkness);
+}
+#else
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) packed_position: u32,
+    @location(1) packed_block: u32,
+#ifdef PACKED_NORMAL
+    @location(2) packed_normal: u32,
+#endif
+}
+
+@vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
+    // chunk-local, 10 bits an axis in sixteenths of a block
+    let p = vertex.packed_position;
+    let steps = vec3<u32>(p & 1023u, (p >> 10u) & 1023u, (p >> 20u) & 1023u);
+    let darkness = (vertex.packed_block >> 16u) & 15u;
+    var out = place(vertex.instance_index, vec3<f32>(steps) / 16.0, vertex.packed_block, darkness);
+#ifdef PACKED_NORMAL
+    // 10 bits an axis from -1 to 1
+    let n = vertex.packed_normal;
+    let normal = vec3<f32>(vec3<u32>(n & 1023u, (n >> 10u) & 1023u, (n >> 20u) & 1023u)) / 511.5 - 1.0;
+    out.normal = (get_model_matrix(vertex.instance_index) * vec4<f32>(normal, 0.0)).xyz;
+#endif
+    return out;
+}
+#endif
+
+fn place(instance_index: u32, position: vec3<f32>, packed_block: u32, darkness: u32) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position = mesh_position_local_to_clip(
-        get_model_matrix(vertex.instance_index),
-        vec4<f32>(vertex.position, 1.0),
-    );
-    out.position = vertex.position;
-    out.packed_block = vertex.packed_block;
+    let local = vec4<f32>(position, 1.0);
+    let model = get_model_matrix(instance_index);
+    out.clip_position = mesh_position_local_to_clip(model, local);
+    out.position = mesh_position_local_to_world(model, local).xyz;
+    out.packed_block = packed_block;
+    out.light = MAX_LIGHT - f32(darkness);
+    out.normal = vec3<f32>(0.0);
     return out;
 }
 
+// how filled the blocks around a point are, blended between their centers
+fn occupancy(p: vec3<f32>) -> f32 {
+    let size = vec3<f32>(textureDimensions(occlusion));
+    return textureSampleLevel(occlusion, occlusion_sampler, p / size, 0.0).r;
+}
+
+// darkens the face in corners and under overhangs by how much of the space
+// just in front of it, and a little further out, is filled
+fn ambient_occlusion(p: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (occlusion_strength <= 0.0) {
+        return 1.0;
+    }
+    let near = occupancy(p + normal * 0.5);
+    let far = occupancy(p + normal * 1.5);
+    return 1.0 - occlusion_strength * clamp(near * 0.7 + far * 0.3, 0.0, 1.0);
+}
+
+// the designations of the cell at `p`, nothing outside the map
+fn designation(p: vec3<f32>) -> vec4<f32> {
+    let cell = vec3<i32>(floor(p));
+    let size = vec3<i32>(textureDimensions(designations));
+    if (any(cell < vec3<i32>(0)) || any(cell >= size)) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(designations, cell, 0);
+}
+
+// the tint of a face from the work queued around it: its own block to be
+// dug out, or one to be built against it, with stripes so it reads as a
+// marking rather than the block's color
+fn designation_tint(p: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    let dig = designation(p - normal * 0.5).r;
+    let build = designation(p + normal * 0.5).g;
+    if (dig + build == 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let stripe = 0.75 + 0.25 * step(0.5, fract(dot(p, vec3<f32>(1.0)) * 2.0));
+    let tint = select(BUILD_TINT, DIG_TINT, dig > 0.0);
+    return vec4<f32>(tint, DESIGNATION_STRENGTH * stripe);
+}
+
+// how much brighter or darker the normal map makes the texel than the
+// flat surface would be, turned to the surface with the tangents of its
+// face. Sloped smooth terrain lays the face's tangent flat onto its slope.
+fn relief(
+    sloped: vec3<f32>,
+    tile: u32,
+    frag: vec2<f32>,
+    face_normal: vec3<f32>,
+    face_tangent: vec3<f32>,
+    face_bitangent: vec3<f32>,
+) -> f32 {
+    if (normal_strength <= 0.0) {
+        return 1.0;
+    }
+
+    var normal = face_normal;
+    var tangent = face_tangent;
+    var bitangent = face_bitangent;
+#ifdef PACKED_NORMAL
+    normal = normalize(sloped);
+    tangent = normalize(face_tangent - normal * dot(normal, face_tangent));
+    bitangent = cross(normal, tangent) * sign(dot(cross(normal, tangent), face_bitangent));
+#endif
+
+    let texel = textureSampleLevel(normal_map, normal_sampler, frag, tile, 0.0).xyz * 2.0 - 1.0;
+    let bent = normalize(tangent * texel.x + bitangent * texel.y + normal * texel.z);
+    let flat_light = dot(normal, RELIEF_LIGHT) * 0.5 + 0.5;
+    let bent_light = dot(bent, RELIEF_LIGHT) * 0.5 + 0.5;
+    return mix(1.0, clamp(bent_light / max(flat_light, 0.05), 0.0, 2.0), normal_strength);
+}
+
+// whether the cell is a full block below the slice, as the occupancy volume
+// has it. Outside the map is open.
+fn is_filled(cell: vec3<i32>) -> bool {
+    let size = vec3<i32>(textureDimensions(occlusion));
+    if (any(cell < vec3<i32>(0)) || any(cell >= size)) {
+        return false;
+    }
+    return textureLoad(occlusion, cell, 0).r > 0.5;
+}
+
+// how much of an outline covers the point `p` on the slice's cut, `pixel`
+// being how far the point moves a pixel over: a dark edge where the block
+// beside it is open, a faint seam where it's another cut block. The tests in
+// terrain/shading.rs check `outline_params` against a copy of this
+fn slice_outline(p: vec3<f32>, pixel: vec2<f32>) -> f32 {
+    let cell = vec3<i32>(vec3<f32>(floor(p.x), f32(terrain_slice_y) - 1.0, floor(p.z)));
+    let inside = fract(p.xz);
+    // pixels to the low and high edge along x and z
+    let low = inside / max(pixel, vec2(1e-5));
+    let high = (1.0 - inside) / max(pixel, vec2(1e-5));
+    let near = 1.0 - smoothstep(vec4(0.0), vec4(outline.y), vec4(low, high));
+
+    var edge = outline.z * max(max(near.x, near.y), max(near.z, near.w));
+    if (!is_filled(cell - vec3<i32>(1, 0, 0))) { edge = max(edge, near.x); }
+    if (!is_filled(cell - vec3<i32>(0, 0, 1))) { edge = max(edge, near.y); }
+    if (!is_filled(cell + vec3<i32>(1, 0, 0))) { edge = max(edge, near.z); }
+    if (!is_filled(cell + vec3<i32>(0, 0, 1))) { edge = max(edge, near.w); }
+    return edge;
+}
+
+// what's left of a face's brightness the deeper it lies under the slice in
+// the dark, 1 for anything lit or near the cut. The tests in
+// terrain/shading.rs check `dimming_params` against a copy of this
+fn interior_dimming(y: f32, light: f32) -> f32 {
+    let depth = smoothstep(dimming.x, dimming.y, f32(terrain_slice_y) - y);
+    let unlit = 1.0 - smoothstep(0.0, dimming.z, light);
+    return 1.0 - dimming.w * depth * unlit;
+}
+
 @fragment
 fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // taken before anything branches, derivatives need every pixel
+    let pixel = fwidth(mesh.position.xz);
+
     var dirt: u32 = 1u;
     let text_width = 2;
     let text_height = 2;
 
-    let block_type = mesh.packed_block & 15u;
-    let block_face = mesh.packed_block >> 4u & 7u;
+    let block_type = mesh.packed_block & 63u;
+    let block_face = mesh.packed_block >> 6u & 7u;
+    let block_orientation = mesh.packed_block >> 9u & 7u;
+    let block_damage = mesh.packed_block >> 12u & 7u;
+    let block_glow = mesh.packed_block >> 15u & 1u;
+
+    // axis the block is turned along: 0 x, 1 y, 2 z
+    var axis: u32 = 1u;
+    switch block_orientation {
+        case 1u, 3u: { axis = 2u; } // North, South
+        case 2u, 4u: { axis = 0u; } // East, West
+        default: {}
+    }
 
     var uv: vec2<f32>;
+    var frag: vec2<f32>;
+    var grain_axis: u32;
 
     let ox = f32(block_type % texture_count);
     let oy = f32(block_type / texture_count);
 
     var shade: f32;
+    var normal: vec3<f32>;
+    // the directions u and v run in across the face
+    var tangent: vec3<f32>;
+    var bitangent: vec3<f32>;
 
     switch block_face {
         case 0u: { // PosX
-            uv = vec2(ox + mesh.position.y % 1.0, oy + mesh.position.z % 1.0);
+            frag = vec2(mesh.position.z % 1.0, mesh.position.y % 1.0);
+            grain_axis = 1u;
             shade = 0.1;
+            normal = vec3(1.0, 0.0, 0.0);
+            tangent = vec3(0.0, 0.0, 1.0);
+            bitangent = vec3(0.0, 1.0, 0.0);
         }
         case 1u: { // NegX
-            uv = vec2(ox + mesh.position.y % 1.0, oy + mesh.position.z % 1.0);
+            frag = vec2(mesh.position.z % 1.0, mesh.position.y % 1.0);
+            grain_axis = 1u;
             shade = 0.4;
+            normal = vec3(-1.0, 0.0, 0.0);
+            tangent = vec3(0.0, 0.0, 1.0);
+            bitangent = vec3(0.0, 1.0, 0.0);
         }
         case 2u: { // PosY
-            let block_y = u32(mesh.position.y / 1.0);
-            let frag_x = mesh.position.x % 1.0;
-            let frag_z = mesh.position.z % 1.0;
-
-            if (block_y == (terrain_slice_y)) {
-                uv = vec2(frag_x, frag_z);
-                shade = 0.0;
-            } else {
-                uv = vec2(ox + frag_x, oy + frag_z);
-                shade = 0.0;
-            }
+            frag = vec2(mesh.position.x % 1.0, mesh.position.z % 1.0);
+            grain_axis = 2u;
+            shade = 0.0;
+            normal = vec3(0.0, 1.0, 0.0);
+            tangent = vec3(1.0, 0.0, 0.0);
+            bitangent = vec3(0.0, 0.0, 1.0);
         }
         case 3u: { // NegY
-            uv = vec2(ox + mesh.position.x % 1.0, oy + mesh.position.z % 1.0);
+            frag = vec2(mesh.position.x % 1.0, mesh.position.z % 1.0);
+            grain_axis = 2u;
             shade = 0.8;
+            normal = vec3(0.0, -1.0, 0.0);
+            tangent = vec3(1.0, 0.0, 0.0);
+            bitangent = vec3(0.0, 0.0, 1.0);
         }
         case 4u: { // PosZ
-            uv = vec2(ox + mesh.position.x % 1.0, oy + mesh.position.y % 1.0);
+            frag = vec2(mesh.position.x % 1.0, mesh.position.y % 1.0);
+            grain_axis = 1u;
             shade = 0.2;
+            normal = vec3(0.0, 0.0, 1.0);
+            tangent = vec3(1.0, 0.0, 0.0);
+            bitangent = vec3(0.0, 1.0, 0.0);
         }
         case 5u, default: { // NegZ
-            uv = vec2(ox + mesh.position.x % 1.0, oy + mesh.position.y % 1.0);
+            frag = vec2(mesh.position.x % 1.0, mesh.position.y % 1.0);
+            grain_axis = 1u;
             shade = 0.5;
+            normal = vec3(0.0, 0.0, -1.0);
+            tangent = vec3(1.0, 0.0, 0.0);
+            bitangent = vec3(0.0, 1.0, 0.0);
         }
     }
 
+    // textures are authored with their grain running along v, turn it to
+    // follow the block's axis
+    if (axis != 1u && axis != grain_axis) {
+        frag = frag.yx;
+        let u = tangent;
+        tangent = bitangent;
+        bitangent = u;
+    }
+
+    uv = vec2(ox, oy) + frag;
+
+    let block_y = u32(mesh.position.y / 1.0);
+    let is_cut = block_face == 2u && block_y == terrain_slice_y;
+    if (is_cut) {
+        uv = vec2(mesh.position.x % 1.0, mesh.position.z % 1.0);
+    }
+
     uv = uv / f32(texture_count);
-    return vec4(1.0 - shade) * textureSample(texture, texture_sampler, uv);
+    var texel = textureSample(texture, texture_sampler, uv);
+
+    // cut-out textures like ladders
+    if (texel.a < 0.5) {
+        discard;
+    }
+
+    // crack overlays sit on the last row of the atlas, one per damage stage
+    let crack_id = texture_count * (texture_count - 1u) + min(block_damage, texture_count) - 1u;
+    let crack_uv = (vec2(f32(crack_id % texture_count), f32(crack_id / texture_count)) + frag) / f32(texture_count);
+    let crack = textureSample(texture, texture_sampler, crack_uv);
+    if (block_damage > 0u && crack.a > 0.5) {
+        texel = vec4(texel.rgb * 0.35, texel.a);
+    }
+
+    // the level alone, straight rather than curved so each step shows, and
+    // the faces shaded a little to keep the shapes readable
+    if (light_debug != 0u) {
+        var full = DEBUG_SKY;
+        if (light_debug == 2u) {
+            full = DEBUG_BLOCK;
+        }
+        let level = mesh.light / MAX_LIGHT;
+        return vec4(full * level * (1.0 - shade * 0.5), 1.0);
+    }
+
+    // blocks that give off light aren't shaded by their face, they glow
+    if (block_glow == 1u) {
+        return vec4(min(texel.rgb * EMISSIVE, vec3(1.0)), texel.a);
+    }
+
+    // each level of light down is a step dimmer, the way it looks to the eye
+    let level = mix(MIN_BRIGHTNESS, 1.0, pow(LIGHT_FALLOFF, MAX_LIGHT - mesh.light));
+    let light = (1.0 - shade) * relief(mesh.normal, block_type, frag, normal, tangent, bitangent)
+        * ambient_occlusion(mesh.position, normal) * level
+        * interior_dimming(mesh.position.y, mesh.light);
+    var lit = vec4(light) * texel;
+    if (is_cut && outline.x > 0.0) {
+        lit = vec4(lit.rgb * (1.0 - outline.x * slice_outline(mesh.position, pixel)), lit.a);
+    }
+    // kept bright in the dark so queued work shows in caves too
+    let tint = designation_tint(mesh.position, normal);
+    return vec4(mix(lit.rgb, tint.rgb, tint.a), lit.a);
 }
@@ -0,0 +1,73 @@
+// Finds the visible faces of a chunk of cubes and writes them as quads in the
+// terrain vertex layout, a packed position then a packed block per vertex.
+// One invocation per cell.
+
+const CHUNK_SIZE: u32 = 16u;
+const PADDED: u32 = 18u;
+
+// a column of cells along y per word for every x and z, bit y set when the
+// cell is filled. Padded by a cell on each side for the neighboring chunks.
+@group(0) @binding(0) var<storage, read> solid: array<u32>;
+// packed block of each face of each cell, two faces to a word
+@group(0) @binding(1) var<storage, read> faces: array<u32>;
+@group(0) @binding(2) var<storage, read_write> vertices: array<u32>;
+@group(0) @binding(3) var<storage, read_write> face_count: atomic<u32>;
+
+// PosX, NegX, PosY, NegY, PosZ, NegZ, the order faces are packed in
+var<private> NORMALS: array<vec3<i32>, 6> = array<vec3<i32>, 6>(
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(-1, 0, 0),
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, -1, 0),
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(0, 0, -1),
+);
+
+// corners of each face, counter-clockwise seen from outside
+var<private> CORNERS: array<vec3<u32>, 24> = array<vec3<u32>, 24>(
+    vec3<u32>(1u, 0u, 0u), vec3<u32>(1u, 1u, 0u), vec3<u32>(1u, 1u, 1u), vec3<u32>(1u, 0u, 1u),
+    vec3<u32>(0u, 0u, 0u), vec3<u32>(0u, 0u, 1u), vec3<u32>(0u, 1u, 1u), vec3<u32>(0u, 1u, 0u),
+    vec3<u32>(0u, 1u, 0u), vec3<u32>(0u, 1u, 1u), vec3<u32>(1u, 1u, 1u), vec3<u32>(1u, 1u, 0u),
+    vec3<u32>(0u, 0u, 0u), vec3<u32>(1u, 0u, 0u), vec3<u32>(1u, 0u, 1u), vec3<u32>(0u, 0u, 1u),
+    vec3<u32>(0u, 0u, 1u), vec3<u32>(1u, 0u, 1u), vec3<u32>(1u, 1u, 1u), vec3<u32>(0u, 1u, 1u),
+    vec3<u32>(0u, 0u, 0u), vec3<u32>(0u, 1u, 0u), vec3<u32>(1u, 1u, 0u), vec3<u32>(1u, 0u, 0u),
+);
+
+// `padded` counts from the corner of the padding
+fn is_solid(padded: vec3<i32>) -> bool {
+    let column = solid[u32(padded.x) + u32(padded.z) * PADDED];
+    return ((column >> u32(padded.y)) & 1u) == 1u;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn mesh(@builtin(global_invocation_id) id: vec3<u32>) {
+    let padded = vec3<i32>(id) + vec3<i32>(1);
+    if (!is_solid(padded)) {
+        return;
+    }
+
+    let cell = id.x + (id.y + id.z * CHUNK_SIZE) * CHUNK_SIZE;
+    let capacity = arrayLength(&vertices) / 8u;
+
+    for (var face = 0u; face < 6u; face++) {
+        if (is_solid(padded + NORMALS[face])) {
+            continue;
+        }
+
+        let slot = atomicAdd(&face_count, 1u);
+        if (slot >= capacity) {
+            return;
+        }
+
+        let word = faces[cell * 3u + face / 2u];
+        let packed = (word >> ((face % 2u) * 16u)) & 0xffffu;
+
+        for (var i = 0u; i < 4u; i++) {
+            // in sixteenths of a block, like every other terrain vertex
+            let corner = (id + CORNERS[face * 4u + i]) * 16u;
+            let vertex = (slot * 4u + i) * 2u;
+            vertices[vertex] = corner.x | (corner.y << 10u) | (corner.z << 20u);
+            vertices[vertex + 1u] = packed;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+#import bevy_pbr::mesh_functions::{get_model_matrix, mesh_position_local_to_clip, mesh_position_local_to_world}
+#import bevy_pbr::mesh_view_bindings::{globals, view}
+
+@group(2) @binding(0) var<uniform> shallow_color: vec4<f32>;
+@group(2) @binding(1) var<uniform> deep_color: vec4<f32>;
+
+// blocks of water it takes to reach the deep color
+const FULL_DEPTH: f32 = 6.0;
+// how far the surface rises and falls with the swell, in blocks
+const WAVE_HEIGHT: f32 = 0.04;
+// how steep the ripples tilt the surface normal
+const RIPPLE_STRENGTH: f32 = 0.35;
+// direction the light comes from, for the glints
+const SUN: vec3<f32> = vec3<f32>(0.4, 1.0, 0.3);
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) packed_position: u32,
+    @location(1) depth: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) position: vec3<f32>,
+    @location(1) depth: f32,
+};
+
+// the slow swell the whole surface rides on
+fn swell(p: vec2<f32>, t: f32) -> f32 {
+    return sin(p.x * 0.9 + t * 1.1) * 0.6 + sin(p.y * 1.3 - t * 0.7) * 0.4;
+}
+
+// small ripples scrolling across the surface in two directions
+fn ripples(p: vec2<f32>, t: f32) -> f32 {
+    return sin(dot(p, vec2<f32>(2.1, 1.4)) + t * 2.3) * 0.5
+        + sin(dot(p, vec2<f32>(-1.7, 2.6)) + t * 1.7) * 0.3
+        + sin(dot(p, vec2<f32>(4.3, -3.1)) + t * 3.1) * 0.2;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // chunk-local, 10 bits an axis in sixteenths of a block
+    let p = vertex.packed_position;
+    let steps = vec3<u32>(p & 1023u, (p >> 10u) & 1023u, (p >> 20u) & 1023u);
+    var local = vec4<f32>(vec3<f32>(steps) / 16.0, 1.0);
+
+    let model = get_model_matrix(vertex.instance_index);
+    let world = mesh_position_local_to_world(model, local).xyz;
+    // the shallows barely move, so the edges stay on the shore
+    local.y += swell(world.xz, globals.time) * WAVE_HEIGHT * min(vertex.depth, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(model, local);
+    out.position = mesh_position_local_to_world(model, local).xyz;
+    out.depth = vertex.depth;
+    return out;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let t = globals.time;
+    let p = mesh.position.xz;
+
+    // the ripples' slope by finite differences, bent into the normal
+    let e = 0.05;
+    let h = ripples(p, t);
+    let slope = vec2<f32>(ripples(p + vec2<f32>(e, 0.0), t) - h, ripples(p + vec2<f32>(0.0, e), t) - h) / e;
+    let normal = normalize(vec3<f32>(-slope.x * RIPPLE_STRENGTH, 1.0, -slope.y * RIPPLE_STRENGTH));
+
+    let to_eye = normalize(view.world_position - mesh.position);
+    // more of the sky is reflected looking across the water than into it
+    let fresnel = pow(1.0 - abs(dot(normal, to_eye)), 3.0);
+    let glint = pow(max(dot(normal, normalize(normalize(SUN) + to_eye)), 0.0), 64.0);
+
+    let deep = clamp(mesh.depth / FULL_DEPTH, 0.0, 1.0);
+    let color = mix(shallow_color, deep_color, deep);
+    let rgb = color.rgb + vec3<f32>(fresnel * 0.25 + glint * 0.6);
+    let alpha = clamp(color.a + fresnel * 0.3 + glint, 0.0, 1.0);
+
+    return vec4<f32>(rgb, alpha);
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use vox_core::{
+    menu::WorldSource,
+    terrain::{parse_size, Mesher, TerrainConfig},
+    worldgen::Landform,
+};
+
+/// Voxel colony sandbox. Every option has a default.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Seed for the world generator.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Map size in blocks, written XxYxZ with Y up, e.g. 128x64x128.
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<IVec3>,
+    /// Generate level ground instead of a sphere.
+    #[arg(long)]
+    pub flat: bool,
+    /// Erode the generated land with this many raindrops, e.g. 5000.
+    #[arg(long, value_name = "DROPLETS")]
+    pub erosion: Option<u32>,
+    /// Let the generated land's steepest slopes slump this many times.
+    #[arg(long, value_name = "PASSES")]
+    pub talus: Option<u32>,
+    /// Draw the terrain as smooth hills instead of cubes.
+    #[arg(long)]
+    pub smooth: bool,
+    /// Draw chunks from storage buffers of faces instead of vertex buffers.
+    #[arg(long, conflicts_with = "smooth")]
+    pub vertex_pulling: bool,
+    /// Leave out the faces on the sides and bottom of the map.
+    #[arg(long)]
+    pub closed: bool,
+    /// Leave the cursor free when the window opens.
+    #[arg(long)]
+    pub no_grab: bool,
+    /// Let other players join on this port.
+    #[arg(long, value_name = "PORT", conflicts_with = "connect")]
+    pub host: Option<u16>,
+    /// Join the game hosted at this address, e.g. 192.168.1.20:7777.
+    #[arg(long, value_name = "ADDR")]
+    pub connect: Option<String>,
+    /// Open a saved world straight away instead of showing the menu.
+    #[arg(long, value_name = "PATH", conflicts_with = "connect")]
+    pub load: Option<PathBuf>,
+    /// Run the simulation without a window, for dedicated servers and
+    /// tests. Generates a world unless one is loaded.
+    #[arg(long, conflicts_with = "connect")]
+    pub headless: bool,
+    /// Record the session to this file, to play back later.
+    #[arg(long, value_name = "PATH", conflicts_with = "connect")]
+    pub record: Option<PathBuf>,
+    /// Play back a recorded session.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["connect", "load", "record"])]
+    pub play: Option<PathBuf>,
+}
+
+impl Args {
+    /// The world to skip the menu for, if any.
+    pub fn world_source(&self) -> Option<WorldSource> {
+        match &self.load {
+            Some(path) => Some(WorldSource::Load(path.clone())),
+            // the replay says which world it starts from
+            None if self.play.is_some() => None,
+            None if self.headless => Some(WorldSource::Generate),
+            None => None,
+        }
+    }
+
+    pub fn terrain_config(&self) -> TerrainConfig {
+        let mut config = TerrainConfig::default();
+        if let Some(size) = self.size {
+            config.size = size;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        if self.flat {
+            config.generator = Landform::Flat;
+        }
+        if let Some(droplets) = self.erosion {
+            config.erosion_droplets = droplets;
+        }
+        if let Some(passes) = self.talus {
+            config.talus_passes = passes;
+        }
+        if self.smooth {
+            config.mesher = Mesher::Smooth;
+        }
+        config.vertex_pulling = self.vertex_pulling;
+        config.cull_oob = self.closed;
+        config
+    }
+}
@@ -1,28 +1,129 @@
+use std::time::Duration;
+
 use bevy::{
+    app::ScheduleRunnerPlugin,
     diagnostic::FrameTimeDiagnosticsPlugin,
-    gizmos,
+    input::InputPlugin,
     pbr::wireframe::{Wireframe, WireframePlugin},
     prelude::*,
 };
-use camera::FlyCamera;
-use slice::SlicePlugin;
-use terrain::TerrainMaterial;
+use clap::Parser;
+
+use vox_core::{
+    agent, animal, audio, build, camera, camera::FlyCamera, collapse, console, creature, daylight,
+    door, fire, growth, input, item, job, lava, light, menu, mining, mods, needs, net, particles,
+    pathfinding, photo, reload, replay, save, settings, sky, slice::SlicePlugin, speed, structure,
+    temperature, terraform, terrain, tick, zone,
+};
 
-mod camera;
-mod slice;
-mod terrain;
+mod cli;
 
 fn main() {
-    App::new()
-        .add_systems(Startup, setup)
-        .add_plugins((DefaultPlugins, MaterialPlugin::<TerrainMaterial>::default()))
-        .add_plugins(terrain::TerrainPlugin)
-        .add_plugins(camera::CameraPlugin)
-        .add_plugins(SlicePlugin)
-        .add_plugins(WireframePlugin)
-        .add_plugins(FrameTimeDiagnosticsPlugin::default())
-        .add_systems(Update, draw_gizmos)
-        .run();
+    let args = cli::Args::parse();
+
+    let mut app = App::new();
+    if args.headless {
+        // the loop runs the simulation at a steady rate with nothing to draw,
+        // carried items still follow whoever carries them
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1. / 60.,
+            ))),
+            InputPlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+        ))
+        .add_plugins(menu::LoadingPlugin);
+    } else {
+        app.add_systems(Startup, setup)
+            .add_plugins(DefaultPlugins)
+            .add_plugins(terrain::TerrainMeshPlugin)
+            .add_plugins(menu::MenuPlugin)
+            .add_plugins(camera::CameraPlugin {
+                grab_cursor: !args.no_grab,
+            })
+            .add_plugins(input::InputMapPlugin)
+            .add_plugins(settings::SettingsPlugin)
+            .add_plugins(SlicePlugin)
+            .add_plugins(sky::SkyPlugin)
+            .add_plugins(daylight::LightingPresetsPlugin)
+            .add_plugins(photo::PhotoModePlugin)
+            .add_plugins(console::ConsolePlugin)
+            .add_plugins(speed::SpeedControlsPlugin)
+            .add_plugins(build::BuildPlugin)
+            .add_plugins(agent::AgentViewPlugin)
+            .add_plugins(animal::AnimalViewPlugin)
+            .add_plugins(creature::CreatureViewPlugin)
+            .add_plugins(needs::NeedsIconPlugin)
+            .add_plugins(item::ItemViewPlugin)
+            .add_plugins(door::DoorViewPlugin)
+            .add_plugins(mining::MiningControlsPlugin)
+            .add_plugins(collapse::CollapseViewPlugin)
+            .add_plugins(fire::FireViewPlugin)
+            .add_plugins(particles::ParticlePlugin)
+            .add_plugins(temperature::TemperatureOverlayPlugin)
+            .add_plugins(light::LightDebugPlugin)
+            .add_plugins(pathfinding::PathDebugPlugin)
+            .add_plugins(terrain::ChunkDebugPlugin)
+            .add_plugins(zone::ZoneOverlayPlugin)
+            .add_plugins(audio::AudioPlugin)
+            .add_plugins(structure::StructureViewPlugin)
+            .add_plugins(terraform::TerraformViewPlugin)
+            .add_plugins(WireframePlugin)
+            .add_plugins(FrameTimeDiagnosticsPlugin)
+            .add_systems(Update, draw_gizmos);
+
+        #[cfg(feature = "physics")]
+        app.add_plugins(vox_core::physics::PhysicsPlugin);
+        #[cfg(feature = "gpu-meshing")]
+        app.add_plugins(terrain::GpuMeshingPlugin);
+    }
+
+    // first, the plugins after it adjust the world generator it sets up
+    app.add_plugins(terrain::TerrainPlugin::new(args.terrain_config()))
+        // before anything reads blocks by name
+        .add_plugins(mods::ModsPlugin)
+        .add_plugins(reload::ReloadPlugin)
+        .add_plugins(save::SavePlugin)
+        .add_plugins(net::NetPlugin {
+            host: args.host,
+            connect: args.connect.clone(),
+        })
+        .add_plugins(replay::ReplayPlugin {
+            record: args.record.clone(),
+            play: args.play.clone(),
+        })
+        .add_plugins(light::LightPlugin)
+        .add_plugins(speed::SpeedPlugin)
+        .add_plugins(tick::SimTickPlugin)
+        .add_plugins(tick::RandomTickPlugin)
+        .add_plugins(growth::GrowthPlugin)
+        .add_plugins(lava::LavaPlugin)
+        .add_plugins(daylight::DaylightPlugin)
+        .add_plugins(temperature::TemperaturePlugin)
+        .add_plugins(zone::ZonePlugin)
+        .add_plugins(job::JobPlugin)
+        .add_plugins(agent::AgentPlugin)
+        .add_plugins(animal::AnimalPlugin)
+        .add_plugins(creature::CreaturePlugin)
+        .add_plugins(needs::NeedsPlugin)
+        .add_plugins(item::ItemPlugin)
+        .add_plugins(door::DoorPlugin)
+        .add_plugins(mining::MiningPlugin)
+        .add_plugins(collapse::CollapsePlugin)
+        .add_plugins(fire::FirePlugin)
+        .add_plugins(structure::StructurePlugin)
+        .add_plugins(terraform::TerraformPlugin);
+
+    #[cfg(feature = "scripting")]
+    app.add_plugins(vox_core::script::ScriptPlugin);
+
+    if let Some(source) = args.world_source() {
+        app.insert_resource(source)
+            .add_systems(Startup, menu::start_loading);
+    }
+
+    app.run();
 }
 
 fn draw_gizmos(mut gizmos: Gizmos) {
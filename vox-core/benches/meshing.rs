@@ -0,0 +1,81 @@
+use bevy::math::IVec3;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use vox_core::terrain::{Block, Mesher, Terrain, TerrainMeshData};
+
+const SIZE: i32 = 64;
+
+/// Builds a map with every cell for which `filled` holds set to stone,
+/// sliced at the top so nothing is clipped.
+fn world(filled: impl Fn(IVec3) -> bool) -> Terrain {
+    let mut terrain = Terrain::new(IVec3::splat(SIZE));
+    for x in 0..SIZE {
+        for y in 0..SIZE {
+            for z in 0..SIZE {
+                let pos = IVec3::new(x, y, z);
+                if filled(pos) {
+                    terrain.set_at(pos, Block::Stone);
+                }
+            }
+        }
+    }
+    terrain.slice = SIZE as u16;
+    terrain
+}
+
+/// Representative worlds, from the cheapest to the worst case for culling.
+fn worlds() -> Vec<(&'static str, Terrain)> {
+    let center = IVec3::splat(SIZE / 2);
+    let radius = SIZE / 2 - 2;
+    let mut rng = StdRng::seed_from_u64(1337);
+    let noise: Vec<bool> = (0..SIZE * SIZE * SIZE).map(|_| rng.gen_bool(0.5)).collect();
+
+    vec![
+        ("solid", world(|_| true)),
+        (
+            "sphere",
+            world(|pos| (pos - center).length_squared() <= radius * radius),
+        ),
+        (
+            "noise",
+            world(|pos| noise[(pos.x + (pos.y + pos.z * SIZE) * SIZE) as usize]),
+        ),
+        (
+            "checkerboard",
+            world(|pos| (pos.x + pos.y + pos.z) % 2 == 0),
+        ),
+    ]
+}
+
+fn mesh_world(terrain: &Terrain, mesher: Mesher, data: &mut TerrainMeshData) -> usize {
+    terrain
+        .chunks()
+        .map(|coord| {
+            mesher.mesh_into(terrain, coord, data);
+            data.indicies.len()
+        })
+        .sum()
+}
+
+fn meshing(c: &mut Criterion) {
+    let worlds = worlds();
+    for (group_name, mesher) in [
+        ("mesh_simple", Mesher::Simple),
+        ("mesh_binary", Mesher::Binary),
+        ("mesh_smooth", Mesher::Smooth),
+    ] {
+        let mut group = c.benchmark_group(group_name);
+        group.sample_size(20);
+
+        let mut data = TerrainMeshData::default();
+        for (name, terrain) in &worlds {
+            group.bench_with_input(BenchmarkId::from_parameter(name), terrain, |b, terrain| {
+                b.iter(|| mesh_world(terrain, mesher, &mut data))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, meshing);
+criterion_main!(benches);
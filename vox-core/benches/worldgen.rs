@@ -0,0 +1,61 @@
+use bevy::math::IVec3;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, SeedableRng};
+use vox_core::{
+    terrain::Terrain,
+    worldgen::{Landform, WorldGenPipeline, WorldGenSettings},
+};
+
+const SIZE: IVec3 = IVec3::new(64, 64, 64);
+
+fn settings(landform: Landform) -> WorldGenSettings {
+    WorldGenSettings {
+        landform,
+        ..Default::default()
+    }
+}
+
+fn pipeline(c: &mut Criterion) {
+    let pipeline = WorldGenPipeline::default();
+    let mut group = c.benchmark_group("worldgen");
+    group.sample_size(10);
+
+    for (name, landform) in [("sphere", Landform::Sphere), ("flat", Landform::Flat)] {
+        let settings = settings(landform);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_batched(
+                || Terrain::new(SIZE),
+                |mut terrain| pipeline.generate(&mut terrain, &settings),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Times each stage on its own, starting from what the stages before it
+/// left behind.
+fn stages(c: &mut Criterion) {
+    let pipeline = WorldGenPipeline::default();
+    let settings = settings(Landform::Sphere);
+    let mut group = c.benchmark_group("worldgen_stage");
+    group.sample_size(10);
+
+    let mut terrain = Terrain::new(SIZE);
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    for name in pipeline.stage_names() {
+        let stage = pipeline.stage(name).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_batched(
+                || (terrain.clone(), StdRng::seed_from_u64(settings.seed)),
+                |(mut terrain, mut rng)| stage.generate(&mut terrain, &settings, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+        stage.generate(&mut terrain, &settings, &mut rng);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline, stages);
+criterion_main!(benches);
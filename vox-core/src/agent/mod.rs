@@ -0,0 +1,322 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    build::BUILD_RATE,
+    creature::{Attack, Health},
+    item::{pick_up, put_down, Carried, DropItemEvent, Item, Stockpiles, Stored},
+    job::{JobId, JobKind, JobQueue},
+    menu::AppState,
+    mining::{fell_tree, find_tree, mine, BlockMinedEvent, MINE_RATE},
+    needs::{Fulfilling, Needs},
+    net::is_authority,
+    pathfinding::{find_path, find_path_traced, is_walkable, PathDebug, PathQuery, SearchTrace},
+    terrain::{Block, Terrain, TerrainModifiedEvent},
+    tick::every,
+};
+
+pub struct AgentPlugin;
+
+/// Draws the agents.
+pub struct AgentViewPlugin;
+
+const AGENT_COUNT: usize = 3;
+
+const AGENT_HEALTH: f32 = 10.;
+
+/// Damage an agent deals with each blow when cornered.
+const AGENT_DAMAGE: f32 = 2.;
+
+#[derive(Component)]
+pub struct Agent {
+    pub speed: f32,
+}
+
+/// Remaining cells an agent will walk through, front first.
+#[derive(Component, Default)]
+pub struct AgentPath {
+    pub cells: VecDeque<IVec3>,
+}
+
+/// The job an agent claimed from the `JobQueue`.
+#[derive(Component)]
+pub struct AgentJob {
+    pub job: JobId,
+}
+
+impl Plugin for AgentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), spawn_agents)
+            .add_systems(OnExit(AppState::InGame), despawn_agents)
+            .add_systems(
+                FixedUpdate,
+                (
+                    assign_jobs.run_if(every(Duration::from_millis(500))),
+                    follow_paths,
+                    work_jobs,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            );
+    }
+}
+
+impl Plugin for AgentViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_agent_meshes)
+            .add_systems(Update, add_agent_meshes.run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Resource)]
+struct AgentAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_agent_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(AgentAssets {
+        mesh: meshes.add(Capsule3d::new(0.25, 0.5)),
+        material: materials.add(Color::rgb_u8(220, 180, 120)),
+    });
+}
+
+fn add_agent_meshes(
+    mut commands: Commands,
+    assets: Res<AgentAssets>,
+    agents: Query<Entity, Added<Agent>>,
+) {
+    for entity in agents.iter() {
+        commands
+            .entity(entity)
+            .insert((assets.mesh.clone(), assets.material.clone()));
+    }
+}
+
+fn despawn_agents(mut commands: Commands, agents: Query<Entity, With<Agent>>) {
+    for entity in agents.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn agent_cell(transform: &Transform) -> IVec3 {
+    transform.translation.floor().as_ivec3()
+}
+
+pub fn cell_center(pos: IVec3) -> Vec3 {
+    pos.as_vec3() + Vec3::splat(0.5)
+}
+
+fn spawn_agents(mut commands: Commands, terrain: Res<Terrain>) {
+    let center = IVec3::new(terrain.size().x / 2, 0, terrain.size().z / 2);
+    let mut spawned = 0;
+
+    'search: for offset in 0..terrain.size().x / 2 {
+        for y in (0..terrain.slice as i32).rev() {
+            let pos = center + IVec3::new(offset * 2, y, 0);
+            if is_walkable(&terrain, pos) {
+                commands.spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(cell_center(pos))),
+                    Agent { speed: 3. },
+                    AgentPath::default(),
+                    Health::new(AGENT_HEALTH),
+                    Attack::new(AGENT_DAMAGE),
+                    Needs::default(),
+                ));
+                spawned += 1;
+                if spawned == AGENT_COUNT {
+                    break 'search;
+                }
+                break;
+            }
+        }
+    }
+}
+
+fn is_adjacent(a: IVec3, b: IVec3) -> bool {
+    a != b && (a - b).abs().max_element() <= 1
+}
+
+#[allow(clippy::type_complexity)]
+fn assign_jobs(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut queue: ResMut<JobQueue>,
+    mut agents: Query<
+        (Entity, &Transform, &mut AgentPath),
+        (With<Agent>, Without<AgentJob>, Without<Fulfilling>),
+    >,
+    mut debug: Option<ResMut<PathDebug>>,
+) {
+    for (agent, transform, mut path) in agents.iter_mut() {
+        let start = agent_cell(transform);
+        let found = queue.find_reachable(start, |target| {
+            let is_goal = |p| is_adjacent(p, target);
+            match debug.as_deref_mut().filter(|debug| debug.wants_trace()) {
+                Some(debug) => {
+                    let mut trace = SearchTrace::default();
+                    let found = find_path_traced(&terrain, start, target, is_goal, &mut trace);
+                    debug.record(PathQuery {
+                        start,
+                        goal: target,
+                        path: found.clone(),
+                        trace,
+                        picked: false,
+                    });
+                    found
+                }
+                None => find_path(&terrain, start, target, is_goal),
+            }
+        });
+
+        if let Some((job, cells)) = found {
+            queue.claim(job, agent);
+            path.cells = cells.into();
+            commands.entity(agent).insert(AgentJob { job });
+        }
+    }
+}
+
+fn follow_paths(
+    time: Res<Time>,
+    terrain: Res<Terrain>,
+    mut agents: Query<(&Agent, &mut Transform, &mut AgentPath)>,
+) {
+    for (agent, mut transform, mut path) in agents.iter_mut() {
+        let Some(next) = path.cells.front().copied() else {
+            continue;
+        };
+
+        if !is_walkable(&terrain, next) {
+            // terrain changed under the route, a new one is found on reassignment
+            path.cells.clear();
+            continue;
+        }
+
+        let target = cell_center(next);
+        let to_target = target - transform.translation;
+        let step = agent.speed * time.delta_seconds();
+
+        if to_target.length() <= step {
+            transform.translation = target;
+            path.cells.pop_front();
+        } else {
+            transform.translation += to_target.normalize() * step;
+        }
+    }
+}
+
+/// Works the job once its agent is next to the target. A haul picks its
+/// item up and sets off for the stockpile, the other jobs are done on the
+/// spot.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn work_jobs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut terrain: ResMut<Terrain>,
+    mut queue: ResMut<JobQueue>,
+    mut stockpiles: ResMut<Stockpiles>,
+    mut agents: Query<(Entity, &Transform, &mut AgentPath, &AgentJob)>,
+    mut items: Query<(&mut Item, Has<Carried>), Without<Stored>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+    mut ev_mined: EventWriter<BlockMinedEvent>,
+    mut ev_drop: EventWriter<DropItemEvent>,
+) {
+    for (agent, transform, mut path, agent_job) in agents.iter_mut() {
+        let Some(job) = queue.get_mut(agent_job.job) else {
+            // done, or cancelled under the agent
+            commands.entity(agent).remove::<AgentJob>();
+            continue;
+        };
+
+        if !path.cells.is_empty() {
+            continue;
+        }
+
+        let cell = agent_cell(transform);
+        let target = job.target();
+        if !is_adjacent(cell, target) {
+            // lost the route, give the job back
+            queue.release(agent_job.job);
+            commands.entity(agent).remove::<AgentJob>();
+            continue;
+        }
+
+        let work = time.delta_seconds();
+        let is_done = match job.kind {
+            JobKind::Mine => mine(
+                &mut terrain,
+                target,
+                MINE_RATE * work,
+                &mut ev_terrain_mod,
+                &mut ev_mined,
+            ),
+            JobKind::Chop => {
+                let tree = find_tree(&terrain, target);
+                let is_chopped = mine(
+                    &mut terrain,
+                    target,
+                    MINE_RATE * work,
+                    &mut ev_terrain_mod,
+                    &mut ev_mined,
+                );
+                if let (true, Some(tree)) = (is_chopped, tree) {
+                    fell_tree(&mut terrain, &tree, &mut ev_mined);
+                    for pos in tree.fruit() {
+                        ev_drop.send(DropItemEvent {
+                            pos,
+                            block: Block::Fruit,
+                        });
+                    }
+                }
+                is_chopped
+            }
+            JobKind::Build(block) => {
+                job.progress += BUILD_RATE * work;
+                let is_built = job.progress >= 1.;
+                if is_built {
+                    terrain.set_at(target, block);
+                    ev_terrain_mod.send(TerrainModifiedEvent);
+                }
+                is_built
+            }
+            JobKind::Haul { item, to } => {
+                let Ok((mut item_data, is_carried)) = items.get_mut(item) else {
+                    // gone, or stored by someone else
+                    queue.remove(agent_job.job);
+                    commands.entity(agent).remove::<AgentJob>();
+                    continue;
+                };
+                if job.picked_up && is_carried {
+                    put_down(&mut commands, &mut stockpiles, item, &mut item_data, to);
+                    true
+                } else {
+                    match find_path(&terrain, cell, to, |p| is_adjacent(p, to)) {
+                        Some(cells) => {
+                            job.pick_up(&terrain);
+                            pick_up(&mut commands, item, agent);
+                            path.cells = cells.into();
+                            false
+                        }
+                        None => {
+                            println!("No way from {} to haul to {}", target, to);
+                            queue.remove(agent_job.job);
+                            commands.entity(agent).remove::<AgentJob>();
+                            continue;
+                        }
+                    }
+                }
+            }
+        };
+
+        if is_done {
+            queue.remove(agent_job.job);
+            commands.entity(agent).remove::<AgentJob>();
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    agent::{agent_cell, cell_center, Agent},
+    menu::AppState,
+    net::is_authority,
+    pathfinding::{find_path, is_walkable},
+    terrain::{Block, Terrain},
+    tick::every,
+};
+
+/// Passive animals roaming the surface. They wander between nearby dry
+/// cells, keep out of water and lava, and run off when an agent comes
+/// close.
+pub struct AnimalPlugin;
+
+/// Draws the animals. Like the blocks they stand on, they aren't drawn at
+/// or above the slice.
+pub struct AnimalViewPlugin;
+
+const ANIMAL_COUNT: usize = 6;
+
+/// Furthest an animal wanders off in one go, in blocks along each axis.
+const WANDER_RADIUS: i32 = 6;
+
+/// Chance an idle animal sets off somewhere each time wandering comes up.
+const WANDER_CHANCE: f64 = 0.3;
+
+/// How close an agent gets before an animal runs.
+const FLEE_RADIUS: i32 = 4;
+
+/// How far from the agent an animal runs to.
+const FLEE_DISTANCE: i32 = 8;
+
+#[derive(Component)]
+pub struct Animal {
+    pub speed: f32,
+    /// Whether it's running from an agent rather than wandering.
+    pub fleeing: bool,
+}
+
+/// Remaining cells an animal will walk through, front first.
+#[derive(Component, Default)]
+pub struct AnimalPath {
+    pub cells: VecDeque<IVec3>,
+}
+
+impl Plugin for AnimalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), spawn_animals)
+            .add_systems(OnExit(AppState::InGame), despawn_animals)
+            .add_systems(
+                FixedUpdate,
+                (
+                    flee_agents.run_if(every(Duration::from_millis(250))),
+                    wander.run_if(every(Duration::from_secs(1))),
+                    follow_animal_paths,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            );
+    }
+}
+
+impl Plugin for AnimalViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_animal_meshes).add_systems(
+            Update,
+            (add_animal_meshes, update_animal_visibility).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct AnimalAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_animal_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(AnimalAssets {
+        mesh: meshes.add(Cuboid::new(0.35, 0.35, 0.6)),
+        material: materials.add(Color::rgb_u8(150, 110, 80)),
+    });
+}
+
+fn add_animal_meshes(
+    mut commands: Commands,
+    assets: Res<AnimalAssets>,
+    animals: Query<Entity, Added<Animal>>,
+) {
+    for entity in animals.iter() {
+        commands
+            .entity(entity)
+            .insert((assets.mesh.clone(), assets.material.clone()));
+    }
+}
+
+fn despawn_animals(mut commands: Commands, animals: Query<Entity, With<Animal>>) {
+    for entity in animals.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Whether an animal would set foot in the cell: standing room, and no water
+/// or lava.
+fn is_dry_ground(terrain: &Terrain, pos: IVec3) -> bool {
+    is_walkable(terrain, pos) && !matches!(terrain.get_at(pos), Block::Water | Block::Lava)
+}
+
+/// The highest dry cell an animal can stand in at `x`, `z`.
+fn surface_cell(terrain: &Terrain, x: i32, z: i32) -> Option<IVec3> {
+    (0..terrain.size().y)
+        .rev()
+        .map(|y| IVec3::new(x, y, z))
+        .find(|pos| terrain.get_at(*pos).is_filled() || is_walkable(terrain, *pos))
+        .filter(|pos| is_dry_ground(terrain, *pos))
+}
+
+/// A path from `start` to the first cell `is_goal` takes, unless it wades
+/// through water or lava on the way.
+fn find_dry_path(
+    terrain: &Terrain,
+    start: IVec3,
+    target: IVec3,
+    is_goal: impl Fn(IVec3) -> bool,
+) -> Option<Vec<IVec3>> {
+    find_path(terrain, start, target, |p| {
+        is_dry_ground(terrain, p) && is_goal(p)
+    })
+    .filter(|cells| cells.iter().all(|cell| is_dry_ground(terrain, *cell)))
+}
+
+fn spawn_animals(mut commands: Commands, terrain: Res<Terrain>) {
+    let mut rng = rand::thread_rng();
+    let size = terrain.size();
+    let mut spawned = 0;
+
+    for _ in 0..ANIMAL_COUNT * 16 {
+        let x = rng.gen_range(0..size.x);
+        let z = rng.gen_range(0..size.z);
+        let Some(pos) = surface_cell(&terrain, x, z) else {
+            continue;
+        };
+
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                cell_center(pos) - Vec3::Y * 0.3,
+            )),
+            Animal {
+                speed: 2.,
+                fleeing: false,
+            },
+            AnimalPath::default(),
+        ));
+        spawned += 1;
+        if spawned == ANIMAL_COUNT {
+            break;
+        }
+    }
+}
+
+/// Animals with an agent too close run the other way, unless they're
+/// already running.
+fn flee_agents(
+    terrain: Res<Terrain>,
+    agents: Query<&Transform, With<Agent>>,
+    mut animals: Query<(&Transform, &mut Animal, &mut AnimalPath)>,
+) {
+    for (transform, mut animal, mut path) in animals.iter_mut() {
+        if animal.fleeing && !path.cells.is_empty() {
+            continue;
+        }
+        animal.fleeing = false;
+
+        let cell = agent_cell(transform);
+        let nearest = agents
+            .iter()
+            .map(agent_cell)
+            .min_by_key(|agent| (*agent - cell).length_squared());
+        let Some(agent) = nearest else {
+            continue;
+        };
+        let away = cell - agent;
+        if away.abs().max_element() > FLEE_RADIUS {
+            continue;
+        }
+
+        // head straight away from the agent, for the heuristic to aim at
+        let heading = away.as_vec3().normalize_or_zero() * FLEE_DISTANCE as f32;
+        let target = cell + heading.round().as_ivec3();
+        let is_safe = |p: IVec3| (p - agent).abs().max_element() >= FLEE_DISTANCE;
+        if let Some(cells) = find_dry_path(&terrain, cell, target, is_safe) {
+            path.cells = cells.into();
+            animal.fleeing = true;
+        }
+    }
+}
+
+/// Now and then an idle animal wanders off to a dry cell nearby.
+fn wander(terrain: Res<Terrain>, mut animals: Query<(&Transform, &Animal, &mut AnimalPath)>) {
+    let mut rng = rand::thread_rng();
+
+    for (transform, animal, mut path) in animals.iter_mut() {
+        if animal.fleeing || !path.cells.is_empty() || !rng.gen_bool(WANDER_CHANCE) {
+            continue;
+        }
+
+        let cell = agent_cell(transform);
+        let x = cell.x + rng.gen_range(-WANDER_RADIUS..=WANDER_RADIUS);
+        let z = cell.z + rng.gen_range(-WANDER_RADIUS..=WANDER_RADIUS);
+        // stay near the height it's at, rather than climbing onto a cliff top
+        let dest = (cell.y - 3..=cell.y + 3)
+            .rev()
+            .map(|y| IVec3::new(x, y, z))
+            .find(|pos| is_dry_ground(&terrain, *pos));
+        let Some(dest) = dest else {
+            continue;
+        };
+
+        if let Some(cells) = find_dry_path(&terrain, cell, dest, |p| p == dest) {
+            path.cells = cells.into();
+        }
+    }
+}
+
+/// Walks animals along their paths, turned the way they're going.
+fn follow_animal_paths(
+    time: Res<Time>,
+    terrain: Res<Terrain>,
+    mut animals: Query<(&Animal, &mut Transform, &mut AnimalPath)>,
+) {
+    for (animal, mut transform, mut path) in animals.iter_mut() {
+        let Some(next) = path.cells.front().copied() else {
+            continue;
+        };
+
+        if !is_dry_ground(&terrain, next) {
+            // terrain changed under the route, it wanders off again later
+            path.cells.clear();
+            continue;
+        }
+
+        let target = cell_center(next) - Vec3::Y * 0.3;
+        let to_target = target - transform.translation;
+        let step = animal.speed * time.delta_seconds();
+
+        let heading = Vec3::new(to_target.x, 0., to_target.z);
+        if heading.length_squared() > 0.0001 {
+            transform.look_to(heading, Vec3::Y);
+        }
+
+        if to_target.length() <= step {
+            transform.translation = target;
+            path.cells.pop_front();
+        } else {
+            transform.translation += to_target.normalize() * step;
+        }
+    }
+}
+
+/// Animals standing at or above the slice are hidden along with the blocks
+/// around them.
+fn update_animal_visibility(
+    terrain: Res<Terrain>,
+    mut animals: Query<(&Transform, &mut Visibility), With<Animal>>,
+) {
+    for (transform, mut visibility) in animals.iter_mut() {
+        let is_visible = agent_cell(transform).y < terrain.slice as i32;
+        let next = if is_visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != next {
+            *visibility = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures::ground;
+
+    #[test]
+    fn animals_keep_out_of_water() {
+        let mut terrain = ground(IVec3::new(8, 4, 3), 1, Block::Stone);
+        // a pond cutting across the whole map
+        for z in 0..3 {
+            terrain.set_at(IVec3::new(4, 1, z), Block::Water);
+        }
+
+        let start = IVec3::new(1, 1, 1);
+        let across = IVec3::new(6, 1, 1);
+        assert!(find_path(&terrain, start, across, |p| p == across).is_some());
+        assert_eq!(
+            find_dry_path(&terrain, start, across, |p| p == across),
+            None
+        );
+        assert_eq!(surface_cell(&terrain, 4, 1), None);
+        assert_eq!(surface_cell(&terrain, 2, 1), Some(IVec3::new(2, 1, 1)));
+    }
+}
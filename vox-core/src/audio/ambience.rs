@@ -0,0 +1,112 @@
+use bevy::{audio::Volume, prelude::*};
+
+use super::{AudioSettings, Synth};
+use crate::{camera::FlyCamera, daylight::TimeOfDay, terrain::Terrain};
+
+/// Seconds a loop takes to fade fully in or out.
+const CROSSFADE_TIME: f32 = 2.;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Ambience {
+    Wind,
+    Crickets,
+    Cave,
+}
+
+impl Ambience {
+    fn synth(&self) -> Synth {
+        match self {
+            Ambience::Wind => Synth {
+                noise: 1.,
+                duration: 8.,
+                lowpass: 400.,
+                tremolo: 0.25,
+                ..default()
+            },
+            Ambience::Crickets => Synth {
+                frequency: 4400.,
+                noise: 0.1,
+                duration: 4.,
+                tremolo: 6.,
+                ..default()
+            },
+            Ambience::Cave => Synth {
+                frequency: 55.,
+                noise: 0.4,
+                duration: 8.,
+                lowpass: 220.,
+                tremolo: 0.125,
+                ..default()
+            },
+        }
+    }
+
+    /// How loud the loop should be in the current context, from 0 to 1.
+    fn target(&self, underground: bool, night: bool) -> f32 {
+        match (self, underground) {
+            (Ambience::Cave, true) => 1.,
+            (_, true) | (Ambience::Cave, false) => 0.,
+            (Ambience::Wind, false) if night => 0.5,
+            (Ambience::Wind, false) => 1.,
+            (Ambience::Crickets, false) if night => 1.,
+            (Ambience::Crickets, false) => 0.,
+        }
+    }
+}
+
+#[derive(Component)]
+pub(super) struct AmbienceLoop {
+    ambience: Ambience,
+    weight: f32,
+}
+
+pub(super) fn setup_ambience(mut commands: Commands, mut synths: ResMut<Assets<Synth>>) {
+    for ambience in [Ambience::Wind, Ambience::Crickets, Ambience::Cave] {
+        commands.spawn((
+            AudioSourceBundle {
+                source: synths.add(ambience.synth()),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.)),
+            },
+            AmbienceLoop {
+                ambience,
+                weight: 0.,
+            },
+        ));
+    }
+}
+
+/// Whether the slice cuts below the surface of the column the camera is over,
+/// so the view shows the inside of the ground.
+fn is_viewing_underground(terrain: &Terrain, camera: Vec3) -> bool {
+    let x = camera.x.floor() as i16;
+    let z = camera.z.floor() as i16;
+    let surface = (0..terrain.size().y as i16)
+        .rev()
+        .find(|y| terrain.get(x, *y, z).is_solid());
+
+    surface.is_some_and(|y| terrain.slice as i16 <= y)
+}
+
+pub(super) fn crossfade_ambience(
+    time: Res<Time<Real>>,
+    terrain: Res<Terrain>,
+    time_of_day: Res<TimeOfDay>,
+    settings: Res<AudioSettings>,
+    cameras: Query<&GlobalTransform, With<FlyCamera>>,
+    mut loops: Query<(&mut AmbienceLoop, Option<&AudioSink>)>,
+) {
+    let underground = cameras
+        .get_single()
+        .is_ok_and(|t| is_viewing_underground(&terrain, t.translation()));
+    let night = time_of_day.is_night();
+    let step = time.delta_seconds() / CROSSFADE_TIME;
+
+    for (mut ambience_loop, sink) in loops.iter_mut() {
+        let target = ambience_loop.ambience.target(underground, night);
+        ambience_loop.weight += (target - ambience_loop.weight).clamp(-step, step);
+
+        if let Some(sink) = sink {
+            sink.set_volume(ambience_loop.weight * settings.master * settings.ambience);
+        }
+    }
+}
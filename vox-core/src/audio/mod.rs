@@ -0,0 +1,319 @@
+use std::{collections::HashMap, f32::consts::TAU, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, Source, SpatialScale, Volume},
+    prelude::*,
+};
+
+use crate::{
+    build::{BuildMode, BuildTool},
+    camera::FlyCamera,
+    terrain::{BlockChangedEvent, BlockMaterial},
+};
+
+mod ambience;
+mod music;
+
+pub struct AudioPlugin;
+
+const SAMPLE_RATE: u32 = 44_100;
+/// Distance in world units within which sounds play at full volume, they
+/// fall off with the square of the distance beyond it.
+const ROLLOFF_DISTANCE: f32 = 8.;
+const LISTENER_GAP: f32 = 0.5;
+/// Caps the sounds started per frame so large edits don't stack up.
+const MAX_SOUNDS_PER_FRAME: usize = 4;
+
+/// Volume levels, each from 0 to 1. Every category is scaled by `master`.
+#[derive(Resource)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub effects: f32,
+    pub ambience: f32,
+    pub music: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.,
+            effects: 1.,
+            ambience: 0.6,
+            music: 0.5,
+        }
+    }
+}
+
+/// A procedural sound: a decaying tone mixed with white noise. Loops stay
+/// seamless as long as the tone and tremolo fit a whole number of cycles into
+/// the duration.
+#[derive(Asset, TypePath, Debug, Copy, Clone, Default)]
+pub struct Synth {
+    /// Pitch of the tonal part, in Hz.
+    pub frequency: f32,
+    /// Share of white noise mixed into the tone, from 0 to 1.
+    pub noise: f32,
+    /// Length in seconds.
+    pub duration: f32,
+    /// How fast the volume fades, per second.
+    pub decay: f32,
+    /// Cutoff of a low-pass filter over the mix in Hz, zero to leave it open.
+    pub lowpass: f32,
+    /// Rate of a slow swell in volume in Hz, zero for a steady sound.
+    pub tremolo: f32,
+}
+
+pub struct SynthDecoder {
+    synth: Synth,
+    sample: u32,
+    total: u32,
+    seed: u32,
+    filtered: f32,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.total {
+            return None;
+        }
+
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let white = (self.seed >> 8) as f32 / (1 << 23) as f32 - 1.;
+
+        let tone = (TAU * self.synth.frequency * t).sin();
+        let mut value = tone * (1. - self.synth.noise) + white * self.synth.noise;
+
+        if self.synth.lowpass > 0. {
+            let alpha = 1. - (-TAU * self.synth.lowpass / SAMPLE_RATE as f32).exp();
+            self.filtered += alpha * (value - self.filtered);
+            value = self.filtered;
+        }
+
+        let attack = (t / 0.005).min(1.);
+        let mut envelope = attack * (-self.synth.decay * t).exp();
+        if self.synth.tremolo > 0. {
+            envelope *= 0.5 - 0.5 * (TAU * self.synth.tremolo * t).cos();
+        }
+
+        Some(value * envelope * 0.5)
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some((self.total - self.sample) as usize)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(self.synth.duration))
+    }
+}
+
+impl Decodable for Synth {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> SynthDecoder {
+        SynthDecoder {
+            synth: *self,
+            sample: 0,
+            total: (self.duration * SAMPLE_RATE as f32) as u32,
+            seed: 0x2545_f491,
+            filtered: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum BlockSound {
+    Dig,
+    Place,
+}
+
+#[derive(Resource)]
+struct SoundAssets {
+    blocks: HashMap<(BlockMaterial, BlockSound), Handle<Synth>>,
+    click: Handle<Synth>,
+}
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Synth>()
+            .add_audio_source::<music::Tune>()
+            .init_resource::<AudioSettings>()
+            .add_systems(
+                Startup,
+                (setup_sounds, ambience::setup_ambience, music::setup_music),
+            )
+            .add_systems(
+                Update,
+                (
+                    attach_listener,
+                    adjust_volume,
+                    play_block_sounds,
+                    play_ui_clicks,
+                    ambience::crossfade_ambience,
+                    music::play_music,
+                ),
+            );
+    }
+}
+
+fn block_synth(material: BlockMaterial, sound: BlockSound) -> Option<Synth> {
+    let synth = match material {
+        BlockMaterial::None => return None,
+        BlockMaterial::Soil => Synth {
+            frequency: 110.,
+            noise: 0.9,
+            duration: 0.2,
+            decay: 22.,
+            ..default()
+        },
+        BlockMaterial::Stone => Synth {
+            frequency: 420.,
+            noise: 0.5,
+            duration: 0.15,
+            decay: 35.,
+            ..default()
+        },
+        BlockMaterial::Wood => Synth {
+            frequency: 230.,
+            noise: 0.3,
+            duration: 0.18,
+            decay: 28.,
+            ..default()
+        },
+    };
+
+    // placing is a duller thud than breaking
+    Some(match sound {
+        BlockSound::Dig => synth,
+        BlockSound::Place => Synth {
+            frequency: synth.frequency * 0.7,
+            noise: synth.noise * 0.6,
+            ..synth
+        },
+    })
+}
+
+fn setup_sounds(mut commands: Commands, mut synths: ResMut<Assets<Synth>>) {
+    let mut blocks = HashMap::new();
+    for material in [
+        BlockMaterial::Soil,
+        BlockMaterial::Stone,
+        BlockMaterial::Wood,
+    ] {
+        for sound in [BlockSound::Dig, BlockSound::Place] {
+            if let Some(synth) = block_synth(material, sound) {
+                blocks.insert((material, sound), synths.add(synth));
+            }
+        }
+    }
+
+    commands.insert_resource(SoundAssets {
+        blocks,
+        click: synths.add(Synth {
+            frequency: 1200.,
+            noise: 0.,
+            duration: 0.05,
+            decay: 80.,
+            ..default()
+        }),
+    });
+}
+
+/// Spatial sounds are heard from the camera.
+fn attach_listener(mut commands: Commands, cameras: Query<Entity, Added<FlyCamera>>) {
+    for entity in cameras.iter() {
+        commands
+            .entity(entity)
+            .insert(SpatialListener::new(LISTENER_GAP));
+    }
+}
+
+/// Minus and equals turn the master volume down and up.
+fn adjust_volume(keys: Res<ButtonInput<KeyCode>>, mut settings: ResMut<AudioSettings>) {
+    let step = if keys.just_pressed(KeyCode::Minus) {
+        -0.1
+    } else if keys.just_pressed(KeyCode::Equal) {
+        0.1
+    } else {
+        return;
+    };
+
+    settings.master = (settings.master + step).clamp(0., 1.);
+    println!("Master volume: {:.0}%", settings.master * 100.);
+}
+
+fn play_block_sounds(
+    mut commands: Commands,
+    assets: Res<SoundAssets>,
+    settings: Res<AudioSettings>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    let mut played = 0;
+
+    for ev in ev_block_changed.read() {
+        let key = if ev.block.is_solid() {
+            (ev.block.def().material, BlockSound::Place)
+        } else {
+            (ev.previous.def().material, BlockSound::Dig)
+        };
+
+        let Some(handle) = assets.blocks.get(&key) else {
+            continue;
+        };
+
+        if played == MAX_SOUNDS_PER_FRAME {
+            continue;
+        }
+        played += 1;
+
+        commands.spawn((
+            AudioSourceBundle {
+                source: handle.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new(settings.master * settings.effects))
+                    .with_spatial(true)
+                    .with_spatial_scale(SpatialScale::new(1. / ROLLOFF_DISTANCE)),
+            },
+            TransformBundle::from_transform(Transform::from_translation(
+                ev.pos.as_vec3() + Vec3::splat(0.5),
+            )),
+        ));
+    }
+}
+
+/// Clicks when build mode is toggled or another tool is picked.
+fn play_ui_clicks(
+    mut commands: Commands,
+    assets: Res<SoundAssets>,
+    settings: Res<AudioSettings>,
+    build: Res<BuildMode>,
+    mut last: Local<Option<(bool, BuildTool)>>,
+) {
+    let current = (build.enabled, build.tool);
+    if last.is_some_and(|last| last != current) {
+        commands.spawn(AudioSourceBundle {
+            source: assets.click.clone(),
+            settings: PlaybackSettings::DESPAWN
+                .with_volume(Volume::new(settings.master * settings.effects)),
+        });
+    }
+    *last = Some(current);
+}
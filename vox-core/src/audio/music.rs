@@ -0,0 +1,231 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{
+    audio::{Source, Volume},
+    prelude::*,
+};
+
+use super::{AudioSettings, SAMPLE_RATE};
+
+/// Seconds of silence between tracks.
+const TRACK_GAP: f32 = 4.;
+
+/// A melody played one note at a time, as (frequency in Hz, seconds) pairs.
+/// A frequency of zero is a rest.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Tune {
+    notes: Arc<Vec<(f32, f32)>>,
+}
+
+impl Tune {
+    /// Builds a tune from semitone offsets above `root`, each held for `beat`
+    /// seconds. `None` rests for a beat.
+    fn from_steps(root: f32, beat: f32, steps: &[Option<i32>]) -> Self {
+        let notes = steps
+            .iter()
+            .map(|step| match step {
+                Some(step) => (root * 2f32.powf(*step as f32 / 12.), beat),
+                None => (0., beat),
+            })
+            .collect();
+
+        Self {
+            notes: Arc::new(notes),
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        self.notes.iter().map(|(_, length)| length).sum()
+    }
+}
+
+pub struct TuneDecoder {
+    notes: Arc<Vec<(f32, f32)>>,
+    note: usize,
+    sample: u32,
+    total: f32,
+}
+
+impl Iterator for TuneDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let (frequency, length) = *self.notes.get(self.note)?;
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+
+        if t >= length {
+            self.note += 1;
+            self.sample = 0;
+            return Some(0.);
+        }
+
+        if frequency == 0. {
+            return Some(0.);
+        }
+
+        // a soft plucked tone with one overtone
+        let phase = std::f32::consts::TAU * frequency * t;
+        let envelope = (t / 0.01).min(1.) * (-3. * t).exp();
+        Some((phase.sin() + 0.3 * (2. * phase).sin()) * envelope * 0.3)
+    }
+}
+
+impl Source for TuneDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(self.total))
+    }
+}
+
+impl Decodable for Tune {
+    type DecoderItem = f32;
+    type Decoder = TuneDecoder;
+
+    fn decoder(&self) -> TuneDecoder {
+        TuneDecoder {
+            notes: self.notes.clone(),
+            note: 0,
+            sample: 0,
+            total: self.duration(),
+        }
+    }
+}
+
+/// Tracks played one after another, looping back to the first.
+#[derive(Resource)]
+pub struct MusicPlaylist {
+    pub tracks: Vec<Handle<Tune>>,
+    pub current: usize,
+}
+
+#[derive(Component)]
+pub(super) struct MusicTrack;
+
+pub(super) fn setup_music(mut commands: Commands, mut tunes: ResMut<Assets<Tune>>) {
+    let tracks = [
+        Tune::from_steps(
+            220.,
+            0.5,
+            &[
+                Some(0),
+                Some(4),
+                Some(7),
+                Some(12),
+                Some(7),
+                Some(4),
+                Some(0),
+                None,
+                Some(5),
+                Some(9),
+                Some(12),
+                Some(9),
+                Some(7),
+                None,
+                Some(0),
+                None,
+            ],
+        ),
+        Tune::from_steps(
+            196.,
+            0.6,
+            &[
+                Some(0),
+                Some(3),
+                Some(7),
+                Some(10),
+                Some(7),
+                None,
+                Some(5),
+                Some(3),
+                Some(0),
+                None,
+                Some(-2),
+                Some(0),
+                None,
+                None,
+            ],
+        ),
+        Tune::from_steps(
+            262.,
+            0.4,
+            &[
+                Some(0),
+                Some(2),
+                Some(4),
+                Some(7),
+                Some(9),
+                Some(7),
+                Some(4),
+                Some(2),
+                Some(0),
+                None,
+                Some(9),
+                Some(7),
+                Some(4),
+                Some(2),
+                Some(0),
+                None,
+            ],
+        ),
+    ];
+
+    commands.insert_resource(MusicPlaylist {
+        tracks: tracks.into_iter().map(|tune| tunes.add(tune)).collect(),
+        current: 0,
+    });
+}
+
+/// Starts the next track once the last one finished and the gap has passed. N
+/// skips to the next track.
+pub(super) fn play_music(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<AudioSettings>,
+    mut playlist: ResMut<MusicPlaylist>,
+    mut silence: Local<f32>,
+    playing: Query<(Entity, Option<&AudioSink>), With<MusicTrack>>,
+) {
+    let volume = settings.master * settings.music;
+
+    if let Ok((entity, sink)) = playing.get_single() {
+        if let Some(sink) = sink {
+            sink.set_volume(volume);
+            if keys.just_pressed(KeyCode::KeyN) {
+                sink.stop();
+                commands.entity(entity).despawn();
+                *silence = TRACK_GAP;
+            }
+        }
+        return;
+    }
+
+    *silence += time.delta_seconds();
+    if *silence < TRACK_GAP || playlist.tracks.is_empty() {
+        return;
+    }
+    *silence = 0.;
+
+    let track = playlist.tracks[playlist.current].clone();
+    playlist.current = (playlist.current + 1) % playlist.tracks.len();
+
+    commands.spawn((
+        AudioSourceBundle {
+            source: track,
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
+        },
+        MusicTrack,
+    ));
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::terrain::Block;
+
+/// Folder the prefab structures are read from.
+pub const BLUEPRINT_DIR: &str = "assets/blueprints";
+
+/// Where worldgen looks for a spot for a blueprint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum Placement {
+    /// Standing on open ground.
+    Surface,
+    /// Sealed inside solid rock.
+    Buried,
+}
+
+/// A prefab chunk of blocks, written as RON. Layers go bottom to top, each a
+/// list of rows along +Z whose characters run along +X. Characters are looked
+/// up in the palette, anything missing from it leaves the terrain as is.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Blueprint {
+    pub name: String,
+    pub placement: Placement,
+    /// How many copies worldgen tries to place.
+    pub count: u32,
+    pub palette: HashMap<char, Block>,
+    pub layers: Vec<Vec<String>>,
+}
+
+impl Blueprint {
+    pub fn parse(source: &str) -> Result<Blueprint, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// Reads every `.ron` file in `dir`. Files that fail to parse are
+    /// reported and skipped. Sorted by name so the result doesn't depend on
+    /// the order the filesystem lists them in.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Vec<Blueprint> {
+        let Ok(entries) = fs::read_dir(dir.as_ref()) else {
+            println!("No blueprints found in {}", dir.as_ref().display());
+            return vec![];
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let parsed = fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|source| Blueprint::parse(&source).map_err(|err| err.to_string()));
+
+                match parsed {
+                    Ok(blueprint) => Some(blueprint),
+                    Err(err) => {
+                        println!("Skipping blueprint {}: {}", path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Extent of the bounding box.
+    pub fn size(&self) -> IVec3 {
+        let z = self.layers.iter().map(|l| l.len()).max().unwrap_or(0);
+        let x = self
+            .layers
+            .iter()
+            .flatten()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        IVec3::new(x as i32, self.layers.len() as i32, z as i32)
+    }
+
+    /// Blocks the blueprint sets, relative to its minimum corner.
+    pub fn cells(&self) -> impl Iterator<Item = (IVec3, Block)> + '_ {
+        self.layers.iter().enumerate().flat_map(move |(y, layer)| {
+            layer.iter().enumerate().flat_map(move |(z, row)| {
+                row.chars().enumerate().filter_map(move |(x, c)| {
+                    let block = self.palette.get(&c)?;
+                    Some((IVec3::new(x as i32, y as i32, z as i32), *block))
+                })
+            })
+        })
+    }
+}
@@ -0,0 +1,369 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::{CameraRay, FlyCamera},
+    input::{Action, ActionState},
+    job::{JobKind, JobQueue},
+    menu::AppState,
+    mining::find_tree,
+    structure::{can_place, place_structure, StructureKind},
+    terrain::{
+        Block, Designation, Designations, Facing, Orientation, Terrain, TerrainModifiedEvent,
+    },
+};
+
+pub struct BuildPlugin;
+
+/// Build progress an agent adds per second of work.
+pub const BUILD_RATE: f32 = 0.5;
+
+const PLACE_REACH: f32 = 64.;
+
+/// What a click in build mode does.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BuildTool {
+    /// Lay out a construction site for an agent to build.
+    Block(Block),
+    /// Place a whole structure at once, without waiting on an agent.
+    Structure(StructureKind),
+    /// Mark a block for an agent to dig out.
+    Mine,
+    /// Mark a tree for an agent to chop down, by its trunk or leaves.
+    Chop,
+    /// Raise the ground under a round brush with left click, lower it with
+    /// right click.
+    Sculpt,
+    /// Even out the ground under a round brush.
+    Smooth,
+}
+
+/// The key that picks each tool.
+const TOOLS: [(KeyCode, BuildTool); 14] = [
+    (KeyCode::Digit1, BuildTool::Block(Block::Dirt)),
+    (KeyCode::Digit2, BuildTool::Block(Block::Stone)),
+    (KeyCode::Digit3, BuildTool::Block(Block::Slab)),
+    (
+        KeyCode::Digit4,
+        BuildTool::Block(Block::Stairs(Facing::North)),
+    ),
+    (
+        KeyCode::Digit5,
+        BuildTool::Block(Block::Ladder(Facing::North)),
+    ),
+    (
+        KeyCode::Digit6,
+        BuildTool::Block(Block::Door(Facing::North)),
+    ),
+    (
+        KeyCode::Digit7,
+        BuildTool::Structure(StructureKind::Workshop),
+    ),
+    (KeyCode::Digit8, BuildTool::Structure(StructureKind::Table)),
+    (
+        KeyCode::Digit9,
+        BuildTool::Block(Block::Log(Orientation::Up)),
+    ),
+    (KeyCode::Digit0, BuildTool::Mine),
+    (KeyCode::KeyC, BuildTool::Chop),
+    (KeyCode::KeyT, BuildTool::Block(Block::Sapling)),
+    (KeyCode::KeyG, BuildTool::Sculpt),
+    (KeyCode::KeyJ, BuildTool::Smooth),
+];
+
+#[derive(Resource)]
+pub struct BuildMode {
+    pub enabled: bool,
+    pub tool: BuildTool,
+    pub target: Option<IVec3>,
+    /// Normal of the face the target was placed against.
+    pub target_normal: IVec3,
+}
+
+impl Default for BuildMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tool: BuildTool::Block(Block::Dirt),
+            target: None,
+            target_normal: IVec3::ZERO,
+        }
+    }
+}
+
+#[derive(Component)]
+struct BuildGhost;
+
+/// Blocks players can lay down in build mode, facing any way.
+pub fn is_placeable(block: Block) -> bool {
+    matches!(
+        block,
+        Block::Dirt
+            | Block::Stone
+            | Block::Slab
+            | Block::Stairs(_)
+            | Block::Ladder(_)
+            | Block::Door(_)
+            | Block::Log(_)
+            | Block::Sapling
+    )
+}
+
+impl Plugin for BuildPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildMode>()
+            .init_resource::<Designations>()
+            .add_systems(Startup, setup_build)
+            .add_systems(
+                Update,
+                (
+                    toggle_build_mode,
+                    update_ghost,
+                    place_construction,
+                    place_structures,
+                    place_mining_sites,
+                    designate_sites,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+fn setup_build(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let cube = meshes.add(Cuboid::new(1.02, 1.02, 1.02));
+    let ghost_material = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.4, 0.7, 1.0, 0.35),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    commands.spawn((
+        PbrBundle {
+            mesh: cube,
+            material: ghost_material,
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        BuildGhost,
+    ));
+}
+
+fn toggle_build_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    mut build: ResMut<BuildMode>,
+) {
+    if actions.just_pressed(Action::ToggleBuildMode) {
+        build.enabled = !build.enabled;
+        println!("Build mode: {}", build.enabled);
+    }
+
+    for (key, tool) in TOOLS {
+        if keys.just_pressed(key) {
+            build.tool = tool;
+        }
+    }
+}
+
+fn update_ghost(
+    terrain: Res<Terrain>,
+    mut build: ResMut<BuildMode>,
+    camera_ray: Res<CameraRay>,
+    queue: Res<JobQueue>,
+    mut ghosts: Query<(&mut Transform, &mut Visibility), With<BuildGhost>>,
+) {
+    build.target = None;
+
+    if build.enabled {
+        if let Some(ray) = camera_ray.0 {
+            if let Some(hit) = terrain.raycast(ray.origin, *ray.direction, PLACE_REACH) {
+                let pos = hit.pos + hit.normal;
+                match build.tool {
+                    BuildTool::Block(block) => {
+                        let is_free = !terrain.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16)
+                            && terrain.get_at(pos) == Block::Empty
+                            && pos.y < terrain.slice as i32
+                            && queue.at(pos).is_none();
+
+                        // ladders need a wall to hang on
+                        let is_supported = match block {
+                            Block::Ladder(_) => hit.normal.y == 0,
+                            // saplings take root in soil
+                            Block::Sapling => {
+                                matches!(terrain.get_at(pos - IVec3::Y), Block::Dirt | Block::Grass)
+                            }
+                            _ => true,
+                        };
+
+                        if hit.normal != IVec3::ZERO && is_free && is_supported {
+                            build.target = Some(pos);
+                            build.target_normal = hit.normal;
+                        }
+                    }
+                    BuildTool::Structure(kind) => {
+                        if hit.normal == IVec3::Y && can_place(&terrain, kind, pos) {
+                            build.target = Some(pos);
+                            build.target_normal = hit.normal;
+                        }
+                    }
+                    BuildTool::Mine => {
+                        if terrain.get_at(hit.pos).is_minable() && queue.at(hit.pos).is_none() {
+                            build.target = Some(hit.pos);
+                            build.target_normal = hit.normal;
+                        }
+                    }
+                    BuildTool::Chop => {
+                        // the ghost sits on the base, where it's chopped
+                        if let Some(tree) = find_tree(&terrain, hit.pos) {
+                            if queue.at(tree.base).is_none() {
+                                build.target = Some(tree.base);
+                                build.target_normal = hit.normal;
+                            }
+                        }
+                    }
+                    BuildTool::Sculpt | BuildTool::Smooth => {
+                        build.target = Some(hit.pos);
+                        build.target_normal = hit.normal;
+                    }
+                }
+            }
+        }
+    }
+
+    let size = match build.tool {
+        BuildTool::Structure(kind) => kind.size().as_vec3(),
+        _ => Vec3::ONE,
+    };
+    for (mut transform, mut visibility) in ghosts.iter_mut() {
+        match build.target {
+            Some(pos) => {
+                transform.translation = pos.as_vec3() + size / 2.;
+                transform.scale = size;
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn place_construction(
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    terrain: Res<Terrain>,
+    mut queue: ResMut<JobQueue>,
+    cameras: Query<&GlobalTransform, With<FlyCamera>>,
+) {
+    if !build.enabled || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    if let (Some(pos), BuildTool::Block(block)) = (build.target, build.tool) {
+        // directional blocks face away from the player, logs lie along the
+        // face they were placed against
+        let look = cameras
+            .get_single()
+            .map_or(Facing::North, |t| Facing::from_dir(t.forward()));
+        let block = match block {
+            Block::Ladder(facing) => {
+                Block::Ladder(Facing::from_offset(-build.target_normal).unwrap_or(facing))
+            }
+            Block::Stairs(_) => Block::Stairs(look),
+            Block::Door(_) => Block::Door(look),
+            Block::Log(orientation) => {
+                Block::Log(Orientation::from_normal(build.target_normal).unwrap_or(orientation))
+            }
+            block => block,
+        };
+
+        queue.push(&terrain, JobKind::Build(block), pos);
+    }
+}
+
+fn place_structures(
+    mut commands: Commands,
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    mut terrain: ResMut<Terrain>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if !build.enabled || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    if let (Some(pos), BuildTool::Structure(kind)) = (build.target, build.tool) {
+        let placed = place_structure(&mut commands, &mut terrain, kind, pos);
+
+        if placed.is_some() {
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
+
+fn place_mining_sites(
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    terrain: Res<Terrain>,
+    mut queue: ResMut<JobQueue>,
+) {
+    if !build.enabled || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Some(pos) = build.target else {
+        return;
+    };
+    match build.tool {
+        BuildTool::Mine => {
+            queue.push(&terrain, JobKind::Mine, pos);
+        }
+        // clicks on the leaves chop the trunk they belong to, from its base
+        BuildTool::Chop => {
+            if let Some(tree) = find_tree(&terrain, pos) {
+                queue.push(&terrain, JobKind::Chop, tree.base);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Marks the queued jobs on the terrain, wherever they were queued from.
+/// Only touches `Designations` when they changed, since that uploads them
+/// again.
+fn designate_sites(mut designations: ResMut<Designations>, queue: Res<JobQueue>) {
+    if !queue.is_changed() {
+        return;
+    }
+
+    let sites = Designations(
+        queue
+            .iter()
+            .filter_map(|(_, job)| match job.kind {
+                JobKind::Mine | JobKind::Chop => Some((job.pos, Designation::Dig)),
+                JobKind::Build(_) => Some((job.pos, Designation::Build)),
+                JobKind::Haul { .. } => None,
+            })
+            .collect(),
+    );
+    designations.set_if_neq(sites);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_block_tool_is_placeable() {
+        for (_, tool) in TOOLS {
+            if let BuildTool::Block(block) = tool {
+                assert!(is_placeable(block), "{}", block);
+            }
+        }
+        assert!(!is_placeable(Block::Empty));
+        assert!(!is_placeable(Block::Structure));
+    }
+}
@@ -0,0 +1,375 @@
+use bevy::{
+    ecs::event::ManualEventReader,
+    input::mouse::MouseMotion,
+    pbr::{FogFalloff, FogSettings},
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{Action, ActionState},
+    menu::AppState,
+    terrain::{Block, GraphicsSettings, Terrain, LIQUID_LEVEL},
+};
+
+pub struct CameraPlugin {
+    /// Whether the cursor is captured as soon as the window opens.
+    pub grab_cursor: bool,
+}
+
+#[derive(Component)]
+pub struct FlyCamera;
+
+/// Narrowest and widest the field of view gets with zooming, in degrees.
+const MIN_FOV: f32 = 15.;
+const MAX_FOV: f32 = 90.;
+
+/// Share of the field of view kept with each step of zooming in.
+const ZOOM_STEP: f32 = 0.9;
+
+/// World-space ray under the cursor, or through the screen center while the
+/// cursor is grabbed, from the fly camera. Worked out once at the start of
+/// each frame for everything that picks blocks. None without a window,
+/// camera or cursor.
+#[derive(Resource, Default)]
+pub struct CameraRay(pub Option<Ray3d>);
+
+/// What the fly camera is inside of, checked each frame. The screen is
+/// tinted while it's anywhere but the open air.
+#[derive(Resource, Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraMedium {
+    #[default]
+    Air,
+    Water,
+    /// Inside the ground, flown into since the camera doesn't collide.
+    Solid,
+}
+
+impl CameraMedium {
+    /// Overlay drawn over the scene, None for no tint.
+    fn tint(&self) -> Option<Color> {
+        match self {
+            CameraMedium::Air => None,
+            CameraMedium::Water => Some(Color::rgba(0.05, 0.2, 0.4, 0.55)),
+            CameraMedium::Solid => Some(Color::rgba(0.06, 0.05, 0.04, 0.9)),
+        }
+    }
+}
+
+/// Full-screen node the medium's tint is drawn with.
+#[derive(Component)]
+struct MediumOverlay;
+
+#[derive(Resource, Default)]
+struct CameraState {
+    reader_motion: ManualEventReader<MouseMotion>,
+}
+
+/// How the fly camera handles, kept in the settings file.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub sensitivity: f32,
+    pub speed: f32,
+    pub shift_multiplier: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.00012,
+            speed: 20.,
+            shift_multiplier: 2.,
+        }
+    }
+}
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraState>()
+            .init_resource::<CameraSettings>()
+            .init_resource::<CameraRay>()
+            .init_resource::<CameraMedium>()
+            .add_systems(PreUpdate, update_camera_ray)
+            .add_systems(OnEnter(AppState::InGame), spawn_medium_overlay)
+            .add_systems(OnExit(AppState::InGame), despawn_medium_overlay)
+            .add_systems(
+                Update,
+                (
+                    apply_camera_translation,
+                    apply_camera_rotation,
+                    zoom_camera,
+                    grab_cursor,
+                    (update_camera_medium, tint_screen).chain(),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(Update, apply_view_distance);
+
+        if self.grab_cursor {
+            app.add_systems(OnEnter(AppState::InGame), initial_grab_cursor);
+        }
+    }
+}
+
+fn grab_cursor(
+    actions: Res<ActionState>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        if actions.just_pressed(Action::GrabCursor) {
+            toggle_grab_cursor(&mut window)
+        }
+    } else {
+        warn!("Primary window not found");
+    }
+}
+
+fn apply_camera_rotation(
+    settings: Res<CameraSettings>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    mut state: ResMut<CameraState>,
+    motion: Res<Events<MouseMotion>>,
+    mut cameras: Query<&mut Transform, With<FlyCamera>>,
+) {
+    if let Ok(window) = primary_window.get_single() {
+        for mut transform in cameras.iter_mut() {
+            let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+            for ev in state.reader_motion.read(&motion) {
+                match window.cursor.grab_mode {
+                    CursorGrabMode::None => (),
+                    _ => {
+                        let window_scale = window.height().min(window.width());
+                        pitch -= (settings.sensitivity * ev.delta.y * window_scale).to_radians();
+                        yaw -= (settings.sensitivity * ev.delta.x * window_scale).to_radians();
+                    }
+                }
+            }
+
+            pitch = pitch.clamp(-1.54, 1.54);
+
+            transform.rotation =
+                Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+        }
+    } else {
+        warn!("Primary window not found");
+    }
+}
+
+fn apply_camera_translation(
+    actions: Res<ActionState>,
+    // keeps flying at the same pace whatever speed the world runs at
+    time: Res<Time<Real>>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<CameraSettings>,
+    mut cameras: Query<&mut Transform, With<FlyCamera>>,
+) {
+    if let Ok(window) = primary_window.get_single() {
+        if window.cursor.grab_mode == CursorGrabMode::None {
+            return;
+        }
+
+        for mut transform in cameras.iter_mut() {
+            let mut delta = Vec3::ZERO;
+            let local_z = *transform.local_z();
+            let forward = *transform.forward();
+            // let forward = -Vec3::new(local_z.x, 0., local_z.z);
+            let right = Vec3::new(local_z.z, 0., -local_z.x);
+
+            for (action, direction) in [
+                (Action::MoveForward, forward),
+                (Action::MoveBack, -forward),
+                (Action::MoveLeft, -right),
+                (Action::MoveRight, right),
+            ] {
+                if actions.pressed(action) {
+                    delta += direction;
+                }
+            }
+            let is_shift = actions.pressed(Action::Sprint);
+
+            delta = delta.normalize_or_zero();
+
+            let speed = if is_shift {
+                settings.speed * settings.shift_multiplier
+            } else {
+                settings.speed
+            };
+
+            transform.translation += delta * time.delta_seconds() * speed;
+        }
+    } else {
+        warn!("Primary window not found");
+    }
+}
+
+/// Narrows the field of view a step for every press of zoom in, and widens
+/// it for zoom out.
+fn zoom_camera(actions: Res<ActionState>, mut cameras: Query<&mut Projection, With<FlyCamera>>) {
+    let steps = actions.presses(Action::ZoomIn) as i32 - actions.presses(Action::ZoomOut) as i32;
+    if steps == 0 {
+        return;
+    }
+
+    for mut projection in cameras.iter_mut() {
+        if let Projection::Perspective(perspective) = &mut *projection {
+            let fov = perspective.fov * ZOOM_STEP.powi(steps);
+            perspective.fov = fov.clamp(MIN_FOV.to_radians(), MAX_FOV.to_radians());
+        }
+    }
+}
+
+/// Pulls the far plane in to the render distance, with fog thickening up
+/// to it if that's on, whenever the settings change or a camera appears.
+fn apply_view_distance(
+    mut commands: Commands,
+    graphics: Res<GraphicsSettings>,
+    mut cameras: Query<(Entity, &mut Projection, Ref<FlyCamera>)>,
+) {
+    for (entity, mut projection, camera) in cameras.iter_mut() {
+        if !graphics.is_changed() && !camera.is_added() {
+            continue;
+        }
+
+        if let Projection::Perspective(perspective) = &mut *projection {
+            perspective.far = graphics.render_distance;
+        }
+        if graphics.fog {
+            commands.entity(entity).insert(FogSettings {
+                falloff: FogFalloff::Linear {
+                    start: graphics.render_distance * 0.5,
+                    end: graphics.render_distance,
+                },
+                ..default()
+            });
+        } else {
+            commands.entity(entity).remove::<FogSettings>();
+        }
+    }
+}
+
+fn toggle_grab_cursor(window: &mut Window) {
+    match window.cursor.grab_mode {
+        CursorGrabMode::None => {
+            window.cursor.grab_mode = CursorGrabMode::Confined;
+            window.cursor.visible = false;
+        }
+        _ => {
+            window.cursor.grab_mode = CursorGrabMode::None;
+            window.cursor.visible = true;
+        }
+    }
+}
+
+fn update_camera_ray(
+    mut camera_ray: ResMut<CameraRay>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<FlyCamera>>,
+) {
+    camera_ray.0 = match (primary_window.get_single(), cameras.get_single()) {
+        (Ok(window), Ok((camera, transform))) => cursor_ray(window, camera, transform),
+        _ => None,
+    };
+}
+
+fn cursor_ray(window: &Window, camera: &Camera, transform: &GlobalTransform) -> Option<Ray3d> {
+    let screen_pos = match window.cursor.grab_mode {
+        CursorGrabMode::None => window.cursor_position()?,
+        _ => Vec2::new(window.width() / 2., window.height() / 2.),
+    };
+
+    camera.viewport_to_world(transform, screen_pos)
+}
+
+/// Looks up the block at the camera. Blocks above the slice aren't drawn, so
+/// the camera is in the open there, and the top of a pool only fills its
+/// cell up to the water line.
+fn update_camera_medium(
+    terrain: Res<Terrain>,
+    mut medium: ResMut<CameraMedium>,
+    cameras: Query<&GlobalTransform, With<FlyCamera>>,
+) {
+    let Ok(transform) = cameras.get_single() else {
+        return;
+    };
+    let eye = transform.translation();
+    let pos = eye.floor().as_ivec3();
+
+    let block = terrain.get_at(pos);
+    let next = if pos.y >= terrain.slice as i32 {
+        CameraMedium::Air
+    } else if block == Block::Water {
+        let is_topped = terrain.get_at(pos + IVec3::Y) == Block::Water;
+        if is_topped || eye.y - pos.y as f32 <= LIQUID_LEVEL {
+            CameraMedium::Water
+        } else {
+            CameraMedium::Air
+        }
+    } else if block.is_filled() {
+        CameraMedium::Solid
+    } else {
+        CameraMedium::Air
+    };
+
+    // only touched on a change, so the overlay only updates then
+    if *medium != next {
+        *medium = next;
+    }
+}
+
+fn spawn_medium_overlay(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            // under the rest of the interface, over the world
+            z_index: ZIndex::Global(-10),
+            ..default()
+        },
+        MediumOverlay,
+    ));
+}
+
+fn despawn_medium_overlay(
+    mut commands: Commands,
+    mut medium: ResMut<CameraMedium>,
+    overlays: Query<Entity, With<MediumOverlay>>,
+) {
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn();
+    }
+    *medium = CameraMedium::Air;
+}
+
+fn tint_screen(
+    medium: Res<CameraMedium>,
+    mut overlays: Query<(&mut BackgroundColor, &mut Visibility), With<MediumOverlay>>,
+) {
+    if !medium.is_changed() {
+        return;
+    }
+
+    for (mut background, mut visibility) in overlays.iter_mut() {
+        match medium.tint() {
+            Some(tint) => {
+                *background = tint.into();
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn initial_grab_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        toggle_grab_cursor(&mut window);
+    } else {
+        warn!("Primary window not found");
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy::{math::bounding::Aabb3d, prelude::*};
+
+use crate::{
+    menu::AppState,
+    terrain::{
+        tile_color, Block, BlockChangedEvent, BlockShape, FaceDir, Terrain, TerrainMesh,
+        TerrainModifiedEvent,
+    },
+};
+
+pub struct CollapsePlugin;
+
+/// Draws the falling blocks, each tinted like the block it was.
+pub struct CollapseViewPlugin;
+
+/// Clusters larger than this are assumed to hold themselves up, which keeps
+/// the check cheap when digging into the bulk of the terrain.
+const MAX_CLUSTER: usize = 4096;
+const GRAVITY: f32 = 20.;
+/// Half the size a falling block collides as, a hair under a cell so it
+/// drops down shafts as wide as itself.
+const FALLING_HALF_SIZE: f32 = 0.49;
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// A block knocked loose by a cave-in, falling until it lands on something.
+#[derive(Component)]
+struct FallingBlock {
+    block: Block,
+    velocity: f32,
+}
+
+#[derive(Resource)]
+struct FallingAssets {
+    cube: Handle<Mesh>,
+    /// Material per terrain texture, tinted with the tile's average color.
+    materials: HashMap<u32, Handle<StandardMaterial>>,
+}
+
+impl Plugin for CollapsePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (check_support, fall_blocks)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(OnExit(AppState::InGame), clear_falling);
+    }
+}
+
+impl Plugin for CollapseViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_collapse).add_systems(
+            Update,
+            add_falling_meshes.run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// Blocks still in the air when the world is left would land in the next one.
+fn clear_falling(mut commands: Commands, falling: Query<Entity, With<FallingBlock>>) {
+    for entity in falling.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn setup_collapse(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(FallingAssets {
+        cube: meshes.add(Cuboid::new(1., 1., 1.)),
+        materials: HashMap::new(),
+    });
+}
+
+/// Flood-fills the solid blocks connected to `start`, lowest first so the
+/// ground is found quickly. Returns the cluster if it doesn't reach the bottom
+/// of the map, `None` when it is supported.
+fn find_floating_cluster(terrain: &Terrain, start: IVec3) -> Option<Vec<IVec3>> {
+    let mut visited = HashSet::from([start]);
+    let mut open = BinaryHeap::from([Reverse((start.y, start.x, start.z))]);
+
+    while let Some(Reverse((y, x, z))) = open.pop() {
+        if y == 0 || visited.len() > MAX_CLUSTER {
+            return None;
+        }
+
+        let pos = IVec3::new(x, y, z);
+        for offset in NEIGHBORS {
+            let next = pos + offset;
+            if terrain.get_at(next).is_solid() && visited.insert(next) {
+                open.push(Reverse((next.y, next.x, next.z)));
+            }
+        }
+    }
+
+    Some(visited.into_iter().collect())
+}
+
+/// After a block is removed, drops whatever it was holding up.
+fn check_support(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let mut checked = HashSet::new();
+
+    for ev in ev_block_changed.read() {
+        if ev.block.is_solid() {
+            continue;
+        }
+
+        for offset in NEIGHBORS {
+            let start = ev.pos + offset;
+            if checked.contains(&start) || !terrain.get_at(start).is_solid() {
+                continue;
+            }
+
+            let Some(cluster) = find_floating_cluster(&terrain, start) else {
+                checked.insert(start);
+                continue;
+            };
+
+            println!("Cave-in of {} blocks", cluster.len());
+
+            for pos in cluster {
+                let block = terrain.get_at(pos);
+                terrain.set_at(pos, Block::Empty);
+                checked.insert(pos);
+
+                // entity-backed blocks are torn down with their cell
+                if block.def().shape == BlockShape::Custom {
+                    continue;
+                }
+
+                commands.spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(
+                        pos.as_vec3() + Vec3::splat(0.5),
+                    )),
+                    FallingBlock {
+                        block,
+                        velocity: 0.,
+                    },
+                ));
+            }
+
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
+
+/// Gives new falling blocks their mesh, with a material per terrain
+/// texture.
+fn add_falling_meshes(
+    mut commands: Commands,
+    terrain_mesh: Res<TerrainMesh>,
+    images: Res<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut assets: ResMut<FallingAssets>,
+    falling: Query<(Entity, &FallingBlock), Added<FallingBlock>>,
+) {
+    for (entity, falling_block) in falling.iter() {
+        let texture_id = falling_block.block.texture_id(FaceDir::PosX);
+        let material = match assets.materials.get(&texture_id) {
+            Some(material) => material.clone(),
+            None => {
+                let color = images
+                    .get(&terrain_mesh.texture)
+                    .and_then(|image| tile_color(image, texture_id))
+                    .unwrap_or(Color::GRAY);
+                let material = materials.add(color);
+                assets.materials.insert(texture_id, material.clone());
+                material
+            }
+        };
+
+        commands
+            .entity(entity)
+            .insert((assets.cube.clone(), material));
+    }
+}
+
+/// Moves falling blocks down and writes them back into the terrain where they
+/// land, in the cell their bottom came to rest nearest. They sink through
+/// liquids and take the place of the liquid at the bottom. A block landing
+/// in an occupied cell is lost.
+fn fall_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut terrain: ResMut<Terrain>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+    mut falling: Query<(Entity, &mut FallingBlock, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut falling_block, mut transform) in falling.iter_mut() {
+        falling_block.velocity += GRAVITY * dt;
+        let aabb = Aabb3d::new(transform.translation, Vec3::splat(FALLING_HALF_SIZE));
+        let sweep = terrain.sweep_aabb(aabb, Vec3::NEG_Y * falling_block.velocity * dt);
+        transform.translation += sweep.delta;
+
+        if sweep.blocked.y {
+            let mut pos = transform.translation.floor().as_ivec3();
+            pos.y = (transform.translation.y - FALLING_HALF_SIZE).round() as i32;
+            let landed_in = terrain.get_at(pos);
+            if landed_in == Block::Empty || landed_in.def().shape == BlockShape::Liquid {
+                terrain.set_at(pos, falling_block.block);
+                ev_terrain_mod.send(TerrainModifiedEvent);
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures;
+
+    #[test]
+    fn only_clusters_cut_off_from_the_ground_float() {
+        let mut terrain = fixtures::ground(IVec3::splat(16), 4, Block::Stone);
+        // a pillar holding up a slab, and a slab beside it on nothing
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(3, 4, 3),
+            IVec3::new(4, 8, 4),
+            Block::Stone,
+        );
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(2, 8, 2),
+            IVec3::new(6, 9, 6),
+            Block::Dirt,
+        );
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(10, 8, 10),
+            IVec3::new(13, 9, 12),
+            Block::Dirt,
+        );
+
+        assert_eq!(find_floating_cluster(&terrain, IVec3::new(5, 8, 5)), None);
+        assert_eq!(find_floating_cluster(&terrain, IVec3::new(3, 0, 3)), None);
+
+        let mut cluster = find_floating_cluster(&terrain, IVec3::new(11, 8, 11)).unwrap();
+        cluster.sort_by_key(|pos| (pos.x, pos.z));
+        let expected: Vec<_> = terrain
+            .iter_region(IVec3::new(10, 8, 10), IVec3::new(13, 9, 12))
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(cluster, expected);
+
+        // taking the pillar out leaves the first slab hanging too
+        terrain.set_at(IVec3::new(3, 6, 3), Block::Empty);
+        let cluster = find_floating_cluster(&terrain, IVec3::new(5, 8, 5)).unwrap();
+        assert_eq!(cluster.len(), 16 + 1);
+    }
+}
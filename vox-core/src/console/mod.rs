@@ -0,0 +1,281 @@
+use std::num::{ParseFloatError, ParseIntError};
+
+use bevy::{input::InputSystem, prelude::*, window::ReceivedCharacter};
+use rand::Rng;
+
+use crate::{
+    camera::CameraRay,
+    input::ActionSystem,
+    job::PrioritizeJobsEvent,
+    menu::{AppState, RegenerateWorldEvent},
+    terraform::{ExplosionEvent, TerraformEvent, TerraformOp},
+    terrain::{parse_size, Block, Terrain, TerrainStats},
+    zone::{ZoneEvent, ZoneKind},
+};
+
+/// A one-line command prompt, opened and closed with the backquote key.
+/// While it's open the keyboard only types into it.
+///
+/// - `regen [XxYxZ] [seed]` generates a new world, keeping the current size
+///   and picking a random seed for whatever is left out.
+/// - `fill <block>`, `clear`, `replace <block> <block>` and
+///   `flatten <y> [block]` reshape the selection, `undo` takes the last of
+///   them back. Blocks are written as in blueprints, e.g. `Stone` or
+///   `Ramp(North)`.
+/// - `zone <stockpile|meeting|bedroom>` sets the floor of the selection aside as a
+///   new zone, `unzone` clears the zones from it.
+/// - `priority <0-9>` sets the priority of the jobs in the selection.
+/// - `explode <radius> [power]` sets off an explosion at the block under the
+///   cursor, as strong as its radius unless a power is given.
+/// - `stats` prints the `TerrainStats`.
+pub struct ConsolePlugin;
+
+/// How far away the block under the cursor can be for `explode`.
+const TARGET_REACH: f32 = 64.;
+
+/// The command being typed, None while the prompt is closed.
+#[derive(Resource, Default)]
+pub struct Console {
+    line: Option<String>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.line.is_some()
+    }
+}
+
+#[derive(Component)]
+struct ConsoleText;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_systems(Startup, setup_console)
+            .add_systems(
+                PreUpdate,
+                type_command
+                    .after(InputSystem)
+                    .before(ActionSystem)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(Update, show_console.run_if(resource_changed::<Console>));
+    }
+}
+
+fn setup_console(mut commands: Commands) {
+    let text = TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 20.,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(8.),
+        left: Val::Px(8.),
+        ..default()
+    })
+    .with_background_color(Color::rgba(0., 0., 0., 0.6));
+
+    commands.spawn((text, ConsoleText, Visibility::Hidden));
+}
+
+/// Runs before anything else reads the keyboard, so it can swallow the keys
+/// typed into the prompt.
+#[allow(clippy::too_many_arguments)]
+fn type_command(
+    mut console: ResMut<Console>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    terrain: Res<Terrain>,
+    stats: Res<TerrainStats>,
+    camera_ray: Res<CameraRay>,
+    mut ev_regenerate: EventWriter<RegenerateWorldEvent>,
+    mut ev_terraform: EventWriter<TerraformEvent>,
+    mut ev_explosion: EventWriter<ExplosionEvent>,
+    mut ev_zone: EventWriter<ZoneEvent>,
+    mut ev_prioritize: EventWriter<PrioritizeJobsEvent>,
+) {
+    if keys.just_pressed(KeyCode::Backquote) {
+        console.line = match console.line {
+            Some(_) => None,
+            None => Some(String::new()),
+        };
+        ev_chars.clear();
+        keys.reset_all();
+        return;
+    }
+
+    let Some(line) = console.line.as_mut() else {
+        ev_chars.clear();
+        return;
+    };
+
+    for ev in ev_chars.read() {
+        line.extend(ev.char.chars().filter(|c| !c.is_control() && *c != '`'));
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        line.pop();
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        let target = camera_ray
+            .0
+            .and_then(|ray| terrain.raycast(ray.origin, *ray.direction, TARGET_REACH))
+            .map(|hit| hit.pos);
+        if let Err(err) = run_command(
+            line,
+            &terrain,
+            &stats,
+            target,
+            &mut ev_regenerate,
+            &mut ev_terraform,
+            &mut ev_explosion,
+            &mut ev_zone,
+            &mut ev_prioritize,
+        ) {
+            println!("{}", err);
+        }
+        console.line = None;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        console.line = None;
+    }
+
+    keys.reset_all();
+}
+
+/// Runs a line typed into the console, `target` being the block under the
+/// cursor.
+#[allow(clippy::too_many_arguments)]
+fn run_command(
+    line: &str,
+    terrain: &Terrain,
+    stats: &TerrainStats,
+    target: Option<IVec3>,
+    ev_regenerate: &mut EventWriter<RegenerateWorldEvent>,
+    ev_terraform: &mut EventWriter<TerraformEvent>,
+    ev_explosion: &mut EventWriter<ExplosionEvent>,
+    ev_zone: &mut EventWriter<ZoneEvent>,
+    ev_prioritize: &mut EventWriter<PrioritizeJobsEvent>,
+) -> Result<(), String> {
+    let mut words = line.split_whitespace();
+
+    match words.next() {
+        None => Ok(()),
+        Some("regen") => {
+            let size = words.next().map_or(Ok(terrain.size()), parse_size)?;
+            let seed = match words.next() {
+                Some(seed) => seed.parse().map_err(|err: ParseIntError| err.to_string())?,
+                None => rand::thread_rng().gen(),
+            };
+
+            println!(
+                "Regenerating a {}x{}x{} world from seed {}",
+                size.x, size.y, size.z, seed
+            );
+            ev_regenerate.send(RegenerateWorldEvent { size, seed });
+            Ok(())
+        }
+        Some("fill") => {
+            let block = parse_block(words.next())?;
+            ev_terraform.send(TerraformEvent::Apply(TerraformOp::Fill(block)));
+            Ok(())
+        }
+        Some("clear") => {
+            ev_terraform.send(TerraformEvent::Apply(TerraformOp::Clear));
+            Ok(())
+        }
+        Some("replace") => {
+            let from = parse_block(words.next())?;
+            let to = parse_block(words.next())?;
+            ev_terraform.send(TerraformEvent::Apply(TerraformOp::Replace(from, to)));
+            Ok(())
+        }
+        Some("flatten") => {
+            let level = words
+                .next()
+                .ok_or("expected a height")?
+                .parse()
+                .map_err(|err: ParseIntError| err.to_string())?;
+            let block = words
+                .next()
+                .map_or(Ok(Block::Dirt), |w| parse_block(Some(w)))?;
+            ev_terraform.send(TerraformEvent::Apply(TerraformOp::Flatten { level, block }));
+            Ok(())
+        }
+        Some("undo") => {
+            ev_terraform.send(TerraformEvent::Undo);
+            Ok(())
+        }
+        Some("zone") => {
+            let name = words.next().ok_or("expected a zone kind")?;
+            let kind =
+                ZoneKind::from_name(name).ok_or_else(|| format!("no zone kind `{}`", name))?;
+            ev_zone.send(ZoneEvent::Paint(kind));
+            Ok(())
+        }
+        Some("unzone") => {
+            ev_zone.send(ZoneEvent::Erase);
+            Ok(())
+        }
+        Some("priority") => {
+            let priority = words
+                .next()
+                .ok_or("expected a priority")?
+                .parse()
+                .map_err(|err: ParseIntError| err.to_string())?;
+            ev_prioritize.send(PrioritizeJobsEvent(priority));
+            Ok(())
+        }
+        Some("explode") => {
+            let radius: f32 = words
+                .next()
+                .ok_or("expected a radius")?
+                .parse()
+                .map_err(|err: ParseFloatError| err.to_string())?;
+            let power = match words.next() {
+                Some(power) => power
+                    .parse()
+                    .map_err(|err: ParseFloatError| err.to_string())?,
+                None => radius,
+            };
+            let pos = target.ok_or("no block under the cursor")?;
+            ev_explosion.send(ExplosionEvent {
+                center: pos.as_vec3() + Vec3::splat(0.5),
+                radius,
+                power,
+            });
+            Ok(())
+        }
+        Some("stats") => {
+            println!("{}", stats);
+            Ok(())
+        }
+        Some(name) => Err(format!("Unknown command `{}`", name)),
+    }
+}
+
+/// Reads a block written the way blueprints write them.
+fn parse_block(word: Option<&str>) -> Result<Block, String> {
+    let word = word.ok_or("expected a block")?;
+    ron::from_str(word).map_err(|_| format!("no block called `{}`", word))
+}
+
+fn show_console(
+    console: Res<Console>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<ConsoleText>>,
+) {
+    for (mut text, mut visibility) in texts.iter_mut() {
+        match &console.line {
+            Some(line) => {
+                text.sections[0].value = format!("> {}_", line);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    agent::{agent_cell, cell_center, Agent, AgentJob},
+    item::{drop_item, Carried, DropItemEvent, Item},
+    job::{JobKind, JobQueue},
+    light::LightMap,
+    menu::AppState,
+    net::is_authority,
+    pathfinding::{find_path, is_walkable},
+    terrain::{Block, Terrain},
+    tick::every,
+};
+
+/// Hostile creatures crawling out of the dark. They turn up in unlit cells
+/// underground, hunt down the agents nearby and trade blows with them until
+/// one side drops. A slain creature leaves an item behind, a slain agent
+/// gives up its job and drops whatever it carried.
+pub struct CreaturePlugin;
+
+/// Draws the creatures, hidden at or above the slice.
+pub struct CreatureViewPlugin;
+
+const MAX_CREATURES: usize = 4;
+
+/// Brightest a cell can be for a creature to turn up in it.
+const SPAWN_LIGHT: u8 = 2;
+
+/// How far off a creature notices an agent, in blocks along each axis.
+const HUNT_RADIUS: i32 = 16;
+
+/// How close two foes have to be to land a blow.
+const ATTACK_RANGE: f32 = 1.5;
+
+const CREATURE_HEALTH: f32 = 6.;
+const CREATURE_DAMAGE: f32 = 1.;
+
+#[derive(Component)]
+pub struct Creature {
+    pub speed: f32,
+    /// Left lying as an item when the creature is slain.
+    pub drop: Block,
+}
+
+/// Remaining cells a creature will crawl through, front first.
+#[derive(Component, Default)]
+pub struct CreaturePath {
+    pub cells: VecDeque<IVec3>,
+}
+
+/// Hit points, the entity dies once they run out.
+#[derive(Component, Debug)]
+pub struct Health {
+    pub hp: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { hp: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp <= 0.
+    }
+}
+
+/// Blows dealt to foes in reach, one every `cooldown`.
+#[derive(Component, Debug)]
+pub struct Attack {
+    pub damage: f32,
+    pub cooldown: Timer,
+}
+
+impl Attack {
+    pub fn new(damage: f32) -> Self {
+        Self {
+            damage,
+            cooldown: Timer::from_seconds(1., TimerMode::Once),
+        }
+    }
+}
+
+impl Plugin for CreaturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnExit(AppState::InGame), despawn_creatures)
+            .add_systems(
+                FixedUpdate,
+                (
+                    spawn_creatures.run_if(every(Duration::from_secs(5))),
+                    hunt_agents.run_if(every(Duration::from_secs(1))),
+                    follow_creature_paths,
+                    exchange_attacks,
+                    remove_dead,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            );
+    }
+}
+
+impl Plugin for CreatureViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_creature_meshes).add_systems(
+            Update,
+            (add_creature_meshes, update_creature_visibility).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct CreatureAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_creature_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(CreatureAssets {
+        mesh: meshes.add(Capsule3d::new(0.3, 0.3)),
+        material: materials.add(Color::rgb_u8(70, 40, 90)),
+    });
+}
+
+fn add_creature_meshes(
+    mut commands: Commands,
+    assets: Res<CreatureAssets>,
+    creatures: Query<Entity, Added<Creature>>,
+) {
+    for entity in creatures.iter() {
+        commands
+            .entity(entity)
+            .insert((assets.mesh.clone(), assets.material.clone()));
+    }
+}
+
+fn despawn_creatures(mut commands: Commands, creatures: Query<Entity, With<Creature>>) {
+    for entity in creatures.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Whether a creature can turn up in the cell: room to stand, and too dark
+/// to see.
+fn is_lair(terrain: &Terrain, light: &LightMap, pos: IVec3) -> bool {
+    is_walkable(terrain, pos)
+        && terrain.get_at(pos) == Block::Empty
+        && light.get(pos) <= SPAWN_LIGHT
+}
+
+fn is_adjacent(a: IVec3, b: IVec3) -> bool {
+    a != b && (a - b).abs().max_element() <= 1
+}
+
+/// Tops the creatures back up, each in a dark cell picked at random.
+fn spawn_creatures(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    light: Res<LightMap>,
+    creatures: Query<(), With<Creature>>,
+) {
+    if creatures.iter().count() >= MAX_CREATURES {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    let size = terrain.size();
+    let lair = (0..64)
+        .map(|_| {
+            IVec3::new(
+                rng.gen_range(0..size.x),
+                rng.gen_range(0..size.y),
+                rng.gen_range(0..size.z),
+            )
+        })
+        .find(|pos| is_lair(&terrain, &light, *pos));
+    let Some(pos) = lair else {
+        return;
+    };
+
+    commands.spawn((
+        SpatialBundle::from_transform(Transform::from_translation(cell_center(pos))),
+        Creature {
+            speed: 2.5,
+            drop: Block::Coal,
+        },
+        CreaturePath::default(),
+        Health::new(CREATURE_HEALTH),
+        Attack::new(CREATURE_DAMAGE),
+    ));
+}
+
+/// Sends every creature after the nearest agent it can reach.
+fn hunt_agents(
+    terrain: Res<Terrain>,
+    agents: Query<&Transform, With<Agent>>,
+    mut creatures: Query<(&Transform, &mut CreaturePath), With<Creature>>,
+) {
+    for (transform, mut path) in creatures.iter_mut() {
+        let cell = agent_cell(transform);
+        let prey = agents
+            .iter()
+            .map(agent_cell)
+            .filter(|agent| (*agent - cell).abs().max_element() <= HUNT_RADIUS)
+            .min_by_key(|agent| (*agent - cell).length_squared());
+
+        path.cells = prey
+            .and_then(|prey| find_path(&terrain, cell, prey, |p| is_adjacent(p, prey)))
+            .unwrap_or_default()
+            .into();
+    }
+}
+
+fn follow_creature_paths(
+    time: Res<Time>,
+    terrain: Res<Terrain>,
+    mut creatures: Query<(&Creature, &mut Transform, &mut CreaturePath)>,
+) {
+    for (creature, mut transform, mut path) in creatures.iter_mut() {
+        let Some(next) = path.cells.front().copied() else {
+            continue;
+        };
+
+        if !is_walkable(&terrain, next) {
+            // terrain changed under the route, a new one is found on the hunt
+            path.cells.clear();
+            continue;
+        }
+
+        let target = cell_center(next);
+        let to_target = target - transform.translation;
+        let step = creature.speed * time.delta_seconds();
+
+        if to_target.length() <= step {
+            transform.translation = target;
+            path.cells.pop_front();
+        } else {
+            transform.translation += to_target.normalize() * step;
+        }
+    }
+}
+
+/// Creatures and agents strike the nearest foe in reach whenever they're
+/// ready to.
+fn exchange_attacks(
+    time: Res<Time>,
+    mut fighters: Query<(&Transform, &mut Attack, Has<Creature>)>,
+    mut healths: Query<(Entity, &Transform, &mut Health, Has<Creature>)>,
+) {
+    let mut blows = vec![];
+    for (transform, mut attack, is_creature) in fighters.iter_mut() {
+        attack.cooldown.tick(time.delta());
+        if !attack.cooldown.finished() {
+            continue;
+        }
+
+        let pos = transform.translation;
+        let foe = healths
+            .iter()
+            .filter(|(_, _, health, is_foe_creature)| {
+                *is_foe_creature != is_creature && !health.is_dead()
+            })
+            .map(|(foe, foe_transform, _, _)| (foe, foe_transform.translation.distance(pos)))
+            .filter(|(_, distance)| *distance <= ATTACK_RANGE)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((foe, _)) = foe {
+            blows.push((foe, attack.damage));
+            attack.cooldown.reset();
+        }
+    }
+
+    for (foe, damage) in blows {
+        if let Ok((_, _, mut health, _)) = healths.get_mut(foe) {
+            health.hp -= damage;
+        }
+    }
+}
+
+/// Takes the slain off the map. Creatures leave their drop behind, agents
+/// give their job back and let go of what they were carrying.
+#[allow(clippy::type_complexity)]
+fn remove_dead(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut queue: ResMut<JobQueue>,
+    dead: Query<(
+        Entity,
+        &Transform,
+        &Health,
+        Option<&Creature>,
+        Option<&AgentJob>,
+    )>,
+    mut carried: Query<(Entity, &Parent, &mut Item, &GlobalTransform), With<Carried>>,
+    mut ev_drop: EventWriter<DropItemEvent>,
+) {
+    for (entity, transform, health, creature, agent_job) in dead.iter() {
+        if !health.is_dead() {
+            continue;
+        }
+
+        if let Some(creature) = creature {
+            ev_drop.send(DropItemEvent {
+                pos: agent_cell(transform),
+                block: creature.drop,
+            });
+        } else {
+            println!("An agent was killed at {}", agent_cell(transform));
+        }
+
+        if let Some(agent_job) = agent_job {
+            let job = queue.get(agent_job.job);
+            if job.is_some_and(|job| matches!(job.kind, JobKind::Haul { .. }) && job.picked_up) {
+                queue.remove(agent_job.job);
+            } else {
+                queue.release(agent_job.job);
+            }
+        }
+        for (item, parent, mut item_data, item_transform) in carried.iter_mut() {
+            if parent.get() == entity {
+                let at = item_transform.translation();
+                drop_item(&mut commands, &terrain, item, &mut item_data, at);
+            }
+        }
+
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Creatures at or above the slice are hidden along with the blocks around
+/// them.
+fn update_creature_visibility(
+    terrain: Res<Terrain>,
+    mut creatures: Query<(&Transform, &mut Visibility), With<Creature>>,
+) {
+    for (transform, mut visibility) in creatures.iter_mut() {
+        let is_visible = agent_cell(transform).y < terrain.slice as i32;
+        let next = if is_visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != next {
+            *visibility = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures::ground;
+
+    #[test]
+    fn creatures_only_turn_up_in_the_dark() {
+        let mut terrain = ground(IVec3::splat(8), 6, Block::Stone);
+        // a sealed cave, and a lit one next to lava
+        terrain.set_at(IVec3::new(2, 2, 2), Block::Empty);
+        terrain.set_at(IVec3::new(5, 2, 5), Block::Empty);
+        terrain.set_at(IVec3::new(5, 2, 6), Block::Lava);
+        let mut light = LightMap::default();
+        light.compute(&terrain);
+
+        assert!(is_lair(&terrain, &light, IVec3::new(2, 2, 2)));
+        assert!(!is_lair(&terrain, &light, IVec3::new(5, 2, 5)));
+        assert!(!is_lair(&terrain, &light, IVec3::new(2, 6, 2)));
+        assert!(!is_lair(&terrain, &light, IVec3::new(2, 1, 2)));
+    }
+}
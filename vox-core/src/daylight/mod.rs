@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+
+use crate::{
+    input::{Action, ActionState},
+    menu::AppState,
+};
+
+pub struct DaylightPlugin;
+
+/// F7 to F10 by default jump to noon, sunset, midnight and an overcast noon,
+/// setting the clock and the clouds together, so the lighting of different
+/// times of day can be compared at a keypress.
+pub struct LightingPresetsPlugin;
+
+/// Seconds of simulation time in a full in-game day.
+const DAY_LENGTH: f32 = 600.;
+const DAY_AMBIENT: f32 = 80.;
+const NIGHT_AMBIENT: f32 = 10.;
+
+/// Share of the daylight a full overcast holds back.
+const OVERCAST_DIMMING: f32 = 0.6;
+
+/// In-game clock, in hours from 0 to 24.
+#[derive(Resource)]
+pub struct TimeOfDay {
+    pub hour: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self { hour: 8. }
+    }
+}
+
+impl TimeOfDay {
+    /// How bright the sky is, 0 at midnight and 1 at noon.
+    pub fn daylight(&self) -> f32 {
+        let angle = (self.hour / 24.) * std::f32::consts::TAU;
+        (0.5 - 0.5 * angle.cos()).clamp(0., 1.)
+    }
+
+    /// Unit vector toward the sun. It rises in the east, stands a little
+    /// south of overhead at noon and is under the ground at night.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour / 24.) * std::f32::consts::TAU;
+        Vec3::new(angle.sin(), -angle.cos(), 0.3).normalize()
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.hour < 6. || self.hour >= 20.
+    }
+}
+
+/// How much of the sky the clouds cover, from 0 for clear skies to 1 for a
+/// full overcast that dims the day and pulls the fog in.
+#[derive(Resource, Default, Debug, Copy, Clone, PartialEq)]
+pub struct CloudCover(pub f32);
+
+impl CloudCover {
+    /// Share of the daylight that comes through.
+    pub fn transmittance(&self) -> f32 {
+        1. - OVERCAST_DIMMING * self.0.clamp(0., 1.)
+    }
+}
+
+/// A time of day and sky to jump to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightingPreset {
+    Noon,
+    Sunset,
+    Midnight,
+    /// Noon under a full overcast.
+    Overcast,
+}
+
+impl LightingPreset {
+    const ACTIONS: [(Action, LightingPreset); 4] = [
+        (Action::NoonLighting, LightingPreset::Noon),
+        (Action::SunsetLighting, LightingPreset::Sunset),
+        (Action::MidnightLighting, LightingPreset::Midnight),
+        (Action::OvercastLighting, LightingPreset::Overcast),
+    ];
+
+    pub fn hour(self) -> f32 {
+        match self {
+            LightingPreset::Noon | LightingPreset::Overcast => 12.,
+            // the sun on the horizon
+            LightingPreset::Sunset => 18.,
+            LightingPreset::Midnight => 0.,
+        }
+    }
+
+    pub fn clouds(self) -> CloudCover {
+        match self {
+            LightingPreset::Overcast => CloudCover(1.),
+            _ => CloudCover(0.),
+        }
+    }
+}
+
+impl Plugin for DaylightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .init_resource::<CloudCover>()
+            .add_systems(FixedUpdate, advance_time.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                Update,
+                // there are no lights to dim when running headless
+                update_ambient_light
+                    .run_if(resource_exists::<AmbientLight>.and_then(in_state(AppState::InGame))),
+            );
+    }
+}
+
+impl Plugin for LightingPresetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            apply_lighting_preset.run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn apply_lighting_preset(
+    actions: Res<ActionState>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut clouds: ResMut<CloudCover>,
+) {
+    for (action, preset) in LightingPreset::ACTIONS {
+        if actions.just_pressed(action) {
+            time_of_day.hour = preset.hour();
+            *clouds = preset.clouds();
+            println!("Lighting: {:?}", preset);
+        }
+    }
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    time_of_day.hour = (time_of_day.hour + 24. * time.delta_seconds() / DAY_LENGTH) % 24.;
+}
+
+fn update_ambient_light(
+    time_of_day: Res<TimeOfDay>,
+    clouds: Res<CloudCover>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    let daylight = time_of_day.daylight() * clouds.transmittance();
+    ambient.brightness = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_put_the_sun_in_place() {
+        let sun = |preset: LightingPreset| {
+            TimeOfDay {
+                hour: preset.hour(),
+            }
+            .sun_direction()
+        };
+
+        assert!(sun(LightingPreset::Noon).y > 0.9);
+        assert!(sun(LightingPreset::Sunset).y.abs() < 0.01);
+        assert!(sun(LightingPreset::Midnight).y < -0.9);
+        assert_eq!(sun(LightingPreset::Overcast), sun(LightingPreset::Noon));
+        assert!(LightingPreset::Overcast.clouds().transmittance() < 1.);
+        assert_eq!(LightingPreset::Noon.clouds().transmittance(), 1.);
+    }
+}
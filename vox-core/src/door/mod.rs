@@ -0,0 +1,238 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+
+use crate::{
+    agent::{agent_cell, Agent, AgentPath},
+    build::BuildMode,
+    camera::CameraRay,
+    menu::AppState,
+    terrain::{Block, BlockChangedEvent, BlockEntities, Facing, Terrain, TerrainModifiedEvent},
+};
+
+pub struct DoorPlugin;
+
+/// Draws the door panels swinging open and shut, and right click opens and
+/// shuts the door under the cursor.
+pub struct DoorViewPlugin;
+
+/// Seconds a door opened by a passing agent stays open after they leave.
+const AUTO_CLOSE_DELAY: f32 = 1.5;
+/// Radians per second the panel swings.
+const SWING_SPEED: f32 = 6.;
+const PANEL_THICKNESS: f32 = 0.1;
+const INTERACT_REACH: f32 = 64.;
+
+/// Per-voxel door state, kept on the door's block entity. The terrain only
+/// stores `Block::Door`; whether it is open lives here.
+#[derive(Component)]
+pub struct Door {
+    pub pos: IVec3,
+    pub facing: Facing,
+    pub open: bool,
+    /// Counts down while an agent-opened door waits to close, `None` when the
+    /// door was toggled by hand and should stay as it is.
+    close_timer: Option<f32>,
+}
+
+#[derive(Component)]
+struct DoorHinge {
+    angle: f32,
+}
+
+#[derive(Resource)]
+struct DoorAssets {
+    panel: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (spawn_doors, open_doors_for_agents, close_doors)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+impl Plugin for DoorViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_doors).add_systems(
+            Update,
+            (
+                add_door_panels,
+                update_door_visibility,
+                toggle_door_on_click.before(open_doors_for_agents),
+                swing_doors.after(close_doors),
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn setup_doors(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(DoorAssets {
+        panel: meshes.add(Cuboid::new(1., 1., PANEL_THICKNESS)),
+        material: materials.add(Color::rgb_u8(122, 86, 48)),
+    });
+}
+
+/// Yaw that turns the south-authored panel to face `facing`.
+fn facing_yaw(facing: Facing) -> f32 {
+    match facing {
+        Facing::South => 0.,
+        Facing::East => FRAC_PI_2,
+        Facing::North => 2. * FRAC_PI_2,
+        Facing::West => 3. * FRAC_PI_2,
+    }
+}
+
+/// Gives newly placed door blocks their entity.
+fn spawn_doors(
+    mut commands: Commands,
+    mut block_entities: ResMut<BlockEntities>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    for ev in ev_block_changed.read() {
+        let Block::Door(facing) = ev.block else {
+            continue;
+        };
+
+        // the hinge sits on the left edge of the panel, which runs through the
+        // middle of the cell
+        let hinge = ev.pos.as_vec3() + facing.rotate(Vec3::new(0., 0., 0.5));
+        let entity = commands
+            .spawn((
+                SpatialBundle::from_transform(
+                    Transform::from_translation(hinge)
+                        .with_rotation(Quat::from_rotation_y(facing_yaw(facing))),
+                ),
+                DoorHinge { angle: 0. },
+                Door {
+                    pos: ev.pos,
+                    facing,
+                    open: false,
+                    close_timer: None,
+                },
+            ))
+            .id();
+        block_entities.insert(ev.pos, entity);
+    }
+}
+
+/// Hangs a panel off the hinge of every new door.
+fn add_door_panels(
+    mut commands: Commands,
+    assets: Res<DoorAssets>,
+    doors: Query<Entity, Added<Door>>,
+) {
+    for entity in doors.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: assets.panel.clone(),
+                material: assets.material.clone(),
+                transform: Transform::from_xyz(0.5, 0.5, 0.),
+                ..default()
+            });
+        });
+    }
+}
+
+/// Blocks at or above the slice aren't drawn, neither are their doors.
+fn update_door_visibility(
+    terrain: Res<Terrain>,
+    mut ev_terrain_mod: EventReader<TerrainModifiedEvent>,
+    mut doors: Query<(&Door, &mut Visibility)>,
+) {
+    if ev_terrain_mod.is_empty() {
+        return;
+    }
+    ev_terrain_mod.clear();
+
+    for (door, mut visibility) in doors.iter_mut() {
+        *visibility = if door.pos.y < terrain.slice as i32 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn toggle_door_on_click(
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    terrain: Res<Terrain>,
+    block_entities: Res<BlockEntities>,
+    camera_ray: Res<CameraRay>,
+    mut doors: Query<&mut Door>,
+) {
+    if build.enabled || !buttons.just_pressed(MouseButton::Right) {
+        return;
+    }
+
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+
+    let Some(hit) = terrain.raycast(ray.origin, *ray.direction, INTERACT_REACH) else {
+        return;
+    };
+
+    if let Some(entity) = block_entities.get(hit.pos) {
+        if let Ok(mut door) = doors.get_mut(entity) {
+            door.open = !door.open;
+            door.close_timer = None;
+        }
+    }
+}
+
+fn open_doors_for_agents(
+    block_entities: Res<BlockEntities>,
+    agents: Query<(&Transform, &AgentPath), With<Agent>>,
+    mut doors: Query<&mut Door>,
+) {
+    for (transform, path) in agents.iter() {
+        let cells = [Some(agent_cell(transform)), path.cells.front().copied()];
+
+        for cell in cells.into_iter().flatten() {
+            if let Some(entity) = block_entities.get(cell) {
+                if let Ok(mut door) = doors.get_mut(entity) {
+                    if !door.open || door.close_timer.is_some() {
+                        door.open = true;
+                        door.close_timer = Some(AUTO_CLOSE_DELAY);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn close_doors(time: Res<Time>, mut doors: Query<&mut Door>) {
+    for mut door in doors.iter_mut() {
+        if let Some(remaining) = door.close_timer {
+            let remaining = remaining - time.delta_seconds();
+            if remaining <= 0. {
+                door.open = false;
+                door.close_timer = None;
+            } else {
+                door.close_timer = Some(remaining);
+            }
+        }
+    }
+}
+
+fn swing_doors(time: Res<Time>, mut doors: Query<(&Door, &mut DoorHinge, &mut Transform)>) {
+    for (door, mut hinge, mut transform) in doors.iter_mut() {
+        let target = if door.open { FRAC_PI_2 } else { 0. };
+        let step = SWING_SPEED * time.delta_seconds();
+        hinge.angle += (target - hinge.angle).clamp(-step, step);
+        transform.rotation = Quat::from_rotation_y(facing_yaw(door.facing) + hinge.angle);
+    }
+}
@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    build::BuildMode,
+    camera::CameraRay,
+    menu::AppState,
+    net::is_authority,
+    particles::ParticleBurstEvent,
+    terrain::{Block, BlockChangedEvent, BlockEntities, Terrain, TerrainModifiedEvent},
+    tick::every,
+};
+
+pub struct FirePlugin;
+
+/// Draws the flames with their light and embers, and F sets the block under
+/// the cursor alight.
+pub struct FireViewPlugin;
+
+/// Seconds between fire ticks.
+const FIRE_TICK: f32 = 0.25;
+/// Seconds a cell burns before it goes out.
+const BURN_TIME: f32 = 4.;
+/// Chance per tick that a fire lights each flammable neighbor.
+const SPREAD_CHANCE: f32 = 0.08;
+const EMBERS_PER_TICK: u32 = 2;
+const IGNITE_REACH: f32 = 64.;
+const LIGHT_INTENSITY: f32 = 4000.;
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Burning state, kept on the `Block::Fire` cell's block entity.
+#[derive(Component)]
+struct Fire {
+    pos: IVec3,
+    remaining: f32,
+}
+
+#[derive(Component)]
+struct Flame;
+
+#[derive(Resource)]
+struct FireAssets {
+    flame: Handle<Mesh>,
+    flame_material: Handle<StandardMaterial>,
+    ember_material: Handle<StandardMaterial>,
+}
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, spawn_fires.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                FixedUpdate,
+                tick_fires
+                    .run_if(every(Duration::from_secs_f32(FIRE_TICK)))
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            );
+    }
+}
+
+impl Plugin for FireViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_fire)
+            .add_systems(
+                Update,
+                (
+                    ignite_on_key.before(spawn_fires),
+                    add_flames.after(spawn_fires),
+                    flicker_flames,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                FixedUpdate,
+                emit_embers
+                    .run_if(every(Duration::from_secs_f32(FIRE_TICK)))
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+fn setup_fire(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(FireAssets {
+        flame: meshes.add(Cuboid::new(0.7, 0.9, 0.7)),
+        flame_material: materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 0.55, 0.1, 0.7),
+            emissive: Color::rgb(4.0, 1.6, 0.3),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+        ember_material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1.0, 0.4, 0.05),
+            emissive: Color::rgb(6.0, 2.0, 0.2),
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+/// F sets the block under the cursor alight, if it burns.
+fn ignite_on_key(
+    keys: Res<ButtonInput<KeyCode>>,
+    build: Res<BuildMode>,
+    mut terrain: ResMut<Terrain>,
+    camera_ray: Res<CameraRay>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if build.enabled || !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+
+    if let Some(hit) = terrain.raycast(ray.origin, *ray.direction, IGNITE_REACH) {
+        if terrain.get_at(hit.pos).is_flammable() {
+            terrain.set_at(hit.pos, Block::Fire);
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
+
+/// Gives new fire cells their entity.
+fn spawn_fires(
+    mut commands: Commands,
+    mut block_entities: ResMut<BlockEntities>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    for ev in ev_block_changed.read() {
+        if ev.block != Block::Fire {
+            continue;
+        }
+
+        let entity = commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(
+                    ev.pos.as_vec3() + Vec3::splat(0.5),
+                )),
+                Fire {
+                    pos: ev.pos,
+                    remaining: BURN_TIME,
+                },
+            ))
+            .id();
+        block_entities.insert(ev.pos, entity);
+    }
+}
+
+/// Lights every new fire with a flame and a light.
+fn add_flames(mut commands: Commands, assets: Res<FireAssets>, fires: Query<Entity, Added<Fire>>) {
+    for entity in fires.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                PbrBundle {
+                    mesh: assets.flame.clone(),
+                    material: assets.flame_material.clone(),
+                    ..default()
+                },
+                Flame,
+            ));
+            parent.spawn(PointLightBundle {
+                point_light: PointLight {
+                    color: Color::rgb(1.0, 0.6, 0.2),
+                    intensity: LIGHT_INTENSITY,
+                    range: 8.,
+                    ..default()
+                },
+                ..default()
+            });
+        });
+    }
+}
+
+/// Burns fires down, spreads them to flammable neighbors and leaves ash where
+/// there is ground to hold it.
+fn tick_fires(
+    mut terrain: ResMut<Terrain>,
+    mut fires: Query<&mut Fire>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let mut rng = rand::thread_rng();
+    let mut changed = false;
+
+    // spreading and burning out happen on their own once a fire is lit
+    terrain.naturally(|terrain| {
+        for mut fire in fires.iter_mut() {
+            if terrain.get_at(fire.pos) != Block::Fire {
+                continue;
+            }
+
+            for offset in NEIGHBORS {
+                let pos = fire.pos + offset;
+                if terrain.get_at(pos).is_flammable() && rng.gen::<f32>() < SPREAD_CHANCE {
+                    terrain.set_at(pos, Block::Fire);
+                    changed = true;
+                }
+            }
+
+            fire.remaining -= FIRE_TICK;
+            if fire.remaining <= 0. {
+                let remains = if terrain.get_at(fire.pos - IVec3::Y).is_filled() {
+                    Block::Ash
+                } else {
+                    Block::Empty
+                };
+                terrain.set_at(fire.pos, remains);
+                changed = true;
+            }
+        }
+    });
+
+    if changed {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+/// Sparks fly off every burning cell.
+fn emit_embers(
+    terrain: Res<Terrain>,
+    assets: Res<FireAssets>,
+    fires: Query<&Fire>,
+    mut ev_burst: EventWriter<ParticleBurstEvent>,
+) {
+    for fire in fires.iter() {
+        if terrain.get_at(fire.pos) != Block::Fire {
+            continue;
+        }
+
+        ev_burst.send(ParticleBurstEvent {
+            center: fire.pos.as_vec3() + Vec3::new(0.5, 0.8, 0.5),
+            material: assets.ember_material.clone(),
+            count: EMBERS_PER_TICK,
+            gravity: -2.,
+        });
+    }
+}
+
+fn flicker_flames(time: Res<Time>, mut flames: Query<(&Parent, &mut Transform), With<Flame>>) {
+    let t = time.elapsed_seconds();
+
+    for (parent, mut transform) in flames.iter_mut() {
+        // offset each flame's phase so neighbors don't pulse together
+        let phase = parent.get().index() as f32;
+        let height = 0.85 + 0.15 * (t * 9. + phase).sin() * (t * 5.3 + phase * 0.7).cos();
+        transform.scale = Vec3::new(1., height, 1.);
+        transform.translation.y = (height - 1.) * 0.45;
+    }
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    light::LightMap,
+    menu::AppState,
+    temperature::TemperatureMap,
+    terrain::{Block, BlockChangedEvent, Terrain, TerrainModifiedEvent},
+    tick::RandomTickEvent,
+    worldgen::grow_tree,
+};
+
+pub struct GrowthPlugin;
+
+/// Sky light the cell above dirt needs for grass to take.
+const GRASS_LIGHT: u8 = 10;
+/// Chance a ticked sapling grows into a tree. Ticks are rare enough already
+/// that this keeps the wait to a few minutes.
+const SAPLING_GROWTH_CHANCE: f64 = 0.5;
+
+impl Plugin for GrowthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (smother_grass, grow_grass, grow_saplings).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn grow_grass(
+    mut terrain: ResMut<Terrain>,
+    light: Res<LightMap>,
+    mut ev_tick: EventReader<RandomTickEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    for ev in ev_tick.read() {
+        let above = ev.pos + IVec3::Y;
+        let is_lit = !terrain.get_at(above).is_filled() && light.sky(above) >= GRASS_LIGHT;
+
+        let grown = match ev.block {
+            Block::Dirt if is_lit => Block::Grass,
+            Block::Grass if !is_lit => Block::Dirt,
+            _ => continue,
+        };
+
+        terrain.naturally(|terrain| terrain.set_at(ev.pos, grown));
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+/// Grass covered by a full block dies straight away rather than waiting on a
+/// random tick.
+fn smother_grass(
+    mut terrain: ResMut<Terrain>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    for ev in ev_block_changed.read() {
+        let below = ev.pos - IVec3::Y;
+        if ev.block.is_filled() && terrain.get_at(below) == Block::Grass {
+            terrain.naturally(|terrain| terrain.set_at(below, Block::Dirt));
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
+
+/// Saplings need sun and stay dormant through a freeze.
+fn grow_saplings(
+    mut terrain: ResMut<Terrain>,
+    light: Res<LightMap>,
+    temperature: Res<TemperatureMap>,
+    mut ev_tick: EventReader<RandomTickEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for ev in ev_tick.read() {
+        if ev.block != Block::Sapling
+            || light.sky(ev.pos) < GRASS_LIGHT
+            || temperature.is_freezing(ev.pos)
+            || !rng.gen_bool(SAPLING_GROWTH_CHANCE)
+        {
+            continue;
+        }
+
+        if terrain.naturally(|terrain| grow_tree(terrain, ev.pos, &mut rng)) {
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use bevy::{
+    input::{
+        mouse::{MouseScrollUnit, MouseWheel},
+        touchpad::TouchpadMagnify,
+        InputSystem,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Player input by what it does rather than the key it came from. Each frame
+/// the bindings in `InputMap` are checked against the keyboard, mouse and
+/// gamepads, and `ActionState` says which actions are held or were just
+/// pressed. Systems read actions instead of keys, so bindings can be changed
+/// in the settings file and a gamepad works wherever a key does.
+pub struct InputMapPlugin;
+
+/// Resolves `ActionState` from the raw input. Anything that swallows input
+/// before it turns into actions, like the console, runs before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionSystem;
+
+/// Pixels of smooth scrolling, as trackpads send, that make up a notch of
+/// the wheel.
+const PIXELS_PER_NOTCH: f32 = 40.;
+
+/// Change in trackpad magnification that makes up a step of pinching.
+const MAGNIFY_PER_STEP: f32 = 0.05;
+
+/// Something the player can do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    /// Held to fly faster.
+    Sprint,
+    /// Captures and frees the cursor.
+    GrabCursor,
+    SliceUp,
+    SliceDown,
+    ToggleBuildMode,
+    ZoomIn,
+    ZoomOut,
+    /// Jump to a lighting preset, see `LightingPreset`.
+    NoonLighting,
+    SunsetLighting,
+    MidnightLighting,
+    OvercastLighting,
+    TogglePhotoMode,
+}
+
+/// An input an action can be bound to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A notch of the mouse wheel away from the player.
+    WheelUp,
+    /// A notch of the mouse wheel toward the player.
+    WheelDown,
+    /// A step of spreading two fingers apart on a trackpad.
+    PinchOut,
+    /// A step of bringing two fingers together on a trackpad.
+    PinchIn,
+    /// A button on any connected gamepad.
+    Gamepad(GamepadButtonType),
+}
+
+/// The bindings of every action, kept in the settings file. An action can
+/// have any number of them, and one left out of the file keeps its default
+/// bindings.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+    #[serde(deserialize_with = "over_defaults")]
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+/// Reads the bindings in the file over the default ones.
+fn over_defaults<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Action, Vec<Binding>>, D::Error> {
+    let mut bindings = InputMap::default().bindings;
+    bindings.extend(BTreeMap::deserialize(deserializer)?);
+    Ok(bindings)
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::*;
+
+        let bindings = [
+            (
+                Action::MoveForward,
+                vec![Key(KeyCode::KeyW), Gamepad(GamepadButtonType::DPadUp)],
+            ),
+            (
+                Action::MoveBack,
+                vec![Key(KeyCode::KeyS), Gamepad(GamepadButtonType::DPadDown)],
+            ),
+            (
+                Action::MoveLeft,
+                vec![Key(KeyCode::KeyA), Gamepad(GamepadButtonType::DPadLeft)],
+            ),
+            (
+                Action::MoveRight,
+                vec![Key(KeyCode::KeyD), Gamepad(GamepadButtonType::DPadRight)],
+            ),
+            (
+                Action::Sprint,
+                vec![
+                    Key(KeyCode::ShiftLeft),
+                    Gamepad(GamepadButtonType::LeftThumb),
+                ],
+            ),
+            (
+                Action::GrabCursor,
+                vec![Key(KeyCode::Escape), Gamepad(GamepadButtonType::Select)],
+            ),
+            (
+                Action::SliceUp,
+                vec![
+                    WheelUp,
+                    Key(KeyCode::PageUp),
+                    Gamepad(GamepadButtonType::RightTrigger),
+                ],
+            ),
+            (
+                Action::SliceDown,
+                vec![
+                    WheelDown,
+                    Key(KeyCode::PageDown),
+                    Gamepad(GamepadButtonType::LeftTrigger),
+                ],
+            ),
+            (
+                Action::ToggleBuildMode,
+                vec![Key(KeyCode::KeyB), Gamepad(GamepadButtonType::North)],
+            ),
+            (
+                Action::ZoomIn,
+                vec![PinchOut, Gamepad(GamepadButtonType::RightTrigger2)],
+            ),
+            (
+                Action::ZoomOut,
+                vec![PinchIn, Gamepad(GamepadButtonType::LeftTrigger2)],
+            ),
+            (Action::NoonLighting, vec![Key(KeyCode::F7)]),
+            (Action::SunsetLighting, vec![Key(KeyCode::F8)]),
+            (Action::MidnightLighting, vec![Key(KeyCode::F9)]),
+            (Action::OvercastLighting, vec![Key(KeyCode::F10)]),
+            (Action::TogglePhotoMode, vec![Key(KeyCode::F12)]),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+/// Actions held down and just pressed this frame.
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    /// Times each action was pressed this frame, more than once for a few
+    /// notches of the wheel.
+    presses: HashMap<Action, u32>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.presses(action) > 0
+    }
+
+    /// Times `action` was pressed this frame.
+    pub fn presses(&self, action: Action) -> u32 {
+        self.presses.get(&action).copied().unwrap_or_default()
+    }
+}
+
+/// The raw input of one frame that bindings are checked against.
+struct RawInput<'a> {
+    keys: &'a ButtonInput<KeyCode>,
+    mouse: &'a ButtonInput<MouseButton>,
+    gamepad_buttons: &'a ButtonInput<GamepadButton>,
+    gamepads: &'a Gamepads,
+    /// Notches the wheel turned up, and down.
+    wheel: (u32, u32),
+    /// Steps the trackpad was pinched out, and in.
+    pinch: (u32, u32),
+}
+
+impl RawInput<'_> {
+    fn pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            // a notch is over as soon as it happens
+            Binding::WheelUp | Binding::WheelDown | Binding::PinchOut | Binding::PinchIn => {
+                self.presses(binding) > 0
+            }
+            Binding::Gamepad(button) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, button))
+            }),
+        }
+    }
+
+    fn presses(&self, binding: Binding) -> u32 {
+        let just_pressed = match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::WheelUp => return self.wheel.0,
+            Binding::WheelDown => return self.wheel.1,
+            Binding::PinchOut => return self.pinch.0,
+            Binding::PinchIn => return self.pinch.1,
+            Binding::Gamepad(button) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .just_pressed(GamepadButton::new(gamepad, button))
+            }),
+        };
+        just_pressed as u32
+    }
+}
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<ActionState>()
+            .configure_sets(PreUpdate, ActionSystem.after(InputSystem))
+            .add_systems(PreUpdate, update_action_state.in_set(ActionSystem));
+    }
+}
+
+/// Scrolling and pinching that hasn't added up to a whole step yet.
+#[derive(Default)]
+struct Gestures {
+    /// Notches of the wheel, positive up.
+    scroll: f32,
+    /// Steps of pinching, positive out.
+    pinch: f32,
+}
+
+/// Takes the whole steps out of `amount`, leaving the rest to add up with
+/// what comes next. Returns the steps up, and down.
+fn take_steps(amount: &mut f32) -> (u32, u32) {
+    let steps = amount.trunc();
+    *amount -= steps;
+    if steps > 0. {
+        (steps as u32, 0)
+    } else {
+        (0, -steps as u32)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_action_state(
+    map: Res<InputMap>,
+    mut state: ResMut<ActionState>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut gestures: Local<Gestures>,
+    mut ev_wheel: EventReader<MouseWheel>,
+    mut ev_magnify: EventReader<TouchpadMagnify>,
+) {
+    for ev in ev_wheel.read() {
+        gestures.scroll += match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            // trackpads scroll smoothly, a pixel or a few at a time
+            MouseScrollUnit::Pixel => ev.y / PIXELS_PER_NOTCH,
+        };
+    }
+    for ev in ev_magnify.read() {
+        gestures.pinch += ev.0 / MAGNIFY_PER_STEP;
+    }
+
+    let input = RawInput {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepads: &gamepads,
+        wheel: take_steps(&mut gestures.scroll),
+        pinch: take_steps(&mut gestures.pinch),
+    };
+    resolve_actions(&map, &input, &mut state);
+}
+
+fn resolve_actions(map: &InputMap, input: &RawInput, state: &mut ActionState) {
+    state.pressed.clear();
+    state.presses.clear();
+
+    for (action, bindings) in &map.bindings {
+        if bindings.iter().any(|binding| input.pressed(*binding)) {
+            state.pressed.insert(*action);
+        }
+        let presses: u32 = bindings.iter().map(|binding| input.presses(*binding)).sum();
+        if presses > 0 {
+            state.presses.insert(*action, presses);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_follow_any_of_their_bindings() {
+        let mut keys = ButtonInput::<KeyCode>::default();
+        keys.press(KeyCode::KeyW);
+        keys.press(KeyCode::PageUp);
+        keys.clear();
+        keys.press(KeyCode::KeyB);
+        let input = RawInput {
+            keys: &keys,
+            mouse: &ButtonInput::default(),
+            gamepad_buttons: &ButtonInput::default(),
+            gamepads: &Gamepads::default(),
+            wheel: (2, 0),
+            pinch: (0, 0),
+        };
+
+        let mut state = ActionState::default();
+        resolve_actions(&InputMap::default(), &input, &mut state);
+
+        assert!(state.pressed(Action::MoveForward));
+        assert!(!state.just_pressed(Action::MoveForward));
+        assert!(state.just_pressed(Action::ToggleBuildMode));
+        assert!(!state.pressed(Action::MoveBack));
+        // two notches of the wheel, the page key was pressed a frame ago
+        assert_eq!(state.presses(Action::SliceUp), 2);
+        assert_eq!(state.presses(Action::SliceDown), 0);
+    }
+
+    #[test]
+    fn smooth_scrolling_adds_up_to_whole_notches() {
+        let mut scroll = 0.;
+        let mut notches = vec![];
+        for pixels in [15., 15., 15., 15., -90.] {
+            scroll += pixels / PIXELS_PER_NOTCH;
+            notches.push(take_steps(&mut scroll));
+        }
+
+        assert_eq!(notches, vec![(0, 0), (0, 0), (1, 0), (0, 0), (0, 1)]);
+    }
+}
@@ -0,0 +1,442 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    job::{JobKind, JobQueue},
+    menu::AppState,
+    mining::BlockMinedEvent,
+    net::is_authority,
+    terrain::{tile_color, Block, FaceDir, Orientation, Terrain, TerrainMesh},
+    tick::every,
+    zone::{ZoneKind, Zones},
+};
+
+/// Loose blocks left behind by mining, and the hauling that takes them to
+/// the stockpile zones. Every so often each loose item gets a haul job to
+/// the nearest stockpile cell with room for it, agents carry it over and
+/// stack it there.
+pub struct ItemPlugin;
+
+/// Draws the items, each tinted like the block it came from.
+pub struct ItemViewPlugin;
+
+/// Items of one kind a stockpile cell holds.
+pub const STACK_SIZE: usize = 4;
+
+const ITEM_SIZE: f32 = 0.3;
+
+/// How high over an agent's center a carried item rides.
+const CARRY_HEIGHT: f32 = 0.55;
+
+/// A block lying loose in a cell, as an item.
+#[derive(Component, Debug)]
+pub struct Item {
+    pub block: Block,
+    /// The cell it lies in, the last it was put down in while carried.
+    pub cell: IVec3,
+}
+
+/// An item stacked in a stockpile.
+#[derive(Component)]
+pub struct Stored;
+
+/// An item riding along with the agent hauling it.
+#[derive(Component)]
+pub struct Carried;
+
+/// Sent to leave `block` lying as an item where something fell at `pos`.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct DropItemEvent {
+    pub pos: IVec3,
+    pub block: Block,
+}
+
+/// What each stockpile cell holds, and the items on their way to it.
+#[derive(Resource, Default, Debug)]
+pub struct Stockpiles {
+    cells: HashMap<IVec3, Vec<(Entity, Block)>>,
+    /// Cell and kind of every item a haul is headed for a stockpile with.
+    reserved: HashMap<Entity, (IVec3, Block)>,
+}
+
+impl Stockpiles {
+    /// Items stacked in the cell, bottom first.
+    pub fn contents(&self, cell: IVec3) -> &[(Entity, Block)] {
+        self.cells.get(&cell).map_or(&[], |items| items)
+    }
+
+    /// Items of `block` stored across every stockpile.
+    pub fn count(&self, block: Block) -> usize {
+        self.cells
+            .values()
+            .flatten()
+            .filter(|(_, stored)| *stored == block)
+            .count()
+    }
+
+    /// Whether the cell can take another `block`, counting the items already
+    /// headed for it.
+    pub fn has_room(&self, cell: IVec3, block: Block) -> bool {
+        let incoming = self
+            .reserved
+            .values()
+            .filter(|(to, _)| *to == cell)
+            .map(|(_, kind)| kind);
+        let mut kinds = self
+            .contents(cell)
+            .iter()
+            .map(|(_, kind)| kind)
+            .chain(incoming);
+
+        let count = kinds.clone().count();
+        count < STACK_SIZE && kinds.all(|kind| *kind == block)
+    }
+
+    /// The stockpile cell with room for `block` nearest to `from`.
+    pub fn find_room(&self, zones: &Zones, block: Block, from: IVec3) -> Option<IVec3> {
+        zones
+            .cells_of(ZoneKind::Stockpile)
+            .filter(|cell| self.has_room(*cell, block))
+            .min_by_key(|cell| (*cell - from).length_squared())
+    }
+
+    /// The stockpile cell holding a `block` nearest to `from`.
+    pub fn find(&self, block: Block, from: IVec3) -> Option<IVec3> {
+        self.cells
+            .iter()
+            .filter(|(_, items)| items.iter().any(|(_, stored)| *stored == block))
+            .map(|(cell, _)| *cell)
+            .min_by_key(|cell| (*cell - from).length_squared())
+    }
+
+    /// Takes the topmost `block` out of the cell, if it holds one.
+    pub fn take_from(&mut self, cell: IVec3, block: Block) -> Option<Entity> {
+        let items = self.cells.get_mut(&cell)?;
+        let i = items.iter().rposition(|(_, stored)| *stored == block)?;
+        let (item, _) = items.remove(i);
+        if items.is_empty() {
+            self.cells.remove(&cell);
+        }
+        Some(item)
+    }
+
+    /// Holds room in `cell` for the item a haul is bringing.
+    pub fn reserve(&mut self, item: Entity, cell: IVec3, block: Block) {
+        self.reserved.insert(item, (cell, block));
+    }
+
+    /// Stacks the item in `cell`, taking it off the reservations.
+    pub fn store(&mut self, cell: IVec3, item: Entity, block: Block) {
+        self.reserved.remove(&item);
+        self.cells.entry(cell).or_default().push((item, block));
+    }
+
+    /// Takes the item out of whichever stockpile holds it.
+    pub fn take(&mut self, item: Entity) {
+        self.reserved.remove(&item);
+        for items in self.cells.values_mut() {
+            items.retain(|(stored, _)| *stored != item);
+        }
+        self.cells.retain(|_, items| !items.is_empty());
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear();
+        self.reserved.clear();
+    }
+}
+
+/// Item left behind when `block` is dug out, None for blocks that crumble
+/// away.
+pub fn mined_item(block: Block) -> Option<Block> {
+    match block {
+        Block::Grass => Some(Block::Dirt),
+        Block::Log(_) => Some(Block::Log(Orientation::Up)),
+        Block::Leaves | Block::Sapling | Block::Ash | Block::Structure => None,
+        Block::Oob | Block::Empty | Block::Water | Block::Lava | Block::Fire => None,
+        block => Some(block),
+    }
+}
+
+/// The cell an item dropped at `pos` comes to rest in, on the first filled
+/// block below it.
+pub fn rest_cell(terrain: &Terrain, pos: IVec3) -> IVec3 {
+    let mut cell = pos;
+    while cell.y > 0 && !terrain.get_at(cell - IVec3::Y).is_filled() {
+        cell -= IVec3::Y;
+    }
+    cell
+}
+
+/// Where an item sits when it's `height` items up a stack in `cell`.
+fn item_position(cell: IVec3, height: usize) -> Vec3 {
+    cell.as_vec3() + Vec3::new(0.5, ITEM_SIZE * (height as f32 + 0.5), 0.5)
+}
+
+/// Hangs the item off `agent`, who carries it from then on.
+pub fn pick_up(commands: &mut Commands, item: Entity, agent: Entity) {
+    commands
+        .entity(item)
+        .insert((Carried, Transform::from_xyz(0., CARRY_HEIGHT, 0.)))
+        .set_parent(agent);
+}
+
+/// Lets go of a carried item, dropping it to the ground below `at`.
+pub fn drop_item(
+    commands: &mut Commands,
+    terrain: &Terrain,
+    entity: Entity,
+    item: &mut Item,
+    at: Vec3,
+) {
+    item.cell = rest_cell(terrain, at.floor().as_ivec3());
+    commands
+        .entity(entity)
+        .remove_parent()
+        .remove::<Carried>()
+        .insert(Transform::from_translation(item_position(item.cell, 0)));
+}
+
+/// Stacks the item in the stockpile cell `cell`.
+pub fn put_down(
+    commands: &mut Commands,
+    stockpiles: &mut Stockpiles,
+    entity: Entity,
+    item: &mut Item,
+    cell: IVec3,
+) {
+    let height = stockpiles.contents(cell).len();
+    stockpiles.store(cell, entity, item.block);
+    item.cell = cell;
+    commands
+        .entity(entity)
+        .remove_parent()
+        .remove::<Carried>()
+        .insert((
+            Stored,
+            Transform::from_translation(item_position(cell, height)),
+        ));
+}
+
+#[derive(Resource)]
+struct ItemAssets {
+    mesh: Handle<Mesh>,
+    /// Material per terrain texture, tinted with the tile's average color.
+    materials: HashMap<u32, Handle<StandardMaterial>>,
+}
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Stockpiles>()
+            .add_event::<DropItemEvent>()
+            .add_systems(
+                Update,
+                (spawn_drops, release_unzoned_items, drop_abandoned_items)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                FixedUpdate,
+                queue_hauls
+                    .run_if(every(Duration::from_secs(1)))
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            )
+            .add_systems(OnExit(AppState::InGame), clear_items);
+    }
+}
+
+impl Plugin for ItemViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_items)
+            .add_systems(Update, add_item_meshes.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn setup_items(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ItemAssets {
+        mesh: meshes.add(Cuboid::new(ITEM_SIZE, ITEM_SIZE, ITEM_SIZE)),
+        materials: HashMap::new(),
+    });
+}
+
+fn clear_items(
+    mut commands: Commands,
+    mut stockpiles: ResMut<Stockpiles>,
+    items: Query<Entity, With<Item>>,
+) {
+    for entity in items.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    stockpiles.clear();
+}
+
+/// Leaves an item behind in place of every block dug out, and wherever one
+/// is dropped.
+fn spawn_drops(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut ev_mined: EventReader<BlockMinedEvent>,
+    mut ev_drop: EventReader<DropItemEvent>,
+) {
+    let mined = ev_mined
+        .read()
+        .filter_map(|ev| Some((ev.pos, mined_item(ev.block)?)));
+    let dropped = ev_drop.read().map(|ev| (ev.pos, ev.block));
+    for (pos, block) in mined.chain(dropped) {
+        let cell = rest_cell(&terrain, pos);
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(item_position(cell, 0))),
+            Item { block, cell },
+        ));
+    }
+}
+
+/// Gives new items their mesh, with a material per terrain texture.
+fn add_item_meshes(
+    mut commands: Commands,
+    terrain_mesh: Res<TerrainMesh>,
+    images: Res<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut assets: ResMut<ItemAssets>,
+    items: Query<(Entity, &Item), Added<Item>>,
+) {
+    for (entity, item) in items.iter() {
+        let texture_id = item.block.texture_id(FaceDir::PosX);
+        let material = match assets.materials.get(&texture_id) {
+            Some(material) => material.clone(),
+            None => {
+                let color = images
+                    .get(&terrain_mesh.texture)
+                    .and_then(|image| tile_color(image, texture_id))
+                    .unwrap_or(Color::GRAY);
+                let material = materials.add(color);
+                assets.materials.insert(texture_id, material.clone());
+                material
+            }
+        };
+
+        commands
+            .entity(entity)
+            .insert((assets.mesh.clone(), material));
+    }
+}
+
+/// Items stacked where there's no longer a stockpile come loose, to be
+/// hauled off again.
+fn release_unzoned_items(
+    mut commands: Commands,
+    zones: Res<Zones>,
+    mut stockpiles: ResMut<Stockpiles>,
+) {
+    if !zones.is_changed() {
+        return;
+    }
+
+    let unzoned: Vec<_> = stockpiles
+        .cells
+        .keys()
+        .copied()
+        .filter(|cell| zones.kind_at(*cell) != Some(ZoneKind::Stockpile))
+        .collect();
+    for cell in unzoned {
+        for (entity, _) in stockpiles.cells.remove(&cell).unwrap_or_default() {
+            commands.entity(entity).remove::<Stored>();
+        }
+    }
+    stockpiles
+        .reserved
+        .retain(|_, (cell, _)| zones.kind_at(*cell) == Some(ZoneKind::Stockpile));
+}
+
+/// Carried items whose haul was cancelled are dropped where they are.
+fn drop_abandoned_items(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    queue: Res<JobQueue>,
+    mut items: Query<(Entity, &mut Item, &GlobalTransform), With<Carried>>,
+) {
+    for (entity, mut item, transform) in items.iter_mut() {
+        let is_hauled = queue
+            .iter()
+            .any(|(_, job)| matches!(job.kind, JobKind::Haul { item, .. } if item == entity));
+        if is_hauled {
+            continue;
+        }
+
+        drop_item(
+            &mut commands,
+            &terrain,
+            entity,
+            &mut item,
+            transform.translation(),
+        );
+    }
+}
+
+/// Gives every loose item without a haul one to the nearest stockpile with
+/// room, and lets go of the room held for hauls that were given up on.
+#[allow(clippy::type_complexity)]
+fn queue_hauls(
+    terrain: Res<Terrain>,
+    zones: Res<Zones>,
+    mut queue: ResMut<JobQueue>,
+    mut stockpiles: ResMut<Stockpiles>,
+    items: Query<(Entity, &Item), (Without<Stored>, Without<Carried>)>,
+) {
+    let hauled: Vec<_> = queue
+        .iter()
+        .filter_map(|(_, job)| match job.kind {
+            JobKind::Haul { item, .. } => Some(item),
+            _ => None,
+        })
+        .collect();
+    stockpiles.reserved.retain(|item, _| hauled.contains(item));
+
+    for (entity, item) in items.iter() {
+        if hauled.contains(&entity) {
+            continue;
+        }
+        let Some(to) = stockpiles.find_room(&zones, item.block, item.cell) else {
+            continue;
+        };
+        let kind = JobKind::Haul { item: entity, to };
+        if queue.push(&terrain, kind, item.cell).is_some() {
+            stockpiles.reserve(entity, to, item.block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stockpile_cells_stack_one_kind() {
+        let mut stockpiles = Stockpiles::default();
+        let cell = IVec3::new(1, 2, 3);
+        let item = |i| Entity::from_raw(i);
+
+        stockpiles.store(cell, item(0), Block::Stone);
+        stockpiles.reserve(item(1), cell, Block::Stone);
+        assert!(stockpiles.has_room(cell, Block::Stone));
+        assert!(!stockpiles.has_room(cell, Block::Dirt));
+
+        stockpiles.store(cell, item(1), Block::Stone);
+        stockpiles.store(cell, item(2), Block::Stone);
+        stockpiles.reserve(item(3), cell, Block::Stone);
+        assert!(!stockpiles.has_room(cell, Block::Stone));
+        assert_eq!(stockpiles.count(Block::Stone), 3);
+
+        assert_eq!(stockpiles.find(Block::Stone, IVec3::ZERO), Some(cell));
+        assert_eq!(stockpiles.find(Block::Fruit, IVec3::ZERO), None);
+        assert_eq!(stockpiles.take_from(cell, Block::Stone), Some(item(2)));
+
+        stockpiles.take(item(0));
+        stockpiles.take(item(1));
+        stockpiles.take(item(2));
+        stockpiles.take(item(3));
+        assert!(stockpiles.has_room(cell, Block::Dirt));
+        assert!(stockpiles.contents(cell).is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use bevy::prelude::*;
+
+use crate::{
+    menu::AppState,
+    terraform::Selection,
+    terrain::{Block, Terrain},
+};
+
+/// Work queued for agents to claim and carry out. Higher priority jobs are
+/// taken first, nearer ones among those of equal priority, and only once a
+/// path to them is found. `priority <0-9>` in the console sets the priority
+/// of the jobs in the selection.
+pub struct JobPlugin;
+
+pub const MAX_PRIORITY: u8 = 9;
+pub const DEFAULT_PRIORITY: u8 = 5;
+
+/// Identifies a job. Ids are handed out in order and never reused.
+pub type JobId = u32;
+
+/// What a job has an agent do to its block.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JobKind {
+    /// Dig the block out.
+    Mine,
+    /// Build the block in the empty cell.
+    Build(Block),
+    /// Pick up the item lying in the cell and carry it to the stockpile
+    /// cell at `to`.
+    Haul { item: Entity, to: IVec3 },
+    /// Chop the tree down at the base of its trunk.
+    Chop,
+}
+
+impl JobKind {
+    /// Whether the job can be done on `block`.
+    fn accepts(self, block: Block) -> bool {
+        match self {
+            JobKind::Mine => block.is_minable(),
+            JobKind::Build(_) => block == Block::Empty,
+            JobKind::Haul { .. } => !block.is_filled(),
+            JobKind::Chop => matches!(block, Block::Log(_)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub kind: JobKind,
+    pub pos: IVec3,
+    pub priority: u8,
+    pub claimed_by: Option<Entity>,
+    /// Build progress, finished at 1.
+    pub progress: f32,
+    /// Whether a haul has picked its item up and is carrying it to its
+    /// destination.
+    pub picked_up: bool,
+    /// What was at the target when the job was queued or last moved on. The
+    /// job is cancelled if anything else turns up there.
+    expected: Block,
+}
+
+impl Job {
+    /// Cell the job is being done at, the destination once a haul has
+    /// picked its block up.
+    pub fn target(&self) -> IVec3 {
+        match self.kind {
+            JobKind::Haul { to, .. } if self.picked_up => to,
+            _ => self.pos,
+        }
+    }
+
+    /// Marks the haul's item as picked up, from then on it's headed for its
+    /// destination, which has to stay as it is.
+    pub fn pick_up(&mut self, terrain: &Terrain) {
+        self.picked_up = true;
+        self.expected = terrain.get_at(self.target());
+    }
+}
+
+/// Every job queued on the map.
+#[derive(Resource, Default, Debug)]
+pub struct JobQueue {
+    jobs: BTreeMap<JobId, Job>,
+    next_id: JobId,
+}
+
+impl JobQueue {
+    /// Queues `kind` on the block at `pos` at the default priority. None if
+    /// the job can't be done on that block, or the block already has a job.
+    pub fn push(&mut self, terrain: &Terrain, kind: JobKind, pos: IVec3) -> Option<JobId> {
+        self.push_with_priority(terrain, kind, pos, DEFAULT_PRIORITY)
+    }
+
+    pub fn push_with_priority(
+        &mut self,
+        terrain: &Terrain,
+        kind: JobKind,
+        pos: IVec3,
+        priority: u8,
+    ) -> Option<JobId> {
+        let block = terrain.get_at(pos);
+        if !kind.accepts(block) || self.at(pos).is_some() {
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.insert(
+            id,
+            Job {
+                kind,
+                pos,
+                priority: priority.min(MAX_PRIORITY),
+                claimed_by: None,
+                progress: 0.,
+                picked_up: false,
+                expected: block,
+            },
+        );
+        Some(id)
+    }
+
+    pub fn get(&self, id: JobId) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: JobId) -> Option<&mut Job> {
+        self.jobs.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (JobId, &Job)> {
+        self.jobs.iter().map(|(id, job)| (*id, job))
+    }
+
+    /// Jobs queued from `id` on, in the order they were queued.
+    pub fn queued_since(&self, id: JobId) -> impl Iterator<Item = (JobId, &Job)> {
+        self.jobs.range(id..).map(|(id, job)| (*id, job))
+    }
+
+    /// Id the next job queued will get.
+    pub fn next_id(&self) -> JobId {
+        self.next_id
+    }
+
+    /// The job on the block at `pos`, if any.
+    pub fn at(&self, pos: IVec3) -> Option<JobId> {
+        self.iter()
+            .find(|(_, job)| job.pos == pos)
+            .map(|(id, _)| id)
+    }
+
+    pub fn set_priority(&mut self, id: JobId, priority: u8) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.priority = priority.min(MAX_PRIORITY);
+        }
+    }
+
+    /// Hands the job to `agent`, unless someone already has it.
+    pub fn claim(&mut self, id: JobId, agent: Entity) -> bool {
+        match self.jobs.get_mut(&id) {
+            Some(job) if job.claimed_by.is_none() => {
+                job.claimed_by = Some(agent);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gives the job back for anyone to claim.
+    pub fn release(&mut self, id: JobId) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.claimed_by = None;
+        }
+    }
+
+    /// Takes the job off the queue, done or given up on.
+    pub fn remove(&mut self, id: JobId) -> Option<Job> {
+        self.jobs.remove(&id)
+    }
+
+    /// Unclaimed jobs, highest priority first and nearest to `from` among
+    /// equals.
+    pub fn available(&self, from: IVec3) -> Vec<JobId> {
+        let mut ids: Vec<_> = self
+            .iter()
+            .filter(|(_, job)| job.claimed_by.is_none())
+            .map(|(id, job)| {
+                let distance = (job.target() - from).length_squared();
+                (Reverse(job.priority), distance, id)
+            })
+            .collect();
+        ids.sort();
+        ids.into_iter().map(|(_, _, id)| id).collect()
+    }
+
+    /// The first available job `find_route` finds a route to, with the
+    /// route. `find_route` is given the job's target and returns the cells
+    /// leading next to it.
+    pub fn find_reachable(
+        &self,
+        from: IVec3,
+        mut find_route: impl FnMut(IVec3) -> Option<Vec<IVec3>>,
+    ) -> Option<(JobId, Vec<IVec3>)> {
+        self.available(from).into_iter().find_map(|id| {
+            let route = find_route(self.jobs[&id].target())?;
+            Some((id, route))
+        })
+    }
+
+    /// Jobs whose target changed under them.
+    pub fn stale(&self, terrain: &Terrain) -> Vec<JobId> {
+        self.iter()
+            .filter(|(_, job)| terrain.get_at(job.target()) != job.expected)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Takes the jobs whose target changed under them off the queue.
+    pub fn cancel_stale(&mut self, terrain: &Terrain) -> Vec<(JobId, Job)> {
+        self.stale(terrain)
+            .into_iter()
+            .filter_map(|id| Some((id, self.jobs.remove(&id)?)))
+            .collect()
+    }
+
+    fn clear(&mut self) {
+        self.jobs.clear();
+    }
+}
+
+/// Sent by the console to set the priority of the jobs in the selection.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct PrioritizeJobsEvent(pub u8);
+
+impl Plugin for JobPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<JobQueue>()
+            .init_resource::<Selection>()
+            .add_event::<PrioritizeJobsEvent>()
+            .add_systems(
+                Update,
+                (cancel_stale_jobs, prioritize_jobs).run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), clear_jobs);
+    }
+}
+
+/// Drops the jobs still waiting on agents when the world is left.
+fn clear_jobs(mut queue: ResMut<JobQueue>) {
+    queue.clear();
+}
+
+/// Checks every job against the terrain first, so the queue isn't marked
+/// changed on the frames nothing is cancelled.
+fn cancel_stale_jobs(terrain: Res<Terrain>, mut queue: ResMut<JobQueue>) {
+    if !queue.stale(&terrain).is_empty() {
+        queue.cancel_stale(&terrain);
+    }
+}
+
+fn prioritize_jobs(
+    selection: Res<Selection>,
+    mut queue: ResMut<JobQueue>,
+    mut ev_prioritize: EventReader<PrioritizeJobsEvent>,
+) {
+    for ev in ev_prioritize.read() {
+        let Some((min, max)) = selection.bounds() else {
+            println!("Select two corners with [ and ] first");
+            continue;
+        };
+
+        let ids: Vec<_> = queue
+            .iter()
+            .filter(|(_, job)| job.pos.cmpge(min).all() && job.pos.cmplt(max).all())
+            .map(|(id, _)| id)
+            .collect();
+        for id in &ids {
+            queue.set_priority(*id, ev.0);
+        }
+        println!(
+            "Set {} jobs to priority {}",
+            ids.len(),
+            ev.0.min(MAX_PRIORITY)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures::ground;
+
+    fn terrain() -> Terrain {
+        ground(IVec3::splat(8), 2, Block::Stone)
+    }
+
+    #[test]
+    fn reachable_jobs_are_taken_by_priority() {
+        let terrain = terrain();
+        let mut queue = JobQueue::default();
+        let near = queue.push(&terrain, JobKind::Mine, IVec3::new(1, 1, 1));
+        let urgent =
+            queue.push_with_priority(&terrain, JobKind::Mine, IVec3::new(6, 1, 6), MAX_PRIORITY);
+        let unreachable =
+            queue.push_with_priority(&terrain, JobKind::Mine, IVec3::new(3, 1, 3), MAX_PRIORITY);
+
+        // a job per block, and only on blocks it can be done to
+        assert_eq!(
+            queue.push(&terrain, JobKind::Mine, IVec3::new(1, 1, 1)),
+            None
+        );
+        assert_eq!(
+            queue.push(&terrain, JobKind::Chop, IVec3::new(2, 1, 2)),
+            None
+        );
+
+        let from = IVec3::new(0, 2, 0);
+        let found = queue.find_reachable(from, |target| {
+            (target != IVec3::new(3, 1, 3)).then(|| vec![target + IVec3::Y])
+        });
+        assert_eq!(found.map(|(id, _)| id), urgent);
+        assert_eq!(
+            queue.available(from),
+            vec![unreachable.unwrap(), urgent.unwrap(), near.unwrap()]
+        );
+
+        assert!(queue.claim(urgent.unwrap(), Entity::PLACEHOLDER));
+        assert!(!queue.claim(urgent.unwrap(), Entity::PLACEHOLDER));
+        queue.release(urgent.unwrap());
+        assert!(queue.claim(urgent.unwrap(), Entity::PLACEHOLDER));
+    }
+
+    #[test]
+    fn jobs_are_cancelled_when_their_block_changes() {
+        let mut terrain = terrain();
+        let mut queue = JobQueue::default();
+        let mine = queue
+            .push(&terrain, JobKind::Mine, IVec3::new(1, 1, 1))
+            .unwrap();
+        let build = queue
+            .push(&terrain, JobKind::Build(Block::Dirt), IVec3::new(2, 2, 2))
+            .unwrap();
+        let to = IVec3::new(4, 2, 4);
+        let item = Entity::PLACEHOLDER;
+        let haul = queue
+            .push(&terrain, JobKind::Haul { item, to }, IVec3::new(3, 2, 3))
+            .unwrap();
+
+        // once picked up, the haul only minds its destination
+        queue.get_mut(haul).unwrap().pick_up(&terrain);
+        terrain.set_at(IVec3::new(3, 2, 3), Block::Dirt);
+        assert!(queue.cancel_stale(&terrain).is_empty());
+
+        terrain.set_at(IVec3::new(1, 1, 1), Block::Empty);
+        terrain.set_at(to, Block::Dirt);
+        let cancelled: Vec<_> = queue
+            .cancel_stale(&terrain)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(cancelled, vec![mine, haul]);
+        assert!(queue.get(build).is_some());
+    }
+}
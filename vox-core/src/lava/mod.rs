@@ -0,0 +1,156 @@
+use std::{collections::HashSet, time::Duration};
+
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    menu::AppState,
+    net::is_authority,
+    terrain::{Block, BlockChangedEvent, Terrain, TerrainModifiedEvent},
+    tick::every,
+};
+
+pub struct LavaPlugin;
+
+/// Seconds between lava ticks, slow for a thick liquid.
+const LAVA_TICK: f32 = 0.75;
+/// Chance per tick that lava resting on something creeps sideways.
+const CREEP_CHANCE: f64 = 0.25;
+
+const SIDES: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Cells that held lava last we looked, the only ones the tick visits.
+#[derive(Resource, Default)]
+struct LavaCells(HashSet<IVec3>);
+
+impl Plugin for LavaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LavaCells>()
+            .add_systems(OnEnter(AppState::InGame), find_lava)
+            .add_systems(Update, track_lava.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                FixedUpdate,
+                tick_lava
+                    .run_if(every(Duration::from_secs_f32(LAVA_TICK)))
+                    .run_if(in_state(AppState::InGame).and_then(is_authority)),
+            )
+            .add_systems(OnExit(AppState::InGame), forget_lava);
+    }
+}
+
+fn find_lava(terrain: Res<Terrain>, mut cells: ResMut<LavaCells>) {
+    cells.0 = terrain
+        .iter()
+        .filter(|(_, block)| *block == Block::Lava)
+        .map(|(pos, _)| pos)
+        .collect();
+}
+
+fn forget_lava(mut cells: ResMut<LavaCells>) {
+    cells.0.clear();
+}
+
+fn track_lava(mut cells: ResMut<LavaCells>, mut ev_block_changed: EventReader<BlockChangedEvent>) {
+    for ev in ev_block_changed.read() {
+        if ev.block == Block::Lava {
+            cells.0.insert(ev.pos);
+        }
+    }
+}
+
+/// Lets lava set against water, fall and creep. It moves rather than copies,
+/// so a pool spreads out until it's a block deep and then stays put.
+fn tick_lava(
+    mut terrain: ResMut<Terrain>,
+    mut cells: ResMut<LavaCells>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let mut rng = rand::thread_rng();
+    let mut changed = false;
+
+    // lowest first, so a column falls together instead of leaving gaps
+    let mut lava: Vec<IVec3> = cells.0.drain().collect();
+    lava.sort_by_key(|pos| pos.y);
+
+    // flowing lava is the world changing on its own
+    terrain.naturally(|terrain| {
+        for pos in lava {
+            if terrain.get_at(pos) != Block::Lava {
+                continue;
+            }
+
+            if touches_water(terrain, pos) {
+                terrain.set_at(pos, Block::Stone);
+                changed = true;
+                continue;
+            }
+
+            if let Some(next) = flow(terrain, pos, &mut rng) {
+                terrain.set_at(pos, Block::Empty);
+                terrain.set_at(next, Block::Lava);
+                changed = true;
+                continue;
+            }
+
+            cells.0.insert(pos);
+        }
+    });
+
+    if changed {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+fn touches_water(terrain: &Terrain, pos: IVec3) -> bool {
+    SIDES
+        .iter()
+        .chain(&[IVec3::Y, IVec3::NEG_Y])
+        .any(|offset| terrain.get_at(pos + *offset) == Block::Water)
+}
+
+/// Where the lava at `pos` moves this tick, if anywhere.
+fn flow(terrain: &Terrain, pos: IVec3, rng: &mut impl Rng) -> Option<IVec3> {
+    let below = pos - IVec3::Y;
+    if terrain.get_at(below) == Block::Empty {
+        return Some(below);
+    }
+    if !rng.gen_bool(CREEP_CHANCE) {
+        return None;
+    }
+
+    // a single layer only spreads where it can run downhill
+    let piled = terrain.get_at(pos + IVec3::Y) == Block::Lava;
+    let mut sides = SIDES;
+    sides.shuffle(rng);
+    sides.into_iter().map(|offset| pos + offset).find(|side| {
+        terrain.get_at(*side) == Block::Empty
+            && (piled || terrain.get_at(*side - IVec3::Y) == Block::Empty)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::terrain::fixtures::ground;
+
+    #[test]
+    fn lava_runs_downhill_and_sets_against_water() {
+        let mut terrain = ground(IVec3::splat(8), 2, Block::Stone);
+        terrain.set_at(IVec3::new(4, 1, 4), Block::Empty);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // next to the hole, it creeps into it sooner or later
+        let edge = IVec3::new(3, 2, 4);
+        assert!((0..64).any(|_| flow(&terrain, edge, &mut rng) == Some(IVec3::new(4, 2, 4))));
+
+        // out on the flat with nothing piled on top, it stays
+        let flat = IVec3::new(1, 2, 1);
+        assert!((0..64).all(|_| flow(&terrain, flat, &mut rng).is_none()));
+
+        // water next to it sets it before it gets the chance
+        terrain.set_at(IVec3::new(3, 2, 5), Block::Water);
+        assert!(touches_water(&terrain, edge));
+    }
+}
@@ -0,0 +1,48 @@
+//! The voxel terrain, its meshing and slicing, world generation and the
+//! game's plugins. The `vox-rust` binary assembles these into the game,
+//! other apps can pick the plugins they need.
+
+pub mod agent;
+pub mod animal;
+pub mod audio;
+pub mod blueprint;
+pub mod build;
+pub mod camera;
+pub mod collapse;
+pub mod console;
+pub mod creature;
+pub mod daylight;
+pub mod door;
+pub mod fire;
+pub mod growth;
+pub mod input;
+pub mod item;
+pub mod job;
+pub mod lava;
+pub mod light;
+pub mod menu;
+pub mod mining;
+pub mod mods;
+pub mod needs;
+pub mod net;
+pub mod particles;
+pub mod pathfinding;
+pub mod photo;
+#[cfg(feature = "physics")]
+pub mod physics;
+pub mod reload;
+pub mod replay;
+pub mod save;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod settings;
+pub mod sky;
+pub mod slice;
+pub mod speed;
+pub mod structure;
+pub mod temperature;
+pub mod terraform;
+pub mod terrain;
+pub mod tick;
+pub mod worldgen;
+pub mod zone;
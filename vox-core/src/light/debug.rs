@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+
+use super::{LightMap, MAX_LIGHT};
+use crate::{
+    camera::{CameraRay, FlyCamera},
+    menu::AppState,
+    terrain::Terrain,
+};
+
+/// How far away the probe reaches for the targeted block.
+const PROBE_REACH: f32 = 64.;
+
+/// F3 cycles through views of the light map, for working on how light
+/// spreads: the terrain colored by its sky light, then by its block light,
+/// then a probe reading out both levels in front of the targeted block.
+pub struct LightDebugPlugin;
+
+/// Which view of the light map is showing.
+#[derive(Resource, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum LightDebug {
+    #[default]
+    Off,
+    /// Terrain drawn in blues by how much sky light reaches it.
+    Sky,
+    /// Terrain drawn in oranges by how much block light reaches it.
+    Block,
+    /// The levels of the open cell in front of the targeted block.
+    Probe,
+}
+
+impl LightDebug {
+    fn next(self) -> Self {
+        match self {
+            LightDebug::Off => LightDebug::Sky,
+            LightDebug::Sky => LightDebug::Block,
+            LightDebug::Block => LightDebug::Probe,
+            LightDebug::Probe => LightDebug::Off,
+        }
+    }
+
+    /// Light level at `pos` the terrain is lit by in this view.
+    pub fn level(self, light: &LightMap, pos: IVec3) -> u8 {
+        match self {
+            LightDebug::Sky => light.sky(pos),
+            LightDebug::Block => light.block(pos),
+            LightDebug::Off | LightDebug::Probe => light.get(pos),
+        }
+    }
+}
+
+#[derive(Component)]
+struct ProbeText;
+
+impl Plugin for LightDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightDebug>()
+            .add_systems(Startup, setup_probe)
+            .add_systems(
+                Update,
+                (cycle_light_debug, show_probe)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+fn setup_probe(mut commands: Commands) {
+    let text = TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 18.,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        ..default()
+    })
+    .with_background_color(Color::rgba(0., 0., 0., 0.6));
+
+    commands.spawn((text, ProbeText, Visibility::Hidden));
+}
+
+fn cycle_light_debug(keys: Res<ButtonInput<KeyCode>>, mut debug: ResMut<LightDebug>) {
+    if keys.just_pressed(KeyCode::F3) {
+        *debug = debug.next();
+        println!("Light debug view: {:?}", *debug);
+    }
+}
+
+/// Outlines the cell in front of the targeted face in the color of its
+/// light, with its sky and block levels printed next to it.
+fn show_probe(
+    mut gizmos: Gizmos,
+    debug: Res<LightDebug>,
+    terrain: Res<Terrain>,
+    light: Res<LightMap>,
+    camera_ray: Res<CameraRay>,
+    cameras: Query<(&Camera, &GlobalTransform), With<FlyCamera>>,
+    mut texts: Query<(&mut Text, &mut Style, &mut Visibility), With<ProbeText>>,
+) {
+    let Ok((mut text, mut style, mut visibility)) = texts.get_single_mut() else {
+        return;
+    };
+    *visibility = Visibility::Hidden;
+
+    if *debug != LightDebug::Probe {
+        return;
+    }
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+    let Some(hit) = terrain.raycast(ray.origin, *ray.direction, PROBE_REACH) else {
+        return;
+    };
+
+    let cell = hit.pos + hit.normal;
+    let center = cell.as_vec3() + Vec3::splat(0.5);
+    let (sky, block) = (light.sky(cell), light.block(cell));
+    let level = sky.max(block) as f32 / MAX_LIGHT as f32;
+    gizmos.cuboid(
+        Transform::from_translation(center).with_scale(Vec3::splat(1.02)),
+        Color::rgb(level, level, 0.3 + 0.7 * level),
+    );
+
+    let Ok((camera, transform)) = cameras.get_single() else {
+        return;
+    };
+    let Some(screen) = camera.world_to_viewport(transform, center) else {
+        return;
+    };
+
+    text.sections[0].value = format!("sky {} block {}", sky, block);
+    style.left = Val::Px(screen.x + 12.);
+    style.top = Val::Px(screen.y - 12.);
+    *visibility = Visibility::Visible;
+}
@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    menu::AppState,
+    terrain::{BlockChangedEvent, Terrain},
+};
+
+mod debug;
+
+pub use debug::{LightDebug, LightDebugPlugin};
+
+pub struct LightPlugin;
+
+pub const MAX_LIGHT: u8 = 15;
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Sky and block light per voxel, each from 0 to `MAX_LIGHT`. Sky light is
+/// full where a cell sees the sky straight up, block light at blocks that
+/// give it off like lava. Both fall off by one per step as they spread.
+#[derive(Resource, Default)]
+pub struct LightMap {
+    size: IVec3,
+    sky: Vec<u8>,
+    block: Vec<u8>,
+}
+
+impl LightMap {
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        cell_index(self.size, pos)
+    }
+
+    /// Sky light at `pos`. Everything outside the map is open sky.
+    pub fn sky(&self, pos: IVec3) -> u8 {
+        self.index(pos).map_or(MAX_LIGHT, |i| self.sky[i])
+    }
+
+    /// Block light at `pos`, none outside the map.
+    pub fn block(&self, pos: IVec3) -> u8 {
+        self.index(pos).map_or(0, |i| self.block[i])
+    }
+
+    /// The brighter of the sky and block light at `pos`.
+    pub fn get(&self, pos: IVec3) -> u8 {
+        self.sky(pos).max(self.block(pos))
+    }
+
+    /// Forgets the light, so the next update works it out from scratch. For
+    /// when chunks are replaced without logging their blocks.
+    pub fn reset(&mut self) {
+        *self = LightMap::default();
+    }
+
+    /// Recomputes the whole grid: sunlight falls straight down each column
+    /// until it hits an opaque block, block light starts at the blocks that
+    /// give it off, then both flood outward.
+    pub fn compute(&mut self, terrain: &Terrain) {
+        self.size = terrain.size();
+        let volume = (self.size.x * self.size.y * self.size.z) as usize;
+        self.sky.clear();
+        self.sky.resize(volume, 0);
+        self.block.clear();
+        self.block.resize(volume, 0);
+        let mut open_sky = VecDeque::new();
+        let mut open_block = VecDeque::new();
+
+        for x in 0..self.size.x {
+            for z in 0..self.size.z {
+                let surface = terrain.surface_height(x, z).unwrap_or(-1);
+                for y in (0..self.size.y).rev() {
+                    let pos = IVec3::new(x, y, z);
+                    let block = terrain.get_at(pos);
+                    let Some(i) = self.index(pos) else {
+                        continue;
+                    };
+
+                    if block.light() > 0 {
+                        self.block[i] = block.light();
+                        open_block.push_back(pos);
+                    }
+
+                    if y > surface {
+                        self.sky[i] = MAX_LIGHT;
+                        open_sky.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        let size = self.size;
+        flood(size, &mut self.sky, terrain, open_sky, |_, _| {});
+        flood(size, &mut self.block, terrain, open_block, |_, _| {});
+    }
+
+    /// Works the light out again around the cells in `changed`, returning
+    /// every cell whose sky or block light changed. Sky light can change all
+    /// the way down the open column under a cell.
+    fn relight(&mut self, terrain: &Terrain, changed: &[IVec3]) -> HashSet<IVec3> {
+        let mut columns = vec![];
+        for pos in changed {
+            columns.push(*pos);
+            let mut below = *pos - IVec3::Y;
+            while below.y >= 0 && !terrain.get_at(below).is_filled() {
+                columns.push(below);
+                below -= IVec3::Y;
+            }
+        }
+
+        let size = self.size;
+        let sky_source = |pos: IVec3| {
+            let surface = terrain.surface_height(pos.x, pos.z).unwrap_or(-1);
+            if pos.y > surface {
+                MAX_LIGHT
+            } else {
+                0
+            }
+        };
+        let block_source = |pos: IVec3| terrain.get_at(pos).light();
+
+        let sky = relight_cells(size, &mut self.sky, terrain, &columns, sky_source);
+        let block = relight_cells(size, &mut self.block, terrain, changed, block_source);
+
+        sky.into_iter()
+            .filter(|(pos, before)| self.sky(*pos) != *before)
+            .chain(
+                block
+                    .into_iter()
+                    .filter(|(pos, before)| self.block(*pos) != *before),
+            )
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+}
+
+/// Index of `pos` in a grid `size` big, None outside it.
+fn cell_index(size: IVec3, pos: IVec3) -> Option<usize> {
+    let in_bounds = pos.cmpge(IVec3::ZERO).all() && pos.cmplt(size).all();
+
+    in_bounds.then(|| {
+        (pos.x as usize * size.z as usize + pos.z as usize) * size.y as usize + pos.y as usize
+    })
+}
+
+/// Darkens everything the cells in `starts` lit, then floods `light` back in
+/// from the sources in the darkened area and the light around it. Returns
+/// the cells it touched with their light from before.
+fn relight_cells(
+    size: IVec3,
+    light: &mut [u8],
+    terrain: &Terrain,
+    starts: &[IVec3],
+    source: impl Fn(IVec3) -> u8,
+) -> HashMap<IVec3, u8> {
+    let mut before = HashMap::new();
+    let mut dark = VecDeque::new();
+    let mut open = VecDeque::new();
+
+    for pos in starts {
+        let Some(i) = cell_index(size, *pos) else {
+            continue;
+        };
+        before.entry(*pos).or_insert(light[i]);
+        dark.push_back((*pos, light[i]));
+        light[i] = 0;
+    }
+
+    // cells dimmer than the one darkened next to them took their light from
+    // it, brighter ones have a light of their own to spread back in
+    while let Some((pos, level)) = dark.pop_front() {
+        for offset in NEIGHBORS {
+            let next = pos + offset;
+            let Some(i) = cell_index(size, next) else {
+                continue;
+            };
+            let next_level = light[i];
+            if next_level == 0 {
+                continue;
+            }
+
+            if next_level < level {
+                before.entry(next).or_insert(next_level);
+                dark.push_back((next, next_level));
+                light[i] = 0;
+            } else {
+                open.push_back(next);
+            }
+        }
+    }
+
+    for pos in before.keys() {
+        let i = cell_index(size, *pos).unwrap();
+        let level = source(*pos);
+        if level > light[i] {
+            light[i] = level;
+            open.push_back(*pos);
+        }
+    }
+
+    flood(size, light, terrain, open, |pos, level| {
+        before.entry(pos).or_insert(level);
+    });
+    before
+}
+
+/// Spreads `light` out from the cells in `open` through everything that
+/// isn't a full block, one level dimmer per step. `lit` gets each cell it
+/// brightens with the light the cell had.
+fn flood(
+    size: IVec3,
+    light: &mut [u8],
+    terrain: &Terrain,
+    mut open: VecDeque<IVec3>,
+    mut lit: impl FnMut(IVec3, u8),
+) {
+    while let Some(pos) = open.pop_front() {
+        let level = cell_index(size, pos).map_or(0, |i| light[i]);
+        if level <= 1 {
+            continue;
+        }
+
+        for offset in NEIGHBORS {
+            let next = pos + offset;
+            let Some(i) = cell_index(size, next) else {
+                continue;
+            };
+
+            if light[i] < level - 1 && !terrain.get_at(next).is_filled() {
+                lit(next, light[i]);
+                light[i] = level - 1;
+                open.push_back(next);
+            }
+        }
+    }
+}
+
+impl Plugin for LightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightMap>()
+            .add_systems(OnEnter(AppState::InGame), setup_light)
+            .add_systems(Update, update_light.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn setup_light(terrain: Res<Terrain>, mut light: ResMut<LightMap>) {
+    light.compute(&terrain);
+}
+
+/// Relights around the blocks that changed, and remeshes the chunks whose
+/// lighting changed with them, even where no block did. Works the whole map
+/// out again when its size changed or the light was reset.
+pub(crate) fn update_light(
+    mut terrain: ResMut<Terrain>,
+    mut light: ResMut<LightMap>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    if light.size != terrain.size() {
+        ev_block_changed.clear();
+        light.compute(&terrain);
+        terrain.mark_all_dirty();
+        return;
+    }
+
+    let changed: Vec<_> = ev_block_changed.read().map(|ev| ev.pos).collect();
+    if changed.is_empty() {
+        return;
+    }
+    for pos in light.relight(&terrain, &changed) {
+        terrain.mark_dirty(pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{fixtures, Block};
+
+    #[test]
+    fn relighting_matches_a_full_compute() {
+        let mut terrain = fixtures::ground(IVec3::new(24, 20, 24), 10, Block::Stone);
+        // a cave with a lava pool, lit from the sky through a shaft
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(4, 3, 4),
+            IVec3::new(16, 7, 16),
+            Block::Empty,
+        );
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(5, 7, 5),
+            IVec3::new(6, 10, 6),
+            Block::Empty,
+        );
+        terrain.set_at(IVec3::new(14, 3, 14), Block::Lava);
+        let mut light = LightMap::default();
+        light.compute(&terrain);
+
+        let edits = [
+            // close the shaft, open another, put the lava out, block a passage
+            (IVec3::new(5, 9, 5), Block::Stone),
+            (IVec3::new(12, 9, 12), Block::Empty),
+            (IVec3::new(12, 8, 12), Block::Empty),
+            (IVec3::new(12, 7, 12), Block::Empty),
+            (IVec3::new(14, 3, 14), Block::Empty),
+            (IVec3::new(9, 4, 4), Block::Lava),
+            (IVec3::new(10, 3, 8), Block::Stone),
+        ];
+        for (pos, block) in edits {
+            let before = LightMap {
+                size: light.size,
+                sky: light.sky.clone(),
+                block: light.block.clone(),
+            };
+            terrain.set_at(pos, block);
+            let changed = light.relight(&terrain, &[pos]);
+            assert!(!changed.is_empty());
+
+            let mut full = LightMap::default();
+            full.compute(&terrain);
+            assert!(light.sky == full.sky, "sky after setting {}", pos);
+            assert!(
+                light.block == full.block,
+                "block light after setting {}",
+                pos
+            );
+
+            let expected: HashSet<_> = terrain
+                .iter()
+                .map(|(pos, _)| pos)
+                .filter(|pos| {
+                    light.sky(*pos) != before.sky(*pos) || light.block(*pos) != before.block(*pos)
+                })
+                .collect();
+            assert_eq!(changed, expected);
+        }
+    }
+}
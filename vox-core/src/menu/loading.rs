@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+
+use crate::{
+    net::{is_authority, Client},
+    save::WorldSave,
+    terrain::{Terrain, TerrainMesh, TerrainModifiedEvent},
+    worldgen::{WorldGenPipeline, WorldGenRun, WorldGenSettings},
+    zone::Zones,
+};
+
+use super::{AppState, MenuScreen, RegenerateWorldEvent, WorldSource, BACKGROUND_COLOR};
+
+/// The app states and building the world on `Loading`, without any of the
+/// screens, so it runs headless too.
+pub struct LoadingPlugin;
+
+/// Share of the loading bar filled by building the world, meshing it fills
+/// the rest.
+const BUILD_SHARE: f32 = 0.8;
+
+const BAR_COLOR: Color = Color::rgb(0.4, 0.55, 0.35);
+const BAR_BACKGROUND: Color = Color::rgb(0.2, 0.22, 0.25);
+
+/// How far along loading is, sent as each part of it finishes.
+#[derive(Event, Debug, Clone)]
+pub struct LoadingProgressEvent {
+    /// What's being worked on now.
+    pub label: String,
+    /// From 0 to 1.
+    pub progress: f32,
+}
+
+#[derive(Component)]
+pub struct LoadingScreen;
+
+#[derive(Component)]
+pub struct LoadingLabel;
+
+/// The filled part of the loading bar.
+#[derive(Component)]
+pub struct LoadingBar;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<AppState>()
+            .add_event::<RegenerateWorldEvent>()
+            .add_event::<LoadingProgressEvent>()
+            .add_systems(
+                Update,
+                (
+                    regenerate_world
+                        .run_if(not(in_state(AppState::Loading)).and_then(is_authority)),
+                    load_world.run_if(in_state(AppState::Loading)),
+                ),
+            );
+    }
+}
+
+/// Skips the menu, for when the world to play was picked before starting.
+pub fn start_loading(mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(AppState::Loading);
+}
+
+/// Swaps in an empty map of the requested size and goes through `Loading` to
+/// fill it. Leaving `InGame` tears down what was built on the old one.
+fn regenerate_world(
+    mut commands: Commands,
+    mut ev_regenerate: EventReader<RegenerateWorldEvent>,
+    mut settings: ResMut<WorldGenSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(ev) = ev_regenerate.read().last() else {
+        return;
+    };
+
+    settings.seed = ev.seed;
+    commands.insert_resource(Terrain::new(ev.size));
+    commands.insert_resource(WorldSource::Generate);
+    next_state.set(AppState::Loading);
+}
+
+pub fn show_loading_screen(mut commands: Commands, source: Res<WorldSource>) {
+    let text = match source.as_ref() {
+        WorldSource::Generate => "Generating world...".to_string(),
+        WorldSource::Load(path) => format!("Loading {}...", path.display()),
+        WorldSource::Remote => "Joining server...".to_string(),
+    };
+
+    let root = NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(16.),
+            ..default()
+        },
+        background_color: BACKGROUND_COLOR.into(),
+        // stays over the game while its first meshes are built
+        z_index: ZIndex::Global(100),
+        ..default()
+    };
+    let bar = NodeBundle {
+        style: Style {
+            width: Val::Px(400.),
+            height: Val::Px(16.),
+            ..default()
+        },
+        background_color: BAR_BACKGROUND.into(),
+        ..default()
+    };
+    let fill = NodeBundle {
+        style: Style {
+            width: Val::Percent(0.),
+            height: Val::Percent(100.),
+            ..default()
+        },
+        background_color: BAR_COLOR.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((root, LoadingScreen))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 32.,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+            parent.spawn(bar).with_children(|parent| {
+                parent.spawn((fill, LoadingBar));
+            });
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                LoadingLabel,
+            ));
+        });
+}
+
+pub fn despawn_loading_screen(mut commands: Commands, screens: Query<Entity, With<LoadingScreen>>) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// Fills the bar and names the step under way as progress comes in.
+pub fn show_loading_progress(
+    mut ev_progress: EventReader<LoadingProgressEvent>,
+    mut bars: Query<&mut Style, With<LoadingBar>>,
+    mut labels: Query<&mut Text, With<LoadingLabel>>,
+) {
+    let Some(ev) = ev_progress.read().last() else {
+        return;
+    };
+
+    for mut style in bars.iter_mut() {
+        style.width = Val::Percent(ev.progress.clamp(0., 1.) * 100.);
+    }
+    for mut text in labels.iter_mut() {
+        text.sections[0].value = ev.label.clone();
+    }
+}
+
+/// Keeps the loading screen up over a world just entered until every chunk
+/// has its first mesh.
+pub fn finish_loading_screen(
+    mut commands: Commands,
+    terrain_mesh: Option<Res<TerrainMesh>>,
+    screens: Query<Entity, With<LoadingScreen>>,
+    mut ev_progress: EventWriter<LoadingProgressEvent>,
+) {
+    if screens.is_empty() {
+        return;
+    }
+
+    let meshed = terrain_mesh.map_or(1., |terrain_mesh| terrain_mesh.meshed());
+    if meshed < 1. {
+        ev_progress.send(LoadingProgressEvent {
+            label: "Meshing".to_string(),
+            progress: BUILD_SHARE + (1. - BUILD_SHARE) * meshed,
+        });
+        return;
+    }
+
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// Builds the world once the loading screen has had a frame to draw, then
+/// starts the game. A new world is generated a stage a frame so the screen
+/// can show its progress. A save that can't be read sends the player back
+/// to the list, a server being joined is waited on until the whole map is
+/// in.
+#[allow(clippy::too_many_arguments)]
+fn load_world(
+    mut commands: Commands,
+    source: Res<WorldSource>,
+    mut terrain: ResMut<Terrain>,
+    mut settings: ResMut<WorldGenSettings>,
+    pipeline: Res<WorldGenPipeline>,
+    screen: Option<ResMut<MenuScreen>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+    client: Option<Res<Client>>,
+    mut shown: Local<bool>,
+    mut run: Local<Option<WorldGenRun>>,
+    mut ev_progress: EventWriter<LoadingProgressEvent>,
+) {
+    if !*shown {
+        *shown = true;
+        return;
+    }
+
+    if matches!(source.as_ref(), WorldSource::Remote) && !client.is_some_and(|c| c.is_ready()) {
+        return;
+    }
+
+    if matches!(source.as_ref(), WorldSource::Generate) {
+        let generation = run.get_or_insert_with(|| pipeline.start(&settings));
+        if let Some(stage) = pipeline.step(generation, &mut terrain, &settings) {
+            ev_progress.send(LoadingProgressEvent {
+                label: format!("Generating: {}", stage),
+                progress: BUILD_SHARE * pipeline.progress(generation),
+            });
+            return;
+        }
+        *run = None;
+    }
+    *shown = false;
+
+    let zones = match source.as_ref() {
+        WorldSource::Generate => Zones::new(terrain.size()),
+        WorldSource::Load(path) => match WorldSave::read(path) {
+            Ok(save) => {
+                settings.seed = save.seed;
+                *terrain = save.terrain;
+                save.zones
+            }
+            Err(err) => {
+                println!("Failed to load {}: {}", path.display(), err);
+                if let Some(mut screen) = screen {
+                    *screen = MenuScreen::LoadWorld;
+                }
+                next_state.set(AppState::MainMenu);
+                return;
+            }
+        },
+        // the chunks went straight into the terrain as they arrived
+        WorldSource::Remote => Zones::new(terrain.size()),
+    };
+    commands.insert_resource(zones);
+
+    // the world starts out this way, nothing to announce block by block
+    terrain.take_changes();
+    terrain.mark_unedited();
+
+    ev_progress.send(LoadingProgressEvent {
+        label: "Meshing".to_string(),
+        progress: BUILD_SHARE,
+    });
+    ev_terrain_mod.send(TerrainModifiedEvent);
+    next_state.set(AppState::InGame);
+}
@@ -0,0 +1,421 @@
+use std::path::PathBuf;
+
+use bevy::{
+    prelude::*,
+    window::{ReceivedCharacter, WindowMode},
+};
+use rand::Rng;
+
+use crate::{
+    save,
+    settings::WindowSettings,
+    terrain::{GraphicsSettings, Terrain},
+    worldgen::{Landform, WorldGenSettings},
+};
+
+mod loading;
+
+pub use loading::{start_loading, LoadingPlugin, LoadingProgressEvent};
+
+pub struct MenuPlugin;
+
+/// Top-level flow of the game. Gameplay systems only run `InGame`.
+#[derive(States, Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    /// Building the chosen world, for a frame or more.
+    Loading,
+    InGame,
+}
+
+/// Where the `Loading` state gets its world from.
+#[derive(Resource, Debug, Clone)]
+pub enum WorldSource {
+    /// Runs the world generator on a fresh map.
+    Generate,
+    /// Reads a save file.
+    Load(PathBuf),
+    /// Waits for the map from the server being joined.
+    Remote,
+}
+
+/// Throws the current world away and generates a fresh one, from the
+/// new-world page or mid-game. Players who joined keep the map they were sent.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct RegenerateWorldEvent {
+    pub size: IVec3,
+    pub seed: u64,
+}
+
+/// Map sizes offered for a new world.
+const SIZE_PRESETS: [IVec3; 3] = [
+    IVec3::new(32, 32, 32),
+    IVec3::new(64, 48, 64),
+    IVec3::new(128, 64, 128),
+];
+
+/// Render distances offered in the settings, in blocks.
+const RENDER_DISTANCES: [f32; 4] = [128., 256., 512., 1000.];
+
+/// Longest seed the entry takes, anything past it wouldn't fit a u64.
+const MAX_SEED_DIGITS: usize = 19;
+
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.22, 0.25);
+const HOVERED_COLOR: Color = Color::rgb(0.3, 0.33, 0.38);
+const PRESSED_COLOR: Color = Color::rgb(0.4, 0.55, 0.35);
+const BACKGROUND_COLOR: Color = Color::rgb(0.08, 0.09, 0.1);
+
+/// Which page of the menu is showing.
+#[derive(Resource, Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum MenuScreen {
+    #[default]
+    Main,
+    NewWorld,
+    LoadWorld,
+    Settings,
+}
+
+/// The new-world options being filled in, starting from the command line.
+#[derive(Resource)]
+struct NewWorldForm {
+    seed: String,
+    size: IVec3,
+    landform: Landform,
+}
+
+impl FromWorld for NewWorldForm {
+    fn from_world(world: &mut World) -> Self {
+        let settings = world.resource::<WorldGenSettings>();
+        Self {
+            seed: settings.seed.to_string(),
+            size: world.resource::<Terrain>().size(),
+            landform: settings.landform,
+        }
+    }
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component, Debug, Clone)]
+enum MenuButton {
+    NewWorld,
+    LoadWorld,
+    Settings,
+    Back,
+    RandomSeed,
+    CycleSize,
+    CycleLandform,
+    Create,
+    Load(PathBuf),
+    ToggleOcclusion,
+    ToggleNormalMaps,
+    ToggleSliceOutlines,
+    ToggleInteriorDimming,
+    ToggleFog,
+    CycleRenderDistance,
+    CycleWindowMode,
+}
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(LoadingPlugin)
+            .init_resource::<MenuScreen>()
+            .init_resource::<NewWorldForm>()
+            .add_systems(
+                Update,
+                (type_seed, press_buttons, show_menu)
+                    .chain()
+                    .run_if(in_state(AppState::MainMenu)),
+            )
+            .add_systems(OnExit(AppState::MainMenu), despawn_menu)
+            .add_systems(
+                OnEnter(AppState::Loading),
+                (
+                    loading::despawn_loading_screen,
+                    loading::show_loading_screen,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(AppState::MainMenu), loading::despawn_loading_screen)
+            .add_systems(
+                Update,
+                (
+                    loading::finish_loading_screen.run_if(in_state(AppState::InGame)),
+                    loading::show_loading_progress,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn landform_name(landform: Landform) -> &'static str {
+    match landform {
+        Landform::Sphere => "Sphere",
+        Landform::Flat => "Flat",
+    }
+}
+
+fn window_mode_name(mode: WindowMode) -> &'static str {
+    match mode {
+        WindowMode::Windowed => "Windowed",
+        WindowMode::BorderlessFullscreen => "Borderless",
+        WindowMode::SizedFullscreen | WindowMode::Fullscreen => "Fullscreen",
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+/// Rebuilds the menu whenever the page, the form or the settings changed.
+fn show_menu(
+    mut commands: Commands,
+    screen: Res<MenuScreen>,
+    form: Res<NewWorldForm>,
+    graphics: Res<GraphicsSettings>,
+    window: Res<WindowSettings>,
+    roots: Query<Entity, With<MenuRoot>>,
+) {
+    let is_changed =
+        screen.is_changed() || form.is_changed() || graphics.is_changed() || window.is_changed();
+    if !is_changed {
+        return;
+    }
+
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+
+    let root = NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(12.),
+            ..default()
+        },
+        background_color: BACKGROUND_COLOR.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((root, MenuRoot))
+        .with_children(|parent| match *screen {
+            MenuScreen::Main => {
+                spawn_label(parent, "vox-rust", 48.);
+                spawn_button(parent, "New World", MenuButton::NewWorld);
+                spawn_button(parent, "Load World", MenuButton::LoadWorld);
+                spawn_button(parent, "Settings", MenuButton::Settings);
+            }
+            MenuScreen::NewWorld => {
+                spawn_label(parent, "New World", 36.);
+                let seed = if form.seed.is_empty() {
+                    "random"
+                } else {
+                    &form.seed
+                };
+                spawn_label(parent, &format!("Seed: {}_", seed), 24.);
+                spawn_button(parent, "Random Seed", MenuButton::RandomSeed);
+                let size = form.size;
+                let size = format!("Size: {}x{}x{}", size.x, size.y, size.z);
+                spawn_button(parent, &size, MenuButton::CycleSize);
+                let landform = format!("Generator: {}", landform_name(form.landform));
+                spawn_button(parent, &landform, MenuButton::CycleLandform);
+                spawn_button(parent, "Create", MenuButton::Create);
+                spawn_button(parent, "Back", MenuButton::Back);
+            }
+            MenuScreen::LoadWorld => {
+                spawn_label(parent, "Load World", 36.);
+                let saves = save::list_saves();
+                if saves.is_empty() {
+                    spawn_label(parent, "No saved worlds, F5 saves in game", 20.);
+                }
+                for path in saves {
+                    let name = path
+                        .file_stem()
+                        .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+                    spawn_button(parent, &name, MenuButton::Load(path));
+                }
+                spawn_button(parent, "Back", MenuButton::Back);
+            }
+            MenuScreen::Settings => {
+                spawn_label(parent, "Settings", 36.);
+                let occlusion =
+                    format!("Ambient Occlusion: {}", on_off(graphics.ambient_occlusion));
+                spawn_button(parent, &occlusion, MenuButton::ToggleOcclusion);
+                let normal_maps = format!("Normal Maps: {}", on_off(graphics.normal_maps));
+                spawn_button(parent, &normal_maps, MenuButton::ToggleNormalMaps);
+                let outlines = format!("Slice Outlines: {}", on_off(graphics.slice_outlines));
+                spawn_button(parent, &outlines, MenuButton::ToggleSliceOutlines);
+                let dimming = format!("Dark Interiors: {}", on_off(graphics.interior_dimming));
+                spawn_button(parent, &dimming, MenuButton::ToggleInteriorDimming);
+                let fog = format!("Fog: {}", on_off(graphics.fog));
+                spawn_button(parent, &fog, MenuButton::ToggleFog);
+                let distance = format!("Render Distance: {}", graphics.render_distance);
+                spawn_button(parent, &distance, MenuButton::CycleRenderDistance);
+                let mode = format!("Window: {}", window_mode_name(window.mode));
+                spawn_button(parent, &mode, MenuButton::CycleWindowMode);
+                spawn_button(parent, "Back", MenuButton::Back);
+            }
+        });
+}
+
+fn spawn_label(parent: &mut ChildBuilder, text: &str, font_size: f32) {
+    parent.spawn(TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size,
+            color: Color::WHITE,
+            ..default()
+        },
+    ));
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, button: MenuButton) {
+    let bundle = ButtonBundle {
+        style: Style {
+            width: Val::Px(320.),
+            height: Val::Px(44.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: BUTTON_COLOR.into(),
+        ..default()
+    };
+
+    parent.spawn((bundle, button)).with_children(|parent| {
+        spawn_label(parent, text, 22.);
+    });
+}
+
+fn despawn_menu(mut commands: Commands, roots: Query<Entity, With<MenuRoot>>) {
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+/// Digits go into the seed while the new-world page is up, backspace takes
+/// them off again.
+fn type_seed(
+    screen: Res<MenuScreen>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    mut form: ResMut<NewWorldForm>,
+) {
+    if *screen != MenuScreen::NewWorld {
+        ev_chars.clear();
+        return;
+    }
+
+    for ev in ev_chars.read() {
+        for c in ev.char.chars().filter(|c| c.is_ascii_digit()) {
+            if form.seed.len() < MAX_SEED_DIGITS {
+                form.seed.push(c);
+            }
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        form.seed.pop();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn press_buttons(
+    mut commands: Commands,
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut screen: ResMut<MenuScreen>,
+    mut form: ResMut<NewWorldForm>,
+    mut settings: ResMut<WorldGenSettings>,
+    mut graphics: ResMut<GraphicsSettings>,
+    mut window: ResMut<WindowSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut ev_regenerate: EventWriter<RegenerateWorldEvent>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Pressed => PRESSED_COLOR,
+            Interaction::Hovered => HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            MenuButton::NewWorld => *screen = MenuScreen::NewWorld,
+            MenuButton::LoadWorld => *screen = MenuScreen::LoadWorld,
+            MenuButton::Settings => *screen = MenuScreen::Settings,
+            MenuButton::Back => *screen = MenuScreen::Main,
+            MenuButton::RandomSeed => {
+                form.seed = rand::thread_rng().gen::<u32>().to_string();
+            }
+            MenuButton::CycleSize => {
+                // a size from the command line that isn't a preset goes
+                // back to the first one
+                let next = SIZE_PRESETS
+                    .iter()
+                    .position(|size| *size == form.size)
+                    .map_or(0, |i| (i + 1) % SIZE_PRESETS.len());
+                form.size = SIZE_PRESETS[next];
+            }
+            MenuButton::CycleLandform => {
+                form.landform = match form.landform {
+                    Landform::Sphere => Landform::Flat,
+                    Landform::Flat => Landform::Sphere,
+                };
+            }
+            MenuButton::Create => {
+                settings.landform = form.landform;
+                ev_regenerate.send(RegenerateWorldEvent {
+                    size: form.size,
+                    seed: form
+                        .seed
+                        .parse()
+                        .unwrap_or_else(|_| rand::thread_rng().gen()),
+                });
+            }
+            MenuButton::Load(path) => {
+                commands.insert_resource(WorldSource::Load(path.clone()));
+                next_state.set(AppState::Loading);
+            }
+            MenuButton::ToggleOcclusion => {
+                graphics.ambient_occlusion = !graphics.ambient_occlusion;
+            }
+            MenuButton::ToggleNormalMaps => graphics.normal_maps = !graphics.normal_maps,
+            MenuButton::ToggleSliceOutlines => {
+                graphics.slice_outlines = !graphics.slice_outlines;
+            }
+            MenuButton::ToggleInteriorDimming => {
+                graphics.interior_dimming = !graphics.interior_dimming;
+            }
+            MenuButton::ToggleFog => graphics.fog = !graphics.fog,
+            MenuButton::CycleRenderDistance => {
+                // a distance set in the settings file goes back to the first
+                let next = RENDER_DISTANCES
+                    .iter()
+                    .position(|distance| *distance == graphics.render_distance)
+                    .map_or(0, |i| (i + 1) % RENDER_DISTANCES.len());
+                graphics.render_distance = RENDER_DISTANCES[next];
+            }
+            MenuButton::CycleWindowMode => {
+                window.mode = match window.mode {
+                    WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+                    WindowMode::BorderlessFullscreen => WindowMode::Fullscreen,
+                    WindowMode::SizedFullscreen | WindowMode::Fullscreen => WindowMode::Windowed,
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+
+use crate::{
+    build::BuildMode,
+    camera::CameraRay,
+    menu::AppState,
+    terrain::{Block, Terrain, TerrainModifiedEvent},
+};
+
+pub use tree::{fell_tree, find_tree, Tree};
+
+mod tree;
+
+pub struct MiningPlugin;
+
+/// Holding the left mouse button digs at the block under the cursor.
+pub struct MiningControlsPlugin;
+
+/// Seconds of mining work applied per second, by hand or by an agent.
+pub const MINE_RATE: f32 = 1.;
+
+const MINE_REACH: f32 = 64.;
+
+/// Sent by `mine` for every block it breaks and `fell_tree` for every block
+/// it takes down, with the block as it was.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct BlockMinedEvent {
+    pub pos: IVec3,
+    pub block: Block,
+}
+
+impl Plugin for MiningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BlockMinedEvent>();
+    }
+}
+
+impl Plugin for MiningControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, mine_held_block.run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// Applies `work` to the block at `pos`, remeshing whenever its crack stage
+/// changes or it breaks. Returns whether the block broke.
+pub fn mine(
+    terrain: &mut Terrain,
+    pos: IVec3,
+    work: f32,
+    ev_terrain_mod: &mut EventWriter<TerrainModifiedEvent>,
+    ev_mined: &mut EventWriter<BlockMinedEvent>,
+) -> bool {
+    let block = terrain.get_at(pos);
+    let stage = terrain.damage_stage(pos);
+    let broken = terrain.add_damage(pos, work);
+
+    if broken || terrain.damage_stage(pos) != stage {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+    if broken {
+        ev_mined.send(BlockMinedEvent { pos, block });
+    }
+
+    broken
+}
+
+/// Holding the left mouse button outside of build mode digs at the block under
+/// the cursor.
+fn mine_held_block(
+    time: Res<Time>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    mut terrain: ResMut<Terrain>,
+    camera_ray: Res<CameraRay>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+    mut ev_mined: EventWriter<BlockMinedEvent>,
+) {
+    if build.enabled || !buttons.pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+
+    if let Some(hit) = terrain.raycast(ray.origin, *ray.direction, MINE_REACH) {
+        let work = MINE_RATE * time.delta_seconds();
+        if mine(
+            &mut terrain,
+            hit.pos,
+            work,
+            &mut ev_terrain_mod,
+            &mut ev_mined,
+        ) {
+            println!("Mined {}", hit.pos);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use super::BlockMinedEvent;
+use crate::{
+    terrain::{Block, Terrain},
+    worldgen::CANOPY_RADIUS,
+};
+
+/// Most logs a tree is made of, anything bigger was built rather than grown.
+const MAX_LOGS: usize = 32;
+
+/// Leaves of the canopy for each fruit a felled tree drops.
+const LEAVES_PER_FRUIT: usize = 8;
+
+/// A tree standing on the map, as found by `find_tree`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    /// The lowest log of the trunk, where it's chopped.
+    pub base: IVec3,
+    pub logs: Vec<IVec3>,
+    pub leaves: Vec<IVec3>,
+}
+
+impl Tree {
+    /// Where the fruit the tree drops when felled hangs, among its leaves.
+    pub fn fruit(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.leaves.iter().copied().step_by(LEAVES_PER_FRUIT)
+    }
+}
+
+/// The tree the log or leaves at `pos` belong to. A tree is a trunk of
+/// joined logs rooted in soil, with the leaves its canopy could have grown.
+pub fn find_tree(terrain: &Terrain, pos: IVec3) -> Option<Tree> {
+    let start = match terrain.get_at(pos) {
+        Block::Log(_) => pos,
+        // the nearest log a canopy reaches to
+        Block::Leaves => cells_between(
+            pos - IVec3::splat(CANOPY_RADIUS),
+            pos + IVec3::splat(CANOPY_RADIUS),
+        )
+        .filter(|log| matches!(terrain.get_at(*log), Block::Log(_)))
+        .min_by_key(|log| (*log - pos).length_squared())?,
+        _ => return None,
+    };
+
+    let trunk =
+        terrain.flood_fill_limited(start, MAX_LOGS, |_, block| matches!(block, Block::Log(_)));
+    if trunk.truncated {
+        return None;
+    }
+
+    let mut logs: Vec<_> = trunk.cells.into_iter().collect();
+    logs.sort_by_key(|log| (log.y, log.x, log.z));
+    let base = logs[0];
+    if !matches!(terrain.get_at(base - IVec3::Y), Block::Dirt | Block::Grass) {
+        return None;
+    }
+
+    let (min, max) = logs.iter().fold((base, base), |(min, max), log| {
+        (min.min(*log), max.max(*log))
+    });
+    // as far out and up from the trunk as a grown canopy reaches
+    let reach = IVec3::new(CANOPY_RADIUS, 0, CANOPY_RADIUS);
+    let leaves = cells_between(min - reach, max + IVec3::splat(CANOPY_RADIUS))
+        .filter(|leaf| terrain.get_at(*leaf) == Block::Leaves)
+        .collect();
+
+    Some(Tree { base, logs, leaves })
+}
+
+/// Cells of the box from `min` to `max`, both included.
+fn cells_between(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+    (min.y..=max.y).flat_map(move |y| {
+        (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| IVec3::new(x, y, z)))
+    })
+}
+
+/// Takes the rest of a tree down once its base is chopped out, announcing
+/// every block as mined so the logs drop as wood.
+pub fn fell_tree(terrain: &mut Terrain, tree: &Tree, ev_mined: &mut EventWriter<BlockMinedEvent>) {
+    for &pos in tree.logs.iter().chain(&tree.leaves) {
+        let block = terrain.get_at(pos);
+        if matches!(block, Block::Log(_) | Block::Leaves) {
+            terrain.set_at(pos, Block::Empty);
+            ev_mined.send(BlockMinedEvent { pos, block });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        terrain::{fixtures::ground, Orientation},
+        worldgen::grow_tree,
+    };
+
+    #[test]
+    fn trees_are_found_from_their_leaves() {
+        let mut terrain = ground(IVec3::splat(16), 2, Block::Grass);
+        let base = IVec3::new(8, 2, 8);
+        assert!(grow_tree(&mut terrain, base, &mut StdRng::seed_from_u64(1)));
+
+        let top = (base.y..16)
+            .take_while(|y| matches!(terrain.get_at(IVec3::new(8, *y, 8)), Block::Log(_)))
+            .last()
+            .unwrap();
+        let tree = find_tree(&terrain, IVec3::new(9, top + 1, 8)).unwrap();
+        assert_eq!(tree.base, base);
+        assert_eq!(tree.logs.len() as i32, top - base.y + 1);
+        let leaves = terrain.iter().filter(|(_, b)| *b == Block::Leaves).count();
+        assert_eq!(tree.leaves.len(), leaves);
+
+        // a log lying on stone was put there
+        terrain.set_at(IVec3::new(2, 2, 2), Block::Stone);
+        terrain.set_at(IVec3::new(2, 3, 2), Block::Log(Orientation::Up));
+        assert_eq!(find_tree(&terrain, IVec3::new(2, 3, 2)), None);
+    }
+}
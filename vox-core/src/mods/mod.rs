@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        texture::{CompressedImageFormats, ImageSampler, ImageType},
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    light::MAX_LIGHT,
+    terrain::{
+        check_block_names, register_block, BlockDef, BlockMaterial, BlockShape, TerrainMesh,
+        TEXTURE_COUNT,
+    },
+    worldgen::WorldGenSettings,
+};
+
+/// Folder content packs are discovered in, one folder per pack.
+pub const MOD_DIR: &str = "mods";
+
+/// Describes a pack, at the top of its folder.
+const PACK_FILE: &str = "pack.ron";
+
+/// Blueprints in this folder of a pack join the built-in ones.
+const PACK_BLUEPRINT_DIR: &str = "blueprints";
+
+/// Atlas tiles the built-in blocks leave free, rows 3 to 6. The last row
+/// holds the crack overlays.
+const MOD_TILES: Range<u32> = 3 * TEXTURE_COUNT..(TEXTURE_COUNT - 1) * TEXTURE_COUNT;
+
+/// Loads every pack in `MOD_DIR` at startup. Packs go in folder name order
+/// and their blocks in the order they're listed, so the same packs always
+/// give the same block ids. Saves keep the names of the modded blocks they
+/// use to match them up again if the packs change.
+pub struct ModsPlugin;
+
+/// A `pack.ron`.
+#[derive(Debug, Deserialize)]
+struct Pack {
+    name: String,
+    #[serde(default)]
+    blocks: Vec<PackBlock>,
+}
+
+/// A block as written in a pack, textures given as paths in the pack.
+#[derive(Debug, Deserialize)]
+struct PackBlock {
+    name: String,
+    texture: PathBuf,
+    #[serde(default)]
+    end_texture: Option<PathBuf>,
+    shape: BlockShape,
+    hardness: f32,
+    material: BlockMaterial,
+    #[serde(default)]
+    flammable: bool,
+    #[serde(default)]
+    light: u8,
+}
+
+/// Tiles from the packs waiting to be copied into the terrain atlas.
+#[derive(Resource, Default)]
+struct ModTextures {
+    tiles: Vec<(u32, Image)>,
+}
+
+impl Plugin for ModsPlugin {
+    fn build(&self, app: &mut App) {
+        let mut textures = TextureSlots::default();
+
+        for dir in pack_dirs(MOD_DIR) {
+            match load_pack(&dir, &mut textures) {
+                Ok(name) => println!("Loaded pack {}", name),
+                Err(err) => println!("Failed to load pack {}: {}", dir.display(), err),
+            }
+
+            let blueprints = dir.join(PACK_BLUEPRINT_DIR);
+            if blueprints.is_dir() {
+                if let Some(mut settings) = app.world.get_resource_mut::<WorldGenSettings>() {
+                    settings.blueprint_dirs.push(blueprints);
+                }
+            }
+        }
+
+        app.insert_resource(ModTextures {
+            tiles: textures.tiles,
+        })
+        .add_systems(
+            Update,
+            add_tiles_to_atlas.run_if(resource_exists::<TerrainMesh>),
+        );
+    }
+}
+
+/// Folders in `dir` with a pack file, sorted by name.
+fn pack_dirs(dir: impl AsRef<Path>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut dirs: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.join(PACK_FILE).is_file())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Atlas tiles handed out to pack textures so far.
+#[derive(Default)]
+struct TextureSlots {
+    assigned: HashMap<PathBuf, u32>,
+    tiles: Vec<(u32, Image)>,
+}
+
+impl TextureSlots {
+    /// The tile holding the image at `path`, reading it in if it's new.
+    fn slot(&mut self, path: PathBuf) -> Result<u32, String> {
+        if let Some(slot) = self.assigned.get(&path) {
+            return Ok(*slot);
+        }
+
+        let slot = MOD_TILES.start + self.tiles.len() as u32;
+        if !MOD_TILES.contains(&slot) {
+            return Err("out of atlas space for textures".to_string());
+        }
+
+        let bytes = fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let image = Image::from_buffer(
+            &bytes,
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::nearest(),
+            RenderAssetUsages::default(),
+        )
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        self.assigned.insert(path, slot);
+        self.tiles.push((slot, image));
+        Ok(slot)
+    }
+
+    /// Forgets every tile after the first `len`.
+    fn truncate(&mut self, len: usize) {
+        self.tiles.truncate(len);
+        self.assigned
+            .retain(|_, slot| *slot < MOD_TILES.start + len as u32);
+    }
+}
+
+/// Registers the blocks of the pack in `dir`, returning its name. The whole
+/// pack is checked first, so one that fails registers none of its blocks
+/// and leaves no tiles behind.
+fn load_pack(dir: &Path, textures: &mut TextureSlots) -> Result<String, String> {
+    let source = fs::read_to_string(dir.join(PACK_FILE)).map_err(|err| err.to_string())?;
+    let pack: Pack = ron::from_str(&source).map_err(|err| err.to_string())?;
+
+    let names: Vec<_> = pack
+        .blocks
+        .iter()
+        .map(|block| block.name.as_str())
+        .collect();
+    check_block_names(&names)?;
+
+    let loaded = textures.tiles.len();
+    let slots = pack
+        .blocks
+        .iter()
+        .map(|block| {
+            let texture_id = textures.slot(dir.join(&block.texture))?;
+            let end_texture_id = match &block.end_texture {
+                Some(path) => Some(textures.slot(dir.join(path))?),
+                None => None,
+            };
+            Ok((texture_id, end_texture_id))
+        })
+        .collect::<Result<Vec<_>, String>>();
+    let slots = match slots {
+        Ok(slots) => slots,
+        Err(err) => {
+            textures.truncate(loaded);
+            return Err(err);
+        }
+    };
+
+    for (block, (texture_id, end_texture_id)) in pack.blocks.into_iter().zip(slots) {
+        register_block(BlockDef {
+            // registered once for the life of the game
+            name: Box::leak(block.name.into_boxed_str()),
+            texture_id,
+            end_texture_id,
+            shape: block.shape,
+            hardness: block.hardness,
+            material: block.material,
+            flammable: block.flammable,
+            light: block.light.min(MAX_LIGHT),
+        })?;
+    }
+
+    Ok(pack.name)
+}
+
+/// Copies the pack tiles into the atlas once it has loaded. Tiles have to
+/// match the atlas' tile size.
+fn add_tiles_to_atlas(
+    mut mod_textures: ResMut<ModTextures>,
+    terrain_mesh: Res<TerrainMesh>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if mod_textures.tiles.is_empty() {
+        return;
+    }
+    let Some(atlas) = images.get_mut(&terrain_mesh.texture) else {
+        return;
+    };
+
+    let atlas_width = atlas.width();
+    let tile = atlas_width / TEXTURE_COUNT;
+    let format = atlas.texture_descriptor.format;
+
+    for (slot, image) in mod_textures.tiles.drain(..) {
+        let converted = if image.texture_descriptor.format == format {
+            Some(image)
+        } else {
+            image.convert(format)
+        };
+        let Some(image) = converted.filter(|i| i.width() == tile && i.height() == tile) else {
+            println!(
+                "Skipping a pack texture, tiles must be {}x{} RGBA",
+                tile, tile
+            );
+            continue;
+        };
+
+        let texel = format.block_copy_size(None).unwrap_or(4) as usize;
+        let row = tile as usize * texel;
+        let ox = (slot % TEXTURE_COUNT * tile) as usize;
+        let oy = (slot / TEXTURE_COUNT * tile) as usize;
+
+        for y in 0..tile as usize {
+            let start = ((oy + y) * atlas_width as usize + ox) * texel;
+            atlas.data[start..start + row].copy_from_slice(&image.data[y * row..(y + 1) * row]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::find_modded;
+
+    /// A pack folder in the temp dir holding `pack` and the terrain atlas as
+    /// `tile.png`, gone once the test is done.
+    struct TempPack(PathBuf);
+
+    impl TempPack {
+        fn new(name: &str, pack: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("vox-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(PACK_FILE), pack).unwrap();
+            let atlas = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/terrain.png");
+            fs::copy(atlas, dir.join("tile.png")).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempPack {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn block(name: &str, texture: &str) -> String {
+        format!(
+            "(name: \"{}\", texture: \"{}\", shape: Cube, hardness: 1, material: Stone)",
+            name, texture
+        )
+    }
+
+    #[test]
+    fn packs_that_fail_register_none_of_their_blocks() {
+        let mut textures = TextureSlots::default();
+
+        let pack = format!(
+            "(name: \"Twins\", blocks: [{}, {}])",
+            block("PackTestTwin", "tile.png"),
+            block("PackTestTwin", "tile.png")
+        );
+        let twins = TempPack::new("twins", &pack);
+        assert!(load_pack(&twins.0, &mut textures).is_err());
+        assert!(find_modded("PackTestTwin").is_none());
+
+        let pack = format!(
+            "(name: \"Missing\", blocks: [{}, {}])",
+            block("PackTestFound", "tile.png"),
+            block("PackTestMissing", "missing.png")
+        );
+        let missing = TempPack::new("missing", &pack);
+        assert!(load_pack(&missing.0, &mut textures).is_err());
+        assert!(find_modded("PackTestFound").is_none());
+        assert!(textures.tiles.is_empty() && textures.assigned.is_empty());
+
+        let pack = format!(
+            "(name: \"Fine\", blocks: [{}])",
+            block("PackTestFine", "tile.png")
+        );
+        let fine = TempPack::new("fine", &pack);
+        assert_eq!(load_pack(&fine.0, &mut textures).unwrap(), "Fine");
+        assert!(find_modded("PackTestFine").is_some());
+        assert_eq!(textures.tiles.len(), 1);
+    }
+}
@@ -0,0 +1,465 @@
+use std::time::Duration;
+
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use crate::{
+    agent::{agent_cell, Agent, AgentJob, AgentPath},
+    item::Stockpiles,
+    job::{JobKind, JobQueue},
+    menu::AppState,
+    net::is_authority,
+    pathfinding::find_path,
+    terrain::{Block, Terrain},
+    tick::every,
+    zone::{ZoneKind, Zones},
+};
+
+/// What keeps agents going. Hunger and rest run down over time; a hungry
+/// agent drops its job to eat fruit from a stockpile, a tired one to sleep
+/// in a bedroom zone.
+pub struct NeedsPlugin;
+
+/// An icon over each agent's head showing what it's after.
+pub struct NeedsIconPlugin;
+
+/// Seconds between need updates.
+const NEEDS_TICK: f32 = 1.;
+
+/// Hunger lost each tick, a full agent goes hungry in about five minutes.
+const HUNGER_DECAY: f32 = 0.0025;
+
+/// Rest lost each tick while awake.
+const REST_DECAY: f32 = 0.002;
+
+/// Rest regained each second asleep.
+const REST_RECOVERY: f32 = 0.05;
+
+/// Below this an agent goes to eat.
+const HUNGRY: f32 = 0.3;
+
+/// Below this an agent goes to sleep.
+const TIRED: f32 = 0.2;
+
+/// How high over an agent's center its status icon floats.
+const ICON_HEIGHT: f32 = 0.8;
+
+/// Need meters, each from 0 for desperate to 1 for fully satisfied.
+#[derive(Component, Debug)]
+pub struct Needs {
+    pub hunger: f32,
+    pub rest: f32,
+}
+
+impl Default for Needs {
+    fn default() -> Self {
+        Self {
+            hunger: 1.,
+            rest: 1.,
+        }
+    }
+}
+
+/// A need an agent set its work aside to see to.
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub enum Fulfilling {
+    /// Off to eat from the stockpile cell.
+    Eat(IVec3),
+    /// Off to sleep in the bedroom cell, or asleep once there.
+    Sleep(IVec3),
+}
+
+/// The icon floating over an agent.
+#[derive(Component)]
+struct StatusIcon;
+
+#[derive(Resource)]
+struct StatusIconAssets {
+    mesh: Handle<Mesh>,
+    hungry: Handle<StandardMaterial>,
+    tired: Handle<StandardMaterial>,
+    asleep: Handle<StandardMaterial>,
+}
+
+impl Plugin for NeedsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                decay_needs.run_if(every(Duration::from_secs_f32(NEEDS_TICK))),
+                seek_needs.run_if(every(Duration::from_secs_f32(NEEDS_TICK))),
+                fulfill_needs,
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame).and_then(is_authority)),
+        );
+    }
+}
+
+impl Plugin for NeedsIconPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_status_icons).add_systems(
+            Update,
+            (add_status_icons, update_status_icons).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn setup_status_icons(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut icon = |color| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            unlit: true,
+            ..default()
+        })
+    };
+    commands.insert_resource(StatusIconAssets {
+        mesh: meshes.add(Cuboid::new(0.15, 0.15, 0.15)),
+        hungry: icon(Color::rgb(0.9, 0.3, 0.2)),
+        tired: icon(Color::rgb(0.7, 0.4, 1.)),
+        asleep: icon(Color::rgb(0.3, 0.5, 1.)),
+    });
+}
+
+/// Runs hunger down, and rest too unless asleep.
+fn decay_needs(mut agents: Query<(&mut Needs, Option<&Fulfilling>)>) {
+    for (mut needs, fulfilling) in agents.iter_mut() {
+        needs.hunger = (needs.hunger - HUNGER_DECAY).max(0.);
+        if !matches!(fulfilling, Some(Fulfilling::Sleep(_))) {
+            needs.rest = (needs.rest - REST_DECAY).max(0.);
+        }
+    }
+}
+
+/// Sends hungry agents to the nearest fruit and tired ones to a free bed,
+/// giving back the job they were on. Agents carrying a haul finish it
+/// first.
+#[allow(clippy::type_complexity)]
+fn seek_needs(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    zones: Res<Zones>,
+    stockpiles: Res<Stockpiles>,
+    mut queue: ResMut<JobQueue>,
+    mut agents: Query<
+        (
+            Entity,
+            &Transform,
+            &Needs,
+            &mut AgentPath,
+            Option<&AgentJob>,
+        ),
+        (With<Agent>, Without<Fulfilling>),
+    >,
+    sleepers: Query<&Fulfilling>,
+) {
+    let mut taken_beds: Vec<_> = sleepers
+        .iter()
+        .filter_map(|fulfilling| match fulfilling {
+            Fulfilling::Sleep(cell) => Some(*cell),
+            _ => None,
+        })
+        .collect();
+
+    for (agent, transform, needs, mut path, agent_job) in agents.iter_mut() {
+        let job = agent_job.and_then(|agent_job| queue.get(agent_job.job));
+        if job.is_some_and(|job| matches!(job.kind, JobKind::Haul { .. }) && job.picked_up) {
+            continue;
+        }
+
+        let cell = agent_cell(transform);
+        let errand = if needs.hunger < HUNGRY {
+            stockpiles.find(Block::Fruit, cell).and_then(|food| {
+                let cells = find_path(&terrain, cell, food, |p| {
+                    (p - food).abs().max_element() <= 1
+                })?;
+                Some((Fulfilling::Eat(food), cells))
+            })
+        } else {
+            None
+        };
+        let errand = errand.or_else(|| {
+            if needs.rest >= TIRED {
+                return None;
+            }
+            let bed = zones
+                .cells_of(ZoneKind::Bedroom)
+                .filter(|bed| !taken_beds.contains(bed))
+                .min_by_key(|bed| (*bed - cell).length_squared())?;
+            let cells = find_path(&terrain, cell, bed, |p| p == bed)?;
+            taken_beds.push(bed);
+            Some((Fulfilling::Sleep(bed), cells))
+        });
+        let Some((fulfilling, cells)) = errand else {
+            continue;
+        };
+
+        if let Some(agent_job) = agent_job {
+            queue.release(agent_job.job);
+            commands.entity(agent).remove::<AgentJob>();
+        }
+        path.cells = cells.into();
+        commands.entity(agent).insert(fulfilling);
+    }
+}
+
+/// Eats or sleeps once the agent gets where it was going. Agents that lost
+/// their way or found the fruit gone go back to work.
+fn fulfill_needs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut stockpiles: ResMut<Stockpiles>,
+    mut agents: Query<(Entity, &Transform, &AgentPath, &mut Needs, &Fulfilling)>,
+) {
+    for (agent, transform, path, mut needs, fulfilling) in agents.iter_mut() {
+        if !path.cells.is_empty() {
+            continue;
+        }
+
+        let cell = agent_cell(transform);
+        let is_done = match *fulfilling {
+            Fulfilling::Eat(food) => {
+                if (cell - food).abs().max_element() <= 1 {
+                    if let Some(item) = stockpiles.take_from(food, Block::Fruit) {
+                        commands.entity(item).despawn_recursive();
+                        needs.hunger = 1.;
+                    }
+                }
+                true
+            }
+            Fulfilling::Sleep(bed) if cell == bed => {
+                needs.rest = (needs.rest + REST_RECOVERY * time.delta_seconds()).min(1.);
+                needs.rest >= 1.
+            }
+            Fulfilling::Sleep(_) => true,
+        };
+
+        if is_done {
+            commands.entity(agent).remove::<Fulfilling>();
+        }
+    }
+}
+
+/// Hangs an icon over every agent that has needs.
+fn add_status_icons(
+    mut commands: Commands,
+    assets: Res<StatusIconAssets>,
+    agents: Query<Entity, Added<Needs>>,
+) {
+    for agent in agents.iter() {
+        let icon = commands
+            .spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: assets.hungry.clone(),
+                    transform: Transform::from_xyz(0., ICON_HEIGHT, 0.),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                NotShadowCaster,
+                StatusIcon,
+            ))
+            .id();
+        commands.entity(agent).add_child(icon);
+    }
+}
+
+/// Shows the most pressing state of the agent under each icon: asleep,
+/// hungry, tired, or nothing at all.
+#[allow(clippy::type_complexity)]
+fn update_status_icons(
+    assets: Res<StatusIconAssets>,
+    agents: Query<(&Needs, Option<&Fulfilling>)>,
+    mut icons: Query<(&Parent, &mut Handle<StandardMaterial>, &mut Visibility), With<StatusIcon>>,
+) {
+    for (parent, mut material, mut visibility) in icons.iter_mut() {
+        let Ok((needs, fulfilling)) = agents.get(parent.get()) else {
+            continue;
+        };
+
+        let shown = match fulfilling {
+            Some(Fulfilling::Sleep(_)) => Some(&assets.asleep),
+            _ if needs.hunger < HUNGRY => Some(&assets.hungry),
+            _ if needs.rest < TIRED => Some(&assets.tired),
+            _ => None,
+        };
+        let next = match shown {
+            Some(shown) => {
+                if *material != *shown {
+                    *material = shown.clone();
+                }
+                Visibility::Inherited
+            }
+            None => Visibility::Hidden,
+        };
+        if *visibility != next {
+            *visibility = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{agent::cell_center, terrain::fixtures};
+
+    /// A world on flat stone ground four blocks deep, with two beds.
+    fn world() -> (World, [IVec3; 2]) {
+        let mut world = World::new();
+        let terrain = fixtures::ground(IVec3::splat(16), 4, Block::Stone);
+        let mut zones = Zones::new(terrain.size());
+        let bedroom = zones.add(ZoneKind::Bedroom).unwrap();
+        let beds = [IVec3::new(12, 4, 12), IVec3::new(12, 4, 13)];
+        for bed in beds {
+            zones.paint(bed, Some(bedroom));
+        }
+
+        world.insert_resource(terrain);
+        world.insert_resource(zones);
+        world.init_resource::<Stockpiles>();
+        world.init_resource::<JobQueue>();
+        world.init_resource::<Time>();
+        (world, beds)
+    }
+
+    fn spawn_agent(world: &mut World, cell: IVec3, needs: Needs) -> Entity {
+        world
+            .spawn((
+                Agent { speed: 1. },
+                Transform::from_translation(cell_center(cell)),
+                AgentPath::default(),
+                needs,
+            ))
+            .id()
+    }
+
+    #[test]
+    fn rest_only_runs_down_while_awake() {
+        let (mut world, beds) = world();
+        let awake = spawn_agent(&mut world, IVec3::new(2, 4, 2), Needs::default());
+        let asleep = spawn_agent(&mut world, beds[0], Needs::default());
+        world.entity_mut(asleep).insert(Fulfilling::Sleep(beds[0]));
+
+        world.run_system_once(decay_needs);
+
+        let needs = world.get::<Needs>(awake).unwrap();
+        assert_eq!(needs.hunger, 1. - HUNGER_DECAY);
+        assert_eq!(needs.rest, 1. - REST_DECAY);
+        let needs = world.get::<Needs>(asleep).unwrap();
+        assert_eq!(needs.hunger, 1. - HUNGER_DECAY);
+        assert_eq!(needs.rest, 1.);
+    }
+
+    #[test]
+    fn tired_agents_take_a_bed_each_unless_carrying_a_haul() {
+        let (mut world, beds) = world();
+        let tired = || Needs {
+            hunger: 1.,
+            rest: TIRED / 2.,
+        };
+        let miner = spawn_agent(&mut world, IVec3::new(2, 4, 2), tired());
+        let idle = spawn_agent(&mut world, IVec3::new(3, 4, 2), tired());
+        let hauler = spawn_agent(&mut world, IVec3::new(4, 4, 2), tired());
+        let rested = spawn_agent(&mut world, IVec3::new(5, 4, 2), Needs::default());
+
+        let item = world.spawn_empty().id();
+        let (mine, haul) = world.resource_scope(|world, mut queue: Mut<JobQueue>| {
+            let terrain = world.resource::<Terrain>();
+            let mine = queue.push(terrain, JobKind::Mine, IVec3::new(8, 3, 8));
+            let haul = queue.push(
+                terrain,
+                JobKind::Haul {
+                    item,
+                    to: IVec3::new(9, 4, 9),
+                },
+                IVec3::new(6, 4, 6),
+            );
+            (mine.unwrap(), haul.unwrap())
+        });
+        let mut queue = world.resource_mut::<JobQueue>();
+        queue.claim(mine, miner);
+        queue.claim(haul, hauler);
+        queue.get_mut(haul).unwrap().picked_up = true;
+        world.entity_mut(miner).insert(AgentJob { job: mine });
+        world.entity_mut(hauler).insert(AgentJob { job: haul });
+
+        world.run_system_once(seek_needs);
+
+        let mut taken: Vec<_> = [miner, idle]
+            .map(|agent| match world.get::<Fulfilling>(agent) {
+                Some(Fulfilling::Sleep(bed)) => *bed,
+                other => panic!("{:?}", other),
+            })
+            .into();
+        taken.sort_by_key(|bed| bed.z);
+        assert_eq!(taken, beds);
+
+        // the miner gave its job back, the hauler kept going
+        let queue = world.resource::<JobQueue>();
+        assert_eq!(queue.get(mine).unwrap().claimed_by, None);
+        assert!(world.get::<AgentJob>(miner).is_none());
+        assert_eq!(queue.get(haul).unwrap().claimed_by, Some(hauler));
+        assert!(world.get::<Fulfilling>(hauler).is_none());
+        assert!(world.get::<Fulfilling>(rested).is_none());
+    }
+
+    #[test]
+    fn agents_eat_and_sleep_once_they_arrive() {
+        let (mut world, beds) = world();
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        let food = IVec3::new(6, 4, 6);
+        let fruit = world.spawn_empty().id();
+        world
+            .resource_mut::<Stockpiles>()
+            .store(food, fruit, Block::Fruit);
+
+        let hungry = Needs {
+            hunger: 0.1,
+            rest: 1.,
+        };
+        let eater = spawn_agent(&mut world, food + IVec3::X, hungry);
+        world.entity_mut(eater).insert(Fulfilling::Eat(food));
+        let sleeper = spawn_agent(
+            &mut world,
+            beds[0],
+            Needs {
+                hunger: 1.,
+                rest: 1. - REST_RECOVERY / 2.,
+            },
+        );
+        world.entity_mut(sleeper).insert(Fulfilling::Sleep(beds[0]));
+        let dozing = spawn_agent(
+            &mut world,
+            beds[1],
+            Needs {
+                hunger: 1.,
+                rest: 0.,
+            },
+        );
+        world.entity_mut(dozing).insert(Fulfilling::Sleep(beds[1]));
+        let lost = spawn_agent(&mut world, IVec3::new(2, 4, 2), Needs::default());
+        world.entity_mut(lost).insert(Fulfilling::Sleep(beds[1]));
+
+        world.run_system_once(fulfill_needs);
+
+        assert_eq!(world.get::<Needs>(eater).unwrap().hunger, 1.);
+        assert!(world.get_entity(fruit).is_none());
+        assert_eq!(world.resource::<Stockpiles>().count(Block::Fruit), 0);
+        assert!(world.get::<Fulfilling>(eater).is_none());
+
+        // a night's sleep ends once rested, an agent that never got to its
+        // bed gives up on it
+        assert_eq!(world.get::<Needs>(sleeper).unwrap().rest, 1.);
+        assert!(world.get::<Fulfilling>(sleeper).is_none());
+        assert_eq!(world.get::<Needs>(dozing).unwrap().rest, REST_RECOVERY);
+        assert!(world.get::<Fulfilling>(dozing).is_some());
+        assert!(world.get::<Fulfilling>(lost).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    build::is_placeable,
+    light::LightMap,
+    menu::{start_loading, AppState, WorldSource},
+    replay::Playback,
+    terrain::{check_size, Block, BlockChangedEvent, Terrain, TerrainModifiedEvent},
+    worldgen::WorldGenSettings,
+};
+
+use protocol::{Connection, Message};
+
+mod protocol;
+
+/// Runs the game as a server others can join, or joins one. The server owns
+/// the terrain and the simulation: clients send the edits they'd like made
+/// and get whole chunks back whenever one changes.
+pub struct NetPlugin {
+    /// Port to accept players on.
+    pub host: Option<u16>,
+    /// Server to join, as host:port.
+    pub connect: Option<String>,
+}
+
+/// Listens for players while hosting.
+#[derive(Resource)]
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<Connection>,
+}
+
+/// The connection to the server while joined to one.
+#[derive(Resource)]
+pub struct Client {
+    connection: Connection,
+    /// Chunks still to come before the map is complete, None until the
+    /// server said how many.
+    pending: Option<u32>,
+}
+
+impl Client {
+    /// Whether the whole map has arrived.
+    pub fn is_ready(&self) -> bool {
+        self.pending == Some(0)
+    }
+}
+
+/// Run condition for systems that advance the simulation, which only the
+/// server or a single player game does. Clients see its results through
+/// the chunks the server sends, a replay through the changes it recorded.
+pub fn is_authority(client: Option<Res<Client>>, playback: Option<Res<Playback>>) -> bool {
+    client.is_none() && playback.is_none()
+}
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(port) = self.host {
+            match host(port) {
+                Ok(server) => {
+                    println!("Hosting on port {}", port);
+                    app.insert_resource(server).add_systems(
+                        Update,
+                        (accept_clients, receive_edits, broadcast_changes)
+                            .chain()
+                            .run_if(in_state(AppState::InGame)),
+                    );
+                }
+                Err(err) => println!("Failed to host on port {}: {}", port, err),
+            }
+        }
+
+        if let Some(addr) = &self.connect {
+            match join(addr) {
+                Ok(client) => {
+                    println!("Joining {}", addr);
+                    app.insert_resource(client)
+                        .insert_resource(WorldSource::Remote)
+                        .add_systems(Startup, start_loading)
+                        .add_systems(
+                            Update,
+                            (receive_chunks, send_edits).chain().run_if(
+                                resource_exists::<Client>
+                                    .and_then(not(in_state(AppState::MainMenu))),
+                            ),
+                        );
+                }
+                Err(err) => println!("Failed to join {}: {}", addr, err),
+            }
+        }
+    }
+}
+
+fn host(port: u16) -> Result<Server, String> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|err| err.to_string())?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+
+    Ok(Server {
+        listener,
+        clients: vec![],
+    })
+}
+
+fn join(addr: &str) -> Result<Client, String> {
+    let stream = TcpStream::connect(addr).map_err(|err| err.to_string())?;
+
+    Ok(Client {
+        connection: Connection::new(stream)?,
+        pending: None,
+    })
+}
+
+/// Every chunk of the map, for a player that just joined.
+fn world_messages(terrain: &Terrain, settings: &WorldGenSettings) -> Vec<Message> {
+    let chunks: Vec<_> = terrain
+        .chunks()
+        .filter_map(|coord| {
+            let bytes = terrain.encode_chunk(coord)?;
+            Some(Message::Chunk { coord, bytes })
+        })
+        .collect();
+    let welcome = Message::Welcome {
+        seed: settings.seed,
+        size: terrain.size(),
+        slice: terrain.slice,
+        chunks: chunks.len() as u32,
+    };
+
+    std::iter::once(welcome).chain(chunks).collect()
+}
+
+fn accept_clients(
+    mut server: ResMut<Server>,
+    terrain: Res<Terrain>,
+    settings: Res<WorldGenSettings>,
+) {
+    loop {
+        let stream = match server.listener.accept() {
+            Ok((stream, addr)) => {
+                println!("Player joined from {}", addr);
+                stream
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => {
+                println!("Failed to accept a player: {}", err);
+                break;
+            }
+        };
+
+        match Connection::new(stream) {
+            Ok(mut connection) => {
+                for message in world_messages(&terrain, &settings) {
+                    connection.send(&message);
+                }
+                server.clients.push(connection);
+            }
+            Err(err) => println!("Failed to set up a player: {}", err),
+        }
+    }
+}
+
+/// Applies the edits players asked for, dropping anyone who disconnected.
+/// Only blocks build mode lays down, or clearing one, are taken, and only
+/// inside the map.
+fn receive_edits(mut server: ResMut<Server>, mut terrain: ResMut<Terrain>) {
+    server.clients.retain_mut(|client| match client.receive() {
+        Ok(messages) => {
+            for message in messages {
+                let Message::Edit { pos, block } = message else {
+                    continue;
+                };
+                if terrain.contains(pos) && (block == Block::Empty || is_placeable(block)) {
+                    terrain.set_at(pos, block);
+                } else {
+                    println!("Dropped a bad edit from a player: {} at {}", block, pos);
+                }
+            }
+            true
+        }
+        Err(err) => {
+            println!("Player left: {}", err);
+            false
+        }
+    });
+}
+
+/// Sends every chunk that changed to every player.
+fn broadcast_changes(
+    mut server: ResMut<Server>,
+    terrain: Res<Terrain>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    let chunks: HashSet<_> = ev_block_changed
+        .read()
+        .map(|ev| Terrain::chunk_of(ev.pos))
+        .collect();
+
+    let messages: Vec<_> = chunks
+        .into_iter()
+        .filter_map(|coord| {
+            let bytes = terrain.encode_chunk(coord)?;
+            Some(Message::Chunk { coord, bytes })
+        })
+        .collect();
+
+    server.clients.retain_mut(|client| {
+        for message in &messages {
+            client.send(message);
+        }
+        client.flush().is_ok()
+    });
+}
+
+/// Takes in the map while loading, then the chunks the server sends back
+/// after each change.
+fn receive_chunks(
+    mut commands: Commands,
+    mut client: ResMut<Client>,
+    mut terrain: ResMut<Terrain>,
+    mut settings: ResMut<WorldGenSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut light: Option<ResMut<LightMap>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let messages = match client.connection.receive() {
+        Ok(messages) => messages,
+        Err(err) => {
+            println!("Lost the server: {}", err);
+            commands.remove_resource::<Client>();
+            next_state.set(AppState::MainMenu);
+            return;
+        }
+    };
+
+    let mut changed = false;
+    for message in messages {
+        match message {
+            Message::Welcome {
+                seed,
+                size,
+                slice,
+                chunks,
+            } => {
+                if let Err(err) = check_size(size) {
+                    println!("Bad map from the server: {}", err);
+                    commands.remove_resource::<Client>();
+                    next_state.set(AppState::MainMenu);
+                    return;
+                }
+                *terrain = Terrain::new(size);
+                terrain.slice = slice.min(size.y as u16);
+                settings.seed = seed;
+                client.pending = Some(chunks);
+            }
+            Message::Chunk { coord, bytes } => {
+                if let Err(err) = terrain.decode_chunk(coord, &bytes) {
+                    println!("Bad chunk {} from the server: {}", coord, err);
+                }
+                if let Some(pending) = &mut client.pending {
+                    *pending = pending.saturating_sub(1);
+                }
+                changed = true;
+            }
+            Message::Edit { .. } => {}
+        }
+    }
+
+    if changed {
+        // the chunks come in whole, without a block change to relight around
+        if let Some(light) = &mut light {
+            light.reset();
+        }
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+/// Asks the server to make the edits made here. Chunks coming back from the
+/// server don't raise block changes, so they aren't echoed.
+fn send_edits(mut client: ResMut<Client>, mut ev_block_changed: EventReader<BlockChangedEvent>) {
+    for ev in ev_block_changed.read() {
+        client.connection.send(&Message::Edit {
+            pos: ev.pos,
+            block: ev.block,
+        });
+    }
+
+    if let Err(err) = client.connection.flush() {
+        println!("Failed to reach the server: {}", err);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use bevy::prelude::*;
+
+use crate::terrain::Block;
+
+/// What travels between server and clients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// First thing a client hears, `chunks` more messages bring the map.
+    Welcome {
+        seed: u64,
+        size: IVec3,
+        slice: u16,
+        chunks: u32,
+    },
+    /// A whole chunk in the compact chunk encoding, on joining and whenever
+    /// anything in it changes.
+    Chunk { coord: IVec3, bytes: Vec<u8> },
+    /// A client asking for a block to change. The server decides.
+    Edit { pos: IVec3, block: Block },
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        let put_ivec3 = |bytes: &mut Vec<u8>, v: IVec3| {
+            for n in v.to_array() {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+        };
+
+        match self {
+            Message::Welcome {
+                seed,
+                size,
+                slice,
+                chunks,
+            } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&seed.to_le_bytes());
+                put_ivec3(&mut bytes, *size);
+                bytes.extend_from_slice(&slice.to_le_bytes());
+                bytes.extend_from_slice(&chunks.to_le_bytes());
+            }
+            Message::Chunk { coord, bytes: data } => {
+                bytes.push(1);
+                put_ivec3(&mut bytes, *coord);
+                bytes.extend_from_slice(data);
+            }
+            Message::Edit { pos, block } => {
+                bytes.push(2);
+                put_ivec3(&mut bytes, *pos);
+                bytes.extend_from_slice(&block.id().to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Message, String> {
+        let mut reader = Reader { rest: bytes };
+
+        let message = match reader.take::<1>()?[0] {
+            0 => Message::Welcome {
+                seed: u64::from_le_bytes(reader.take()?),
+                size: reader.ivec3()?,
+                slice: u16::from_le_bytes(reader.take()?),
+                chunks: u32::from_le_bytes(reader.take()?),
+            },
+            1 => Message::Chunk {
+                coord: reader.ivec3()?,
+                bytes: reader.rest.to_vec(),
+            },
+            2 => {
+                let pos = reader.ivec3()?;
+                let id = u16::from_le_bytes(reader.take()?);
+                let block = Block::from_id(id).ok_or_else(|| format!("unknown block id {}", id))?;
+                Message::Edit { pos, block }
+            }
+            tag => return Err(format!("unknown message {}", tag)),
+        };
+
+        Ok(message)
+    }
+}
+
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.rest.len() < N {
+            return Err("message ends early".to_string());
+        }
+        let (head, tail) = self.rest.split_at(N);
+        self.rest = tail;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn ivec3(&mut self) -> Result<IVec3, String> {
+        let mut v = [0; 3];
+        for n in &mut v {
+            *n = i32::from_le_bytes(self.take()?);
+        }
+        Ok(IVec3::from_array(v))
+    }
+}
+
+/// Largest message a peer may send. A whole chunk is far smaller, so a
+/// longer length is a broken or hostile peer rather than a big message.
+const MAX_MESSAGE: usize = 1 << 20;
+
+/// A non-blocking stream of length-prefixed messages. Writes that the
+/// socket can't take yet wait in the outbox for the next flush.
+pub struct Connection {
+    stream: TcpStream,
+    inbox: Vec<u8>,
+    outbox: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Self, String> {
+        stream
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        stream.set_nodelay(true).map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            stream,
+            inbox: vec![],
+            outbox: vec![],
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) {
+        let bytes = message.encode();
+        self.outbox
+            .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.outbox.extend_from_slice(&bytes);
+    }
+
+    /// Writes as much of the outbox as the socket takes.
+    pub fn flush(&mut self) -> Result<(), String> {
+        while !self.outbox.is_empty() {
+            match self.stream.write(&self.outbox) {
+                Ok(0) => return Err("connection closed".to_string()),
+                Ok(n) => {
+                    self.outbox.drain(..n);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Every whole message that has arrived since the last call.
+    pub fn receive(&mut self) -> Result<Vec<Message>, String> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err("connection closed".to_string()),
+                Ok(n) => self.inbox.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+
+        let mut messages = vec![];
+        while self.inbox.len() >= 4 {
+            let len = u32::from_le_bytes(self.inbox[..4].try_into().unwrap()) as usize;
+            if len > MAX_MESSAGE {
+                return Err(format!("message of {} bytes is too long", len));
+            }
+            if self.inbox.len() < 4 + len {
+                break;
+            }
+            messages.push(Message::decode(&self.inbox[4..4 + len])?);
+            self.inbox.drain(..4 + len);
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn roundtrip(message: Message) {
+        let bytes = message.encode();
+        assert_eq!(Message::decode(&bytes), Ok(message.clone()));
+
+        // cut short anywhere before the end, other than the open ended chunk
+        // bytes
+        let whole = match &message {
+            Message::Chunk { bytes: data, .. } => bytes.len() - data.len(),
+            _ => bytes.len(),
+        };
+        for len in 0..whole {
+            assert!(
+                Message::decode(&bytes[..len]).is_err(),
+                "{:?} {}",
+                message,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        roundtrip(Message::Welcome {
+            seed: 0xdead_beef_cafe,
+            size: IVec3::new(256, 128, -3),
+            slice: 90,
+            chunks: 512,
+        });
+        roundtrip(Message::Chunk {
+            coord: IVec3::new(-1, 2, 30),
+            bytes: vec![2, 1, 0, 1, 7, 0],
+        });
+        roundtrip(Message::Chunk {
+            coord: IVec3::ZERO,
+            bytes: vec![],
+        });
+        roundtrip(Message::Edit {
+            pos: IVec3::new(5, -6, 7),
+            block: Block::Stone,
+        });
+        assert!(Message::decode(&[9]).is_err());
+    }
+
+    fn pair() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let near = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (far, _) = listener.accept().unwrap();
+        (Connection::new(near).unwrap(), far)
+    }
+
+    /// Reads until something arrives, the far end's writes land eventually.
+    fn receive(connection: &mut Connection) -> Result<Vec<Message>, String> {
+        for _ in 0..200 {
+            let messages = connection.receive()?;
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        Ok(vec![])
+    }
+
+    #[test]
+    fn frames_wait_until_whole() {
+        let (mut connection, mut far) = pair();
+        let message = Message::Edit {
+            pos: IVec3::new(1, 2, 3),
+            block: Block::Dirt,
+        };
+        let bytes = message.encode();
+        let mut frame = (bytes.len() as u32).to_le_bytes().to_vec();
+        frame.extend_from_slice(&bytes);
+
+        // a frame cut short is held until the rest comes
+        far.write_all(&frame[..frame.len() - 2]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(connection.receive(), Ok(vec![]));
+        far.write_all(&frame[frame.len() - 2..]).unwrap();
+        assert_eq!(receive(&mut connection), Ok(vec![message]));
+    }
+
+    #[test]
+    fn oversized_frames_drop_the_connection() {
+        let (mut connection, mut far) = pair();
+        far.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        assert!(receive(&mut connection).is_err());
+    }
+}
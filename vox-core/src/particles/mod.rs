@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    menu::AppState,
+    terrain::{tile_color, BlockChangedEvent, BlockShape, FaceDir, Terrain, TerrainMesh},
+};
+
+pub struct ParticlePlugin;
+
+const BURST_SIZE: u32 = 12;
+const LIFETIME: f32 = 0.8;
+const GRAVITY: f32 = 14.;
+const PARTICLE_SIZE: f32 = 0.12;
+/// Broken blocks that burst apart in a frame at most, the rest of a big
+/// crater vanishes quietly rather than burying the scene in particles.
+const MAX_BREAK_BURSTS: usize = 64;
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    /// Downward acceleration, negative for embers that drift up.
+    gravity: f32,
+    age: f32,
+}
+
+/// Asks for a burst of particles from other systems.
+#[derive(Event)]
+pub struct ParticleBurstEvent {
+    pub center: Vec3,
+    pub material: Handle<StandardMaterial>,
+    pub count: u32,
+    pub gravity: f32,
+}
+
+#[derive(Resource)]
+struct ParticleAssets {
+    mesh: Handle<Mesh>,
+    /// Material per terrain texture, tinted with the tile's average color.
+    materials: HashMap<u32, Handle<StandardMaterial>>,
+    /// State of the xorshift generator scattering the bursts.
+    seed: u32,
+}
+
+impl ParticleAssets {
+    fn next_f32(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ParticleBurstEvent>()
+            .add_systems(Startup, setup_particles)
+            .add_systems(
+                Update,
+                (spawn_break_particles, spawn_bursts, update_particles)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+fn setup_particles(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ParticleAssets {
+        mesh: meshes.add(Cuboid::new(PARTICLE_SIZE, PARTICLE_SIZE, PARTICLE_SIZE)),
+        materials: HashMap::new(),
+        seed: 0x9e37_79b9,
+    });
+}
+
+fn spawn_break_particles(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    images: Res<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut assets: ResMut<ParticleAssets>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    let mut bursts = 0;
+    for ev in ev_block_changed.read() {
+        let shape = ev.previous.def().shape;
+        if ev.block.is_solid()
+            || matches!(shape, BlockShape::None | BlockShape::Custom)
+            || ev.pos.y >= terrain.slice as i32
+            || bursts == MAX_BREAK_BURSTS
+        {
+            continue;
+        }
+        bursts += 1;
+
+        let texture_id = ev.previous.texture_id(FaceDir::PosX);
+        let material = match assets.materials.get(&texture_id) {
+            Some(material) => material.clone(),
+            None => {
+                let Some(color) = images
+                    .get(&terrain_mesh.texture)
+                    .and_then(|image| tile_color(image, texture_id))
+                else {
+                    continue;
+                };
+                let material = materials.add(color);
+                assets.materials.insert(texture_id, material.clone());
+                material
+            }
+        };
+
+        let center = ev.pos.as_vec3() + Vec3::splat(0.5);
+        spawn_burst(
+            &mut commands,
+            &mut assets,
+            center,
+            &material,
+            BURST_SIZE,
+            GRAVITY,
+        );
+    }
+}
+
+fn spawn_bursts(
+    mut commands: Commands,
+    mut assets: ResMut<ParticleAssets>,
+    mut ev_burst: EventReader<ParticleBurstEvent>,
+) {
+    for ev in ev_burst.read() {
+        spawn_burst(
+            &mut commands,
+            &mut assets,
+            ev.center,
+            &ev.material,
+            ev.count,
+            ev.gravity,
+        );
+    }
+}
+
+fn spawn_burst(
+    commands: &mut Commands,
+    assets: &mut ParticleAssets,
+    center: Vec3,
+    material: &Handle<StandardMaterial>,
+    count: u32,
+    gravity: f32,
+) {
+    for _ in 0..count {
+        let offset = Vec3::new(
+            assets.next_f32() - 0.5,
+            assets.next_f32() - 0.5,
+            assets.next_f32() - 0.5,
+        );
+        let velocity = offset * 4. + Vec3::Y * (2. + assets.next_f32() * 2.);
+
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(center + offset * 0.6),
+                ..default()
+            },
+            Particle {
+                velocity,
+                gravity,
+                age: 0.,
+            },
+        ));
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.age += dt;
+        if particle.age >= LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity.y -= particle.gravity * dt;
+        transform.translation += particle.velocity * dt;
+        transform.scale = Vec3::splat(1. - particle.age / LIFETIME);
+    }
+}
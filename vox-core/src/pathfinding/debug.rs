@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+use super::{find_path_traced, SearchTrace};
+use crate::{
+    agent::{Agent, AgentPath},
+    camera::CameraRay,
+    menu::AppState,
+    terrain::Terrain,
+};
+
+/// How far away a block can be picked for a query.
+const PICK_REACH: f32 = 64.;
+
+const PATH_COLOR: Color = Color::YELLOW;
+const AGENT_PATH_COLOR: Color = Color::CYAN;
+const CLOSED_COLOR: Color = Color::rgba(1., 0.3, 0.2, 0.6);
+const OPEN_COLOR: Color = Color::rgba(0.3, 1., 0.3, 0.6);
+
+/// F4 turns on the path debug view: the route of every agent, and the cells
+/// the last search expanded and left queued. While it's on, middle clicking
+/// two blocks searches from the first to the second.
+pub struct PathDebugPlugin;
+
+/// State of the path debug view.
+#[derive(Resource, Default)]
+pub struct PathDebug {
+    pub enabled: bool,
+    /// The last search made while the view was on.
+    pub last: Option<PathQuery>,
+    /// The start of a query being picked, waiting on its goal.
+    picked_start: Option<IVec3>,
+}
+
+impl PathDebug {
+    /// Whether searches should be traced and handed in with `record`.
+    /// Picked queries stay up until the next one is picked.
+    pub fn wants_trace(&self) -> bool {
+        self.enabled && !self.last.as_ref().is_some_and(|query| query.picked)
+    }
+
+    pub fn record(&mut self, query: PathQuery) {
+        self.last = Some(query);
+    }
+}
+
+/// A traced search.
+#[derive(Debug, Clone)]
+pub struct PathQuery {
+    pub start: IVec3,
+    pub goal: IVec3,
+    pub path: Option<Vec<IVec3>>,
+    pub trace: SearchTrace,
+    /// Picked by hand rather than made by an agent.
+    pub picked: bool,
+}
+
+impl Plugin for PathDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PathDebug>().add_systems(
+            Update,
+            (toggle_path_debug, pick_query, draw_path_debug)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn toggle_path_debug(keys: Res<ButtonInput<KeyCode>>, mut debug: ResMut<PathDebug>) {
+    if keys.just_pressed(KeyCode::F4) {
+        debug.enabled = !debug.enabled;
+        debug.last = None;
+        debug.picked_start = None;
+        println!(
+            "Path debug view: {}",
+            if debug.enabled { "on" } else { "off" }
+        );
+    }
+}
+
+/// The first middle click picks where the query starts, the second where it
+/// goes. Each is the open cell in front of the clicked face.
+fn pick_query(
+    buttons: Res<ButtonInput<MouseButton>>,
+    terrain: Res<Terrain>,
+    camera_ray: Res<CameraRay>,
+    mut debug: ResMut<PathDebug>,
+) {
+    if !debug.enabled || !buttons.just_pressed(MouseButton::Middle) {
+        return;
+    }
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+    let Some(hit) = terrain.raycast(ray.origin, *ray.direction, PICK_REACH) else {
+        return;
+    };
+    let cell = hit.pos + hit.normal;
+
+    let Some(start) = debug.picked_start.take() else {
+        debug.picked_start = Some(cell);
+        return;
+    };
+
+    let mut trace = SearchTrace::default();
+    let path = find_path_traced(&terrain, start, cell, |pos| pos == cell, &mut trace);
+    println!(
+        "Path from {} to {}: {} after expanding {} cells",
+        start,
+        cell,
+        path.as_ref()
+            .map_or("none".to_string(), |path| format!("{} steps", path.len())),
+        trace.closed.len()
+    );
+    debug.record(PathQuery {
+        start,
+        goal: cell,
+        path,
+        trace,
+        picked: true,
+    });
+}
+
+fn cell_center(pos: IVec3) -> Vec3 {
+    pos.as_vec3() + Vec3::splat(0.5)
+}
+
+fn mark_cell(gizmos: &mut Gizmos, pos: IVec3, size: f32, color: Color) {
+    gizmos.cuboid(
+        Transform::from_translation(cell_center(pos)).with_scale(Vec3::splat(size)),
+        color,
+    );
+}
+
+fn draw_path_debug(
+    mut gizmos: Gizmos,
+    debug: Res<PathDebug>,
+    agents: Query<(&Transform, &AgentPath), With<Agent>>,
+) {
+    if !debug.enabled {
+        return;
+    }
+
+    for (transform, path) in agents.iter() {
+        if path.cells.is_empty() {
+            continue;
+        }
+        let points = std::iter::once(transform.translation)
+            .chain(path.cells.iter().map(|cell| cell_center(*cell)));
+        gizmos.linestrip(points, AGENT_PATH_COLOR);
+    }
+
+    if let Some(start) = debug.picked_start {
+        mark_cell(&mut gizmos, start, 1.02, PATH_COLOR);
+    }
+
+    let Some(query) = &debug.last else {
+        return;
+    };
+    for pos in &query.trace.closed {
+        mark_cell(&mut gizmos, *pos, 0.3, CLOSED_COLOR);
+    }
+    for pos in &query.trace.open {
+        mark_cell(&mut gizmos, *pos, 0.3, OPEN_COLOR);
+    }
+    mark_cell(&mut gizmos, query.start, 1.02, PATH_COLOR);
+    mark_cell(&mut gizmos, query.goal, 1.02, PATH_COLOR);
+    if let Some(path) = &query.path {
+        let points = std::iter::once(query.start)
+            .chain(path.iter().copied())
+            .map(cell_center);
+        gizmos.linestrip(points, PATH_COLOR);
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy::math::IVec3;
+
+use crate::terrain::{Facing, Terrain};
+
+mod debug;
+
+pub use debug::{PathDebug, PathDebugPlugin, PathQuery};
+
+/// Upper bound on expanded nodes so an unreachable goal can't stall a frame.
+const MAX_VISITED: usize = 8192;
+/// Cost of stepping up or down a plain one-block ledge, dearer than the 14
+/// of a ramp so agents take one when it's close by.
+const LEDGE_COST: u32 = 20;
+
+/// The cells an A* search reached, for drawing it.
+#[derive(Debug, Clone, Default)]
+pub struct SearchTrace {
+    /// Cells expanded, in the order they were.
+    pub closed: Vec<IVec3>,
+    /// Cells found but still queued when the search ended.
+    pub open: Vec<IVec3>,
+}
+
+/// A cell an agent can stand in: open itself, with a filled block beneath it
+/// or a ladder to hold on to.
+pub fn is_walkable(terrain: &Terrain, pos: IVec3) -> bool {
+    !terrain.get_at(pos).is_filled()
+        && (terrain.get_at(pos - IVec3::Y).is_filled() || is_ladder(terrain, pos))
+}
+
+fn is_ladder(terrain: &Terrain, pos: IVec3) -> bool {
+    terrain.get_at(pos).def().shape.is_climbable()
+}
+
+fn incline(terrain: &Terrain, pos: IVec3) -> Option<Facing> {
+    terrain.get_at(pos).def().shape.incline()
+}
+
+fn neighbors(terrain: &Terrain, pos: IVec3) -> Vec<(IVec3, u32)> {
+    let mut result = vec![];
+    let head_clear = !terrain.get_at(pos + IVec3::Y).is_filled();
+
+    // climb up and down ladders
+    if is_ladder(terrain, pos) && is_walkable(terrain, pos + IVec3::Y) {
+        result.push((pos + IVec3::Y, 10));
+    }
+
+    if is_ladder(terrain, pos - IVec3::Y) {
+        result.push((pos - IVec3::Y, 10));
+    }
+
+    for facing in Facing::ALL {
+        let side = pos + facing.offset();
+
+        if is_walkable(terrain, side) {
+            result.push((side, 10));
+        }
+
+        // walk up a ramp or stairs onto the ledge it leads to, or step up
+        // onto a ledge one block high, which is slower
+        let up = side + IVec3::Y;
+        if incline(terrain, pos) == Some(facing) && is_walkable(terrain, up) {
+            result.push((up, 14));
+        } else if head_clear && terrain.get_at(side).is_filled() && is_walkable(terrain, up) {
+            result.push((up, LEDGE_COST));
+        }
+
+        // walk down a ramp or stairs that rises toward this cell, or step
+        // down off a ledge one block high
+        let down = side - IVec3::Y;
+        if incline(terrain, down) == Some(facing.opposite()) && is_walkable(terrain, down) {
+            result.push((down, 14));
+        } else if !terrain.get_at(side).is_filled()
+            && !is_walkable(terrain, side)
+            && is_walkable(terrain, down)
+        {
+            result.push((down, LEDGE_COST));
+        }
+    }
+
+    result
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct OpenNode {
+    pos: IVec3,
+    cost: u32,
+    estimate: u32,
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| other.cost.cmp(&self.cost))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A* search from `start` to the first walkable cell accepted by `is_goal`.
+/// `target` is only used as the heuristic anchor. Returns the cells to walk
+/// through, excluding `start`.
+pub fn find_path(
+    terrain: &Terrain,
+    start: IVec3,
+    target: IVec3,
+    is_goal: impl Fn(IVec3) -> bool,
+) -> Option<Vec<IVec3>> {
+    search(terrain, start, target, is_goal, None)
+}
+
+/// Like `find_path`, also filling `trace` with the cells the search went
+/// through.
+pub fn find_path_traced(
+    terrain: &Terrain,
+    start: IVec3,
+    target: IVec3,
+    is_goal: impl Fn(IVec3) -> bool,
+    trace: &mut SearchTrace,
+) -> Option<Vec<IVec3>> {
+    search(terrain, start, target, is_goal, Some(trace))
+}
+
+fn search(
+    terrain: &Terrain,
+    start: IVec3,
+    target: IVec3,
+    is_goal: impl Fn(IVec3) -> bool,
+    mut trace: Option<&mut SearchTrace>,
+) -> Option<Vec<IVec3>> {
+    let heuristic = |p: IVec3| {
+        let d = (p - target).abs();
+        (d.x + d.y + d.z) as u32 * 10
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<IVec3, IVec3> = HashMap::new();
+    let mut costs: HashMap<IVec3, u32> = HashMap::new();
+
+    open.push(OpenNode {
+        pos: start,
+        cost: 0,
+        estimate: heuristic(start),
+    });
+    costs.insert(start, 0);
+
+    if let Some(trace) = trace.as_deref_mut() {
+        trace.closed.clear();
+    }
+
+    let path = loop {
+        let Some(current) = open.pop() else {
+            break None;
+        };
+
+        if is_goal(current.pos) {
+            let mut path = vec![current.pos];
+            let mut cursor = current.pos;
+            while let Some(prev) = came_from.get(&cursor) {
+                if *prev == start {
+                    break;
+                }
+                path.push(*prev);
+                cursor = *prev;
+            }
+            path.reverse();
+            if current.pos == start {
+                path.clear();
+            }
+            break Some(path);
+        }
+
+        if costs.len() > MAX_VISITED {
+            break None;
+        }
+
+        if current.cost > *costs.get(&current.pos).unwrap_or(&u32::MAX) {
+            continue;
+        }
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.closed.push(current.pos);
+        }
+
+        for (next, step_cost) in neighbors(terrain, current.pos) {
+            let cost = current.cost + step_cost;
+            if cost < *costs.get(&next).unwrap_or(&u32::MAX) {
+                costs.insert(next, cost);
+                came_from.insert(next, current.pos);
+                open.push(OpenNode {
+                    pos: next,
+                    cost,
+                    estimate: cost + heuristic(next),
+                });
+            }
+        }
+    };
+
+    if let Some(trace) = trace {
+        let closed: HashSet<_> = trace.closed.iter().collect();
+        trace.open = costs
+            .keys()
+            .filter(|pos| !closed.contains(pos))
+            .copied()
+            .collect();
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{
+        fixtures::{fill, ground},
+        Block,
+    };
+
+    #[test]
+    fn traced_search_finds_the_same_path() {
+        let mut terrain = ground(IVec3::splat(16), 1, Block::Stone);
+        for z in 0..12 {
+            terrain.set_at(IVec3::new(6, 1, z), Block::Stone);
+        }
+        let (start, goal) = (IVec3::new(2, 1, 2), IVec3::new(10, 1, 2));
+
+        let mut trace = SearchTrace::default();
+        let traced = find_path_traced(&terrain, start, goal, |p| p == goal, &mut trace);
+        let path = find_path(&terrain, start, goal, |p| p == goal).unwrap();
+        assert_eq!(traced.as_ref(), Some(&path));
+        assert_eq!(path.last(), Some(&goal));
+
+        // every step but the goal was expanded on the way
+        assert_eq!(trace.closed[0], start);
+        for pos in &path[..path.len() - 1] {
+            assert!(trace.closed.contains(pos));
+        }
+        assert!(trace.open.iter().all(|pos| !trace.closed.contains(pos)));
+    }
+
+    #[test]
+    fn paths_step_over_plain_ledges() {
+        let mut terrain = ground(IVec3::splat(16), 1, Block::Stone);
+        // a one-block ledge across the whole map, with no ramp onto it
+        fill(
+            &mut terrain,
+            IVec3::new(6, 1, 0),
+            IVec3::new(16, 2, 16),
+            Block::Stone,
+        );
+        let (start, goal) = (IVec3::new(2, 1, 2), IVec3::new(10, 2, 2));
+
+        let path = find_path(&terrain, start, goal, |p| p == goal).unwrap();
+        assert!(path.contains(&IVec3::new(6, 2, 2)));
+        assert_eq!(path.last(), Some(&goal));
+
+        let back = find_path(&terrain, goal, start, |p| p == start).unwrap();
+        assert!(back.contains(&IVec3::new(5, 1, 2)));
+        assert_eq!(back.last(), Some(&start));
+    }
+}
@@ -0,0 +1,241 @@
+use bevy::{pbr::wireframe::Wireframe, prelude::*, transform::TransformSystem};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::FlyCamera,
+    input::{Action, ActionState},
+    menu::AppState,
+    terrain::{Terrain, TerrainModifiedEvent},
+};
+
+/// F12 by default turns on a photo mode for recording the world: the
+/// interface and the gizmos are hidden, the camera circles the middle of the
+/// map at a steady pace, and the slice can step down layer by layer to show
+/// what's inside. Pressing it again puts the camera, the slice and the
+/// interface back.
+pub struct PhotoModePlugin;
+
+/// How the photo mode films, kept in the settings file.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhotoSettings {
+    /// Degrees the camera circles a second.
+    pub orbit_speed: f32,
+    /// Hide meshes drawn as wireframes too.
+    pub hide_wireframe: bool,
+    /// Seconds between steps of the slice down a layer, 0 to leave it.
+    pub slice_interval: f32,
+}
+
+impl Default for PhotoSettings {
+    fn default() -> Self {
+        Self {
+            orbit_speed: 10.,
+            hide_wireframe: true,
+            slice_interval: 0.,
+        }
+    }
+}
+
+/// Where the camera circles, and what to put back afterwards. None while
+/// the photo mode is off.
+#[derive(Resource, Default)]
+pub struct PhotoMode(Option<Orbit>);
+
+impl PhotoMode {
+    pub fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+struct Orbit {
+    focus: Vec3,
+    radius: f32,
+    /// Height of the camera above the focus.
+    height: f32,
+    /// Radians around the focus.
+    angle: f32,
+    /// Seconds since the slice last stepped down.
+    slice_timer: f32,
+    /// Camera and slice from before, to go back to.
+    camera: Transform,
+    slice: u16,
+    /// Entities hidden, with how they were shown.
+    hidden: Vec<(Entity, Visibility)>,
+}
+
+impl Orbit {
+    /// Where the camera is at the current angle.
+    fn eye(&self) -> Vec3 {
+        self.focus
+            + Vec3::new(
+                self.radius * self.angle.cos(),
+                self.height,
+                self.radius * self.angle.sin(),
+            )
+    }
+}
+
+impl Plugin for PhotoModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhotoSettings>()
+            .init_resource::<PhotoMode>()
+            .add_systems(Update, toggle_photo_mode.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                PostUpdate,
+                // after anything else has moved the camera this frame
+                orbit_camera
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), reset_photo_mode);
+    }
+}
+
+/// Where the camera circles from `eye`: the middle of the map at half its
+/// height, at the distance and height the camera is at already.
+fn start_orbit(terrain: &Terrain, eye: Transform) -> Orbit {
+    let focus = terrain.size().as_vec3() / 2.;
+    let offset = eye.translation - focus;
+    let radius = offset
+        .xz()
+        .length()
+        .max(terrain.size().as_vec3().xz().length() / 2.);
+
+    Orbit {
+        focus,
+        radius,
+        height: offset.y,
+        angle: offset.z.atan2(offset.x),
+        slice_timer: 0.,
+        camera: eye,
+        slice: terrain.slice,
+        hidden: vec![],
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn toggle_photo_mode(
+    actions: Res<ActionState>,
+    settings: Res<PhotoSettings>,
+    mut photo: ResMut<PhotoMode>,
+    mut terrain: ResMut<Terrain>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut cameras: Query<&mut Transform, With<FlyCamera>>,
+    mut shown: Query<
+        (Entity, &mut Visibility),
+        Or<((With<Node>, Without<Parent>), With<Wireframe>)>,
+    >,
+    wireframes: Query<(), With<Wireframe>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if !actions.just_pressed(Action::TogglePhotoMode) {
+        return;
+    }
+    let Ok(mut camera) = cameras.get_single_mut() else {
+        return;
+    };
+    let (gizmo_config, _) = gizmos.config_mut::<DefaultGizmoConfigGroup>();
+
+    match photo.0.take() {
+        None => {
+            let mut orbit = start_orbit(&terrain, *camera);
+            for (entity, mut visibility) in shown.iter_mut() {
+                if !settings.hide_wireframe && wireframes.contains(entity) {
+                    continue;
+                }
+                orbit.hidden.push((entity, *visibility));
+                *visibility = Visibility::Hidden;
+            }
+            gizmo_config.enabled = false;
+            photo.0 = Some(orbit);
+            println!("Photo mode: on");
+        }
+        Some(orbit) => {
+            for (entity, visibility) in orbit.hidden {
+                if let Ok((_, mut shown)) = shown.get_mut(entity) {
+                    *shown = visibility;
+                }
+            }
+            gizmo_config.enabled = true;
+            *camera = orbit.camera;
+            if terrain.slice != orbit.slice {
+                terrain.set_slice(orbit.slice);
+                ev_terrain_mod.send(TerrainModifiedEvent);
+            }
+            println!("Photo mode: off");
+        }
+    }
+}
+
+/// Circles the camera around the focus, looking at it, and steps the slice
+/// down until it reaches the bottom. Goes by real time, so the footage
+/// keeps its pace while the world is paused.
+fn orbit_camera(
+    time: Res<Time<Real>>,
+    settings: Res<PhotoSettings>,
+    mut photo: ResMut<PhotoMode>,
+    mut terrain: ResMut<Terrain>,
+    mut cameras: Query<&mut Transform, With<FlyCamera>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let Some(orbit) = photo.0.as_mut() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+
+    orbit.angle += settings.orbit_speed.to_radians() * dt;
+    let eye = orbit.eye();
+    for mut transform in cameras.iter_mut() {
+        *transform = Transform::from_translation(eye).looking_at(orbit.focus, Vec3::Y);
+    }
+
+    if settings.slice_interval <= 0. || terrain.slice <= 1 {
+        return;
+    }
+    orbit.slice_timer += dt;
+    if orbit.slice_timer >= settings.slice_interval {
+        orbit.slice_timer -= settings.slice_interval;
+        let slice = terrain.slice - 1;
+        terrain.set_slice(slice);
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+/// Whatever was hidden that outlasts the world is shown again, the camera
+/// and slice go with the world.
+fn reset_photo_mode(
+    mut photo: ResMut<PhotoMode>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut shown: Query<&mut Visibility>,
+) {
+    let Some(orbit) = photo.0.take() else {
+        return;
+    };
+    for (entity, visibility) in orbit.hidden {
+        if let Ok(mut shown) = shown.get_mut(entity) {
+            *shown = visibility;
+        }
+    }
+    gizmos.config_mut::<DefaultGizmoConfigGroup>().0.enabled = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orbits_start_from_the_camera() {
+        let terrain = Terrain::new(IVec3::new(32, 16, 32));
+        let eye = Transform::from_xyz(-20., 30., 50.);
+
+        let orbit = start_orbit(&terrain, eye);
+        assert_eq!(orbit.focus, Vec3::new(16., 8., 16.));
+        assert!(orbit.eye().distance(eye.translation) < 1e-3);
+
+        // too close in to take the whole map in, so it backs off
+        let orbit = start_orbit(&terrain, Transform::from_xyz(17., 20., 16.));
+        assert!(orbit.radius > 22.);
+        assert_eq!(orbit.eye().y, 20.);
+    }
+}
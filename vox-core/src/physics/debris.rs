@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+
+use crate::terrain::{tile_color, BlockChangedEvent, FaceDir, Terrain, TerrainMesh};
+
+const PIECE_SIZE: f32 = 0.25;
+/// Seconds a piece tumbles around before it's cleared away.
+const LIFETIME: f32 = 6.;
+/// Broken blocks that fall apart in a frame at most, so a crater doesn't
+/// bring the physics to a crawl.
+const MAX_BROKEN_PER_FRAME: usize = 32;
+
+/// Whether broken blocks fall apart into pieces that roll off. Purely for
+/// looks, so it can be turned off on slow machines.
+#[derive(Resource)]
+pub struct DebrisSettings {
+    pub enabled: bool,
+    /// Pieces a broken block splits into.
+    pub pieces: u32,
+}
+
+impl Default for DebrisSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pieces: 4,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Debris {
+    age: f32,
+}
+
+#[derive(Resource)]
+pub struct DebrisAssets {
+    mesh: Handle<Mesh>,
+    /// Material per terrain texture, tinted with the tile's average color.
+    materials: HashMap<u32, Handle<StandardMaterial>>,
+}
+
+pub fn setup_debris(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(DebrisAssets {
+        mesh: meshes.add(Cuboid::new(PIECE_SIZE, PIECE_SIZE, PIECE_SIZE)),
+        materials: HashMap::new(),
+    });
+}
+
+/// Scatters a few rigid pieces from every block that broke into open air.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_debris(
+    mut commands: Commands,
+    settings: Res<DebrisSettings>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    images: Res<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut assets: ResMut<DebrisAssets>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    if !settings.enabled {
+        ev_block_changed.clear();
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut broken = 0;
+    for ev in ev_block_changed.read() {
+        if ev.block.is_solid()
+            || ev.previous.collision_height().is_none()
+            || ev.pos.y >= terrain.slice as i32
+            || broken == MAX_BROKEN_PER_FRAME
+        {
+            continue;
+        }
+        broken += 1;
+
+        let texture_id = ev.previous.texture_id(FaceDir::PosX);
+        let material = match assets.materials.get(&texture_id) {
+            Some(material) => material.clone(),
+            None => {
+                let Some(color) = images
+                    .get(&terrain_mesh.texture)
+                    .and_then(|image| tile_color(image, texture_id))
+                else {
+                    continue;
+                };
+                let material = materials.add(color);
+                assets.materials.insert(texture_id, material.clone());
+                material
+            }
+        };
+
+        for _ in 0..settings.pieces {
+            let offset = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * (1. - PIECE_SIZE);
+            let spin = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 4. - 2.;
+            let push = Vec3::new(rng.gen::<f32>() - 0.5, 1., rng.gen::<f32>() - 0.5);
+
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(
+                        ev.pos.as_vec3() + offset + Vec3::splat(PIECE_SIZE / 2.),
+                    ),
+                    ..default()
+                },
+                RigidBody::Dynamic,
+                Collider::cuboid(PIECE_SIZE / 2., PIECE_SIZE / 2., PIECE_SIZE / 2.),
+                Velocity {
+                    linvel: push * 2.,
+                    angvel: spin,
+                },
+                Debris { age: 0. },
+            ));
+        }
+    }
+}
+
+pub fn age_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut debris: Query<(Entity, &mut Debris)>,
+) {
+    for (entity, mut piece) in debris.iter_mut() {
+        piece.age += time.delta_seconds();
+        if piece.age >= LIFETIME {
+            commands.entity(entity).despawn();
+        }
+    }
+}
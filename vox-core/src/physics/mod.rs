@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    menu::AppState,
+    terrain::{BlockChangedEvent, Terrain, CHUNK_SIZE},
+};
+
+use debris::{age_debris, setup_debris, spawn_debris, DebrisSettings};
+
+mod debris;
+
+pub struct PhysicsPlugin;
+
+/// Static collider of one chunk, rebuilt alongside its mesh.
+#[derive(Component)]
+struct TerrainCollider;
+
+/// Collider entity of each chunk that has anything solid in it.
+#[derive(Resource, Default)]
+struct ChunkColliders {
+    entities: HashMap<IVec3, Entity>,
+}
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+            .init_resource::<ChunkColliders>()
+            .init_resource::<DebrisSettings>()
+            .add_systems(Startup, setup_debris)
+            .add_systems(OnEnter(AppState::InGame), setup_colliders)
+            .add_systems(
+                Update,
+                (update_colliders, spawn_debris, age_debris).run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Boxes covering the solid cells of `chunk`, relative to its corner. Each
+/// column is merged into as few boxes as its runs of solid cells need.
+fn chunk_boxes(terrain: &Terrain, chunk: IVec3) -> Vec<(Vect, Rot, Collider)> {
+    let origin = chunk * CHUNK_SIZE;
+    let mut boxes = vec![];
+
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            // bottom of the run being built and the height it reached
+            let mut run: Option<(f32, f32)> = None;
+
+            for y in 0..=CHUNK_SIZE {
+                let height = if y < CHUNK_SIZE {
+                    terrain
+                        .get_at(origin + IVec3::new(x, y, z))
+                        .collision_height()
+                } else {
+                    None
+                };
+
+                run = match (run, height) {
+                    // only full blocks let the run carry on upward
+                    (Some((bottom, top)), Some(h)) if top == y as f32 => {
+                        Some((bottom, y as f32 + h))
+                    }
+                    (run, height) => {
+                        if let Some((bottom, top)) = run {
+                            let half = Vec3::new(0.5, (top - bottom) / 2., 0.5);
+                            let center = Vec3::new(x as f32, bottom, z as f32) + half;
+                            boxes.push((
+                                center,
+                                Rot::IDENTITY,
+                                Collider::cuboid(half.x, half.y, half.z),
+                            ));
+                        }
+                        height.map(|h| (y as f32, y as f32 + h))
+                    }
+                };
+            }
+        }
+    }
+
+    boxes
+}
+
+/// Replaces the collider of `chunk`, dropping it if nothing in the chunk is
+/// solid.
+fn rebuild_collider(
+    commands: &mut Commands,
+    terrain: &Terrain,
+    colliders: &mut ChunkColliders,
+    chunk: IVec3,
+) {
+    if let Some(entity) = colliders.entities.remove(&chunk) {
+        commands.entity(entity).despawn();
+    }
+
+    let boxes = chunk_boxes(terrain, chunk);
+    if boxes.is_empty() {
+        return;
+    }
+
+    let entity = commands
+        .spawn((
+            TransformBundle::from_transform(Transform::from_translation(
+                (chunk * CHUNK_SIZE).as_vec3(),
+            )),
+            RigidBody::Fixed,
+            Collider::compound(boxes),
+            TerrainCollider,
+        ))
+        .id();
+    colliders.entities.insert(chunk, entity);
+}
+
+fn setup_colliders(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut colliders: ResMut<ChunkColliders>,
+    existing: Query<Entity, With<TerrainCollider>>,
+) {
+    for entity in existing.iter() {
+        commands.entity(entity).despawn();
+    }
+    colliders.entities.clear();
+
+    for chunk in terrain.chunks() {
+        rebuild_collider(&mut commands, &terrain, &mut colliders, chunk);
+    }
+}
+
+/// Rebuilds the collider of every chunk a block changed in.
+fn update_colliders(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut colliders: ResMut<ChunkColliders>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+) {
+    let chunks: HashSet<_> = ev_block_changed
+        .read()
+        .map(|ev| Terrain::chunk_of(ev.pos))
+        .collect();
+
+    for chunk in chunks {
+        rebuild_collider(&mut commands, &terrain, &mut colliders, chunk);
+    }
+}
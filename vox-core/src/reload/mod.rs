@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use serde::Deserialize;
+
+use crate::{
+    light::LightMap,
+    menu::{AppState, WorldSource},
+    net::is_authority,
+    terrain::{set_overrides, BlockOverride, Terrain, TerrainModifiedEvent},
+    worldgen::{OreVein, Stratum, WorldGenPipeline, WorldGenSettings},
+};
+
+/// Changes to the built-in blocks, by name.
+const BLOCKS_FILE: &str = "assets/blocks.ron";
+/// Changes to the world generator's settings.
+const WORLDGEN_FILE: &str = "assets/worldgen.ron";
+
+/// How often the files are checked for changes.
+const POLL: Duration = Duration::from_secs(1);
+
+/// Applies `blocks.ron` and `worldgen.ron` at startup and again whenever
+/// either is saved. New block definitions remesh the whole map. New worldgen
+/// settings regenerate the world if no block in it has been changed since it
+/// was generated, other than by the world itself, and otherwise wait for the
+/// next world.
+pub struct ReloadPlugin;
+
+/// Fields of `WorldGenSettings` that can be set from a file, any left out
+/// keep their value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WorldGenConfig {
+    water_table: Option<i32>,
+    river_count: Option<u32>,
+    strata: Option<Vec<Stratum>>,
+    ores: Option<Vec<OreVein>>,
+}
+
+/// When each watched file was last seen modified.
+#[derive(Resource, Default)]
+struct Watched {
+    modified: HashMap<&'static str, SystemTime>,
+}
+
+impl Watched {
+    /// Whether the file at `path` changed since the last look. The first
+    /// look only notes the time.
+    fn changed(&mut self, path: &'static str) -> bool {
+        let Ok(time) = fs::metadata(path).and_then(|meta| meta.modified()) else {
+            return false;
+        };
+        self.modified
+            .insert(path, time)
+            .is_some_and(|last| last != time)
+    }
+}
+
+#[derive(Event)]
+struct WorldGenReloaded;
+
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        let mut watched = Watched::default();
+        watched.changed(BLOCKS_FILE);
+        watched.changed(WORLDGEN_FILE);
+
+        if let Err(err) = load_blocks() {
+            println!("Failed to load {}: {}", BLOCKS_FILE, err);
+        }
+        if let Some(mut settings) = app.world.get_resource_mut::<WorldGenSettings>() {
+            if let Err(err) = load_worldgen(&mut settings) {
+                println!("Failed to load {}: {}", WORLDGEN_FILE, err);
+            }
+        }
+
+        app.insert_resource(watched)
+            .add_event::<WorldGenReloaded>()
+            .add_systems(
+                Update,
+                (
+                    (reload_blocks, reload_worldgen).run_if(on_timer(POLL)),
+                    regenerate_world.run_if(in_state(AppState::InGame).and_then(is_authority)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Reads `BLOCKS_FILE` into the block registry. A missing file is fine and
+/// leaves the blocks as built.
+fn load_blocks() -> Result<(), String> {
+    let Ok(source) = fs::read_to_string(BLOCKS_FILE) else {
+        return Ok(());
+    };
+    let overrides: HashMap<String, BlockOverride> =
+        ron::from_str(&source).map_err(|err| err.to_string())?;
+    set_overrides(overrides)
+}
+
+/// Reads `WORLDGEN_FILE` into `settings`. A missing file is fine and leaves
+/// the settings as they are.
+fn load_worldgen(settings: &mut WorldGenSettings) -> Result<(), String> {
+    let Ok(source) = fs::read_to_string(WORLDGEN_FILE) else {
+        return Ok(());
+    };
+    let config: WorldGenConfig = ron::from_str(&source).map_err(|err| err.to_string())?;
+
+    if let Some(water_table) = config.water_table {
+        settings.water_table = water_table;
+    }
+    if let Some(river_count) = config.river_count {
+        settings.river_count = river_count;
+    }
+    if let Some(strata) = config.strata {
+        settings.strata = strata;
+    }
+    if let Some(ores) = config.ores {
+        settings.ores = ores;
+    }
+    Ok(())
+}
+
+fn reload_blocks(
+    mut watched: ResMut<Watched>,
+    mut terrain: ResMut<Terrain>,
+    mut light: Option<ResMut<LightMap>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if !watched.changed(BLOCKS_FILE) {
+        return;
+    }
+
+    match load_blocks() {
+        Ok(()) => {
+            println!("Reloaded {}", BLOCKS_FILE);
+            terrain.mark_all_dirty();
+            // blocks may give off a different light
+            if let Some(light) = &mut light {
+                light.reset();
+            }
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+        Err(err) => println!("Failed to reload {}: {}", BLOCKS_FILE, err),
+    }
+}
+
+fn reload_worldgen(
+    mut watched: ResMut<Watched>,
+    mut settings: ResMut<WorldGenSettings>,
+    mut ev_reloaded: EventWriter<WorldGenReloaded>,
+) {
+    if !watched.changed(WORLDGEN_FILE) {
+        return;
+    }
+
+    match load_worldgen(&mut settings) {
+        Ok(()) => {
+            println!("Reloaded {}", WORLDGEN_FILE);
+            ev_reloaded.send(WorldGenReloaded);
+        }
+        Err(err) => println!("Failed to reload {}: {}", WORLDGEN_FILE, err),
+    }
+}
+
+/// Makes the world again with the reloaded settings, unless it came from
+/// somewhere other than the generator or a block in it has been changed.
+fn regenerate_world(
+    mut terrain: ResMut<Terrain>,
+    settings: Res<WorldGenSettings>,
+    pipeline: Res<WorldGenPipeline>,
+    source: Res<WorldSource>,
+    mut ev_reloaded: EventReader<WorldGenReloaded>,
+    mut light: Option<ResMut<LightMap>>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if ev_reloaded.read().count() == 0 || !matches!(source.as_ref(), WorldSource::Generate) {
+        return;
+    }
+    if terrain.is_edited() {
+        println!("The world has been edited, the new settings apply to the next one");
+        return;
+    }
+
+    let slice = terrain.slice;
+    *terrain = Terrain::new(terrain.size());
+    pipeline.generate(&mut terrain, &settings);
+    terrain.slice = slice;
+    terrain.take_changes();
+    terrain.mark_unedited();
+    terrain.mark_all_dirty();
+    if let Some(light) = &mut light {
+        light.reset();
+    }
+    ev_terrain_mod.send(TerrainModifiedEvent);
+}
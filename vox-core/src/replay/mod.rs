@@ -0,0 +1,287 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    job::{JobId, JobKind, JobQueue},
+    menu::{start_loading, AppState, WorldSource},
+    terrain::{check_size, Block, BlockChangedEvent, Terrain, TerrainModifiedEvent},
+    tick::SimTick,
+    worldgen::{Landform, WorldGenSettings},
+};
+
+/// Records a session to a file, or plays one back. A replay holds the world
+/// it started from and then everything that happened to it, one line per
+/// event stamped with the simulation tick it happened on.
+///
+/// The simulation rolls its dice with `thread_rng`, so re-running it
+/// wouldn't end up in the same place. The changes it
+/// made are recorded along with the player's, and playback holds the
+/// simulation off until the last of them has been applied.
+pub struct ReplayPlugin {
+    /// File to record the session to.
+    pub record: Option<PathBuf>,
+    /// Replay to play back instead of showing the menu.
+    pub play: Option<PathBuf>,
+}
+
+/// The world a replay starts from, the first line of the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReplayHeader {
+    seed: u64,
+    size: [i32; 3],
+    landform: Landform,
+    /// Save the session started from, None for a freshly generated world.
+    save: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ReplayEvent {
+    /// A block changed, by hand or by the simulation.
+    Block {
+        pos: [i32; 3],
+        block: Block,
+    },
+    Slice(u16),
+    /// A block designated to be dug out.
+    Mine {
+        pos: [i32; 3],
+    },
+    /// A block laid out for construction.
+    Construct {
+        pos: [i32; 3],
+        block: Block,
+    },
+    /// A log designated to be chopped down.
+    Chop {
+        pos: [i32; 3],
+    },
+}
+
+/// Writes the session out as it happens, so a crash still leaves a replay
+/// of everything up to it.
+#[derive(Resource)]
+struct Recorder {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    slice: Option<u16>,
+    /// Jobs queued before this one have been recorded.
+    next_job: JobId,
+}
+
+/// The replay being played back, removed once it runs out.
+#[derive(Resource)]
+pub struct Playback {
+    header: ReplayHeader,
+    events: VecDeque<(u64, ReplayEvent)>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(path) = &self.record {
+            println!("Recording to {}", path.display());
+            app.insert_resource(Recorder {
+                path: path.clone(),
+                writer: None,
+                slice: None,
+                next_job: 0,
+            })
+            .add_systems(OnEnter(AppState::InGame), start_recording)
+            .add_systems(
+                Last,
+                record.run_if(in_state(AppState::InGame).and_then(resource_exists::<Recorder>)),
+            );
+        }
+
+        if let Some(path) = &self.play {
+            match read_replay(path) {
+                Ok(playback) => {
+                    println!(
+                        "Playing {}, {} events",
+                        path.display(),
+                        playback.events.len()
+                    );
+                    let source = match &playback.header.save {
+                        Some(save) => WorldSource::Load(save.clone()),
+                        None => WorldSource::Generate,
+                    };
+                    app.insert_resource(playback)
+                        .insert_resource(source)
+                        .add_systems(Startup, (setup_playback, start_loading))
+                        .add_systems(
+                            Update,
+                            play.run_if(
+                                in_state(AppState::InGame).and_then(resource_exists::<Playback>),
+                            ),
+                        );
+                }
+                Err(err) => println!("Failed to read replay {}: {}", path.display(), err),
+            }
+        }
+    }
+}
+
+fn read_replay(path: &PathBuf) -> Result<Playback, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut lines = text.lines().enumerate();
+
+    let header: ReplayHeader = match lines.next() {
+        Some((_, line)) => ron::from_str(line).map_err(|err| format!("line 1: {}", err))?,
+        None => return Err("replay is empty".to_string()),
+    };
+    check_size(IVec3::from_array(header.size)).map_err(|err| format!("line 1: {}", err))?;
+
+    let events = lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| ron::from_str(line).map_err(|err| format!("line {}: {}", i + 1, err)))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Playback { header, events })
+}
+
+/// Opens the file afresh each time a world starts, once its seed is known.
+fn start_recording(
+    mut recorder: ResMut<Recorder>,
+    terrain: Res<Terrain>,
+    settings: Res<WorldGenSettings>,
+    source: Res<WorldSource>,
+    queue: Res<JobQueue>,
+) {
+    let header = ReplayHeader {
+        seed: settings.seed,
+        size: terrain.size().to_array(),
+        landform: settings.landform,
+        save: match source.as_ref() {
+            WorldSource::Load(path) => Some(path.clone()),
+            _ => None,
+        },
+    };
+
+    let file = File::create(&recorder.path).map_err(|err| err.to_string());
+    let written = file.and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        let line = ron::to_string(&header).map_err(|err| err.to_string())?;
+        writeln!(writer, "{}", line).map_err(|err| err.to_string())?;
+        Ok(writer)
+    });
+
+    match written {
+        Ok(writer) => recorder.writer = Some(writer),
+        Err(err) => println!("Failed to record to {}: {}", recorder.path.display(), err),
+    }
+    recorder.slice = None;
+    recorder.next_job = queue.next_id();
+}
+
+/// Runs last so the frame's block changes have been flushed.
+fn record(
+    mut recorder: ResMut<Recorder>,
+    tick: Res<SimTick>,
+    terrain: Res<Terrain>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+    queue: Res<JobQueue>,
+) {
+    let mut events = vec![];
+
+    if recorder.slice != Some(terrain.slice) {
+        recorder.slice = Some(terrain.slice);
+        events.push(ReplayEvent::Slice(terrain.slice));
+    }
+    for (_, job) in queue.queued_since(recorder.next_job) {
+        let pos = job.pos.to_array();
+        events.push(match job.kind {
+            JobKind::Mine => ReplayEvent::Mine { pos },
+            JobKind::Build(block) => ReplayEvent::Construct { pos, block },
+            JobKind::Chop => ReplayEvent::Chop { pos },
+            // queued by the simulation for the items it dropped
+            JobKind::Haul { .. } => continue,
+        });
+    }
+    recorder.next_job = queue.next_id();
+    for ev in ev_block_changed.read() {
+        events.push(ReplayEvent::Block {
+            pos: ev.pos.to_array(),
+            block: ev.block,
+        });
+    }
+
+    let tick = tick.0;
+    let Some(writer) = &mut recorder.writer else {
+        return;
+    };
+    let written = events.iter().try_for_each(|event| {
+        let line = ron::to_string(&(tick, event)).map_err(|err| err.to_string())?;
+        writeln!(writer, "{}", line).map_err(|err| err.to_string())
+    });
+
+    if let Err(err) = written.and_then(|_| writer.flush().map_err(|err| err.to_string())) {
+        println!("Stopped recording: {}", err);
+        recorder.writer = None;
+    }
+}
+
+/// Sets the world up the way it was when recording started.
+fn setup_playback(
+    playback: Res<Playback>,
+    mut terrain: ResMut<Terrain>,
+    mut settings: ResMut<WorldGenSettings>,
+) {
+    settings.seed = playback.header.seed;
+    settings.landform = playback.header.landform;
+    *terrain = Terrain::new(IVec3::from_array(playback.header.size));
+}
+
+/// Applies every event due by the current tick.
+fn play(
+    mut commands: Commands,
+    tick: Res<SimTick>,
+    mut playback: ResMut<Playback>,
+    mut terrain: ResMut<Terrain>,
+    mut queue: ResMut<JobQueue>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let mut modified = false;
+    while playback
+        .events
+        .front()
+        .is_some_and(|(due, _)| *due <= tick.0)
+    {
+        let Some((_, event)) = playback.events.pop_front() else {
+            break;
+        };
+        match event {
+            ReplayEvent::Block { pos, block } => {
+                terrain.set_at(IVec3::from_array(pos), block);
+                modified = true;
+            }
+            ReplayEvent::Slice(slice) => {
+                terrain.set_slice(slice);
+                modified = true;
+            }
+            ReplayEvent::Mine { pos } => {
+                queue.push(&terrain, JobKind::Mine, IVec3::from_array(pos));
+            }
+            ReplayEvent::Construct { pos, block } => {
+                queue.push(&terrain, JobKind::Build(block), IVec3::from_array(pos));
+            }
+            ReplayEvent::Chop { pos } => {
+                queue.push(&terrain, JobKind::Chop, IVec3::from_array(pos));
+            }
+        }
+    }
+
+    if modified {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+
+    if playback.events.is_empty() {
+        println!("Replay finished after {} ticks", tick.0);
+        commands.remove_resource::<Playback>();
+    }
+}
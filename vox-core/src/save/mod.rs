@@ -0,0 +1,524 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    menu::AppState,
+    terrain::{
+        builtin_blocks, check_size, find_builtin, find_modded, modded_blocks, Block, Terrain,
+        CHUNK_SIZE, MODDED_ID_BASE,
+    },
+    worldgen::WorldGenSettings,
+    zone::{ZoneKind, Zones},
+};
+
+use region::RegionStore;
+
+mod region;
+
+/// Folder worlds are saved to and listed from.
+pub const SAVE_DIR: &str = "saves";
+
+pub struct SavePlugin;
+
+/// Extension of saved worlds, a folder since region files came in and a
+/// single file before.
+pub const SAVE_EXTENSION: &str = "world";
+
+/// Holds the header of a world saved as region files.
+const META_FILE: &str = "world.meta";
+
+/// Marks a file as a saved world.
+const MAGIC: &[u8; 4] = b"VOXW";
+/// Every chunk inline after the header, in one file.
+const SINGLE_FILE_VERSION: u8 = 1;
+/// Header on its own, chunks in region files next to it.
+const REGION_VERSION: u8 = 2;
+/// As region saves, with the names of the modded blocks after the header
+/// so their ids can be matched up with the mods installed when loading.
+const MODDED_VERSION: u8 = 3;
+/// As modded saves, with the zones after the block table.
+const ZONED_VERSION: u8 = 4;
+/// As zoned saves, with the chunk size after the header and the names of
+/// every block in the table, built-in ones too, so saves outlive changes to
+/// either.
+const NAMED_VERSION: u8 = 5;
+
+/// Edge of the chunks in saves from before their size was written down.
+const LEGACY_CHUNK_SIZE: i32 = 16;
+
+/// A world read back from disk, with the seed that made it.
+pub struct WorldSave {
+    pub seed: u64,
+    pub terrain: Terrain,
+    pub zones: Zones,
+}
+
+impl WorldSave {
+    /// Reads a world saved in any version of the format.
+    pub fn read(path: impl AsRef<Path>) -> Result<WorldSave, String> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let meta = fs::read(path.join(META_FILE)).map_err(|err| err.to_string())?;
+            let mut reader = Reader { rest: &meta };
+            let (version, mut save) = read_header(&mut reader)?;
+            let migration = match version {
+                REGION_VERSION => Migration::legacy(HashMap::new()),
+                MODDED_VERSION => Migration::legacy(read_block_table(&mut reader)?),
+                ZONED_VERSION => {
+                    let modded = read_block_table(&mut reader)?;
+                    read_zones(&mut reader, &mut save.zones)?;
+                    Migration::legacy(modded)
+                }
+                NAMED_VERSION => {
+                    let migration = read_named_table(&mut reader)?;
+                    read_zones(&mut reader, &mut save.zones)?;
+                    migration
+                }
+                _ => return Err(format!("unsupported save version {}", version)),
+            };
+
+            let mut regions = RegionStore::new(path);
+            let remap = |id| migration.remap(id);
+            if migration.chunk_size == CHUNK_SIZE {
+                for coord in save.terrain.chunks().collect::<Vec<_>>() {
+                    // chunks never written stay empty
+                    if let Some(bytes) = regions.load_chunk(coord)? {
+                        save.terrain.decode_chunk_with(coord, &bytes, remap)?;
+                    }
+                }
+            } else {
+                let size = migration.chunk_size;
+                let count = (save.terrain.size() + IVec3::splat(size - 1)) / size;
+                for x in 0..count.x {
+                    for y in 0..count.y {
+                        for z in 0..count.z {
+                            let coord = IVec3::new(x, y, z);
+                            if let Some(bytes) = regions.load_chunk(coord)? {
+                                save.terrain
+                                    .decode_resized_chunk(coord, size, &bytes, remap)?;
+                            }
+                        }
+                    }
+                }
+            }
+            return Ok(save);
+        }
+
+        let bytes = fs::read(path).map_err(|err| err.to_string())?;
+        let mut reader = Reader { rest: &bytes };
+        let (version, mut save) = read_header(&mut reader)?;
+        if version != SINGLE_FILE_VERSION {
+            return Err(format!("unsupported save version {}", version));
+        }
+
+        for coord in save.terrain.chunks().collect::<Vec<_>>() {
+            let length = u32::from_le_bytes(reader.take()?);
+            save.terrain
+                .decode_chunk(coord, reader.bytes(length as usize)?)?;
+        }
+        Ok(save)
+    }
+
+    /// Writes the header and zones to the folder at `path` and every chunk
+    /// into the region files beside it.
+    pub fn write(
+        terrain: &Terrain,
+        zones: &Zones,
+        settings: &WorldGenSettings,
+        path: impl AsRef<Path>,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::create_dir_all(path).map_err(|err| err.to_string())?;
+
+        let mut meta = MAGIC.to_vec();
+        meta.push(NAMED_VERSION);
+        meta.extend_from_slice(&settings.seed.to_le_bytes());
+        for side in terrain.size().to_array() {
+            meta.extend_from_slice(&side.to_le_bytes());
+        }
+        meta.extend_from_slice(&terrain.slice.to_le_bytes());
+
+        // i16 map coordinates keep the chunk size well within a byte
+        meta.push(CHUNK_SIZE as u8);
+
+        let blocks: Vec<_> = builtin_blocks()
+            .into_iter()
+            .chain(modded_blocks())
+            .collect();
+        meta.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+        for block in blocks {
+            let name = block.def().name.as_bytes();
+            meta.extend_from_slice(&block.id().to_le_bytes());
+            // the registry keeps names short enough for the length byte
+            meta.push(name.len() as u8);
+            meta.extend_from_slice(name);
+        }
+        write_zones(&mut meta, zones, terrain.size());
+        fs::write(path.join(META_FILE), meta).map_err(|err| err.to_string())?;
+
+        let mut regions = RegionStore::new(path);
+        for coord in terrain.chunks() {
+            if let Some(bytes) = terrain.encode_chunk(coord) {
+                regions.save_chunk(coord, &bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the magic, version, seed, size and slice every version starts
+/// with, returning the version and an empty map of the right size.
+fn read_header(reader: &mut Reader) -> Result<(u8, WorldSave), String> {
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err("not a saved world".to_string());
+    }
+    let [version] = reader.take()?;
+    let seed = u64::from_le_bytes(reader.take()?);
+
+    let mut size = [0; 3];
+    for side in &mut size {
+        *side = i32::from_le_bytes(reader.take()?);
+    }
+    let size = IVec3::from_array(size);
+    check_size(size)?;
+    let slice = u16::from_le_bytes(reader.take()?);
+
+    let mut terrain = Terrain::new(size);
+    terrain.slice = slice.min(size.y as u16);
+    let zones = Zones::new(size);
+    Ok((
+        version,
+        WorldSave {
+            seed,
+            terrain,
+            zones,
+        },
+    ))
+}
+
+/// Reads the modded block table, returning how to renumber each saved id
+/// that differs from the id its block has now.
+fn read_block_table(reader: &mut Reader) -> Result<HashMap<u16, u16>, String> {
+    let count = u16::from_le_bytes(reader.take()?);
+    let mut remap = HashMap::new();
+
+    for _ in 0..count {
+        let id = u16::from_le_bytes(reader.take()?);
+        let [len] = reader.take()?;
+        let name = String::from_utf8_lossy(reader.bytes(len as usize)?).to_string();
+        let block = find_modded(&name)
+            .ok_or_else(|| format!("needs block {} from a mod that isn't installed", name))?;
+        if block.id() != id {
+            remap.insert(id, block.id());
+        }
+    }
+    Ok(remap)
+}
+
+/// How to read the chunks of a save written by another build: the size
+/// they were cut into and how to renumber the block ids in them.
+struct Migration {
+    chunk_size: i32,
+    /// Built-in kinds, by the high byte of their id, that are numbered
+    /// differently now, or None for kinds no longer around.
+    kinds: HashMap<u16, Option<u16>>,
+    /// Modded ids that are numbered differently now.
+    modded: HashMap<u16, u16>,
+}
+
+impl Migration {
+    /// Saves from before the chunk size and built-in names were written
+    /// down, which can only have been made with both as they were then.
+    fn legacy(modded: HashMap<u16, u16>) -> Self {
+        Self {
+            chunk_size: LEGACY_CHUNK_SIZE,
+            kinds: HashMap::new(),
+            modded,
+        }
+    }
+
+    /// The id a saved block has now. Blocks of a kind that's gone turn to
+    /// empty space rather than some other block that took their number.
+    fn remap(&self, id: u16) -> u16 {
+        if id >= MODDED_ID_BASE {
+            return self.modded.get(&id).copied().unwrap_or(id);
+        }
+
+        match self.kinds.get(&(id >> 8)) {
+            Some(Some(kind)) => kind << 8 | id & 0xff,
+            Some(None) => Block::Empty.id(),
+            None => id,
+        }
+    }
+}
+
+/// Reads the chunk size and the table of every saved block's name. Missing
+/// modded blocks fail the load as in older saves; missing built-in ones are
+/// dropped with a warning, since no mod would bring them back.
+fn read_named_table(reader: &mut Reader) -> Result<Migration, String> {
+    let [chunk_size] = reader.take()?;
+    let mut migration = Migration {
+        chunk_size: chunk_size as i32,
+        kinds: HashMap::new(),
+        modded: HashMap::new(),
+    };
+    if migration.chunk_size == 0 {
+        return Err("bad chunk size 0".to_string());
+    }
+
+    let count = u16::from_le_bytes(reader.take()?);
+    for _ in 0..count {
+        let id = u16::from_le_bytes(reader.take()?);
+        let [len] = reader.take()?;
+        let name = String::from_utf8_lossy(reader.bytes(len as usize)?).to_string();
+
+        if id >= MODDED_ID_BASE {
+            let block = find_modded(&name)
+                .ok_or_else(|| format!("needs block {} from a mod that isn't installed", name))?;
+            if block.id() != id {
+                migration.modded.insert(id, block.id());
+            }
+            continue;
+        }
+
+        match find_builtin(&name) {
+            Some(block) if block.id() >> 8 != id >> 8 => {
+                migration.kinds.insert(id >> 8, Some(block.id() >> 8));
+            }
+            Some(_) => {}
+            None => {
+                println!("Block {} no longer exists, loading it as empty space", name);
+                migration.kinds.insert(id >> 8, None);
+            }
+        }
+    }
+    Ok(migration)
+}
+
+/// Writes the kind of every zone, then the grid of every level anything is
+/// painted on. Zones of a map of another size are left out.
+fn write_zones(meta: &mut Vec<u8>, zones: &Zones, size: IVec3) {
+    if zones.size() != size {
+        meta.extend_from_slice(&0u16.to_le_bytes());
+        meta.extend_from_slice(&0u16.to_le_bytes());
+        return;
+    }
+
+    let kinds: Vec<_> = zones.zones().collect();
+    meta.extend_from_slice(&(kinds.len() as u16).to_le_bytes());
+    for (id, kind) in kinds {
+        meta.extend_from_slice(&id.to_le_bytes());
+        meta.push(kind.id());
+    }
+
+    let levels: Vec<_> = (0..size.y as usize)
+        .filter(|y| !zones.level(*y).is_empty())
+        .collect();
+    meta.extend_from_slice(&(levels.len() as u16).to_le_bytes());
+    for y in levels {
+        meta.extend_from_slice(&(y as u16).to_le_bytes());
+        for id in zones.level(y) {
+            meta.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+}
+
+/// Reads the zones `write_zones` wrote into `zones`, already sized to the
+/// map.
+fn read_zones(reader: &mut Reader, zones: &mut Zones) -> Result<(), String> {
+    let count = u16::from_le_bytes(reader.take()?);
+    for _ in 0..count {
+        let id = u16::from_le_bytes(reader.take()?);
+        let [kind] = reader.take()?;
+        let kind = ZoneKind::from_id(kind).ok_or_else(|| format!("bad zone kind {}", kind))?;
+        zones.insert_zone(id, kind);
+    }
+
+    let size = zones.size();
+    let columns = (size.x * size.z) as usize;
+    let levels = u16::from_le_bytes(reader.take()?);
+    for _ in 0..levels {
+        let y = u16::from_le_bytes(reader.take()?) as usize;
+        if y >= size.y as usize {
+            return Err(format!("zones on level {} outside the map", y));
+        }
+        let grid: Vec<_> = reader
+            .bytes(columns * 2)?
+            .chunks_exact(2)
+            .map(|id| u16::from_le_bytes([id[0], id[1]]))
+            .collect();
+        // 0 is unzoned
+        if let Some(id) = grid
+            .iter()
+            .find(|id| **id != 0 && zones.kind(**id).is_none())
+        {
+            return Err(format!(
+                "zone {} on level {} isn't in the zone table",
+                id, y
+            ));
+        }
+        zones.insert_level(y, grid);
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.rest.len() < n {
+            return Err("save ends early".to_string());
+        }
+        let (head, tail) = self.rest.split_at(n);
+        self.rest = tail;
+        Ok(head)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+}
+
+/// Save files in `SAVE_DIR`, newest first.
+pub fn list_saves() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(SAVE_DIR) else {
+        return vec![];
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == SAVE_EXTENSION))
+        .collect();
+    // names carry the time they were written
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, quick_save.run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// F5 writes the world to a new save in `SAVE_DIR`.
+fn quick_save(
+    keys: Res<ButtonInput<KeyCode>>,
+    terrain: Res<Terrain>,
+    zones: Res<Zones>,
+    settings: Res<WorldGenSettings>,
+) {
+    if !keys.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = Path::new(SAVE_DIR).join(format!("world-{}.{}", time, SAVE_EXTENSION));
+
+    match WorldSave::write(&terrain, &zones, &settings, &path) {
+        Ok(()) => println!("Saved world to {}", path.display()),
+        Err(err) => println!("Failed to save world to {}: {}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{fixtures, Orientation};
+
+    fn table_entry(bytes: &mut Vec<u8>, id: u16, name: &str) {
+        bytes.extend_from_slice(&id.to_le_bytes());
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    #[test]
+    fn saves_from_before_a_registry_and_chunk_size_change_still_load() {
+        // stone under an old number, and a block since taken out
+        let mut meta = vec![2];
+        meta.extend_from_slice(&2u16.to_le_bytes());
+        table_entry(&mut meta, 0x6300, "Stone");
+        table_entry(&mut meta, 0x6400, "Marble");
+        let migration = read_named_table(&mut Reader { rest: &meta }).unwrap();
+
+        assert_eq!(migration.remap(0x6300), Block::Stone.id());
+        assert_eq!(migration.remap(0x6400), Block::Empty.id());
+        assert_eq!(migration.remap(Block::Dirt.id()), Block::Dirt.id());
+
+        // a chunk two cells on a side, its low x half stone and the rest marble
+        let mut chunk = vec![1, 2];
+        chunk.extend_from_slice(&0x6300u16.to_le_bytes());
+        chunk.extend_from_slice(&0x6400u16.to_le_bytes());
+        chunk.extend_from_slice(&[4, 0, 4, 1]);
+
+        let mut terrain = Terrain::new(IVec3::splat(3));
+        terrain
+            .decode_resized_chunk(IVec3::new(1, 0, 0), 2, &chunk, |id| migration.remap(id))
+            .unwrap();
+
+        assert_eq!(terrain.get_at(IVec3::new(2, 1, 1)), Block::Stone);
+        assert_eq!(terrain.get_at(IVec3::new(2, 0, 0)), Block::Stone);
+        assert_eq!(terrain.get_at(IVec3::new(1, 0, 0)), Block::Empty);
+        assert_eq!(terrain.get_at(IVec3::new(3, 0, 0)), Block::Oob);
+    }
+
+    #[test]
+    fn worlds_come_back_as_they_were_saved() {
+        let path = std::env::temp_dir().join(format!("vox-save-{}.world", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+
+        let size = IVec3::new(40, 20, 24);
+        let mut terrain = fixtures::ground(size, 6, Block::Stone);
+        terrain.set_at(IVec3::new(35, 6, 20), Block::Log(Orientation::Up));
+        terrain.set_at(IVec3::new(3, 2, 3), Block::Empty);
+        terrain.slice = 11;
+        let mut zones = Zones::new(size);
+        let stockpile = zones.add(ZoneKind::Stockpile).unwrap();
+        zones.paint(IVec3::new(2, 6, 2), Some(stockpile));
+        zones.paint(IVec3::new(33, 6, 17), Some(stockpile));
+        let settings = WorldGenSettings {
+            seed: 42,
+            ..default()
+        };
+
+        let written = WorldSave::write(&terrain, &zones, &settings, &path);
+        let save = WorldSave::read(&path);
+        let _ = fs::remove_dir_all(&path);
+        written.unwrap();
+        let save = save.unwrap();
+
+        assert_eq!(save.seed, 42);
+        assert_eq!(save.terrain.size(), size);
+        assert_eq!(save.terrain.slice, 11);
+        assert!(save.terrain.iter().eq(terrain.iter()));
+        assert_eq!(save.zones.zone_at(IVec3::new(33, 6, 17)), Some(stockpile));
+        assert_eq!(save.zones, zones);
+    }
+
+    #[test]
+    fn zones_missing_from_the_table_are_refused() {
+        let mut zones = Zones::new(IVec3::new(2, 1, 2));
+        let mut meta = vec![];
+        meta.extend_from_slice(&1u16.to_le_bytes());
+        meta.extend_from_slice(&1u16.to_le_bytes());
+        meta.push(ZoneKind::Stockpile.id());
+        meta.extend_from_slice(&1u16.to_le_bytes());
+        meta.extend_from_slice(&0u16.to_le_bytes());
+        for id in [0u16, 1, 7, 0] {
+            meta.extend_from_slice(&id.to_le_bytes());
+        }
+
+        let err = read_zones(&mut Reader { rest: &meta }, &mut zones).unwrap_err();
+        assert!(err.contains("zone 7"), "{}", err);
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+/// Edge length of a region, in chunks.
+const REGION_SIZE: i32 = 8;
+
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Marks a file as a region.
+const MAGIC: &[u8; 4] = b"VOXR";
+/// Bumped whenever the region layout changes.
+const VERSION: u8 = 1;
+/// Magic, version, then an offset and length per chunk.
+const HEADER_LEN: u64 = (MAGIC.len() + 1 + REGION_CHUNKS * 8) as u64;
+
+/// Chunks of a saved world grouped into region files, a cube of
+/// `REGION_SIZE` chunks each. A table at the top of every file says where
+/// each chunk's bytes sit, so one chunk can be read or written without
+/// touching the rest of the world.
+pub struct RegionStore {
+    dir: PathBuf,
+    open: HashMap<IVec3, RegionFile>,
+}
+
+impl RegionStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            open: HashMap::new(),
+        }
+    }
+
+    /// Bytes saved for chunk `coord`, None if it was never written.
+    pub fn load_chunk(&mut self, coord: IVec3) -> Result<Option<Vec<u8>>, String> {
+        let (region, slot) = locate(coord);
+        match self.region(region, false)? {
+            Some(file) => file.read(slot),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_chunk(&mut self, coord: IVec3, bytes: &[u8]) -> Result<(), String> {
+        let (region, slot) = locate(coord);
+        match self.region(region, true)? {
+            Some(file) => file.write(slot, bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// The open file for `region`, opening it first if needed. Missing
+    /// files are only made when `create` is set.
+    fn region(&mut self, region: IVec3, create: bool) -> Result<Option<&mut RegionFile>, String> {
+        if !self.open.contains_key(&region) {
+            let path = self
+                .dir
+                .join(format!("r.{}.{}.{}.region", region.x, region.y, region.z));
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            let file =
+                RegionFile::open(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            self.open.insert(region, file);
+        }
+
+        Ok(self.open.get_mut(&region))
+    }
+}
+
+/// Region holding chunk `coord` and the chunk's slot in its table.
+fn locate(coord: IVec3) -> (IVec3, usize) {
+    let region = coord.div_euclid(IVec3::splat(REGION_SIZE));
+    let local = coord.rem_euclid(IVec3::splat(REGION_SIZE));
+    let slot = (local.x * REGION_SIZE + local.z) * REGION_SIZE + local.y;
+    (region, slot as usize)
+}
+
+struct RegionFile {
+    file: File,
+    /// Offset and length of each chunk, a zero length for none.
+    table: Vec<(u32, u32)>,
+}
+
+impl RegionFile {
+    /// Opens the region at `path`, writing an empty table if it's new.
+    fn open(path: &Path) -> Result<Self, String> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|err| err.to_string())?;
+
+        let len = file.metadata().map_err(|err| err.to_string())?.len();
+        if len == 0 {
+            let mut header = MAGIC.to_vec();
+            header.push(VERSION);
+            header.resize(HEADER_LEN as usize, 0);
+            file.write_all(&header).map_err(|err| err.to_string())?;
+
+            return Ok(Self {
+                file,
+                table: vec![(0, 0); REGION_CHUNKS],
+            });
+        }
+
+        let mut header = vec![0; HEADER_LEN as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| "region header ends early".to_string())?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err("not a region file".to_string());
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            return Err(format!("unsupported region version {}", version));
+        }
+
+        let table = header[MAGIC.len() + 1..]
+            .chunks_exact(8)
+            .map(|entry| {
+                let offset = u32::from_le_bytes(entry[..4].try_into().unwrap());
+                let length = u32::from_le_bytes(entry[4..].try_into().unwrap());
+                (offset, length)
+            })
+            .collect();
+
+        Ok(Self { file, table })
+    }
+
+    fn read(&mut self, slot: usize) -> Result<Option<Vec<u8>>, String> {
+        let (offset, length) = self.table[slot];
+        if length == 0 {
+            return Ok(None);
+        }
+
+        let mut bytes = vec![0; length as usize];
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .map_err(|err| err.to_string())?;
+        Ok(Some(bytes))
+    }
+
+    /// Writes the chunk into the first gap it fits in, or after the last
+    /// chunk, and only then points the table at it. The bytes it replaces
+    /// stay as they were until the table no longer needs them, so a write
+    /// cut short leaves the old chunk readable, and the space it held is
+    /// taken by later writes.
+    fn write(&mut self, slot: usize, bytes: &[u8]) -> Result<(), String> {
+        let offset = self.free_offset(bytes.len() as u64);
+
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(bytes))
+            .and_then(|_| self.file.sync_data())
+            .map_err(|err| err.to_string())?;
+
+        self.table[slot] = (offset as u32, bytes.len() as u32);
+        let mut entry = (offset as u32).to_le_bytes().to_vec();
+        entry.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+
+        let entry_pos = (MAGIC.len() + 1 + slot * 8) as u64;
+        self.file
+            .seek(SeekFrom::Start(entry_pos))
+            .and_then(|_| self.file.write_all(&entry))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Start of the first stretch of `length` bytes no chunk in the table
+    /// uses, past the end of the last chunk if there's no gap that long.
+    fn free_offset(&self, length: u64) -> u64 {
+        let mut used: Vec<_> = self
+            .table
+            .iter()
+            .filter(|(_, length)| *length > 0)
+            .map(|(offset, length)| (*offset as u64, *offset as u64 + *length as u64))
+            .collect();
+        used.sort_unstable();
+
+        let mut free = HEADER_LEN;
+        for (start, end) in used {
+            if start >= free + length {
+                break;
+            }
+            free = free.max(end);
+        }
+        free
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh region file in the temp dir, gone once the test is done.
+    struct TempRegion(PathBuf);
+
+    impl TempRegion {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("vox-{}-{}.region", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+
+        fn open(&self) -> RegionFile {
+            RegionFile::open(&self.0).unwrap()
+        }
+
+        fn len(&self) -> u64 {
+            std::fs::metadata(&self.0).unwrap().len()
+        }
+    }
+
+    impl Drop for TempRegion {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn rewrites_never_overwrite_the_live_chunk() {
+        let region = TempRegion::new("rewrite");
+        let mut file = region.open();
+
+        file.write(0, &[1; 100]).unwrap();
+        file.write(1, &[2; 50]).unwrap();
+        let (first, _) = file.table[0];
+
+        // a smaller chunk still goes to fresh space, after the live ones
+        file.write(0, &[3; 80]).unwrap();
+        assert_ne!(file.table[0].0, first);
+        assert_eq!(file.read(0).unwrap(), Some(vec![3; 80]));
+        assert_eq!(region.len(), HEADER_LEN + 230);
+
+        // and the space it left is taken by the next write that fits
+        file.write(1, &[4; 90]).unwrap();
+        assert_eq!(file.table[1].0, first);
+        assert_eq!(region.len(), HEADER_LEN + 230);
+
+        drop(file);
+        let mut file = region.open();
+        assert_eq!(file.read(0).unwrap(), Some(vec![3; 80]));
+        assert_eq!(file.read(1).unwrap(), Some(vec![4; 90]));
+        assert_eq!(file.read(2).unwrap(), None);
+    }
+
+    #[test]
+    fn growing_chunks_append_and_the_file_stops_growing() {
+        let region = TempRegion::new("append");
+        let mut file = region.open();
+
+        file.write(0, &[1; 10]).unwrap();
+        file.write(0, &[2; 200]).unwrap();
+        assert_eq!(file.table[0], (HEADER_LEN as u32 + 10, 200));
+        assert_eq!(file.read(0).unwrap(), Some(vec![2; 200]));
+
+        // saving the same chunks over and over settles into the same space
+        let mut settled = 0;
+        for round in 0..20 {
+            file.write(0, &[round; 200]).unwrap();
+            file.write(1, &[round; 120]).unwrap();
+            if round == 4 {
+                settled = region.len();
+            }
+        }
+        assert_eq!(region.len(), settled);
+        assert_eq!(file.read(0).unwrap(), Some(vec![19; 200]));
+        assert_eq!(file.read(1).unwrap(), Some(vec![19; 120]));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::{
+    menu::AppState,
+    terrain::{Block, Terrain, TerrainModifiedEvent},
+    tick::RandomTickEvent,
+    worldgen::{WorldGenPipeline, WorldGenSettings, WorldGenStage},
+};
+
+/// Folder scripts are loaded from at startup.
+pub const SCRIPT_DIR: &str = "assets/scripts";
+
+/// Keeps a runaway script from hanging the game.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Loads the rhai scripts in `SCRIPT_DIR`. A script hooks in by defining
+/// any of these functions:
+///
+/// - `generate(world)` runs as a worldgen stage named after the file, after
+///   the built-in ones.
+/// - `on_tick(world, x, y, z, block)` runs for every random tick.
+///
+/// `world` reads and writes blocks by name, as in `world.get(x, y, z)` and
+/// `world.set(x, y, z, "Log(Up)")`, has `size_x`, `size_y` and `size_z`,
+/// and rolls dice with `world.rand(n)` for a number below `n`.
+pub struct ScriptPlugin;
+
+struct Script {
+    name: String,
+    ast: Arc<AST>,
+}
+
+#[derive(Resource)]
+struct Scripts {
+    engine: Arc<Engine>,
+    /// Scripts that define `on_tick`.
+    tick: Vec<Script>,
+}
+
+/// What a script sees of the world. The terrain is moved in for the length
+/// of the call and back out after, so scripts never hold on to it.
+#[derive(Clone)]
+struct ScriptWorld {
+    state: Arc<Mutex<WorldState>>,
+}
+
+struct WorldState {
+    terrain: Terrain,
+    rng: StdRng,
+    modified: bool,
+}
+
+impl ScriptWorld {
+    fn new(terrain: Terrain, rng: StdRng) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(WorldState {
+                terrain,
+                rng,
+                modified: false,
+            })),
+        }
+    }
+
+    /// Hands the terrain back, and whether the script changed it.
+    fn finish(self) -> (Terrain, bool) {
+        let mut state = self.state.lock().unwrap();
+        let terrain = std::mem::replace(&mut state.terrain, Terrain::new(IVec3::ZERO));
+        (terrain, state.modified)
+    }
+
+    fn get(&mut self, x: i64, y: i64, z: i64) -> String {
+        let block = self.state.lock().unwrap().terrain.get_at(pos(x, y, z));
+        ron::to_string(&block).unwrap_or_default()
+    }
+
+    fn set(&mut self, x: i64, y: i64, z: i64, name: &str) -> Result<(), Box<EvalAltResult>> {
+        let block: Block = ron::from_str(name).map_err(|_| format!("unknown block {}", name))?;
+        let mut state = self.state.lock().unwrap();
+        state.terrain.set_at(pos(x, y, z), block);
+        state.modified = true;
+        Ok(())
+    }
+
+    fn rand(&mut self, n: i64) -> i64 {
+        if n <= 0 {
+            return 0;
+        }
+        self.state.lock().unwrap().rng.gen_range(0..n)
+    }
+
+    fn size(&mut self) -> IVec3 {
+        self.state.lock().unwrap().terrain.size()
+    }
+}
+
+fn pos(x: i64, y: i64, z: i64) -> IVec3 {
+    IVec3::new(x as i32, y as i32, z as i32)
+}
+
+/// A script's `generate` run as a step of world generation.
+struct ScriptStage {
+    name: &'static str,
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+impl WorldGenStage for ScriptStage {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn generate(&self, terrain: &mut Terrain, _: &WorldGenSettings, rng: &mut StdRng) {
+        let taken = std::mem::replace(terrain, Terrain::new(IVec3::ZERO));
+        // drawn from the pipeline's generator so the same seed gives the same map
+        let world = ScriptWorld::new(taken, StdRng::seed_from_u64(rng.gen()));
+
+        let result = self.engine.call_fn::<Dynamic>(
+            &mut Scope::new(),
+            &self.ast,
+            "generate",
+            (world.clone(),),
+        );
+        if let Err(err) = result {
+            println!("Script {} failed: {}", self.name, err);
+        }
+
+        *terrain = world.finish().0;
+    }
+}
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        let engine = Arc::new(new_engine());
+        let mut tick = vec![];
+
+        app.init_resource::<WorldGenPipeline>();
+        for Script { name, ast } in load_scripts(&engine, SCRIPT_DIR) {
+            let defines = |f: &str| ast.iter_functions().any(|meta| meta.name == f);
+
+            if defines("generate") {
+                app.world
+                    .resource_mut::<WorldGenPipeline>()
+                    .add_stage(ScriptStage {
+                        // stage names live as long as the game
+                        name: Box::leak(name.clone().into_boxed_str()),
+                        engine: engine.clone(),
+                        ast: ast.clone(),
+                    });
+            }
+            if defines("on_tick") {
+                tick.push(Script { name, ast });
+            }
+        }
+
+        app.insert_resource(Scripts { engine, tick })
+            .add_systems(Update, run_tick_scripts.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .register_type_with_name::<ScriptWorld>("World")
+        .register_fn("get", ScriptWorld::get)
+        .register_fn("set", ScriptWorld::set)
+        .register_fn("rand", ScriptWorld::rand)
+        .register_get("size_x", |world: &mut ScriptWorld| world.size().x as i64)
+        .register_get("size_y", |world: &mut ScriptWorld| world.size().y as i64)
+        .register_get("size_z", |world: &mut ScriptWorld| world.size().z as i64);
+    engine
+}
+
+/// Compiles every script in `dir`, skipping the ones that don't compile.
+fn load_scripts(engine: &Engine, dir: impl AsRef<Path>) -> Vec<Script> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect();
+    // load in a stable order so stages always run the same way round
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            match engine.compile_file(path.clone()) {
+                Ok(ast) => {
+                    println!("Loaded script {}", name);
+                    Some(Script {
+                        name,
+                        ast: Arc::new(ast),
+                    })
+                }
+                Err(err) => {
+                    println!("Failed to load script {}: {}", path.display(), err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn run_tick_scripts(
+    scripts: Res<Scripts>,
+    mut terrain: ResMut<Terrain>,
+    mut ev_tick: EventReader<RandomTickEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if scripts.tick.is_empty() {
+        ev_tick.clear();
+        return;
+    }
+
+    let ticks: Vec<_> = ev_tick.read().copied().collect();
+    if ticks.is_empty() {
+        return;
+    }
+
+    let taken = std::mem::replace(&mut *terrain, Terrain::new(IVec3::ZERO));
+    let world = ScriptWorld::new(taken, StdRng::from_entropy());
+
+    for script in &scripts.tick {
+        for ev in &ticks {
+            let block = ron::to_string(&ev.block).unwrap_or_default();
+            let args = (
+                world.clone(),
+                ev.pos.x as i64,
+                ev.pos.y as i64,
+                ev.pos.z as i64,
+                block,
+            );
+            let result =
+                scripts
+                    .engine
+                    .call_fn::<Dynamic>(&mut Scope::new(), &script.ast, "on_tick", args);
+            if let Err(err) = result {
+                println!("Script {} failed: {}", script.name, err);
+                break;
+            }
+        }
+    }
+
+    let (taken, modified) = world.finish();
+    *terrain = taken;
+    if modified {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
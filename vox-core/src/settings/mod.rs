@@ -0,0 +1,194 @@
+use std::{fs, path::Path};
+
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraSettings, input::InputMap, photo::PhotoSettings, terrain::GraphicsSettings,
+};
+
+/// Keeps the player's settings in `SETTINGS_FILE`: the camera, the input
+/// bindings, the graphics options, the window mode and the photo mode. They're read once at
+/// startup and written back whenever any of them changes, so they outlast a
+/// restart.
+pub struct SettingsPlugin;
+
+const SETTINGS_FILE: &str = "config/settings.ron";
+
+/// How the window takes up the screen.
+#[derive(Resource, Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: WindowMode,
+}
+
+/// Everything in `SETTINGS_FILE`, anything left out of it keeps its default.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SettingsFile {
+    camera: CameraSettings,
+    input: InputMap,
+    graphics: GraphicsSettings,
+    window: WindowSettings,
+    photo: PhotoSettings,
+}
+
+impl SettingsFile {
+    fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// The settings as last read or written, so unchanged ones aren't written
+/// again.
+#[derive(Resource, Default)]
+struct SavedSettings(String);
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = load_settings().unwrap_or_else(|err| {
+            println!("Failed to load {}: {}", SETTINGS_FILE, err);
+            SettingsFile::default()
+        });
+        let saved = settings.to_ron().unwrap_or_default();
+
+        app.insert_resource(settings.camera)
+            .insert_resource(settings.input)
+            .insert_resource(settings.graphics)
+            .insert_resource(settings.window)
+            .insert_resource(settings.photo)
+            .insert_resource(SavedSettings(saved))
+            .add_systems(
+                Update,
+                (
+                    apply_window_mode.run_if(resource_changed::<WindowSettings>),
+                    save_settings,
+                ),
+            );
+    }
+}
+
+/// Reads `SETTINGS_FILE`. A missing file is fine and leaves everything at
+/// its default.
+fn load_settings() -> Result<SettingsFile, String> {
+    let Ok(source) = fs::read_to_string(SETTINGS_FILE) else {
+        return Ok(SettingsFile::default());
+    };
+    ron::from_str(&source).map_err(|err| err.to_string())
+}
+
+fn apply_window_mode(
+    settings: Res<WindowSettings>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if let Ok(mut window) = primary_window.get_single_mut() {
+        window.mode = settings.mode;
+    }
+}
+
+/// Writes the settings to `SETTINGS_FILE` after any of them changed.
+fn save_settings(
+    mut saved: ResMut<SavedSettings>,
+    camera: Res<CameraSettings>,
+    input: Res<InputMap>,
+    graphics: Res<GraphicsSettings>,
+    window: Res<WindowSettings>,
+    photo: Res<PhotoSettings>,
+) {
+    let is_changed = camera.is_changed()
+        || input.is_changed()
+        || graphics.is_changed()
+        || window.is_changed()
+        || photo.is_changed();
+    if !is_changed {
+        return;
+    }
+
+    let settings = SettingsFile {
+        camera: camera.clone(),
+        input: input.clone(),
+        graphics: graphics.clone(),
+        window: *window,
+        photo: photo.clone(),
+    };
+    let result = settings.to_ron().and_then(|text| {
+        if text == saved.0 {
+            return Ok(());
+        }
+        if let Some(dir) = Path::new(SETTINGS_FILE).parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        fs::write(SETTINGS_FILE, &text).map_err(|err| err.to_string())?;
+        saved.0 = text;
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        println!("Failed to save {}: {}", SETTINGS_FILE, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Action, Binding};
+
+    #[test]
+    fn settings_left_out_of_the_file_keep_their_defaults() {
+        let settings: SettingsFile = ron::from_str(
+            "(
+                camera: (sensitivity: 0.0002),
+                input: (bindings: {MoveForward: [Key(KeyZ)]}),
+                window: (mode: Fullscreen),
+            )",
+        )
+        .unwrap();
+
+        assert_eq!(settings.camera.sensitivity, 0.0002);
+        assert_eq!(settings.camera.speed, CameraSettings::default().speed);
+        assert_eq!(
+            settings.input.bindings[&Action::MoveForward],
+            vec![Binding::Key(KeyCode::KeyZ)]
+        );
+        assert_eq!(
+            settings.input.bindings[&Action::MoveBack],
+            InputMap::default().bindings[&Action::MoveBack]
+        );
+        assert!(settings.graphics.ambient_occlusion);
+        assert_eq!(settings.window.mode, WindowMode::Fullscreen);
+
+        let text = settings.to_ron().unwrap();
+        let again: SettingsFile = ron::from_str(&text).unwrap();
+        assert_eq!(again.to_ron().unwrap(), text);
+    }
+
+    #[test]
+    fn graphics_toggles_roundtrip() {
+        let settings = SettingsFile::default();
+        assert!(settings.graphics.normal_maps);
+        assert!(settings.graphics.slice_outlines);
+        assert!(settings.graphics.interior_dimming);
+
+        let mut settings = SettingsFile::default();
+        settings.graphics.normal_maps = false;
+        settings.graphics.slice_outlines = false;
+        settings.graphics.interior_dimming = false;
+        let again: SettingsFile = ron::from_str(&settings.to_ron().unwrap()).unwrap();
+        assert!(!again.graphics.normal_maps);
+        assert!(!again.graphics.slice_outlines);
+        assert!(!again.graphics.interior_dimming);
+        assert!(again.graphics.ambient_occlusion);
+
+        // files from before a toggle existed turn it on
+        let old: SettingsFile =
+            ron::from_str("(graphics: (ambient_occlusion: false, fog: false))").unwrap();
+        assert!(!old.graphics.ambient_occlusion);
+        assert!(old.graphics.normal_maps);
+        assert!(old.graphics.slice_outlines);
+        assert!(old.graphics.interior_dimming);
+    }
+}
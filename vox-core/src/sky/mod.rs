@@ -0,0 +1,135 @@
+use bevy::{
+    pbr::{FogSettings, MaterialPipeline, MaterialPipelineKey, NotShadowCaster},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+    },
+};
+
+use crate::{
+    camera::FlyCamera,
+    daylight::{CloudCover, TimeOfDay},
+    menu::AppState,
+    terrain::GraphicsSettings,
+};
+
+/// Draws the sky as a box around the camera, shaded from the sun's place in
+/// the day: blue by day, glowing at the horizon around sunrise and sunset,
+/// and dark with stars at night.
+pub struct SkyPlugin;
+
+/// Half the width of the sky box, its corners well inside the camera's far
+/// plane. It shrinks to stay inside shorter render distances.
+const SKY_RADIUS: f32 = 400.;
+
+/// Color of the horizon at noon and at midnight, as in `sky.wgsl`. Fog
+/// fades into it so the distance blends into the sky.
+const DAY_HORIZON: Vec3 = Vec3::new(0.68, 0.8, 0.95);
+const NIGHT_HORIZON: Vec3 = Vec3::new(0.03, 0.04, 0.09);
+
+/// Share of the render distance the fog starts at under clear skies, and
+/// under a full overcast.
+const CLEAR_FOG_START: f32 = 0.5;
+const OVERCAST_FOG_START: f32 = 0.1;
+
+#[derive(Component)]
+struct Sky;
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct SkyMaterial {
+    /// Toward the sun in xyz, how bright the day is in w.
+    #[uniform(0)]
+    sun: Vec4,
+}
+
+impl Material for SkyMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/sky.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // seen from inside
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<SkyMaterial>::default())
+            .add_systems(OnEnter(AppState::InGame), spawn_sky)
+            .add_systems(OnExit(AppState::InGame), despawn_sky)
+            .add_systems(Update, update_sky.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn spawn_sky(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SkyMaterial>>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Cuboid::from_size(Vec3::splat(SKY_RADIUS * 2.))),
+            material: materials.add(SkyMaterial { sun: Vec4::Y }),
+            ..default()
+        },
+        NotShadowCaster,
+        Sky,
+    ));
+}
+
+fn despawn_sky(mut commands: Commands, skies: Query<Entity, With<Sky>>) {
+    for entity in skies.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Keeps the sky centered on the camera and the sun where the clock says,
+/// with the fog taking on the color of the horizon. Clouds dim both and
+/// bring the fog closer.
+#[allow(clippy::type_complexity)]
+fn update_sky(
+    time_of_day: Res<TimeOfDay>,
+    clouds: Res<CloudCover>,
+    graphics: Res<GraphicsSettings>,
+    mut materials: ResMut<Assets<SkyMaterial>>,
+    mut cameras: Query<(&Transform, Option<&mut FogSettings>), (With<FlyCamera>, Without<Sky>)>,
+    mut skies: Query<(&mut Transform, &Handle<SkyMaterial>), With<Sky>>,
+) {
+    let Ok((camera, fog)) = cameras.get_single_mut() else {
+        return;
+    };
+
+    let daylight = time_of_day.daylight() * clouds.transmittance();
+    if let Some(mut fog) = fog {
+        let horizon = NIGHT_HORIZON.lerp(DAY_HORIZON, daylight);
+        fog.color = Color::rgb_linear(horizon.x, horizon.y, horizon.z);
+        let start = CLEAR_FOG_START.lerp(OVERCAST_FOG_START, clouds.0.clamp(0., 1.));
+        fog.falloff = FogFalloff::Linear {
+            start: graphics.render_distance * start,
+            end: graphics.render_distance,
+        };
+    }
+
+    // sides half a render distance out, so even the corners sit inside the
+    // far plane
+    let scale = (graphics.render_distance * 0.5 / SKY_RADIUS).min(1.);
+    for (mut transform, handle) in skies.iter_mut() {
+        transform.translation = camera.translation;
+        transform.scale = Vec3::splat(scale);
+
+        let sun = time_of_day.sun_direction().extend(daylight);
+        if let Some(material) = materials.get_mut(handle) {
+            material.sun = sun;
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use bevy::{
+    app::{Plugin, Update},
+    ecs::{
+        event::EventWriter,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Res, ResMut},
+    },
+};
+
+use crate::{
+    input::{Action, ActionState},
+    menu::AppState,
+    terrain::{Terrain, TerrainModifiedEvent},
+};
+
+pub struct SlicePlugin;
+
+impl Plugin for SlicePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_systems(Update, scroll_events.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn scroll_events(
+    actions: Res<ActionState>,
+    mut terrain: ResMut<Terrain>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let scroll =
+        actions.presses(Action::SliceUp) as i32 - actions.presses(Action::SliceDown) as i32;
+    if scroll == 0 {
+        return;
+    }
+
+    let slice = (terrain.slice as i32 + scroll).clamp(0, terrain.size().y - 1);
+    terrain.set_slice(slice as u16);
+
+    println!("Slice: {}", terrain.slice);
+    ev_terrain_mod.send(TerrainModifiedEvent);
+}
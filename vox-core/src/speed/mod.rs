@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+use crate::menu::AppState;
+
+/// How fast the simulation runs. The speed scales virtual time, which sets
+/// how many fixed simulation ticks run each second and is what the rest of
+/// the simulation steps by, while the camera and sounds go by real time so
+/// they stay responsive however fast the world runs, or when it stops.
+pub struct SpeedPlugin;
+
+/// Space to pause and unpause, keypad plus and minus to speed up and slow
+/// down, with the current speed shown in the corner.
+pub struct SpeedControlsPlugin;
+
+/// The speeds the simulation steps through, as multiples of real time.
+pub const SPEEDS: [u32; 3] = [1, 2, 4];
+
+#[derive(Resource, Default, Debug, Copy, Clone, PartialEq)]
+pub struct SimSpeed {
+    pub paused: bool,
+    /// Index into `SPEEDS`.
+    step: usize,
+}
+
+impl SimSpeed {
+    /// Multiple of real time the simulation runs at while unpaused.
+    pub fn multiplier(&self) -> u32 {
+        SPEEDS[self.step]
+    }
+
+    /// Multiple of real time the simulation runs at, 0 while paused.
+    pub fn factor(&self) -> u32 {
+        if self.paused {
+            0
+        } else {
+            self.multiplier()
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.step = (self.step + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.step = self.step.saturating_sub(1);
+    }
+}
+
+#[derive(Component)]
+struct SpeedText;
+
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimSpeed>()
+            .add_systems(Update, apply_sim_speed.run_if(resource_changed::<SimSpeed>))
+            .add_systems(OnExit(AppState::InGame), reset_sim_speed);
+    }
+}
+
+impl Plugin for SpeedControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), spawn_speed_text)
+            .add_systems(OnExit(AppState::InGame), despawn_speed_text)
+            .add_systems(
+                Update,
+                (
+                    change_sim_speed,
+                    show_sim_speed.run_if(resource_changed::<SimSpeed>),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Leaving the world leaves the speed behind with it.
+fn reset_sim_speed(mut speed: ResMut<SimSpeed>) {
+    *speed = SimSpeed::default();
+}
+
+fn apply_sim_speed(speed: Res<SimSpeed>, mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(speed.multiplier() as f32);
+    if speed.paused {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+fn change_sim_speed(keys: Res<ButtonInput<KeyCode>>, mut speed: ResMut<SimSpeed>) {
+    if keys.just_pressed(KeyCode::Space) {
+        speed.paused = !speed.paused;
+    }
+    if keys.just_pressed(KeyCode::NumpadAdd) {
+        speed.faster();
+    }
+    if keys.just_pressed(KeyCode::NumpadSubtract) {
+        speed.slower();
+    }
+}
+
+fn speed_label(speed: &SimSpeed) -> String {
+    if speed.paused {
+        "Paused".to_string()
+    } else {
+        format!("{}x", speed.multiplier())
+    }
+}
+
+fn spawn_speed_text(mut commands: Commands, speed: Res<SimSpeed>) {
+    let text = TextBundle::from_section(
+        speed_label(&speed),
+        TextStyle {
+            font_size: 20.,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(8.),
+        right: Val::Px(8.),
+        ..default()
+    })
+    .with_background_color(Color::rgba(0., 0., 0., 0.6));
+
+    commands.spawn((text, SpeedText));
+}
+
+fn despawn_speed_text(mut commands: Commands, texts: Query<Entity, With<SpeedText>>) {
+    for entity in texts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn show_sim_speed(speed: Res<SimSpeed>, mut texts: Query<&mut Text, With<SpeedText>>) {
+    let Ok(mut text) = texts.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = speed_label(&speed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_steps_stop_at_either_end() {
+        let mut speed = SimSpeed::default();
+        speed.slower();
+        assert_eq!(speed.factor(), 1);
+
+        speed.faster();
+        speed.faster();
+        speed.faster();
+        assert_eq!(speed.factor(), 4);
+
+        speed.paused = true;
+        assert_eq!(speed.factor(), 0);
+        assert_eq!(speed.multiplier(), 4);
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    build::{BuildMode, BuildTool},
+    camera::CameraRay,
+    menu::AppState,
+    terrain::{Block, BlockChangedEvent, BlockEntities, Terrain, TerrainModifiedEvent},
+};
+
+pub struct StructurePlugin;
+
+/// Draws the structures, and right click in build mode tears down the one
+/// under the cursor.
+pub struct StructureViewPlugin;
+
+const DEMOLISH_REACH: f32 = 64.;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StructureKind {
+    Workshop,
+    Table,
+}
+
+impl StructureKind {
+    /// Footprint in voxels, extending from the origin toward +X, +Y and +Z.
+    pub fn size(&self) -> IVec3 {
+        match self {
+            StructureKind::Workshop => IVec3::new(3, 2, 3),
+            StructureKind::Table => IVec3::new(2, 1, 1),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            StructureKind::Workshop => Color::rgb_u8(122, 86, 48),
+            StructureKind::Table => Color::rgb_u8(150, 110, 64),
+        }
+    }
+}
+
+/// Root of a placed multi-block structure. Every voxel of the footprint holds
+/// `Block::Structure` and maps to this entity in `BlockEntities`.
+#[derive(Component)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub origin: IVec3,
+}
+
+impl Structure {
+    pub fn cells(&self) -> impl Iterator<Item = IVec3> {
+        footprint(self.kind, self.origin)
+    }
+}
+
+/// Footprint cells by structure root, so a change to any one voxel can take the
+/// whole structure down.
+#[derive(Resource, Default)]
+struct StructureIndex {
+    roots: HashMap<IVec3, Entity>,
+}
+
+impl Plugin for StructurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StructureIndex>().add_systems(
+            Update,
+            (register_structures, remove_broken_structures)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+impl Plugin for StructureViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                add_structure_meshes,
+                demolish_on_click.before(remove_broken_structures),
+                update_structure_visibility.after(remove_broken_structures),
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+fn footprint(kind: StructureKind, origin: IVec3) -> impl Iterator<Item = IVec3> {
+    let size = kind.size();
+    (0..size.x).flat_map(move |x| {
+        (0..size.y).flat_map(move |y| (0..size.z).map(move |z| origin + IVec3::new(x, y, z)))
+    })
+}
+
+/// Whether every voxel of the footprint is free and below the slice, with the
+/// bottom layer resting on filled ground.
+pub fn can_place(terrain: &Terrain, kind: StructureKind, origin: IVec3) -> bool {
+    footprint(kind, origin).all(|pos| {
+        !terrain.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16)
+            && pos.y < terrain.slice as i32
+            && terrain.get_at(pos) == Block::Empty
+            && (pos.y != origin.y || terrain.get_at(pos - IVec3::Y).is_filled())
+    })
+}
+
+/// Writes the footprint into the terrain and spawns the structure's root
+/// entity. Returns `None` when the footprint doesn't fit.
+pub fn place_structure(
+    commands: &mut Commands,
+    terrain: &mut Terrain,
+    kind: StructureKind,
+    origin: IVec3,
+) -> Option<Entity> {
+    if !can_place(terrain, kind, origin) {
+        return None;
+    }
+
+    let root = commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_translation(origin.as_vec3())),
+            Structure { kind, origin },
+        ))
+        .id();
+
+    for pos in footprint(kind, origin) {
+        terrain.set_at(pos, Block::Structure);
+    }
+
+    Some(root)
+}
+
+/// Builds every new structure out of a top and legs.
+fn add_structure_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    structures: Query<(Entity, &Structure), Added<Structure>>,
+) {
+    for (root, structure) in structures.iter() {
+        let size = structure.kind.size().as_vec3();
+        let material = materials.add(structure.kind.color());
+        let top = 0.15;
+        let top_mesh = meshes.add(Cuboid::new(size.x - 0.1, top, size.z - 0.1));
+        let leg_mesh = meshes.add(Cuboid::new(0.15, size.y - top, 0.15));
+
+        commands.entity(root).with_children(|parent| {
+            // a bench top on four legs spanning the footprint
+            parent.spawn(PbrBundle {
+                mesh: top_mesh,
+                material: material.clone(),
+                transform: Transform::from_xyz(size.x / 2., size.y - top / 2., size.z / 2.),
+                ..default()
+            });
+
+            for (x, z) in [
+                (0.15, 0.15),
+                (size.x - 0.15, 0.15),
+                (0.15, size.z - 0.15),
+                (size.x - 0.15, size.z - 0.15),
+            ] {
+                parent.spawn(PbrBundle {
+                    mesh: leg_mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(x, (size.y - top) / 2., z),
+                    ..default()
+                });
+            }
+        });
+    }
+}
+
+/// Points every footprint voxel of a new structure at its root. Runs a frame
+/// after placement so the block change flush doesn't take the entries back out.
+fn register_structures(
+    mut index: ResMut<StructureIndex>,
+    mut block_entities: ResMut<BlockEntities>,
+    structures: Query<(Entity, &Structure), Added<Structure>>,
+) {
+    for (root, structure) in structures.iter() {
+        for pos in structure.cells() {
+            index.roots.insert(pos, root);
+            block_entities.insert(pos, root);
+        }
+    }
+}
+
+/// Right click on a structure in build mode tears it down, unless a brush is
+/// in hand.
+fn demolish_on_click(
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    mut terrain: ResMut<Terrain>,
+    camera_ray: Res<CameraRay>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    if !build.enabled
+        || matches!(build.tool, BuildTool::Sculpt | BuildTool::Smooth)
+        || !buttons.just_pressed(MouseButton::Right)
+    {
+        return;
+    }
+
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+
+    if let Some(hit) = terrain.raycast(ray.origin, *ray.direction, DEMOLISH_REACH) {
+        if terrain.get_at(hit.pos) == Block::Structure {
+            // clearing one voxel is enough, the rest follows
+            terrain.set_at(hit.pos, Block::Empty);
+            ev_terrain_mod.send(TerrainModifiedEvent);
+        }
+    }
+}
+
+/// When any voxel of a structure is replaced, the rest of it goes too.
+fn remove_broken_structures(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    mut index: ResMut<StructureIndex>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    for ev in ev_block_changed.read() {
+        if ev.block == Block::Structure {
+            continue;
+        }
+
+        let Some(root) = index.roots.remove(&ev.pos) else {
+            continue;
+        };
+
+        let cells: Vec<IVec3> = index
+            .roots
+            .iter()
+            .filter(|(_, r)| **r == root)
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        for pos in cells {
+            index.roots.remove(&pos);
+            block_entities.remove(pos);
+            if terrain.get_at(pos) == Block::Structure {
+                terrain.set_at(pos, Block::Empty);
+            }
+        }
+
+        if let Some(entity) = commands.get_entity(root) {
+            entity.despawn_recursive();
+        }
+        ev_terrain_mod.send(TerrainModifiedEvent);
+    }
+}
+
+/// Structures are hidden once their base is at or above the slice.
+fn update_structure_visibility(
+    terrain: Res<Terrain>,
+    mut ev_terrain_mod: EventReader<TerrainModifiedEvent>,
+    mut structures: Query<(&Structure, &mut Visibility)>,
+) {
+    if ev_terrain_mod.is_empty() {
+        return;
+    }
+    ev_terrain_mod.clear();
+
+    for (structure, mut visibility) in structures.iter_mut() {
+        *visibility = if structure.origin.y < terrain.slice as i32 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
@@ -0,0 +1,228 @@
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    build::BuildMode,
+    daylight::TimeOfDay,
+    menu::AppState,
+    terrain::{Terrain, TerrainModifiedEvent},
+    tick::every,
+    worldgen::biome_at,
+};
+
+pub struct TemperaturePlugin;
+
+/// H toggles a heat map drawn over the terrain.
+pub struct TemperatureOverlayPlugin;
+
+/// Temperature deep rock settles at, whatever the weather above.
+const UNDERGROUND_TEMPERATURE: f32 = 10.;
+/// Blocks below the surface over which the surface temperature fades out.
+const DEPTH_FALLOFF: f32 = 4.;
+/// Share of the gap to its target a column closes per step.
+const RELAXATION: f32 = 0.1;
+/// Share of the difference to its neighbors' average a column takes per step.
+const DIFFUSION: f32 = 0.2;
+const STEP: Duration = Duration::from_millis(500);
+
+const NEIGHBORS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Surface air temperature per column in degrees Celsius, with the height of
+/// the surface it sits on. Columns warm and cool toward their biome's daily
+/// cycle and bleed into their neighbors, so the field stays smooth.
+#[derive(Resource, Default)]
+pub struct TemperatureMap {
+    size: IVec3,
+    columns: Vec<f32>,
+    surface: Vec<i32>,
+}
+
+impl TemperatureMap {
+    fn index(&self, x: i32, z: i32) -> Option<usize> {
+        let in_bounds = x >= 0 && z >= 0 && x < self.size.x && z < self.size.z;
+        in_bounds.then(|| x as usize * self.size.z as usize + z as usize)
+    }
+
+    /// Temperature at `pos`. Cells above the surface share the column's air,
+    /// those below fade toward the steady temperature of deep rock.
+    pub fn temperature(&self, pos: IVec3) -> f32 {
+        let Some(i) = self.index(pos.x, pos.z) else {
+            return UNDERGROUND_TEMPERATURE;
+        };
+
+        let depth = (self.surface[i] - pos.y).max(0) as f32;
+        let blend = (-depth / DEPTH_FALLOFF).exp();
+        UNDERGROUND_TEMPERATURE + (self.columns[i] - UNDERGROUND_TEMPERATURE) * blend
+    }
+
+    pub fn is_freezing(&self, pos: IVec3) -> bool {
+        self.temperature(pos) <= 0.
+    }
+
+    /// Finds the first open cell of every column, resizing the field if
+    /// the map changed size.
+    fn update_surface(&mut self, terrain: &Terrain) {
+        if self.size != terrain.size() {
+            self.size = terrain.size();
+            let columns = (self.size.x * self.size.z) as usize;
+            self.columns = vec![UNDERGROUND_TEMPERATURE; columns];
+            self.surface = vec![0; columns];
+        }
+
+        for x in 0..self.size.x {
+            for z in 0..self.size.z {
+                let top = terrain.surface_height(x, z).map_or(0, |y| y + 1);
+
+                if let Some(i) = self.index(x, z) {
+                    self.surface[i] = top;
+                }
+            }
+        }
+    }
+
+    /// Moves every column a step toward its target and its neighbors.
+    fn step(&mut self, time_of_day: &TimeOfDay) {
+        let previous = self.columns.clone();
+        // noon is the warmest hour, midnight the coldest
+        let cycle = time_of_day.daylight() * 2. - 1.;
+
+        for x in 0..self.size.x {
+            for z in 0..self.size.z {
+                let Some(i) = self.index(x, z) else {
+                    continue;
+                };
+                let biome = biome_at(self.size, x, z);
+                let target = biome.base_temperature() + biome.daily_swing() * cycle;
+
+                let (sum, count) = NEIGHBORS
+                    .iter()
+                    .filter_map(|(dx, dz)| self.index(x + dx, z + dz))
+                    .fold((0., 0.), |(sum, count), n| (sum + previous[n], count + 1.));
+
+                let current = previous[i];
+                self.columns[i] =
+                    current + (target - current) * RELAXATION + (sum / count - current) * DIFFUSION;
+            }
+        }
+    }
+}
+
+/// Whether the heat-map overlay is drawn over the terrain.
+#[derive(Resource, Default)]
+struct TemperatureOverlay {
+    enabled: bool,
+}
+
+impl Plugin for TemperaturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TemperatureMap>()
+            .add_systems(OnEnter(AppState::InGame), setup_temperature)
+            .add_systems(Update, update_surface.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                FixedUpdate,
+                step_temperature
+                    .run_if(every(STEP))
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+impl Plugin for TemperatureOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TemperatureOverlay>().add_systems(
+            Update,
+            (toggle_overlay, draw_overlay).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// Starts every column on its target so the field doesn't have to warm up
+/// from nothing.
+fn setup_temperature(
+    terrain: Res<Terrain>,
+    time_of_day: Res<TimeOfDay>,
+    mut temperature: ResMut<TemperatureMap>,
+) {
+    temperature.update_surface(&terrain);
+
+    let cycle = time_of_day.daylight() * 2. - 1.;
+    let size = temperature.size;
+    for x in 0..size.x {
+        for z in 0..size.z {
+            if let Some(i) = temperature.index(x, z) {
+                let biome = biome_at(size, x, z);
+                temperature.columns[i] = biome.base_temperature() + biome.daily_swing() * cycle;
+            }
+        }
+    }
+}
+
+fn update_surface(
+    terrain: Res<Terrain>,
+    mut temperature: ResMut<TemperatureMap>,
+    mut ev_terrain_mod: EventReader<TerrainModifiedEvent>,
+) {
+    if ev_terrain_mod.is_empty() {
+        return;
+    }
+    ev_terrain_mod.clear();
+
+    temperature.update_surface(&terrain);
+}
+
+fn step_temperature(time_of_day: Res<TimeOfDay>, mut temperature: ResMut<TemperatureMap>) {
+    temperature.step(&time_of_day);
+}
+
+/// H shows the heat map, outside of build mode.
+fn toggle_overlay(
+    keys: Res<ButtonInput<KeyCode>>,
+    build: Res<BuildMode>,
+    mut overlay: ResMut<TemperatureOverlay>,
+) {
+    if !build.enabled && keys.just_pressed(KeyCode::KeyH) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+/// Color of a temperature on the heat map, blue at -10 through to red at 35.
+fn heat_color(temperature: f32) -> Color {
+    let t = ((temperature + 10.) / 45.).clamp(0., 1.);
+    Color::hsl(240. * (1. - t), 0.9, 0.5)
+}
+
+/// Tints a square over the top of each column, or over the slice where it
+/// cuts through the ground.
+fn draw_overlay(
+    mut gizmos: Gizmos,
+    terrain: Res<Terrain>,
+    temperature: Res<TemperatureMap>,
+    overlay: Res<TemperatureOverlay>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+
+    let rotation = Quat::from_rotation_x(FRAC_PI_2);
+    for x in 0..temperature.size.x {
+        for z in 0..temperature.size.z {
+            let Some(i) = temperature.index(x, z) else {
+                continue;
+            };
+            let y = temperature.surface[i].min(terrain.slice as i32);
+            if y == 0 {
+                continue;
+            }
+
+            let pos = IVec3::new(x, y, z);
+            let center = pos.as_vec3() + Vec3::new(0.5, 0.02, 0.5);
+            gizmos.rect(
+                center,
+                rotation,
+                Vec2::splat(0.9),
+                heat_color(temperature.temperature(pos)),
+            );
+        }
+    }
+}
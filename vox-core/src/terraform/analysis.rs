@@ -0,0 +1,228 @@
+use std::fmt::{self, Write};
+
+use bevy::prelude::*;
+
+use super::Selection;
+use crate::terrain::{Block, BlockChangedEvent, Terrain};
+
+/// Whether the open space in a selection is shut in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hollow {
+    /// There's no open space, only full blocks.
+    Solid,
+    /// Every open cell is walled in within the selection.
+    Sealed,
+    /// Open space runs out of the selection, or to the side of the map, from
+    /// this cell.
+    Leaks(IVec3),
+}
+
+/// What a box selection holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionReport {
+    pub size: IVec3,
+    /// Blocks of each kind, most common first.
+    pub counts: Vec<(Block, usize)>,
+    /// Blocks that are full cubes, the rest is open.
+    pub filled: usize,
+    pub hollow: Hollow,
+}
+
+impl SelectionReport {
+    pub fn volume(&self) -> usize {
+        (self.size.x * self.size.y * self.size.z) as usize
+    }
+}
+
+impl fmt::Display for SelectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let filled = self.filled as f32 / self.volume().max(1) as f32;
+        writeln!(
+            f,
+            "Selection: {}x{}x{}, {} blocks",
+            self.size.x,
+            self.size.y,
+            self.size.z,
+            self.volume()
+        )?;
+        writeln!(
+            f,
+            "Filled {:.0}%, open {:.0}%",
+            filled * 100.,
+            (1. - filled) * 100.
+        )?;
+        match self.hollow {
+            Hollow::Solid => write!(f, "Solid")?,
+            Hollow::Sealed => write!(f, "Sealed")?,
+            Hollow::Leaks(pos) => write!(f, "Leaks at {}", pos)?,
+        }
+        for (block, count) in &self.counts {
+            write!(f, "\n  {:?}: {}", block, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the blocks from `min` up to but not including `max` and checks
+/// whether its open space is sealed. Full blocks are the walls, anything
+/// else is open.
+pub fn analyze(terrain: &Terrain, min: IVec3, max: IVec3) -> SelectionReport {
+    let mut counts: Vec<(Block, usize)> = vec![];
+    let mut filled = 0;
+    let mut open = vec![];
+
+    for (pos, block) in terrain.iter_region(min, max) {
+        match counts.iter_mut().find(|(b, _)| *b == block) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((block, 1)),
+        }
+        if block.is_filled() {
+            filled += 1;
+        } else {
+            open.push(pos);
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let in_box = |pos: IVec3| pos.cmpge(min).all() && pos.cmplt(max).all();
+    // the fill stays in the box, so it can't take more cells than it holds
+    let volume = filled + open.len();
+    let mut hollow = if open.is_empty() {
+        Hollow::Solid
+    } else {
+        Hollow::Sealed
+    };
+    let mut visited = std::collections::HashSet::new();
+
+    for start in open {
+        if !visited.insert(start) {
+            continue;
+        }
+
+        let mut leak = None;
+        let region = terrain.flood_fill_limited(start, volume, |pos, block| {
+            if block.is_filled() {
+                return false;
+            }
+            if !in_box(pos) {
+                leak.get_or_insert(pos);
+                return false;
+            }
+            true
+        });
+        let leak = leak.or_else(|| {
+            // the side of the map, from whichever cell lies against it
+            let edge = terrain.size() - IVec3::ONE;
+            region.reaches_edge.then(|| {
+                let against = |pos: &&IVec3| pos.cmpeq(IVec3::ZERO).any() || pos.cmpeq(edge).any();
+                *region.cells.iter().find(against).unwrap_or(&start)
+            })
+        });
+
+        if let Some(pos) = leak {
+            hollow = Hollow::Leaks(pos);
+            break;
+        }
+        visited.extend(region.cells);
+    }
+
+    SelectionReport {
+        size: max - min,
+        counts,
+        filled,
+        hollow,
+    }
+}
+
+#[derive(Component)]
+pub(super) struct SelectionText;
+
+pub(super) fn spawn_selection_text(mut commands: Commands) {
+    let text = TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 16.,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(8.),
+        right: Val::Px(8.),
+        ..default()
+    })
+    .with_background_color(Color::rgba(0., 0., 0., 0.6));
+
+    commands.spawn((text, SelectionText, Visibility::Hidden));
+}
+
+pub(super) fn despawn_selection_text(
+    mut commands: Commands,
+    texts: Query<Entity, With<SelectionText>>,
+) {
+    for entity in texts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Describes the selection once both corners are set, again whenever it
+/// moves or a block inside it changes.
+pub(super) fn show_selection_report(
+    selection: Res<Selection>,
+    terrain: Res<Terrain>,
+    mut ev_block_changed: EventReader<BlockChangedEvent>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<SelectionText>>,
+) {
+    let bounds = selection.bounds();
+    let is_inside =
+        |pos: IVec3| bounds.is_some_and(|(min, max)| pos.cmpge(min).all() && pos.cmplt(max).all());
+    let is_edited = ev_block_changed.read().any(|ev| is_inside(ev.pos));
+    if !selection.is_changed() && !is_edited {
+        return;
+    }
+
+    let mut report = String::new();
+    if let Some((min, max)) = bounds {
+        let _ = write!(report, "{}", analyze(&terrain, min, max));
+    }
+
+    for (mut text, mut visibility) in texts.iter_mut() {
+        if bounds.is_some() {
+            text.sections[0].value.clone_from(&report);
+            *visibility = Visibility::Visible;
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures::room;
+
+    #[test]
+    fn reports_count_blocks_and_find_leaks() {
+        let mut terrain = room();
+        let (min, max) = (IVec3::splat(4), IVec3::splat(9));
+
+        let report = analyze(&terrain, min, max);
+        assert_eq!(report.size, IVec3::splat(5));
+        assert_eq!(report.filled, 125 - 27);
+        assert_eq!(
+            report.counts,
+            vec![(Block::Stone, 125 - 27), (Block::Empty, 27)]
+        );
+        assert_eq!(report.hollow, Hollow::Sealed);
+
+        // a door out through the wall
+        terrain.set_at(IVec3::new(8, 5, 6), Block::Empty);
+        let report = analyze(&terrain, min, max);
+        assert_eq!(report.hollow, Hollow::Leaks(IVec3::new(9, 5, 6)));
+
+        // a selection inside the wall is solid
+        let report = analyze(&terrain, IVec3::splat(4), IVec3::new(9, 5, 9));
+        assert_eq!(report.hollow, Hollow::Solid);
+    }
+}
@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    build::{BuildMode, BuildTool},
+    camera::CameraRay,
+    menu::AppState,
+    terrain::{Block, Terrain, TerrainModifiedEvent},
+};
+
+mod analysis;
+
+pub use analysis::{analyze, Hollow, SelectionReport};
+
+/// Box selections and the console commands that reshape them, and
+/// explosions.
+pub struct TerraformPlugin;
+
+/// `[` and `]` put the selection's corners on the block under the cursor,
+/// and once both are set a panel measures what's inside. The sculpt and
+/// smooth brushes reshape the ground under the cursor.
+pub struct TerraformViewPlugin;
+
+const SELECT_REACH: f32 = 64.;
+
+/// Columns within this many blocks of the brush center are reshaped.
+const BRUSH_RADIUS: i32 = 4;
+
+/// Largest selection a command runs on, in blocks.
+const MAX_VOLUME: i32 = 1 << 20;
+
+/// Edits kept for `undo`, oldest dropped first.
+const MAX_HISTORY: usize = 32;
+
+/// Largest radius an explosion reaches, in blocks.
+pub const MAX_EXPLOSION_RADIUS: f32 = 32.;
+
+/// Two opposite corner blocks of the selected box, each set on its own.
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub corners: [Option<IVec3>; 2],
+}
+
+impl Selection {
+    /// The lowest corner and the corner one past the highest, once both
+    /// corners are set.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        let [Some(a), Some(b)] = self.corners else {
+            return None;
+        };
+        Some((a.min(b), a.max(b) + IVec3::ONE))
+    }
+}
+
+/// A change to every block of the selection at once.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TerraformOp {
+    Fill(Block),
+    Clear,
+    /// Swaps one block for another, leaving the rest alone.
+    Replace(Block, Block),
+    /// Clears the selection from `level` up and fills the open blocks below
+    /// it with `block`.
+    Flatten {
+        level: i32,
+        block: Block,
+    },
+}
+
+/// Sent by the console to run a command on the selection.
+#[derive(Event, Debug, Copy, Clone)]
+pub enum TerraformEvent {
+    Apply(TerraformOp),
+    /// Puts back the blocks the last edit changed.
+    Undo,
+}
+
+/// Blows a round crater into the terrain. Each block within `radius` of
+/// `center` takes `power` seconds of mining work, less the further out it
+/// is, down to none at the edge, so soft blocks give way further out than
+/// hard ones and blocks that can't be mined stay. Blocks that hold out are
+/// left cracked. Broken blocks burst apart like mined ones, and the crater
+/// can be undone like a command.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct ExplosionEvent {
+    pub center: Vec3,
+    pub radius: f32,
+    pub power: f32,
+}
+
+/// Blocks an edit replaced, as they were before it.
+#[derive(Default)]
+pub struct TerraformEdit {
+    previous: Vec<(IVec3, Block)>,
+    /// Mining progress of every block the edit damaged, as it was before.
+    damaged: Vec<(IVec3, f32)>,
+}
+
+impl TerraformEdit {
+    /// Sets the block at `pos`, remembering what it was.
+    fn set(&mut self, terrain: &mut Terrain, pos: IVec3, block: Block) {
+        let previous = terrain.get_at(pos);
+        if block != previous {
+            terrain.set_at(pos, block);
+            self.previous.push((pos, previous));
+        }
+    }
+
+    /// Stacks another block of the surface's kind on a column, moving grass
+    /// up to the new top.
+    fn raise_column(&mut self, terrain: &mut Terrain, x: i32, z: i32) {
+        let Some(y) = terrain.surface_height(x, z) else {
+            return;
+        };
+        let top = IVec3::new(x, y, z);
+        let surface = terrain.get_at(top);
+        let above = top + IVec3::Y;
+
+        if terrain.get_at(above) != Block::Empty {
+            return;
+        }
+        if surface == Block::Grass {
+            self.set(terrain, top, Block::Dirt);
+        }
+        self.set(terrain, above, surface);
+    }
+
+    /// Takes the top block off a column, growing grass on the dirt below if
+    /// it was grassy.
+    fn lower_column(&mut self, terrain: &mut Terrain, x: i32, z: i32) {
+        let Some(y) = terrain.surface_height(x, z) else {
+            return;
+        };
+        // keep the bottom of the map
+        if y == 0 {
+            return;
+        }
+        let top = IVec3::new(x, y, z);
+        let surface = terrain.get_at(top);
+
+        // plants and ramps on top would be left floating
+        self.set(terrain, top + IVec3::Y, Block::Empty);
+        self.set(terrain, top, Block::Empty);
+
+        let below = top - IVec3::Y;
+        if surface == Block::Grass && terrain.get_at(below) == Block::Dirt {
+            self.set(terrain, below, Block::Grass);
+        }
+    }
+
+    /// Applies `work` seconds of mining to the block at `pos`, remembering it
+    /// if it breaks.
+    fn damage(&mut self, terrain: &mut Terrain, pos: IVec3, work: f32) {
+        let previous = terrain.get_at(pos);
+        self.damaged.push((pos, terrain.damage(pos)));
+        if terrain.add_damage(pos, work) {
+            self.previous.push((pos, previous));
+        }
+    }
+
+    /// Number of blocks replaced.
+    pub fn len(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Whether the edit neither replaced nor damaged any block.
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.damaged.is_empty()
+    }
+}
+
+#[derive(Resource, Default)]
+struct TerraformHistory {
+    edits: Vec<TerraformEdit>,
+}
+
+impl Plugin for TerraformPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<TerraformHistory>()
+            .add_event::<TerraformEvent>()
+            .add_event::<ExplosionEvent>()
+            .add_systems(
+                Update,
+                (run_terraform, run_explosions)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), clear_terraform);
+    }
+}
+
+impl Plugin for TerraformViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                select_corners.before(run_terraform),
+                brush_on_click.after(run_explosions),
+                draw_selection,
+                analysis::show_selection_report,
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(OnEnter(AppState::InGame), analysis::spawn_selection_text)
+        .add_systems(OnExit(AppState::InGame), analysis::despawn_selection_text);
+    }
+}
+
+/// Applies `op` to the blocks from `min` up to but not including `max`,
+/// returning what it replaced.
+pub fn terraform(terrain: &mut Terrain, min: IVec3, max: IVec3, op: TerraformOp) -> TerraformEdit {
+    let mut edit = TerraformEdit::default();
+    let blocks: Vec<_> = terrain.iter_region(min, max).collect();
+
+    for (pos, previous) in blocks {
+        let block = match op {
+            TerraformOp::Fill(block) => block,
+            TerraformOp::Clear => Block::Empty,
+            TerraformOp::Replace(from, to) if previous == from => to,
+            TerraformOp::Replace(..) => continue,
+            TerraformOp::Flatten { level, .. } if pos.y >= level => Block::Empty,
+            TerraformOp::Flatten { block, .. } if previous == Block::Empty => block,
+            TerraformOp::Flatten { .. } => continue,
+        };
+
+        edit.set(terrain, pos, block);
+    }
+
+    edit
+}
+
+/// Moves the surface of every column within `radius` of `center` up by one
+/// block when `raise` is set, down by one otherwise. A grassy surface stays
+/// grassy with dirt under it, other surfaces grow or shrink in kind.
+pub fn sculpt(terrain: &mut Terrain, center: IVec3, radius: i32, raise: bool) -> TerraformEdit {
+    let mut edit = TerraformEdit::default();
+
+    for (x, z) in brush(center, radius) {
+        if raise {
+            edit.raise_column(terrain, x, z);
+        } else {
+            edit.lower_column(terrain, x, z);
+        }
+    }
+
+    edit
+}
+
+/// Brings every column within `radius` of `center` to the average height of
+/// the columns around it, wearing down bumps and filling in pits.
+pub fn smooth(terrain: &mut Terrain, center: IVec3, radius: i32) -> TerraformEdit {
+    let mut edit = TerraformEdit::default();
+
+    // heights as they were before the stroke, so the order columns are
+    // visited in doesn't matter
+    let heights: HashMap<_, _> = brush(center, radius + 1)
+        .filter_map(|(x, z)| Some(((x, z), terrain.surface_height(x, z)?)))
+        .collect();
+
+    let heights = &heights;
+    for (x, z) in brush(center, radius) {
+        let Some(&height) = heights.get(&(x, z)) else {
+            continue;
+        };
+        let around: Vec<_> = (-1..=1)
+            .flat_map(|dx| (-1..=1).filter_map(move |dz| heights.get(&(x + dx, z + dz))))
+            .collect();
+        let target = (around.iter().copied().sum::<i32>() as f32 / around.len() as f32).round();
+
+        for _ in height..target as i32 {
+            edit.raise_column(terrain, x, z);
+        }
+        for _ in target as i32..height {
+            edit.lower_column(terrain, x, z);
+        }
+    }
+
+    edit
+}
+
+/// Carves the crater of an `ExplosionEvent` out of the terrain, returning
+/// the blocks it broke.
+pub fn explode(terrain: &mut Terrain, center: Vec3, radius: f32, power: f32) -> TerraformEdit {
+    let mut edit = TerraformEdit::default();
+    let radius = radius.min(MAX_EXPLOSION_RADIUS);
+    if radius <= 0. || power <= 0. {
+        return edit;
+    }
+
+    let min = (center - radius).floor().as_ivec3();
+    let max = (center + radius).ceil().as_ivec3();
+    let blocks: Vec<_> = terrain.iter_region(min, max).collect();
+
+    for (pos, block) in blocks {
+        let distance = (pos.as_vec3() + Vec3::splat(0.5)).distance(center);
+        if distance >= radius || !block.is_minable() {
+            continue;
+        }
+        edit.damage(terrain, pos, power * (1. - distance / radius));
+    }
+
+    edit
+}
+
+/// Columns of a round brush.
+fn brush(center: IVec3, radius: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-radius..=radius)
+        .flat_map(move |dx| (-radius..=radius).map(move |dz| (dx, dz)))
+        .filter(move |(dx, dz)| dx * dx + dz * dz <= radius * radius)
+        .map(move |(dx, dz)| (center.x + dx, center.z + dz))
+}
+
+/// Puts back the blocks `edit` replaced, and the cracks of the ones it
+/// damaged.
+pub fn undo(terrain: &mut Terrain, edit: TerraformEdit) {
+    for (pos, block) in edit.previous.into_iter().rev() {
+        terrain.set_at(pos, block);
+    }
+    // setting a block clears its damage, so it goes back last
+    for (pos, damage) in edit.damaged {
+        terrain.set_damage(pos, damage);
+    }
+}
+
+fn record(history: &mut TerraformHistory, edit: TerraformEdit) {
+    if history.edits.len() == MAX_HISTORY {
+        history.edits.remove(0);
+    }
+    history.edits.push(edit);
+}
+
+fn clear_terraform(mut selection: ResMut<Selection>, mut history: ResMut<TerraformHistory>) {
+    selection.corners = [None; 2];
+    history.edits.clear();
+}
+
+fn select_corners(
+    keys: Res<ButtonInput<KeyCode>>,
+    terrain: Res<Terrain>,
+    camera_ray: Res<CameraRay>,
+    mut selection: ResMut<Selection>,
+) {
+    let corner = if keys.just_pressed(KeyCode::BracketLeft) {
+        0
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        1
+    } else {
+        return;
+    };
+
+    let Some(ray) = camera_ray.0 else {
+        return;
+    };
+
+    if let Some(hit) = terrain.raycast(ray.origin, *ray.direction, SELECT_REACH) {
+        selection.corners[corner] = Some(hit.pos);
+    }
+}
+
+fn run_terraform(
+    mut terrain: ResMut<Terrain>,
+    selection: Res<Selection>,
+    mut history: ResMut<TerraformHistory>,
+    mut ev_terraform: EventReader<TerraformEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    for ev in ev_terraform.read() {
+        let op = match *ev {
+            TerraformEvent::Apply(op) => op,
+            TerraformEvent::Undo => {
+                match history.edits.pop() {
+                    Some(edit) => {
+                        println!("Undid {} blocks", edit.len());
+                        undo(&mut terrain, edit);
+                        ev_terrain_mod.send(TerrainModifiedEvent);
+                    }
+                    None => println!("Nothing to undo"),
+                }
+                continue;
+            }
+        };
+
+        let Some((min, max)) = selection.bounds() else {
+            println!("Select two corners with [ and ] first");
+            continue;
+        };
+        let size = max - min;
+        if size.x * size.y * size.z > MAX_VOLUME {
+            println!("Selection is larger than {} blocks", MAX_VOLUME);
+            continue;
+        }
+
+        let edit = terraform(&mut terrain, min, max, op);
+        println!("Changed {} blocks", edit.len());
+        if edit.is_empty() {
+            continue;
+        }
+
+        ev_terrain_mod.send(TerrainModifiedEvent);
+        record(&mut history, edit);
+    }
+}
+
+fn run_explosions(
+    mut terrain: ResMut<Terrain>,
+    mut history: ResMut<TerraformHistory>,
+    mut ev_explosion: EventReader<ExplosionEvent>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    for ev in ev_explosion.read() {
+        let edit = explode(&mut terrain, ev.center, ev.radius, ev.power);
+        println!("Explosion broke {} blocks", edit.len());
+
+        // cracks show even when nothing broke
+        ev_terrain_mod.send(TerrainModifiedEvent);
+        if !edit.is_empty() {
+            record(&mut history, edit);
+        }
+    }
+}
+
+/// With the sculpt tool, left click raises the ground around the target and
+/// right click lowers it. With the smooth tool either smooths it. Each
+/// stroke can be undone like a command.
+fn brush_on_click(
+    buttons: Res<ButtonInput<MouseButton>>,
+    build: Res<BuildMode>,
+    mut terrain: ResMut<Terrain>,
+    mut history: ResMut<TerraformHistory>,
+    mut ev_terrain_mod: EventWriter<TerrainModifiedEvent>,
+) {
+    let (true, Some(target)) = (build.enabled, build.target) else {
+        return;
+    };
+
+    let raise = if buttons.just_pressed(MouseButton::Left) {
+        true
+    } else if buttons.just_pressed(MouseButton::Right) {
+        false
+    } else {
+        return;
+    };
+
+    let edit = match build.tool {
+        BuildTool::Sculpt => sculpt(&mut terrain, target, BRUSH_RADIUS, raise),
+        BuildTool::Smooth => smooth(&mut terrain, target, BRUSH_RADIUS),
+        _ => return,
+    };
+    if !edit.is_empty() {
+        ev_terrain_mod.send(TerrainModifiedEvent);
+        record(&mut history, edit);
+    }
+}
+
+fn draw_selection(selection: Res<Selection>, build: Res<BuildMode>, mut gizmos: Gizmos) {
+    let is_brush = matches!(build.tool, BuildTool::Sculpt | BuildTool::Smooth);
+    if let (true, true, Some(target)) = (build.enabled, is_brush, build.target) {
+        gizmos.circle(
+            target.as_vec3() + Vec3::new(0.5, 1.05, 0.5),
+            Direction3d::Y,
+            BRUSH_RADIUS as f32 + 0.5,
+            Color::YELLOW,
+        );
+    }
+
+    let (min, max) = match selection.corners {
+        [Some(a), Some(b)] => (a.min(b), a.max(b) + IVec3::ONE),
+        [Some(a), None] | [None, Some(a)] => (a, a + IVec3::ONE),
+        [None, None] => return,
+    };
+
+    let size = (max - min).as_vec3();
+    let transform = Transform::from_translation(min.as_vec3() + size / 2.).with_scale(size);
+    gizmos.cuboid(transform, Color::YELLOW);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures;
+
+    fn ground() -> Terrain {
+        let mut terrain = Terrain::new(IVec3::splat(16));
+        for (pos, _) in terrain.iter().collect::<Vec<_>>() {
+            if pos.y < 4 + pos.x % 3 {
+                terrain.set_at(pos, Block::Dirt);
+            }
+        }
+        terrain
+    }
+
+    #[test]
+    fn flatten_levels_the_selection() {
+        let mut terrain = ground();
+        let op = TerraformOp::Flatten {
+            level: 5,
+            block: Block::Stone,
+        };
+        terraform(&mut terrain, IVec3::ZERO, IVec3::new(8, 16, 8), op);
+
+        for x in 0..8 {
+            assert!(terrain.get_at(IVec3::new(x, 4, 3)).is_filled());
+            assert_eq!(terrain.get_at(IVec3::new(x, 5, 3)), Block::Empty);
+        }
+        // outside the selection stays as it was
+        assert_eq!(terrain.get_at(IVec3::new(9, 4, 3)), Block::Empty);
+    }
+
+    #[test]
+    fn sculpting_keeps_the_grass_on_top() {
+        let mut terrain = ground();
+        let center = IVec3::new(8, 0, 8);
+        for x in 0..16 {
+            for z in 0..16 {
+                let y = terrain.surface_height(x, z).unwrap();
+                terrain.set_at(IVec3::new(x, y, z), Block::Grass);
+            }
+        }
+
+        sculpt(&mut terrain, center, 2, true);
+        let y = terrain.surface_height(8, 8).unwrap();
+        assert_eq!(y, 6);
+        assert_eq!(terrain.get_at(IVec3::new(8, y, 8)), Block::Grass);
+        assert_eq!(terrain.get_at(IVec3::new(8, y - 1, 8)), Block::Dirt);
+
+        sculpt(&mut terrain, center, 2, false);
+        sculpt(&mut terrain, center, 2, false);
+        let y = terrain.surface_height(8, 8).unwrap();
+        assert_eq!(y, 4);
+        assert_eq!(terrain.get_at(IVec3::new(8, y, 8)), Block::Grass);
+        // outside the brush
+        assert_eq!(terrain.surface_height(8, 11), Some(5));
+    }
+
+    #[test]
+    fn smoothing_wears_down_a_spike() {
+        let mut terrain = ground();
+        for y in 0..12 {
+            terrain.set_at(IVec3::new(7, y, 7), Block::Stone);
+        }
+
+        smooth(&mut terrain, IVec3::new(7, 0, 7), 2);
+        let y = terrain.surface_height(7, 7).unwrap();
+        assert!(y < 7, "spike still {} high", y);
+    }
+
+    #[test]
+    fn explosions_break_soft_blocks_further_out() {
+        let mut terrain = fixtures::ground(IVec3::splat(16), 16, Block::Dirt);
+        fixtures::fill(
+            &mut terrain,
+            IVec3::new(8, 0, 0),
+            IVec3::splat(16),
+            Block::Stone,
+        );
+        // a block cracked before the blast is cracked again after the undo
+        let cracked = IVec3::new(13, 7, 7);
+        terrain.add_damage(cracked, 1.);
+        let before: Vec<_> = terrain.iter().collect();
+        let cracked_damage = terrain.damage(cracked);
+        let cracked_stage = terrain.damage_stage(cracked);
+        assert!(cracked_stage > 0);
+
+        let center = Vec3::splat(8.);
+        let edit = explode(&mut terrain, center, 6., 6.);
+
+        // dirt breaks with a sixth of the power, stone needs half
+        assert_eq!(terrain.get_at(IVec3::new(3, 7, 7)), Block::Empty);
+        assert_eq!(terrain.get_at(IVec3::new(10, 7, 7)), Block::Empty);
+        assert_eq!(terrain.get_at(IVec3::new(12, 7, 7)), Block::Stone);
+        assert!(terrain.damage_stage(IVec3::new(12, 7, 7)) > 0);
+        assert_eq!(terrain.get_at(IVec3::new(1, 7, 7)), Block::Dirt);
+        assert!(terrain.damage(cracked) > cracked_damage);
+
+        undo(&mut terrain, edit);
+        assert!(terrain.iter().eq(before));
+        assert_eq!(terrain.damage_stage(IVec3::new(12, 7, 7)), 0);
+        assert_eq!(terrain.damage(cracked), cracked_damage);
+        assert_eq!(terrain.damage_stage(cracked), cracked_stage);
+    }
+
+    #[test]
+    fn undo_restores_the_blocks() {
+        let mut terrain = ground();
+        let before: Vec<_> = terrain.iter().collect();
+
+        let op = TerraformOp::Replace(Block::Dirt, Block::Clay);
+        let edit = terraform(&mut terrain, IVec3::new(2, 0, 2), IVec3::new(9, 9, 9), op);
+        assert!(!edit.is_empty());
+        assert_eq!(terrain.get_at(IVec3::new(2, 0, 2)), Block::Clay);
+
+        undo(&mut terrain, edit);
+        assert!(terrain.iter().eq(before));
+    }
+}
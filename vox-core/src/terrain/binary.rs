@@ -0,0 +1,313 @@
+use bevy::math::{IVec3, Vec3};
+
+use super::{
+    mesher::{corner_darkness, mark_damage, mark_darkness, pack_block, DARKNESS_SHIFT},
+    shapes, Block, BlockShape, ChunkView, FaceDir, Terrain, TerrainMeshData, CHUNK_SIZE,
+};
+
+const SIZE: usize = CHUNK_SIZE as usize;
+/// Cells along a column, counting the neighbor past either end of the chunk.
+const PADDED: usize = SIZE + 2;
+
+// a padded column has to fit one u32
+const _: () = assert!(PADDED <= 32);
+
+/// The two axes spanning the faces that point along each axis.
+const PLANES: [(usize, usize); 3] = [(1, 2), (0, 2), (0, 1)];
+
+/// Occupancy of a chunk as bit columns along each axis, bit `i` being the
+/// cell at `i - 1` so the neighbors on either side fit too.
+struct Columns {
+    /// Cubes below the slice, inside the chunk.
+    filled: [[[u32; SIZE]; SIZE]; 3],
+    /// Cells whose face towards the negative end of the axis is covered.
+    covers_neg: [[[u32; SIZE]; SIZE]; 3],
+    /// Cells whose face towards the positive end of the axis is covered.
+    covers_pos: [[[u32; SIZE]; SIZE]; 3],
+}
+
+/// What `greedy_quads` finds in a chunk.
+enum Part {
+    /// A block with a partial shape, left to `shapes`.
+    Shape(IVec3, Block),
+    Quad(Quad),
+}
+
+/// A run of identical cube faces merged into one quad.
+struct Quad {
+    /// The first cell of the run.
+    cell: IVec3,
+    axis: usize,
+    positive: bool,
+    /// Cells the quad spans along the first and second axes of its plane.
+    height: usize,
+    width: usize,
+    /// The packed block, with the darkness of all four corners in the order
+    /// `corners` returns them.
+    packed: u32,
+}
+
+impl Quad {
+    fn dir(&self) -> FaceDir {
+        face_dir(self.axis, self.positive)
+    }
+
+    fn corners(&self) -> [Vec3; 4] {
+        let (u_axis, v_axis) = PLANES[self.axis];
+        let mut base = self.cell.as_vec3();
+        if self.positive {
+            base[self.axis] += 1.;
+        }
+        let mut du = Vec3::ZERO;
+        du[u_axis] = self.height as f32;
+        let mut dv = Vec3::ZERO;
+        dv[v_axis] = self.width as f32;
+
+        [base, base + du, base + du + dv, base + dv]
+    }
+}
+
+/// Meshes a chunk like `mesh_chunk`, but finds visible cube faces with shifts
+/// and masks over bit columns and merges runs of identical faces into
+/// larger quads. Partial shapes are meshed the same way as before.
+pub fn mesh_chunk_binary_into(terrain: &Terrain, chunk: IVec3, data: &mut TerrainMeshData) {
+    mesh_binary(&ChunkView::new(terrain, chunk), data);
+}
+
+pub(super) fn mesh_binary(view: &ChunkView, data: &mut TerrainMeshData) {
+    data.clear();
+
+    greedy_quads(view, |part| match part {
+        Part::Shape(pos, block) => {
+            let start = data.packed.len();
+            shapes::mesh_shape(data, view, pos, block);
+            mark_damage(data, start, view.damage_stage(pos));
+            mark_darkness(data, start, view.light_at(pos));
+        }
+        Part::Quad(quad) => push_face(data, quad.corners(), quad.dir(), quad.packed),
+    });
+}
+
+/// The merged faces of a chunk two words each, for drawing by vertex
+/// pulling: the chunk-local cell with the quad's height and width less one
+/// at five bits each, then the packed block. False when the chunk has
+/// partial shapes, which only vertex buffers can draw.
+pub(super) fn pull_faces_into(view: &ChunkView, faces: &mut Vec<u32>) -> bool {
+    faces.clear();
+
+    let min = view.chunk * CHUNK_SIZE;
+    let mut cubes_only = true;
+    greedy_quads(view, |part| match part {
+        Part::Shape(..) => cubes_only = false,
+        Part::Quad(quad) => {
+            let local = (quad.cell - min).as_uvec3();
+            let extent = (quad.height as u32 - 1) << 15 | (quad.width as u32 - 1) << 20;
+            faces.push(local.x | local.y << 5 | local.z << 10 | extent);
+            faces.push(quad.packed);
+        }
+    });
+    cubes_only
+}
+
+/// Hands every partial shape and merged cube face of a chunk to `emit`.
+fn greedy_quads(view: &ChunkView, mut emit: impl FnMut(Part)) {
+    let min = view.chunk * CHUNK_SIZE;
+    let slice = view.slice as i32;
+    let mut columns = Columns {
+        filled: [[[0; SIZE]; SIZE]; 3],
+        covers_neg: [[[0; SIZE]; SIZE]; 3],
+        covers_pos: [[[0; SIZE]; SIZE]; 3],
+    };
+
+    for x in 0..PADDED {
+        for y in 0..PADDED {
+            for z in 0..PADDED {
+                let padded = [x, y, z];
+                let inside = padded.map(|i| (1..=SIZE).contains(&i));
+                // only the cells the columns reach, not the padded edges
+                if inside.iter().filter(|i| !**i).count() > 1 {
+                    continue;
+                }
+
+                let pos = min + IVec3::new(x as i32, y as i32, z as i32) - IVec3::ONE;
+                let block = view.get_at(pos);
+                let shape = block.def().shape;
+
+                if inside == [true; 3] && pos.y < slice {
+                    if shape == BlockShape::Cube {
+                        for (axis, &(u, v)) in PLANES.iter().enumerate() {
+                            columns.filled[axis][padded[u] - 1][padded[v] - 1] |= 1 << padded[axis];
+                        }
+                    } else if shape != BlockShape::None {
+                        emit(Part::Shape(pos, block));
+                    }
+                }
+
+                for (axis, &(u, v)) in PLANES.iter().enumerate() {
+                    if !inside[u] || !inside[v] {
+                        continue;
+                    }
+                    let bit = 1 << padded[axis];
+                    if view.covers(block, face_dir(axis, false)) {
+                        columns.covers_neg[axis][padded[u] - 1][padded[v] - 1] |= bit;
+                    }
+                    if view.covers(block, face_dir(axis, true)) {
+                        columns.covers_pos[axis][padded[u] - 1][padded[v] - 1] |= bit;
+                    }
+                }
+            }
+        }
+    }
+
+    for (axis, &(u_axis, v_axis)) in PLANES.iter().enumerate() {
+        for positive in [true, false] {
+            let dir = face_dir(axis, positive);
+            let mut faces = [[0u32; SIZE]; SIZE];
+
+            for (u, row) in faces.iter_mut().enumerate() {
+                for (v, column) in row.iter_mut().enumerate() {
+                    let filled = columns.filled[axis][u][v];
+                    *column = if positive {
+                        filled & !(columns.covers_neg[axis][u][v] >> 1)
+                    } else {
+                        filled & !(columns.covers_pos[axis][u][v] << 1)
+                    };
+                }
+            }
+
+            // tops at the slice are always drawn, whatever is above
+            let top = slice - 1 - min.y;
+            if axis == 1 && positive && (0..CHUNK_SIZE).contains(&top) {
+                for (u, row) in faces.iter_mut().enumerate() {
+                    for (v, column) in row.iter_mut().enumerate() {
+                        *column |= columns.filled[axis][u][v] & (1 << (top + 1));
+                    }
+                }
+            }
+
+            for layer in 1..=SIZE {
+                let mut rows = [0u32; SIZE];
+                for (row, columns) in rows.iter_mut().zip(&faces) {
+                    for (v, column) in columns.iter().enumerate() {
+                        *row |= ((column >> layer) & 1) << v;
+                    }
+                }
+                if rows.iter().all(|row| *row == 0) {
+                    continue;
+                }
+
+                let cell = |u: usize, v: usize| {
+                    let mut local = IVec3::ZERO;
+                    local[axis] = layer as i32 - 1;
+                    local[u_axis] = u as i32;
+                    local[v_axis] = v as i32;
+                    min + local
+                };
+
+                let mut packed = [[0u32; SIZE]; SIZE];
+                for (u, row) in rows.iter().enumerate() {
+                    let mut bits = *row;
+                    while bits != 0 {
+                        let v = bits.trailing_zeros() as usize;
+                        bits &= bits - 1;
+                        let pos = cell(u, v);
+                        packed[u][v] = pack_block(view.get_at(pos), dir)
+                            | view.damage_stage(pos) << 12
+                            | face_darkness(view, pos, axis, positive) << DARKNESS_SHIFT;
+                    }
+                }
+
+                for u in 0..SIZE {
+                    while rows[u] != 0 {
+                        let v0 = rows[u].trailing_zeros() as usize;
+                        let face = packed[u][v0];
+
+                        // widest run of set bits, cut short where the face changes
+                        let run = (rows[u] >> v0).trailing_ones() as usize;
+                        let width = (v0..v0 + run).take_while(|v| packed[u][*v] == face).count();
+                        let mask = (u32::MAX >> (32 - width)) << v0;
+
+                        let height = (u..SIZE)
+                            .take_while(|uu| {
+                                rows[*uu] & mask == mask
+                                    && packed[*uu][v0..v0 + width].iter().all(|p| *p == face)
+                            })
+                            .count();
+                        for row in &mut rows[u..u + height] {
+                            *row &= !mask;
+                        }
+
+                        emit(Part::Quad(Quad {
+                            cell: cell(u, v0),
+                            axis,
+                            positive,
+                            height,
+                            width,
+                            packed: face,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Darkness of the four corners of the face of the cube at `pos`, four bits
+/// each in the order of `Quad::corners`. Faces only merge where all four
+/// match, so a merged quad shades the same as its cells would.
+fn face_darkness(view: &ChunkView, pos: IVec3, axis: usize, positive: bool) -> u32 {
+    let (u_axis, v_axis) = PLANES[axis];
+    let mut front = pos;
+    front[axis] += if positive { 1 } else { -1 };
+    let mut base = pos;
+    if positive {
+        base[axis] += 1;
+    }
+
+    [(0, 0), (1, 0), (1, 1), (0, 1)]
+        .into_iter()
+        .enumerate()
+        .fold(0, |packed, (i, (du, dv))| {
+            let mut corner = base;
+            corner[u_axis] += du;
+            corner[v_axis] += dv;
+            packed | corner_darkness(view, front, axis, corner) << (i * 4)
+        })
+}
+
+/// Pushes a quad, ordered counter-clockwise when seen from outside. Each
+/// corner keeps only its own darkness out of `packed`.
+fn push_face(data: &mut TerrainMeshData, corners: [Vec3; 4], dir: FaceDir, packed: u32) {
+    let idx = data.positions.len() as u32;
+    let normal = dir.normal().as_vec3();
+    let block = packed & ((1 << DARKNESS_SHIFT) - 1);
+
+    for (i, corner) in corners.into_iter().enumerate() {
+        let darkness = packed >> (DARKNESS_SHIFT + i as u32 * 4) & 15;
+        data.positions.push(corner.to_array());
+        data.normals.push(normal.to_array());
+        data.packed.push(block | darkness << DARKNESS_SHIFT);
+    }
+
+    let facing = (corners[1] - corners[0])
+        .cross(corners[2] - corners[0])
+        .dot(normal);
+    if facing > 0. {
+        data.indicies
+            .extend([idx, idx + 1, idx + 2, idx + 2, idx + 3, idx]);
+    } else {
+        data.indicies
+            .extend([idx, idx + 3, idx + 2, idx + 2, idx + 1, idx]);
+    }
+}
+
+fn face_dir(axis: usize, positive: bool) -> FaceDir {
+    match (axis, positive) {
+        (0, true) => FaceDir::PosX,
+        (0, false) => FaceDir::NegX,
+        (1, true) => FaceDir::PosY,
+        (1, false) => FaceDir::NegY,
+        (2, true) => FaceDir::PosZ,
+        _ => FaceDir::NegZ,
+    }
+}
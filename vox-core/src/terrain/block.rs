@@ -0,0 +1,737 @@
+use bevy::math::{IVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::registry::{self, MODDED_ID_BASE};
+
+/// Horizontal direction a block faces. North is -Z, matching the "front" neighbor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    pub const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    pub fn offset(&self) -> IVec3 {
+        match self {
+            Facing::North => IVec3::NEG_Z,
+            Facing::East => IVec3::X,
+            Facing::South => IVec3::Z,
+            Facing::West => IVec3::NEG_X,
+        }
+    }
+
+    pub fn from_offset(offset: IVec3) -> Option<Facing> {
+        Facing::ALL.into_iter().find(|f| f.offset() == offset)
+    }
+
+    pub fn opposite(&self) -> Facing {
+        match self {
+            Facing::North => Facing::South,
+            Facing::East => Facing::West,
+            Facing::South => Facing::North,
+            Facing::West => Facing::East,
+        }
+    }
+
+    /// The horizontal direction closest to `dir`.
+    pub fn from_dir(dir: Vec3) -> Facing {
+        if dir.x.abs() > dir.z.abs() {
+            if dir.x > 0. {
+                Facing::East
+            } else {
+                Facing::West
+            }
+        } else if dir.z > 0. {
+            Facing::South
+        } else {
+            Facing::North
+        }
+    }
+
+    /// Maps a point in block-local space authored facing south onto this facing,
+    /// rotating about the block center.
+    pub fn rotate(&self, p: Vec3) -> Vec3 {
+        match self {
+            Facing::South => p,
+            Facing::East => Vec3::new(p.z, p.y, 1. - p.x),
+            Facing::North => Vec3::new(1. - p.x, p.y, 1. - p.z),
+            Facing::West => Vec3::new(1. - p.z, p.y, p.x),
+        }
+    }
+}
+
+/// Per-voxel rotation of a directional block: one of the four facings, or
+/// pointing straight up or down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+    Up,
+    Down,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 6] = [
+        Orientation::North,
+        Orientation::East,
+        Orientation::South,
+        Orientation::West,
+        Orientation::Up,
+        Orientation::Down,
+    ];
+
+    pub fn offset(&self) -> IVec3 {
+        match self {
+            Orientation::North => IVec3::NEG_Z,
+            Orientation::East => IVec3::X,
+            Orientation::South => IVec3::Z,
+            Orientation::West => IVec3::NEG_X,
+            Orientation::Up => IVec3::Y,
+            Orientation::Down => IVec3::NEG_Y,
+        }
+    }
+
+    pub fn from_normal(normal: IVec3) -> Option<Orientation> {
+        match normal.to_array() {
+            [0, 1, 0] => Some(Orientation::Up),
+            [0, -1, 0] => Some(Orientation::Down),
+            _ => Facing::from_offset(normal).map(Orientation::from),
+        }
+    }
+
+    /// Packed orientation, zero is left for blocks without one.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Orientation::North => 1,
+            Orientation::East => 2,
+            Orientation::South => 3,
+            Orientation::West => 4,
+            Orientation::Up => 5,
+            Orientation::Down => 6,
+        }
+    }
+}
+
+impl From<Facing> for Orientation {
+    fn from(facing: Facing) -> Self {
+        match facing {
+            Facing::North => Orientation::North,
+            Facing::East => Orientation::East,
+            Facing::South => Orientation::South,
+            Facing::West => Orientation::West,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FaceDir {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl FaceDir {
+    pub fn from_normal(normal: IVec3) -> FaceDir {
+        match normal.to_array() {
+            [1, 0, 0] => FaceDir::PosX,
+            [-1, 0, 0] => FaceDir::NegX,
+            [0, 1, 0] => FaceDir::PosY,
+            [0, -1, 0] => FaceDir::NegY,
+            [0, 0, 1] => FaceDir::PosZ,
+            _ => FaceDir::NegZ,
+        }
+    }
+
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            FaceDir::PosX => IVec3::X,
+            FaceDir::NegX => IVec3::NEG_X,
+            FaceDir::PosY => IVec3::Y,
+            FaceDir::NegY => IVec3::NEG_Y,
+            FaceDir::PosZ => IVec3::Z,
+            FaceDir::NegZ => IVec3::NEG_Z,
+        }
+    }
+
+    /// The two axes the face spans, in x, y, z order.
+    pub fn tangents(&self) -> (IVec3, IVec3) {
+        match self {
+            FaceDir::PosX | FaceDir::NegX => (IVec3::Y, IVec3::Z),
+            FaceDir::PosY | FaceDir::NegY => (IVec3::X, IVec3::Z),
+            FaceDir::PosZ | FaceDir::NegZ => (IVec3::X, IVec3::Y),
+        }
+    }
+
+    pub fn bit(&self) -> u32 {
+        match self {
+            FaceDir::PosX => 0,
+            FaceDir::NegX => 1,
+            FaceDir::PosY => 2,
+            FaceDir::NegY => 3,
+            FaceDir::PosZ => 4,
+            FaceDir::NegZ => 5,
+        }
+    }
+}
+
+/// Geometry the mesher emits for a block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum BlockShape {
+    None,
+    Cube,
+    /// Bottom half of a cube.
+    Slab,
+    /// A bottom slab with a full-height back half toward the facing.
+    Stairs(Facing),
+    /// A slope rising toward the facing.
+    Ramp(Facing),
+    /// A thin panel against the wall on the facing side.
+    Ladder(Facing),
+    /// Two crossed panels through the cell's diagonals, for plants.
+    Cross,
+    /// A cube sitting a little low unless more of the liquid is on top, with
+    /// no faces between cells of the same liquid.
+    Liquid,
+    /// Drawn by the block's own entity, the mesher leaves the cell empty.
+    Custom,
+}
+
+impl BlockShape {
+    /// Whether the shape completely covers the side of its cell in direction
+    /// `face`, hiding whatever neighbor face sits against it.
+    pub fn covers(&self, face: FaceDir) -> bool {
+        match self {
+            BlockShape::None
+            | BlockShape::Ladder(_)
+            | BlockShape::Cross
+            | BlockShape::Liquid
+            | BlockShape::Custom => false,
+            BlockShape::Cube => true,
+            BlockShape::Slab => face == FaceDir::NegY,
+            BlockShape::Stairs(facing) | BlockShape::Ramp(facing) => {
+                face == FaceDir::NegY || face == FaceDir::from_normal(facing.offset())
+            }
+        }
+    }
+
+    pub fn is_climbable(&self) -> bool {
+        matches!(self, BlockShape::Ladder(_))
+    }
+
+    /// Facing of a shape agents can walk up to reach the next level.
+    pub fn incline(&self) -> Option<Facing> {
+        match self {
+            BlockShape::Stairs(facing) | BlockShape::Ramp(facing) => Some(*facing),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Block {
+    Oob,
+    Empty,
+    Dirt,
+    /// Dirt with a grassy top, grows where the sky reaches.
+    Grass,
+    Stone,
+    Clay,
+    Sandstone,
+    Basalt,
+    Coal,
+    Iron,
+    Gold,
+    Ramp(Facing),
+    Slab,
+    Stairs(Facing),
+    Ladder(Facing),
+    Door(Facing),
+    Log(Orientation),
+    Leaves,
+    /// A young tree, grows into a full one over time.
+    Sapling,
+    Water,
+    /// Molten rock, creeps downhill and sets into stone against water.
+    Lava,
+    /// A burning cell, drawn and ticked by its block entity.
+    Fire,
+    /// What's left after a fire burns out.
+    Ash,
+    /// Part of a multi-block structure, drawn by the structure's root entity.
+    Structure,
+    /// Picked from the canopy of a felled tree, what agents eat.
+    Fruit,
+    /// A block added by a mod pack, by its index in the registry.
+    Modded(#[serde(with = "registry::by_name")] u16),
+}
+
+/// What a block is made of, picks the sounds it makes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum BlockMaterial {
+    None,
+    Soil,
+    Stone,
+    Wood,
+}
+
+/// Registry entry describing how a block looks and behaves.
+#[derive(Debug, Copy, Clone)]
+pub struct BlockDef {
+    pub name: &'static str,
+    pub texture_id: u32,
+    /// Texture for the two faces along the block's orientation axis, like the
+    /// rings at the ends of a log. Blocks without an orientation use it for
+    /// their top face only.
+    pub end_texture_id: Option<u32>,
+    pub shape: BlockShape,
+    /// Seconds of work needed to break the block, zero if it can't be mined.
+    pub hardness: f32,
+    pub material: BlockMaterial,
+    /// Whether fire can spread into the block.
+    pub flammable: bool,
+    /// Block light the block gives off, up to `MAX_LIGHT`. Also draws it
+    /// unshaded, glowing.
+    pub light: u8,
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Block::Ramp(facing)
+            | Block::Stairs(facing)
+            | Block::Ladder(facing)
+            | Block::Door(facing) => {
+                write!(f, "{}({:?})", self.def().name, facing)
+            }
+            Block::Log(orientation) => write!(f, "{}({:?})", self.def().name, orientation),
+            _ => write!(f, "{}", self.def().name),
+        }
+    }
+}
+
+impl Block {
+    /// Stable number for the block in saves and over the network, its kind
+    /// in the high byte and its packed facing or orientation in the low one.
+    /// Kinds are written to disk, so new blocks take new numbers. Modded
+    /// blocks count up from `MODDED_ID_BASE` instead, and saves keep a table
+    /// of their names.
+    pub fn id(&self) -> u16 {
+        let (kind, state) = match *self {
+            Block::Oob => (0, 0),
+            Block::Empty => (1, 0),
+            Block::Dirt => (2, 0),
+            Block::Grass => (3, 0),
+            Block::Stone => (4, 0),
+            Block::Clay => (5, 0),
+            Block::Sandstone => (6, 0),
+            Block::Basalt => (7, 0),
+            Block::Coal => (8, 0),
+            Block::Iron => (9, 0),
+            Block::Gold => (10, 0),
+            Block::Ramp(facing) => (11, Orientation::from(facing).bits()),
+            Block::Slab => (12, 0),
+            Block::Stairs(facing) => (13, Orientation::from(facing).bits()),
+            Block::Ladder(facing) => (14, Orientation::from(facing).bits()),
+            Block::Door(facing) => (15, Orientation::from(facing).bits()),
+            Block::Log(orientation) => (16, orientation.bits()),
+            Block::Leaves => (17, 0),
+            Block::Sapling => (18, 0),
+            Block::Water => (19, 0),
+            Block::Fire => (20, 0),
+            Block::Ash => (21, 0),
+            Block::Structure => (22, 0),
+            Block::Lava => (23, 0),
+            Block::Fruit => (24, 0),
+            Block::Modded(index) => return MODDED_ID_BASE + index,
+        };
+
+        (kind << 8) | state as u16
+    }
+
+    /// The block `id` stands for, None for numbers no block gives.
+    pub fn from_id(id: u16) -> Option<Block> {
+        if id >= MODDED_ID_BASE {
+            let index = id - MODDED_ID_BASE;
+            return registry::modded_def(index).map(|_| Block::Modded(index));
+        }
+
+        let state = (id & 0xff) as u32;
+        let orientation = Orientation::ALL.into_iter().find(|o| o.bits() == state);
+        let facing = Facing::ALL
+            .into_iter()
+            .find(|f| Orientation::from(*f).bits() == state);
+
+        let block = match id >> 8 {
+            0 => Block::Oob,
+            1 => Block::Empty,
+            2 => Block::Dirt,
+            3 => Block::Grass,
+            4 => Block::Stone,
+            5 => Block::Clay,
+            6 => Block::Sandstone,
+            7 => Block::Basalt,
+            8 => Block::Coal,
+            9 => Block::Iron,
+            10 => Block::Gold,
+            11 => Block::Ramp(facing?),
+            12 => Block::Slab,
+            13 => Block::Stairs(facing?),
+            14 => Block::Ladder(facing?),
+            15 => Block::Door(facing?),
+            16 => Block::Log(orientation?),
+            17 => Block::Leaves,
+            18 => Block::Sapling,
+            19 => Block::Water,
+            20 => Block::Fire,
+            21 => Block::Ash,
+            22 => Block::Structure,
+            23 => Block::Lava,
+            24 => Block::Fruit,
+            _ => return None,
+        };
+
+        // catches a state on a block that doesn't take one
+        (block.id() == id).then_some(block)
+    }
+
+    /// The block's definition, with any changes `blocks.ron` makes.
+    pub fn def(&self) -> BlockDef {
+        registry::overridden(*self, self.base_def())
+    }
+
+    fn base_def(&self) -> BlockDef {
+        match *self {
+            Block::Oob => BlockDef {
+                name: "Oob",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::None,
+                hardness: 0.,
+                material: BlockMaterial::None,
+                flammable: false,
+                light: 0,
+            },
+            Block::Empty => BlockDef {
+                name: "Empty",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::None,
+                hardness: 0.,
+                material: BlockMaterial::None,
+                flammable: false,
+                light: 0,
+            },
+            Block::Dirt => BlockDef {
+                name: "Dirt",
+                texture_id: 1,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 1.,
+                material: BlockMaterial::Soil,
+                flammable: false,
+                light: 0,
+            },
+            Block::Grass => BlockDef {
+                name: "Grass",
+                texture_id: 1,
+                end_texture_id: Some(7),
+                shape: BlockShape::Cube,
+                hardness: 1.,
+                material: BlockMaterial::Soil,
+                flammable: false,
+                light: 0,
+            },
+            Block::Stone => BlockDef {
+                name: "Stone",
+                texture_id: 2,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 3.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Clay => BlockDef {
+                name: "Clay",
+                texture_id: 15,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 1.5,
+                material: BlockMaterial::Soil,
+                flammable: false,
+                light: 0,
+            },
+            Block::Sandstone => BlockDef {
+                name: "Sandstone",
+                texture_id: 16,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 2.5,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Basalt => BlockDef {
+                name: "Basalt",
+                texture_id: 17,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 4.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Coal => BlockDef {
+                name: "Coal",
+                texture_id: 12,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 3.5,
+                material: BlockMaterial::Stone,
+                flammable: true,
+                light: 0,
+            },
+            Block::Iron => BlockDef {
+                name: "Iron",
+                texture_id: 13,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 4.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Gold => BlockDef {
+                name: "Gold",
+                texture_id: 14,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 4.5,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Ramp(facing) => BlockDef {
+                name: "Ramp",
+                texture_id: 1,
+                end_texture_id: None,
+                shape: BlockShape::Ramp(facing),
+                hardness: 1.,
+                material: BlockMaterial::Soil,
+                flammable: false,
+                light: 0,
+            },
+            Block::Slab => BlockDef {
+                name: "Slab",
+                texture_id: 2,
+                end_texture_id: None,
+                shape: BlockShape::Slab,
+                hardness: 2.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Stairs(facing) => BlockDef {
+                name: "Stairs",
+                texture_id: 2,
+                end_texture_id: None,
+                shape: BlockShape::Stairs(facing),
+                hardness: 2.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            },
+            Block::Ladder(facing) => BlockDef {
+                name: "Ladder",
+                texture_id: 3,
+                end_texture_id: None,
+                shape: BlockShape::Ladder(facing),
+                hardness: 0.5,
+                material: BlockMaterial::Wood,
+                flammable: true,
+                light: 0,
+            },
+            Block::Door(_) => BlockDef {
+                name: "Door",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::Custom,
+                hardness: 1.5,
+                material: BlockMaterial::Wood,
+                flammable: true,
+                light: 0,
+            },
+            Block::Log(_) => BlockDef {
+                name: "Log",
+                texture_id: 4,
+                end_texture_id: Some(5),
+                shape: BlockShape::Cube,
+                hardness: 2.,
+                material: BlockMaterial::Wood,
+                flammable: true,
+                light: 0,
+            },
+            Block::Leaves => BlockDef {
+                name: "Leaves",
+                texture_id: 9,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 0.3,
+                material: BlockMaterial::Soil,
+                flammable: true,
+                light: 0,
+            },
+            Block::Sapling => BlockDef {
+                name: "Sapling",
+                texture_id: 8,
+                end_texture_id: None,
+                shape: BlockShape::Cross,
+                hardness: 0.2,
+                material: BlockMaterial::Soil,
+                flammable: true,
+                light: 0,
+            },
+            Block::Water => BlockDef {
+                name: "Water",
+                texture_id: 10,
+                end_texture_id: None,
+                shape: BlockShape::Liquid,
+                hardness: 0.,
+                material: BlockMaterial::None,
+                flammable: false,
+                light: 0,
+            },
+            Block::Lava => BlockDef {
+                name: "Lava",
+                texture_id: 18,
+                end_texture_id: None,
+                shape: BlockShape::Liquid,
+                hardness: 0.,
+                material: BlockMaterial::None,
+                flammable: false,
+                light: 14,
+            },
+            Block::Fire => BlockDef {
+                name: "Fire",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::Custom,
+                hardness: 0.,
+                material: BlockMaterial::None,
+                flammable: false,
+                light: 0,
+            },
+            Block::Ash => BlockDef {
+                name: "Ash",
+                texture_id: 6,
+                end_texture_id: None,
+                shape: BlockShape::Slab,
+                hardness: 0.5,
+                material: BlockMaterial::Soil,
+                flammable: false,
+                light: 0,
+            },
+            Block::Structure => BlockDef {
+                name: "Structure",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::Custom,
+                hardness: 0.,
+                material: BlockMaterial::Wood,
+                flammable: true,
+                light: 0,
+            },
+            Block::Fruit => BlockDef {
+                name: "Fruit",
+                texture_id: 9,
+                end_texture_id: None,
+                shape: BlockShape::Cross,
+                hardness: 0.2,
+                material: BlockMaterial::Soil,
+                flammable: true,
+                light: 0,
+            },
+            Block::Modded(index) => registry::modded_def(index).unwrap_or(BlockDef {
+                name: "Unknown",
+                texture_id: 0,
+                end_texture_id: None,
+                shape: BlockShape::Cube,
+                hardness: 1.,
+                material: BlockMaterial::Stone,
+                flammable: false,
+                light: 0,
+            }),
+        }
+    }
+
+    /// A full solid cube.
+    pub fn is_filled(&self) -> bool {
+        self.def().shape == BlockShape::Cube
+    }
+
+    /// Anything with geometry, including partial shapes.
+    pub fn is_solid(&self) -> bool {
+        self.def().shape != BlockShape::None
+    }
+
+    /// Height of the box the block collides as, None for blocks bodies pass
+    /// through. Ramps and stairs count as full blocks, which is close enough
+    /// for things tumbling over them.
+    pub fn collision_height(&self) -> Option<f32> {
+        match self.def().shape {
+            BlockShape::Cube | BlockShape::Stairs(_) | BlockShape::Ramp(_) => Some(1.),
+            BlockShape::Slab => Some(0.5),
+            _ => None,
+        }
+    }
+
+    pub fn orientation(&self) -> Option<Orientation> {
+        match *self {
+            Block::Ramp(facing)
+            | Block::Stairs(facing)
+            | Block::Ladder(facing)
+            | Block::Door(facing) => Some(facing.into()),
+            Block::Log(orientation) => Some(orientation),
+            _ => None,
+        }
+    }
+
+    /// Texture of the block's side facing `face`, taking its orientation into
+    /// account.
+    pub fn texture_id(&self, face: FaceDir) -> u32 {
+        let def = self.def();
+        match (def.end_texture_id, self.orientation()) {
+            (Some(end), Some(orientation)) if face.normal().abs() == orientation.offset().abs() => {
+                end
+            }
+            (Some(end), None) if face == FaceDir::PosY => end,
+            _ => def.texture_id,
+        }
+    }
+
+    pub fn covers(&self, face: FaceDir) -> bool {
+        self.def().shape.covers(face)
+    }
+
+    pub fn is_minable(&self) -> bool {
+        self.def().hardness > 0.
+    }
+
+    pub fn is_flammable(&self) -> bool {
+        self.def().flammable
+    }
+
+    /// Block light the block gives off, 0 for most.
+    pub fn light(&self) -> u8 {
+        self.def().light
+    }
+}
@@ -0,0 +1,368 @@
+use std::ops::Range;
+
+use super::{
+    storage::{PalettedChunk, CHUNK_VOLUME},
+    Block,
+};
+
+/// Bumped whenever the layout written by `encode` changes.
+pub const CHUNK_FORMAT_VERSION: u8 = 3;
+
+/// The first version with metadata after the blocks. Chunks from before it
+/// read back with no metadata.
+const META_FORMAT_VERSION: u8 = 2;
+
+/// The first version with a varint block palette length, and two byte
+/// indices for palettes of more than 256 blocks. Before it both were a
+/// byte.
+const WIDE_FORMAT_VERSION: u8 = 3;
+
+/// Packs a chunk for saves and the network: a version byte, the palette of
+/// block ids after its varint length, then runs of cells as a varint length
+/// and a palette index, a byte or two as the palette needs. The metadata
+/// follows the same way, with a palette length that's zero when no cell has
+/// any. A chunk of one block comes to a handful of bytes.
+pub fn encode(chunk: &PalettedChunk) -> Vec<u8> {
+    let (palette, runs) = palette_runs((0..CHUNK_VOLUME).map(|i| chunk.get_id(i)));
+
+    let mut bytes = vec![CHUNK_FORMAT_VERSION];
+    write_varint(&mut bytes, palette.len() as u32);
+    for id in &palette {
+        bytes.extend_from_slice(&id.0.to_le_bytes());
+    }
+    write_runs(&mut bytes, &runs, is_wide(palette.len()));
+
+    let (palette, runs) = palette_runs((0..CHUNK_VOLUME).map(|i| chunk.get_meta(i)));
+    if palette == [0] {
+        write_varint(&mut bytes, 0);
+    } else {
+        write_varint(&mut bytes, palette.len() as u32);
+        bytes.extend_from_slice(&palette);
+        write_runs(&mut bytes, &runs, false);
+    }
+
+    bytes
+}
+
+/// Whether indices into a palette of `len` take two bytes.
+fn is_wide(len: usize) -> bool {
+    len > 1 << u8::BITS
+}
+
+/// The distinct values in order of first appearance, and runs of equal
+/// values as a length and an index into them.
+fn palette_runs<T: PartialEq>(values: impl Iterator<Item = T>) -> (Vec<T>, Vec<(u32, u16)>) {
+    let mut palette: Vec<T> = vec![];
+    let mut runs: Vec<(u32, u16)> = vec![];
+
+    for value in values {
+        let index = match palette.iter().position(|v| *v == value) {
+            Some(index) => index,
+            None => {
+                palette.push(value);
+                palette.len() - 1
+            }
+        } as u16;
+
+        match runs.last_mut() {
+            Some((length, last)) if *last == index => *length += 1,
+            _ => runs.push((1, index)),
+        }
+    }
+
+    (palette, runs)
+}
+
+fn write_runs(bytes: &mut Vec<u8>, runs: &[(u32, u16)], wide: bool) {
+    for (length, index) in runs {
+        write_varint(bytes, *length);
+        if wide {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        } else {
+            bytes.push(*index as u8);
+        }
+    }
+}
+
+/// Reads a chunk back from what `encode` wrote.
+pub fn decode(bytes: &[u8]) -> Result<PalettedChunk, String> {
+    decode_with(bytes, |id| id)
+}
+
+/// Like `decode`, passing each palette id through `remap` first, for chunks
+/// written while block ids were numbered differently.
+pub fn decode_with(bytes: &[u8], remap: impl Fn(u16) -> u16) -> Result<PalettedChunk, String> {
+    let mut chunk = PalettedChunk::filled(Block::Empty);
+    let mut metas = vec![];
+    decode_runs(
+        bytes,
+        CHUNK_VOLUME,
+        remap,
+        |cells, block| {
+            for i in cells {
+                chunk.set(i, block);
+            }
+        },
+        |cells, meta| metas.push((cells, meta)),
+    )?;
+    for (cells, meta) in metas {
+        for i in cells {
+            chunk.set_meta(i, meta);
+        }
+    }
+    Ok(chunk)
+}
+
+/// Reads a chunk of `volume` cells, which is some other size than
+/// `CHUNK_VOLUME` for chunks saved before the chunk size changed, handing
+/// each run of blocks to `fill` and each run of metadata to `fill_meta` as
+/// the range of cells it covers. Runs of zero metadata are left out.
+pub fn decode_runs(
+    bytes: &[u8],
+    volume: usize,
+    remap: impl Fn(u16) -> u16,
+    fill: impl FnMut(Range<usize>, Block),
+    mut fill_meta: impl FnMut(Range<usize>, u8),
+) -> Result<(), String> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    let version = reader.byte()?;
+    if version == 0 || version > CHUNK_FORMAT_VERSION {
+        return Err(format!("unsupported chunk version {}", version));
+    }
+
+    let palette_len = if version >= WIDE_FORMAT_VERSION {
+        reader.varint()? as usize
+    } else {
+        reader.byte()? as usize
+    };
+    let palette = (0..palette_len)
+        .map(|_| {
+            let id = remap(u16::from_le_bytes([reader.byte()?, reader.byte()?]));
+            Block::from_id(id).ok_or_else(|| format!("unknown block id {}", id))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let wide = version >= WIDE_FORMAT_VERSION && is_wide(palette.len());
+    reader.runs(volume, &palette, wide, fill)?;
+
+    if version >= META_FORMAT_VERSION {
+        let palette_len = reader.varint()? as usize;
+        if palette_len > 0 {
+            let palette = (0..palette_len)
+                .map(|_| reader.byte())
+                .collect::<Result<Vec<_>, _>>()?;
+            reader.runs(volume, &palette, false, |cells, meta| {
+                if meta != 0 {
+                    fill_meta(cells, meta);
+                }
+            })?;
+        }
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(format!("{} bytes left over", bytes.len() - reader.pos));
+    }
+
+    Ok(())
+}
+
+/// LEB128, seven bits a byte with the high bit set on all but the last.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.pos).ok_or("chunk data ends early")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Runs covering `volume` cells, each handed to `fill` with its value
+    /// from `palette`. `wide` indices take two bytes.
+    fn runs<T: Copy>(
+        &mut self,
+        volume: usize,
+        palette: &[T],
+        wide: bool,
+        mut fill: impl FnMut(Range<usize>, T),
+    ) -> Result<(), String> {
+        let mut cell = 0;
+        while cell < volume {
+            let length = self.varint()? as usize;
+            let index = if wide {
+                u16::from_le_bytes([self.byte()?, self.byte()?]) as usize
+            } else {
+                self.byte()? as usize
+            };
+            let value = *palette
+                .get(index)
+                .ok_or_else(|| format!("palette index {} out of range", index))?;
+            if length == 0 || cell + length > volume {
+                return Err(format!("run of {} at cell {} doesn't fit", length, cell));
+            }
+
+            fill(cell..cell + length, value);
+            cell += length;
+        }
+        Ok(())
+    }
+
+    fn varint(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint too long".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{register_block, BlockDef, BlockId, Facing, Orientation};
+
+    fn roundtrip(chunk: &PalettedChunk) -> PalettedChunk {
+        decode(&encode(chunk)).unwrap()
+    }
+
+    fn assert_same(a: &PalettedChunk, b: &PalettedChunk) {
+        for i in 0..CHUNK_VOLUME {
+            assert_eq!(a.get(i), b.get(i), "cell {}", i);
+        }
+    }
+
+    #[test]
+    fn uniform_chunk_is_tiny() {
+        let chunk = PalettedChunk::filled(Block::Empty);
+        let bytes = encode(&chunk);
+
+        assert!(bytes.len() <= 8, "{} bytes", bytes.len());
+        assert_same(&chunk, &roundtrip(&chunk));
+    }
+
+    #[test]
+    fn mostly_stone_chunk_is_a_few_dozen_bytes() {
+        let mut chunk = PalettedChunk::filled(Block::Stone);
+        for i in [100, 900, 901, 2000, 3500] {
+            chunk.set(i, Block::Iron);
+        }
+        chunk.set(4000, Block::Log(Orientation::Up));
+        let bytes = encode(&chunk);
+
+        assert!(bytes.len() <= 48, "{} bytes", bytes.len());
+        assert_same(&chunk, &roundtrip(&chunk));
+    }
+
+    #[test]
+    fn mixed_chunk_roundtrips() {
+        let blocks = [
+            Block::Empty,
+            Block::Dirt,
+            Block::Grass,
+            Block::Water,
+            Block::Ramp(Facing::West),
+            Block::Stairs(Facing::North),
+            Block::Door(Facing::South),
+            Block::Log(Orientation::Down),
+        ];
+        let mut chunk = PalettedChunk::filled(Block::Empty);
+        for i in 0..CHUNK_VOLUME {
+            // runs of varying length, some a cell long
+            chunk.set(i, blocks[(i * 7 / (1 + i % 5)) % blocks.len()]);
+        }
+
+        assert_same(&chunk, &roundtrip(&chunk));
+    }
+
+    #[test]
+    fn metadata_roundtrips_and_old_chunks_have_none() {
+        let mut chunk = PalettedChunk::filled(Block::Water);
+        for i in 0..300 {
+            chunk.set_meta(i * 13, (i % 8) as u8);
+        }
+        chunk.set_meta(CHUNK_VOLUME - 1, 255);
+        let copy = roundtrip(&chunk);
+        for i in 0..CHUNK_VOLUME {
+            assert_eq!(chunk.get_meta(i), copy.get_meta(i), "cell {}", i);
+        }
+
+        // a version 1 chunk ends after its blocks
+        let mut bytes = encode(&PalettedChunk::filled(Block::Stone));
+        bytes[0] = 1;
+        bytes.pop();
+        let old = decode(&bytes).unwrap();
+        assert_eq!(old.get(7), Block::Stone);
+        assert_eq!(old.get_meta(7), 0);
+
+        // a version 2 chunk has a byte for its palette length
+        let [lo, hi] = Block::Dirt.id().to_le_bytes();
+        let old = decode(&[2, 1, lo, hi, 0x80, 0x20, 0, 0]).unwrap();
+        assert_eq!(old.get(7), Block::Dirt);
+    }
+
+    #[test]
+    fn chunks_of_more_than_256_blocks_roundtrip() {
+        let blocks: Vec<_> = (0..300)
+            .map(|i| {
+                register_block(BlockDef {
+                    name: Box::leak(format!("Codec test block {}", i).into_boxed_str()),
+                    ..Block::Stone.def()
+                })
+                .unwrap()
+            })
+            .collect();
+
+        let mut chunk = PalettedChunk::filled(Block::Empty);
+        for i in 0..CHUNK_VOLUME {
+            if i % 2 == 1 {
+                chunk.set(i, blocks[i / 2 % blocks.len()]);
+            }
+        }
+        let bytes = encode(&chunk);
+
+        // a varint past 256 takes two bytes
+        assert_eq!(bytes[..3], [CHUNK_FORMAT_VERSION, 0xad, 0x02]);
+        assert_same(&chunk, &decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn every_block_id_roundtrips() {
+        for id in 0..=u16::MAX {
+            if let Some(block) = Block::from_id(id) {
+                assert_eq!(block.id(), id);
+            }
+        }
+        for facing in Facing::ALL {
+            let block = Block::Ladder(facing);
+            assert_eq!(Block::from_id(block.id()), Some(block));
+        }
+        assert_eq!(BlockId::from(Block::Empty), BlockId::EMPTY);
+        assert_eq!(BlockId::from(Block::Oob), BlockId::OOB);
+        assert_eq!(BlockId::EMPTY.block(), Some(Block::Empty));
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let mut bytes = encode(&PalettedChunk::filled(Block::Stone));
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        bytes[0] = CHUNK_FORMAT_VERSION + 1;
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::{math::bounding::Aabb3d, prelude::*};
+
+use super::Terrain;
+
+/// How far a box got along a sweep through the terrain.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepResult {
+    /// The part of the movement the box can make.
+    pub delta: Vec3,
+    /// Axes the box was stopped along.
+    pub blocked: BVec3,
+}
+
+impl SweepResult {
+    pub fn hit(&self) -> bool {
+        self.blocked.any()
+    }
+}
+
+/// Whether two boxes overlap by more than touching.
+fn overlaps(a: &Aabb3d, b: &Aabb3d) -> bool {
+    a.min.cmplt(b.max).all() && a.max.cmpgt(b.min).all()
+}
+
+impl Terrain {
+    /// Collision box of the block in `pos`, as `Block::collision_height`
+    /// gives it. Below the map is solid ground, the sides are open.
+    fn collision_box(&self, pos: IVec3) -> Option<Aabb3d> {
+        let height = if pos.y < 0 {
+            Some(1.)
+        } else {
+            self.get_at(pos).collision_height()
+        };
+
+        height.map(|height| Aabb3d {
+            min: pos.as_vec3(),
+            max: pos.as_vec3() + Vec3::new(1., height, 1.),
+        })
+    }
+
+    /// Collision boxes of the blocks in every cell `aabb` touches.
+    fn collision_boxes(&self, aabb: Aabb3d) -> impl Iterator<Item = Aabb3d> + '_ {
+        let min = aabb.min.floor().as_ivec3();
+        let max = aabb.max.ceil().as_ivec3();
+
+        (min.x..max.x).flat_map(move |x| {
+            (min.z..max.z).flat_map(move |z| {
+                (min.y..max.y).filter_map(move |y| self.collision_box(IVec3::new(x, y, z)))
+            })
+        })
+    }
+
+    /// Whether `aabb` is inside any block something can collide with.
+    pub fn aabb_overlaps_solid(&self, aabb: Aabb3d) -> bool {
+        self.collision_boxes(aabb)
+            .any(|block| overlaps(&aabb, &block))
+    }
+
+    /// Moves `aabb` by `delta` until it runs into a block, one axis at a
+    /// time, up and down first so things land before they slide. Blocks the
+    /// box is already inside of don't stop it, so it can work its way out.
+    pub fn sweep_aabb(&self, aabb: Aabb3d, delta: Vec3) -> SweepResult {
+        let mut aabb = aabb;
+        let mut moved = Vec3::ZERO;
+        let mut blocked = BVec3::FALSE;
+
+        for axis in [1, 0, 2] {
+            let wanted = delta[axis];
+            if wanted == 0. {
+                continue;
+            }
+
+            let mut step = Vec3::ZERO;
+            step[axis] = wanted;
+            let reach = Aabb3d {
+                min: aabb.min.min(aabb.min + step),
+                max: aabb.max.max(aabb.max + step),
+            };
+
+            let mut allowed = wanted;
+            for block in self.collision_boxes(reach) {
+                if overlaps(&aabb, &block) {
+                    continue;
+                }
+                // only blocks lined up with the box on the other axes
+                let mut across = block;
+                across.min[axis] = aabb.min[axis];
+                across.max[axis] = aabb.max[axis];
+                if !overlaps(&aabb, &across) {
+                    continue;
+                }
+
+                if wanted > 0. && block.min[axis] >= aabb.max[axis] {
+                    allowed = allowed.min(block.min[axis] - aabb.max[axis]);
+                } else if wanted < 0. && block.max[axis] <= aabb.min[axis] {
+                    allowed = allowed.max(block.max[axis] - aabb.min[axis]);
+                }
+            }
+
+            if allowed != wanted {
+                blocked.set(axis, true);
+            }
+            moved[axis] = allowed;
+            aabb.min[axis] += allowed;
+            aabb.max[axis] += allowed;
+        }
+
+        SweepResult {
+            delta: moved,
+            blocked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Block;
+
+    fn unit_box(center: Vec3) -> Aabb3d {
+        Aabb3d::new(center, Vec3::splat(0.4))
+    }
+
+    #[test]
+    fn boxes_collide_with_block_shapes() {
+        let mut terrain = Terrain::new(IVec3::splat(8));
+        terrain.set_at(IVec3::new(2, 0, 2), Block::Stone);
+        terrain.set_at(IVec3::new(5, 0, 5), Block::Slab);
+
+        assert!(terrain.aabb_overlaps_solid(unit_box(Vec3::new(2.5, 0.5, 2.5))));
+        assert!(!terrain.aabb_overlaps_solid(unit_box(Vec3::new(3.5, 0.5, 3.5))));
+        // above the slab's top half
+        assert!(!terrain.aabb_overlaps_solid(unit_box(Vec3::new(5.5, 0.95, 5.5))));
+        assert!(terrain.aabb_overlaps_solid(unit_box(Vec3::new(5.5, 0.8, 5.5))));
+        // under the map
+        assert!(terrain.aabb_overlaps_solid(unit_box(Vec3::new(1.5, 0.2, 1.5))));
+    }
+
+    #[test]
+    fn sweeps_stop_at_the_first_block_in_the_way() {
+        let mut terrain = Terrain::new(IVec3::splat(8));
+        terrain.set_at(IVec3::new(2, 0, 2), Block::Stone);
+        terrain.set_at(IVec3::new(4, 1, 2), Block::Stone);
+
+        // falls onto the stone
+        let sweep = terrain.sweep_aabb(unit_box(Vec3::new(2.5, 4.5, 2.5)), Vec3::NEG_Y * 10.);
+        assert!(sweep.blocked.y);
+        assert!((sweep.delta.y + 3.1).abs() < 1e-4);
+
+        // slides along the top of the stone into the wall
+        let sweep = terrain.sweep_aabb(unit_box(Vec3::new(2.5, 1.4, 2.5)), Vec3::new(5., -1., 0.));
+        assert_eq!(sweep.blocked, BVec3::new(true, true, false));
+        assert!((sweep.delta.x - 1.1).abs() < 1e-4);
+        assert_eq!(sweep.delta.y, 0.);
+
+        // nothing in the way
+        let sweep = terrain.sweep_aabb(unit_box(Vec3::new(6.5, 3.5, 6.5)), Vec3::Z * -3.);
+        assert!(!sweep.hit());
+        assert_eq!(sweep.delta, Vec3::Z * -3.);
+    }
+}
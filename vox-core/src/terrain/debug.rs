@@ -0,0 +1,281 @@
+use std::{fmt::Write, time::Duration};
+
+use bevy::prelude::*;
+
+use super::{Block, Terrain, TerrainMesh, TerrainStats, CHUNK_SIZE};
+use crate::{camera::CameraRay, light::LightMap, menu::AppState};
+
+/// F6 outlines every chunk in the color of where it is in remeshing, so
+/// problems with what gets remeshed and when show at a glance, and lists
+/// the `TerrainStats` in the corner. Middle clicking a chunk while it's on
+/// inspects that chunk: what it's made of, its last mesh and its light are
+/// listed under the stats, and printed.
+pub struct ChunkDebugPlugin;
+
+/// How far away a chunk can be clicked to inspect it.
+const INSPECT_REACH: f32 = 500.;
+
+/// Whether the chunk outlines are showing, and which chunk is inspected.
+#[derive(Resource, Default)]
+pub struct ChunkDebug {
+    pub enabled: bool,
+    pub inspected: Option<IVec3>,
+}
+
+/// Where a chunk is in remeshing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkState {
+    /// Its mesh is up to date.
+    Clean,
+    /// Edited since it was last meshed, not yet queued.
+    Dirty,
+    /// Queued for a remesh, waiting on the per-frame budget.
+    Meshing,
+    /// Meshed to nothing, so there's nothing to draw.
+    Empty,
+}
+
+impl ChunkState {
+    fn color(self) -> Color {
+        match self {
+            ChunkState::Clean => Color::rgba(0.6, 0.6, 0.6, 0.3),
+            ChunkState::Dirty => Color::RED,
+            ChunkState::Meshing => Color::ORANGE,
+            ChunkState::Empty => Color::rgba(0.2, 0.4, 1., 0.15),
+        }
+    }
+}
+
+impl TerrainMesh {
+    pub fn chunk_state(&self, terrain: &Terrain, chunk: IVec3) -> ChunkState {
+        if terrain.dirty.contains(&chunk) {
+            ChunkState::Dirty
+        } else if self.queued.contains(&chunk) {
+            ChunkState::Meshing
+        } else if self.empty.contains(&chunk) {
+            ChunkState::Empty
+        } else {
+            ChunkState::Clean
+        }
+    }
+}
+
+#[derive(Component)]
+struct StatsText;
+
+impl Plugin for ChunkDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkDebug>()
+            .add_systems(OnEnter(AppState::InGame), spawn_stats_text)
+            .add_systems(OnExit(AppState::InGame), despawn_stats_text)
+            .add_systems(
+                Update,
+                (
+                    toggle_chunk_debug,
+                    inspect_clicked_chunk,
+                    draw_chunk_debug,
+                    show_terrain_stats,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+fn toggle_chunk_debug(keys: Res<ButtonInput<KeyCode>>, mut debug: ResMut<ChunkDebug>) {
+    if keys.just_pressed(KeyCode::F6) {
+        debug.enabled = !debug.enabled;
+        debug.inspected = None;
+        println!(
+            "Chunk debug view: {}",
+            if debug.enabled { "on" } else { "off" }
+        );
+    }
+}
+
+/// Boxes each chunk, cut to the edge of the map, a little inside its bounds
+/// so neighbors' outlines don't overlap.
+fn draw_chunk_debug(
+    mut gizmos: Gizmos,
+    debug: Res<ChunkDebug>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Option<Res<TerrainMesh>>,
+) {
+    let Some(terrain_mesh) = terrain_mesh.filter(|_| debug.enabled) else {
+        return;
+    };
+
+    for chunk in terrain.chunks() {
+        let min = (chunk * CHUNK_SIZE).as_vec3();
+        let max = ((chunk + IVec3::ONE) * CHUNK_SIZE)
+            .min(terrain.size())
+            .as_vec3();
+        let size = max - min - Vec3::splat(0.1);
+
+        let color = if debug.inspected == Some(chunk) {
+            Color::WHITE
+        } else {
+            terrain_mesh.chunk_state(&terrain, chunk).color()
+        };
+        gizmos.cuboid(
+            Transform::from_translation((min + max) / 2.).with_scale(size),
+            color,
+        );
+    }
+}
+
+/// Middle clicking a block inspects the chunk it's in, clicking nothing
+/// stops inspecting.
+fn inspect_clicked_chunk(
+    buttons: Res<ButtonInput<MouseButton>>,
+    time: Res<Time<Real>>,
+    camera_ray: Res<CameraRay>,
+    mut debug: ResMut<ChunkDebug>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Option<Res<TerrainMesh>>,
+    light: Option<Res<LightMap>>,
+) {
+    let Some(terrain_mesh) = terrain_mesh else {
+        return;
+    };
+    if !debug.enabled || !buttons.just_pressed(MouseButton::Middle) {
+        return;
+    }
+
+    let hit = camera_ray
+        .0
+        .and_then(|ray| terrain.raycast(ray.origin, *ray.direction, INSPECT_REACH));
+    debug.inspected = hit.map(|hit| Terrain::chunk_of(hit.pos));
+
+    if let Some(chunk) = debug.inspected {
+        let report = describe_chunk(
+            &terrain,
+            &terrain_mesh,
+            light.as_deref(),
+            chunk,
+            time.elapsed(),
+        );
+        println!("{}", report);
+    }
+}
+
+/// Everything the inspector shows about `chunk`, `now` being the real time
+/// since startup. The light range is over the cells light can reach.
+fn describe_chunk(
+    terrain: &Terrain,
+    terrain_mesh: &TerrainMesh,
+    light: Option<&LightMap>,
+    chunk: IVec3,
+    now: Duration,
+) -> String {
+    let min = chunk * CHUNK_SIZE;
+    let max = min + IVec3::splat(CHUNK_SIZE);
+
+    let mut histogram: Vec<(Block, usize)> = vec![];
+    let mut light_range: Option<(u8, u8)> = None;
+    for (pos, block) in terrain.iter_region(min, max) {
+        match histogram.iter_mut().find(|(b, _)| *b == block) {
+            Some((_, count)) => *count += 1,
+            None => histogram.push((block, 1)),
+        }
+        // filled blocks are always dark inside
+        if let Some(light) = light.filter(|_| !block.is_filled()) {
+            let level = light.get(pos);
+            light_range = Some(light_range.map_or((level, level), |(low, high)| {
+                (low.min(level), high.max(level))
+            }));
+        }
+    }
+    histogram.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let mut report = format!(
+        "Chunk {}: {:?}",
+        chunk,
+        terrain_mesh.chunk_state(terrain, chunk)
+    );
+    match terrain_mesh.mesh_stats.get(&chunk) {
+        Some(mesh) => {
+            let ago = now.saturating_sub(mesh.meshed_at);
+            let _ = write!(
+                report,
+                "\nMesh: {} vertices, {} indices, {:.2} ms, {:.1}s ago",
+                mesh.vertices,
+                mesh.indices,
+                mesh.took.as_secs_f64() * 1000.,
+                ago.as_secs_f32()
+            );
+        }
+        None => report.push_str("\nMesh: never meshed"),
+    }
+    if let Some((low, high)) = light_range {
+        let _ = write!(report, "\nLight: {} to {}", low, high);
+    }
+    for (block, count) in histogram {
+        let _ = write!(report, "\n  {:?}: {}", block, count);
+    }
+    report
+}
+
+fn spawn_stats_text(mut commands: Commands) {
+    let text = TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 16.,
+            color: Color::WHITE,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(8.),
+        left: Val::Px(8.),
+        ..default()
+    })
+    .with_background_color(Color::rgba(0., 0., 0., 0.6));
+
+    commands.spawn((text, StatsText, Visibility::Hidden));
+}
+
+fn despawn_stats_text(mut commands: Commands, texts: Query<Entity, With<StatsText>>) {
+    for entity in texts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Lists the stats while the chunk debug view is on, followed by the
+/// inspected chunk, which is described afresh every frame as it changes.
+fn show_terrain_stats(
+    debug: Res<ChunkDebug>,
+    stats: Res<TerrainStats>,
+    time: Res<Time<Real>>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Option<Res<TerrainMesh>>,
+    light: Option<Res<LightMap>>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<StatsText>>,
+) {
+    let inspected = debug.inspected.zip(terrain_mesh);
+    if !debug.is_changed() && !stats.is_changed() && inspected.is_none() {
+        return;
+    }
+
+    let mut value = stats.to_string();
+    if let Some((chunk, terrain_mesh)) = inspected {
+        value.push_str("\n\n");
+        value.push_str(&describe_chunk(
+            &terrain,
+            &terrain_mesh,
+            light.as_deref(),
+            chunk,
+            time.elapsed(),
+        ));
+    }
+
+    for (mut text, mut visibility) in texts.iter_mut() {
+        if debug.enabled {
+            text.sections[0].value.clone_from(&value);
+            *visibility = Visibility::Visible;
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use super::{
+    pulling::PulledTerrainMaterial, refresh_terrain_materials, Terrain, TerrainMaterial,
+    TerrainMesh,
+};
+
+/// Work queued on a block, tinting the terrain around it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Designation {
+    /// The block is to be dug out, its own faces are tinted.
+    Dig,
+    /// A block is to be built in the cell, the faces of the blocks around
+    /// it are tinted.
+    Build,
+}
+
+/// Every designated cell, kept up to date by whoever queues the work and
+/// drawn by the terrain shader.
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct Designations(pub HashMap<IVec3, Designation>);
+
+/// One texel per block of the map, red where it's to be dug and green where
+/// it's to be built.
+fn designation_volume(size: IVec3, designations: &Designations) -> Image {
+    let mut data = vec![0; (size.x * size.y * size.z) as usize * 2];
+
+    for (pos, designation) in &designations.0 {
+        write_designation(&mut data, size, *pos, Some(*designation));
+    }
+
+    Image::new(
+        Extent3d {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth_or_array_layers: size.z as u32,
+        },
+        TextureDimension::D3,
+        data,
+        TextureFormat::Rg8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// Rewrites the texel of `pos` in a designation volume of `size`, clearing
+/// it for None. Cells outside the map are left out.
+fn write_designation(data: &mut [u8], size: IVec3, pos: IVec3, designation: Option<Designation>) {
+    if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(size).any() {
+        return;
+    }
+    let i = (pos.x + (pos.y + pos.z * size.y) * size.x) as usize * 2;
+    data[i] = if designation == Some(Designation::Dig) {
+        u8::MAX
+    } else {
+        0
+    };
+    data[i + 1] = if designation == Some(Designation::Build) {
+        u8::MAX
+    } else {
+        0
+    };
+}
+
+/// An empty volume for the material to hold until the first real one.
+pub(super) fn placeholder_designations() -> Image {
+    designation_volume(IVec3::ONE, &Designations::default())
+}
+
+/// Rebuilds the designation volume when the size of the map changes, and
+/// rewrites the cells whose designation changed otherwise. `drawn` is what
+/// the volume holds.
+pub(super) fn update_designations(
+    designations: Res<Designations>,
+    mut drawn: Local<Designations>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut pulled_materials: ResMut<Assets<PulledTerrainMaterial>>,
+) {
+    let size = images
+        .get(&terrain_mesh.designations)
+        .map(|image| image.texture_descriptor.size);
+    let is_stale = size.is_none_or(|size| {
+        IVec3::new(
+            size.width as i32,
+            size.height as i32,
+            size.depth_or_array_layers as i32,
+        ) != terrain.size()
+    });
+    if is_stale {
+        let volume = designation_volume(terrain.size(), &designations);
+        images.insert(&terrain_mesh.designations, volume);
+    } else if designations.is_changed() {
+        let Some(image) = images.get_mut(&terrain_mesh.designations) else {
+            return;
+        };
+        for pos in drawn.0.keys().chain(designations.0.keys()) {
+            let designation = designations.0.get(pos);
+            if drawn.0.get(pos) != designation {
+                write_designation(&mut image.data, terrain.size(), *pos, designation.copied());
+            }
+        }
+    } else {
+        return;
+    }
+    drawn.0.clone_from(&designations.0);
+    refresh_terrain_materials(&terrain_mesh, &mut materials, &mut pulled_materials);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn designations_outside_the_map_are_left_out() {
+        let mut designations = Designations::default();
+        designations.0.insert(IVec3::new(1, 2, 3), Designation::Dig);
+        designations
+            .0
+            .insert(IVec3::new(0, 0, 1), Designation::Build);
+        designations.0.insert(IVec3::new(4, 0, 0), Designation::Dig);
+
+        let image = designation_volume(IVec3::new(4, 4, 4), &designations);
+        let marked: Vec<usize> = (0..image.data.len())
+            .filter(|&i| image.data[i] > 0)
+            .collect();
+        assert_eq!(marked, vec![16 * 2 + 1, (1 + (2 + 3 * 4) * 4) * 2]);
+    }
+
+    #[test]
+    fn rewritten_cells_drop_their_old_designation() {
+        let size = IVec3::new(4, 4, 4);
+        let pos = IVec3::new(1, 2, 3);
+        let mut designations = Designations::default();
+        designations.0.insert(pos, Designation::Dig);
+        let mut image = designation_volume(size, &designations);
+
+        write_designation(&mut image.data, size, pos, Some(Designation::Build));
+        designations.0.insert(pos, Designation::Build);
+        assert!(image.data == designation_volume(size, &designations).data);
+
+        write_designation(&mut image.data, size, pos, None);
+        assert!(image.data.iter().all(|texel| *texel == 0));
+    }
+}
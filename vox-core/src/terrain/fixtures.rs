@@ -0,0 +1,33 @@
+//! Small terrains the tests build on.
+
+use bevy::math::IVec3;
+
+use super::{Block, Terrain};
+
+/// A map of `size` with every layer below `height` filled with `block`.
+pub fn ground(size: IVec3, height: i32, block: Block) -> Terrain {
+    let mut terrain = Terrain::new(size);
+    fill(
+        &mut terrain,
+        IVec3::ZERO,
+        IVec3::new(size.x, height, size.z),
+        block,
+    );
+    terrain
+}
+
+/// A hollow stone box from 4 to 8 on every axis of an empty 16 map, with a
+/// 3x3x3 room inside.
+pub fn room() -> Terrain {
+    let mut terrain = Terrain::new(IVec3::splat(16));
+    fill(&mut terrain, IVec3::splat(4), IVec3::splat(9), Block::Stone);
+    fill(&mut terrain, IVec3::splat(5), IVec3::splat(8), Block::Empty);
+    terrain
+}
+
+/// Sets every block from `min` up to but not including `max`.
+pub fn fill(terrain: &mut Terrain, min: IVec3, max: IVec3, block: Block) {
+    for (pos, _) in terrain.iter_region(min, max).collect::<Vec<_>>() {
+        terrain.set_at(pos, block);
+    }
+}
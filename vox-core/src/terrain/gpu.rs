@@ -0,0 +1,395 @@
+use bevy::{
+    core::cast_slice,
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, GpuMesh},
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{
+            binding_types::{storage_buffer_read_only_sized, storage_buffer_sized},
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferInitDescriptor, BufferUsages, CachedComputePipelineId, ComputePassDescriptor,
+            ComputePipelineDescriptor, IndexFormat, PipelineCache, ShaderStages,
+        },
+        renderer::{RenderContext, RenderDevice},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{
+    mesher::pack_block, update_terrain, update_water_chunk, Block, ChunkView, FaceDir, MeshScratch,
+    Mesher, Terrain, TerrainChunk, TerrainConfig, TerrainMesh, CHUNK_SIZE, MESH_BUDGET,
+};
+use crate::menu::AppState;
+
+const SIZE: usize = CHUNK_SIZE as usize;
+const PADDED: usize = SIZE + 2;
+
+/// Most faces a chunk of cubes can show, every other cell filled.
+const MAX_FACES: u32 = (SIZE * SIZE * SIZE * 3) as u32;
+
+// a chunk at capacity still fits 16-bit indices
+const _: () = assert!(MAX_FACES * 4 <= u16::MAX as u32 + 1);
+
+/// The order faces are packed in, matching `terrain_mesh.wgsl`.
+const FACES: [FaceDir; 6] = [
+    FaceDir::PosX,
+    FaceDir::NegX,
+    FaceDir::PosY,
+    FaceDir::NegY,
+    FaceDir::PosZ,
+    FaceDir::NegZ,
+];
+
+/// Experimental: meshes chunks of nothing but cubes in a compute shader,
+/// writing straight into the vertex buffer the chunk is drawn from. Chunks
+/// with partial shapes keep going through the CPU mesher.
+///
+/// Without indirect draws each chunk draws as many quads as its filled cells
+/// could show, the ones the shader doesn't reach stay collapsed at the
+/// origin. There's no room left in the packed faces for light either, so
+/// these chunks are drawn fully lit.
+pub struct GpuMeshingPlugin;
+
+/// Voxels of one chunk to mesh on the GPU into the mesh asset `mesh`.
+#[derive(Clone)]
+struct GpuMeshJob {
+    mesh: AssetId<Mesh>,
+    /// Filled cells, one word per column along y, padded like the shader reads.
+    solid: Vec<u32>,
+    /// Packed block of each face of each cell, two to a word.
+    faces: Vec<u32>,
+    /// Faces the vertex buffer is sized for.
+    capacity: u32,
+}
+
+/// Chunks handed to the GPU this frame.
+#[derive(Resource, Default)]
+struct GpuMeshJobs(Vec<GpuMeshJob>);
+
+/// Jobs extracted into the render world, waiting for the pipeline or their
+/// mesh.
+#[derive(Resource, Default)]
+struct PendingGpuMeshes(Vec<GpuMeshJob>);
+
+/// Bind groups of the chunks the node meshes this frame.
+#[derive(Resource, Default)]
+struct GpuMeshBatch(Vec<BindGroup>);
+
+#[derive(Resource)]
+struct GpuMeshPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+    /// Quad indices for a chunk at capacity, shared by every chunk.
+    indices: Buffer,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct GpuMeshLabel;
+
+#[derive(Default)]
+struct GpuMeshNode;
+
+impl Plugin for GpuMeshingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GpuMeshJobs>().add_systems(
+            Update,
+            queue_gpu_meshes
+                .before(update_terrain)
+                .run_if(in_state(AppState::InGame)),
+        );
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<PendingGpuMeshes>()
+            .init_resource::<GpuMeshBatch>()
+            .add_systems(ExtractSchedule, extract_gpu_meshes)
+            .add_systems(
+                Render,
+                prepare_gpu_meshes.in_set(RenderSet::PrepareBindGroups),
+            );
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(GpuMeshLabel, GpuMeshNode);
+        graph.add_node_edge(GpuMeshLabel, bevy::render::graph::CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<GpuMeshPipeline>();
+        }
+    }
+}
+
+impl FromWorld for GpuMeshPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let layout = device.create_bind_group_layout(
+            "terrain_mesh_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_read_only_sized(false, None),
+                    storage_buffer_read_only_sized(false, None),
+                    storage_buffer_sized(false, None),
+                    storage_buffer_sized(false, None),
+                ),
+            ),
+        );
+
+        let indices: Vec<u16> = (0..MAX_FACES as u16 * 4)
+            .step_by(4)
+            .flat_map(|i| [i, i + 1, i + 2, i + 2, i + 3, i])
+            .collect();
+        let indices = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("terrain_mesh_indices"),
+            contents: cast_slice(&indices),
+            usage: BufferUsages::INDEX,
+        });
+
+        let shader = world
+            .resource::<AssetServer>()
+            .load("shaders/terrain_mesh.wgsl");
+        let pipeline =
+            world
+                .resource::<PipelineCache>()
+                .queue_compute_pipeline(ComputePipelineDescriptor {
+                    label: Some("terrain_mesh_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    push_constant_ranges: vec![],
+                    shader,
+                    shader_defs: vec![],
+                    entry_point: "mesh".into(),
+                });
+
+        Self {
+            layout,
+            pipeline,
+            indices,
+        }
+    }
+}
+
+/// Takes the chunks of nothing but cubes off the remesh queue before the CPU
+/// mesher sees them, giving each a fresh mesh for the GPU to fill in.
+fn queue_gpu_meshes(
+    mut commands: Commands,
+    mut jobs: ResMut<GpuMeshJobs>,
+    config: Res<TerrainConfig>,
+    mut terrain: ResMut<Terrain>,
+    mut terrain_mesh: ResMut<TerrainMesh>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunks: Query<&mut Handle<Mesh>, With<TerrainChunk>>,
+) {
+    jobs.0.clear();
+    // the shader only knows cubes, and writes vertex buffers
+    if config.mesher == Mesher::Smooth || config.vertex_pulling {
+        return;
+    }
+
+    for coord in terrain.take_dirty_chunks() {
+        terrain_mesh.queue_remesh(coord);
+    }
+
+    let terrain_mesh = &mut *terrain_mesh;
+    let mut queued = vec![];
+    terrain_mesh.pending.retain(|coord| {
+        if jobs.0.len() >= MESH_BUDGET {
+            return true;
+        }
+        let chunk = terrain_mesh.chunks.get(coord);
+        let Some(mut handle) = chunk.and_then(|entity| chunks.get_mut(*entity).ok()) else {
+            return true;
+        };
+        let Some((solid, faces, filled)) = pack_chunk(&ChunkView::new(&terrain, *coord)) else {
+            return true;
+        };
+
+        let mesh = meshes.add(MeshScratch::default().build());
+        jobs.0.push(GpuMeshJob {
+            mesh: mesh.id(),
+            solid,
+            faces,
+            capacity: (filled * 6).min(MAX_FACES),
+        });
+        *handle = mesh;
+        terrain_mesh.empty.remove(coord);
+        terrain_mesh.queued.remove(coord);
+        queued.push(*coord);
+        false
+    });
+
+    // all cubes, so whatever water they had is gone
+    for coord in queued {
+        update_water_chunk(&mut commands, terrain_mesh, &mut meshes, coord, None);
+    }
+}
+
+/// Occupancy and face blocks of `chunk` laid out for the shader, with the
+/// count of filled cells. None when anything in it isn't a cube.
+fn pack_chunk(view: &ChunkView) -> Option<(Vec<u32>, Vec<u32>, u32)> {
+    let min = view.chunk * CHUNK_SIZE - IVec3::ONE;
+    let slice = view.slice as i32;
+    let mut solid = vec![0; PADDED * PADDED];
+    let mut faces = vec![0; SIZE * SIZE * SIZE * 3];
+    let mut filled = 0;
+
+    for x in 0..PADDED {
+        for z in 0..PADDED {
+            for y in 0..PADDED {
+                let pos = min + IVec3::new(x as i32, y as i32, z as i32);
+                let block = view.get_at(pos);
+                let inside = [x, y, z].iter().all(|i| (1..=SIZE).contains(i));
+                if pos.y >= slice {
+                    continue;
+                }
+                // below the slice, so never the open top of the map
+                if view.cull_oob && block == Block::Oob {
+                    solid[x + z * PADDED] |= 1 << y;
+                    continue;
+                }
+                if !block.is_solid() {
+                    continue;
+                }
+                if !block.is_filled() {
+                    // drawn only by the CPU mesher, and seen through from
+                    // the neighbors
+                    if inside {
+                        return None;
+                    }
+                    continue;
+                }
+
+                solid[x + z * PADDED] |= 1 << y;
+                if !inside {
+                    continue;
+                }
+
+                filled += 1;
+                let cell = (x - 1) + ((y - 1) + (z - 1) * SIZE) * SIZE;
+                let damage = view.damage_stage(pos) << 12;
+                for (i, dir) in FACES.iter().enumerate() {
+                    let packed = pack_block(block, *dir) | damage;
+                    faces[cell * 3 + i / 2] |= packed << (i % 2 * 16);
+                }
+            }
+        }
+    }
+
+    Some((solid, faces, filled))
+}
+
+fn extract_gpu_meshes(mut pending: ResMut<PendingGpuMeshes>, jobs: Extract<Res<GpuMeshJobs>>) {
+    if jobs.is_changed() {
+        pending.0.extend(jobs.0.iter().cloned());
+    }
+}
+
+/// Points the chunk meshes at fresh buffers for the node to fill in, once
+/// their placeholder assets made it over and the pipeline is built.
+fn prepare_gpu_meshes(
+    mut pending: ResMut<PendingGpuMeshes>,
+    mut batch: ResMut<GpuMeshBatch>,
+    mut render_meshes: ResMut<RenderAssets<Mesh>>,
+    pipeline: Res<GpuMeshPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    device: Res<RenderDevice>,
+) {
+    batch.0.clear();
+    if pipeline_cache
+        .get_compute_pipeline(pipeline.pipeline)
+        .is_none()
+    {
+        return;
+    }
+
+    for job in pending.0.drain(..) {
+        // replaced again before it was ever drawn
+        let Some(mesh) = render_meshes.get_mut(job.mesh) else {
+            continue;
+        };
+        if job.capacity == 0 {
+            continue;
+        }
+
+        let storage = |label, contents: &[u32], usage| {
+            device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some(label),
+                contents: cast_slice(contents),
+                usage: BufferUsages::STORAGE | usage,
+            })
+        };
+        let solid = storage("terrain_mesh_solid", &job.solid, BufferUsages::empty());
+        let faces = storage("terrain_mesh_faces", &job.faces, BufferUsages::empty());
+        let count = storage("terrain_mesh_count", &[0], BufferUsages::empty());
+        // starts zeroed, quads the shader doesn't write are degenerate
+        let vertices = storage(
+            "terrain_mesh_vertices",
+            &vec![0; job.capacity as usize * 8],
+            BufferUsages::VERTEX,
+        );
+
+        batch.0.push(device.create_bind_group(
+            "terrain_mesh_bind_group",
+            &pipeline.layout,
+            &BindGroupEntries::sequential((
+                solid.as_entire_binding(),
+                faces.as_entire_binding(),
+                vertices.as_entire_binding(),
+                count.as_entire_binding(),
+            )),
+        ));
+
+        *mesh = GpuMesh {
+            vertex_buffer: vertices,
+            vertex_count: job.capacity * 4,
+            buffer_info: GpuBufferInfo::Indexed {
+                buffer: pipeline.indices.clone(),
+                count: job.capacity * 6,
+                index_format: IndexFormat::Uint16,
+            },
+            morph_targets: None,
+            primitive_topology: mesh.primitive_topology,
+            layout: mesh.layout.clone(),
+        };
+    }
+}
+
+impl render_graph::Node for GpuMeshNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let batch = world.resource::<GpuMeshBatch>();
+        let pipeline = world.resource::<GpuMeshPipeline>();
+        let Some(compute) = world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(pipeline.pipeline)
+        else {
+            return Ok(());
+        };
+        if batch.0.is_empty() {
+            return Ok(());
+        }
+
+        let groups = CHUNK_SIZE as u32 / 4;
+        let mut pass =
+            render_context
+                .command_encoder()
+                .begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("terrain_mesh"),
+                    ..default()
+                });
+        pass.set_pipeline(compute);
+        for bind_group in &batch.0 {
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(groups, groups, groups);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use bevy::math::IVec3;
+
+use super::{
+    binary::mesh_binary, shapes, smooth::mesh_smooth, Block, ChunkView, FaceDir, Terrain,
+    CHUNK_SIZE,
+};
+use crate::light::MAX_LIGHT;
+
+/// Where a vertex's darkness sits in its packed block, `MAX_LIGHT` less its
+/// light so vertices packed without any are fully lit. Faces handed to
+/// vertex pulling carry one for each of their four corners from here up.
+pub(super) const DARKNESS_SHIFT: u32 = 16;
+
+/// Vertex streams for one chunk, kept apart from `Mesh` so they can be built
+/// and inspected without the renderer.
+#[derive(Default)]
+pub struct TerrainMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indicies: Vec<u32>,
+    pub packed: Vec<u32>,
+}
+
+impl TerrainMeshData {
+    /// Empties the streams, keeping their allocations.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.indicies.clear();
+        self.packed.clear();
+    }
+}
+
+/// How chunks are turned into geometry.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Mesher {
+    /// A quad for every visible face, looking at each block's neighbors.
+    Simple,
+    /// Bitmask columns with identical neighboring faces merged, much faster
+    /// and with fewer vertices.
+    #[default]
+    Binary,
+    /// Marching cubes over how filled each block's surroundings are, for
+    /// rolling hills instead of cubes.
+    Smooth,
+}
+
+impl Mesher {
+    /// Meshes `chunk` into `data`, reusing its buffers.
+    pub fn mesh_into(self, terrain: &Terrain, chunk: IVec3, data: &mut TerrainMeshData) {
+        self.mesh_view_into(&ChunkView::new(terrain, chunk), data);
+    }
+
+    /// Meshes the chunk of a view taken beforehand into `data`.
+    pub fn mesh_view_into(self, view: &ChunkView, data: &mut TerrainMeshData) {
+        match self {
+            Mesher::Simple => mesh_simple(view, data),
+            Mesher::Binary => mesh_binary(view, data),
+            Mesher::Smooth => mesh_smooth(view, data),
+        }
+    }
+}
+
+/// Meshes the blocks of one chunk below the slice, in world coordinates.
+pub fn mesh_chunk(terrain: &Terrain, chunk: IVec3) -> TerrainMeshData {
+    let mut data = TerrainMeshData::default();
+    mesh_chunk_into(terrain, chunk, &mut data);
+    data
+}
+
+/// Like `mesh_chunk`, reusing the buffers of `data` from an earlier chunk.
+pub fn mesh_chunk_into(terrain: &Terrain, chunk: IVec3, data: &mut TerrainMeshData) {
+    mesh_simple(&ChunkView::new(terrain, chunk), data);
+}
+
+fn mesh_simple(view: &ChunkView, data: &mut TerrainMeshData) {
+    data.clear();
+
+    let mut idx = 0;
+    let min = view.chunk * CHUNK_SIZE;
+    let max = min + IVec3::splat(CHUNK_SIZE);
+
+    for x in min.x..max.x {
+        for z in min.z..max.z {
+            for y in min.y..max.y.min(view.slice as i32) {
+                let block = view.get(x as i16, y as i16, z as i16);
+                let pos = IVec3::new(x, y, z);
+                let start = data.packed.len();
+
+                if !block.is_filled() {
+                    if block.is_solid() {
+                        shapes::mesh_shape(data, view, pos, block);
+                        mark_damage(data, start, view.damage_stage(pos));
+                        mark_darkness(data, start, view.light_at(pos));
+                        idx = data.positions.len() as u32;
+                    }
+                    continue;
+                }
+
+                let fx = x as f32;
+                let fy = y as f32;
+                let fz = z as f32;
+
+                let neighbors = view.get_neighbors_immediate(x as i16, y as i16, z as i16);
+
+                if y == (view.slice as i32 - 1) || !view.covers(neighbors[0], FaceDir::NegY) {
+                    // add face above
+                    data.positions.push([fx, fy + 1., fz]);
+                    data.positions.push([fx + 1., fy + 1., fz]);
+                    data.positions.push([fx + 1., fy + 1., fz + 1.]);
+                    data.positions.push([fx, fy + 1., fz + 1.]);
+
+                    data.packed.push(pack_block(block, FaceDir::PosY));
+                    data.packed.push(pack_block(block, FaceDir::PosY));
+                    data.packed.push(pack_block(block, FaceDir::PosY));
+                    data.packed.push(pack_block(block, FaceDir::PosY));
+
+                    data.normals.push([0., 1., 0.]);
+                    data.normals.push([0., 1., 0.]);
+                    data.normals.push([0., 1., 0.]);
+                    data.normals.push([0., 1., 0.]);
+
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx + 2);
+
+                    idx += 4;
+                }
+
+                if !view.covers(neighbors[1], FaceDir::PosZ) {
+                    // add face in front
+                    data.positions.push([fx, fy, fz]);
+                    data.positions.push([fx, fy + 1., fz]);
+                    data.positions.push([fx + 1., fy + 1., fz]);
+                    data.positions.push([fx + 1., fy, fz]);
+
+                    data.packed.push(pack_block(block, FaceDir::NegZ));
+                    data.packed.push(pack_block(block, FaceDir::NegZ));
+                    data.packed.push(pack_block(block, FaceDir::NegZ));
+                    data.packed.push(pack_block(block, FaceDir::NegZ));
+
+                    data.normals.push([0., 0., -1.]);
+                    data.normals.push([0., 0., -1.]);
+                    data.normals.push([0., 0., -1.]);
+                    data.normals.push([0., 0., -1.]);
+
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx);
+
+                    idx += 4;
+                }
+
+                if !view.covers(neighbors[2], FaceDir::NegX) {
+                    // add face right
+                    data.positions.push([fx + 1., fy, fz]);
+                    data.positions.push([fx + 1., fy, fz + 1.]);
+                    data.positions.push([fx + 1., fy + 1., fz + 1.]);
+                    data.positions.push([fx + 1., fy + 1., fz]);
+
+                    data.packed.push(pack_block(block, FaceDir::PosX));
+                    data.packed.push(pack_block(block, FaceDir::PosX));
+                    data.packed.push(pack_block(block, FaceDir::PosX));
+                    data.packed.push(pack_block(block, FaceDir::PosX));
+
+                    data.normals.push([1., 0., 0.]);
+                    data.normals.push([1., 0., 0.]);
+                    data.normals.push([1., 0., 0.]);
+                    data.normals.push([1., 0., 0.]);
+
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx + 2);
+
+                    idx += 4;
+                }
+
+                if !view.covers(neighbors[3], FaceDir::NegZ) {
+                    // add face behind
+                    data.positions.push([fx, fy, fz + 1.]);
+                    data.positions.push([fx, fy + 1., fz + 1.]);
+                    data.positions.push([fx + 1., fy + 1., fz + 1.]);
+                    data.positions.push([fx + 1., fy, fz + 1.]);
+
+                    data.packed.push(pack_block(block, FaceDir::PosZ));
+                    data.packed.push(pack_block(block, FaceDir::PosZ));
+                    data.packed.push(pack_block(block, FaceDir::PosZ));
+                    data.packed.push(pack_block(block, FaceDir::PosZ));
+
+                    data.normals.push([0., 0., 1.]);
+                    data.normals.push([0., 0., 1.]);
+                    data.normals.push([0., 0., 1.]);
+                    data.normals.push([0., 0., 1.]);
+
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx + 2);
+
+                    idx += 4;
+                }
+
+                if !view.covers(neighbors[4], FaceDir::PosX) {
+                    // add face left
+                    data.positions.push([fx, fy, fz]);
+                    data.positions.push([fx, fy, fz + 1.]);
+                    data.positions.push([fx, fy + 1., fz + 1.]);
+                    data.positions.push([fx, fy + 1., fz]);
+
+                    data.packed.push(pack_block(block, FaceDir::NegX));
+                    data.packed.push(pack_block(block, FaceDir::NegX));
+                    data.packed.push(pack_block(block, FaceDir::NegX));
+                    data.packed.push(pack_block(block, FaceDir::NegX));
+
+                    data.normals.push([-1., 0., 0.]);
+                    data.normals.push([-1., 0., 0.]);
+                    data.normals.push([-1., 0., 0.]);
+                    data.normals.push([-1., 0., 0.]);
+
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx);
+
+                    idx += 4;
+                }
+
+                if !view.covers(neighbors[5], FaceDir::PosY) {
+                    // add face below
+                    data.positions.push([fx, fy, fz]);
+                    data.positions.push([fx + 1., fy, fz]);
+                    data.positions.push([fx + 1., fy, fz + 1.]);
+                    data.positions.push([fx, fy, fz + 1.]);
+
+                    data.packed.push(pack_block(block, FaceDir::NegY));
+                    data.packed.push(pack_block(block, FaceDir::NegY));
+                    data.packed.push(pack_block(block, FaceDir::NegY));
+                    data.packed.push(pack_block(block, FaceDir::NegY));
+
+                    data.normals.push([0., -1., 0.]);
+                    data.normals.push([0., -1., 0.]);
+                    data.normals.push([0., -1., 0.]);
+                    data.normals.push([0., -1., 0.]);
+
+                    data.indicies.push(idx);
+                    data.indicies.push(idx + 1);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 2);
+                    data.indicies.push(idx + 3);
+                    data.indicies.push(idx);
+
+                    idx += 4;
+                }
+
+                mark_damage(data, start, view.damage_stage(pos));
+                light_cube_faces(data, view, start, pos);
+            }
+        }
+    }
+}
+
+/// Stamps the crack stage onto every vertex pushed for a block since `start`.
+pub(super) fn mark_damage(data: &mut TerrainMeshData, start: usize, stage: u32) {
+    for packed in &mut data.packed[start..] {
+        *packed |= (stage & 7) << 12;
+    }
+}
+
+/// Stamps the same darkness onto every vertex pushed since `start`, for the
+/// partial shapes lit by the cell they're in.
+pub(super) fn mark_darkness(data: &mut TerrainMeshData, start: usize, light: u8) {
+    for packed in &mut data.packed[start..] {
+        *packed |= ((MAX_LIGHT - light) as u32) << DARKNESS_SHIFT;
+    }
+}
+
+/// Lights each corner of the cube faces pushed for the block at `pos` since
+/// `start` with `corner_darkness`.
+fn light_cube_faces(data: &mut TerrainMeshData, view: &ChunkView, start: usize, pos: IVec3) {
+    for i in start..data.packed.len() {
+        let normal = IVec3::from_array(data.normals[i].map(|n| n as i32));
+        let corner = IVec3::from_array(data.positions[i].map(|p| p as i32));
+        let axis = normal
+            .abs()
+            .to_array()
+            .iter()
+            .position(|n| *n != 0)
+            .unwrap_or(1);
+        data.packed[i] |= corner_darkness(view, pos + normal, axis, corner) << DARKNESS_SHIFT;
+    }
+}
+
+/// Darkness at `corner` of a cube face facing along `axis`, from the average
+/// light of the four cells in front of the face that share the corner, so
+/// light fades smoothly across faces instead of stepping from block to
+/// block. Full blocks hold no light and are left out, the ones sliced off
+/// count as open to the sky.
+pub(super) fn corner_darkness(view: &ChunkView, front: IVec3, axis: usize, corner: IVec3) -> u32 {
+    let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut total = 0;
+    let mut count = 0;
+
+    for du in [-1, 0] {
+        for dv in [-1, 0] {
+            let mut cell = front;
+            cell[u_axis] = corner[u_axis] + du;
+            cell[v_axis] = corner[v_axis] + dv;
+            if cell.y >= view.slice as i32 {
+                total += MAX_LIGHT as u32;
+                count += 1;
+            } else if !view.get_at(cell).is_filled() {
+                total += view.light_at(cell) as u32;
+                count += 1;
+            }
+        }
+    }
+
+    // rounded, the cell in front on its own when all four are full
+    let light = (total + count / 2)
+        .checked_div(count)
+        .unwrap_or_else(|| view.light_at(front) as u32);
+    MAX_LIGHT as u32 - light
+}
+
+pub(super) fn pack_block(block: Block, dir: FaceDir) -> u32 {
+    let t_id = block.texture_id(dir); // 0-63
+    let f_id = dir.bit(); // 0-7
+    let o_id = block.orientation().map_or(0, |o| o.bits()); // 0-7
+    let glow = (block.light() > 0) as u32; // 0-1, bits 12-14 are the damage
+
+    (t_id & 63) | ((f_id & 7) << 6) | ((o_id & 7) << 9) | (glow << 15)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{light::LightMap, terrain::Facing};
+
+    fn terrain_with(blocks: &[(IVec3, Block)]) -> Terrain {
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE));
+        for &(pos, block) in blocks {
+            terrain.set_at(pos, block);
+        }
+        terrain
+    }
+
+    fn face_count(data: &TerrainMeshData) -> usize {
+        data.indicies.len() / 6
+    }
+
+    /// Every triangle should be counter-clockwise when seen from its normal.
+    fn assert_winding(data: &TerrainMeshData) {
+        for tri in data.indicies.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(data.positions[tri[i] as usize]));
+            let normal = Vec3::from(data.normals[tri[0] as usize]);
+            let facing = (b - a).cross(c - a).dot(normal);
+            assert!(facing > 0., "triangle {:?} faces away from {}", tri, normal);
+        }
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        let data = mesh_chunk(&terrain_with(&[]), IVec3::ZERO);
+        assert!(data.positions.is_empty());
+        assert!(data.indicies.is_empty());
+    }
+
+    #[test]
+    fn lone_cube_has_six_faces() {
+        let data = mesh_chunk(
+            &terrain_with(&[(IVec3::new(4, 2, 4), Block::Stone)]),
+            IVec3::ZERO,
+        );
+        assert_eq!(face_count(&data), 6);
+        assert_eq!(data.positions.len(), 24);
+        assert_eq!(data.normals.len(), 24);
+        assert_eq!(data.packed.len(), 24);
+        assert_winding(&data);
+    }
+
+    #[test]
+    fn touching_cubes_cull_shared_faces() {
+        let terrain = terrain_with(&[
+            (IVec3::new(4, 2, 4), Block::Stone),
+            (IVec3::new(5, 2, 4), Block::Dirt),
+            (IVec3::new(4, 3, 4), Block::Stone),
+        ]);
+        let data = mesh_chunk(&terrain, IVec3::ZERO);
+        assert_eq!(face_count(&data), 18 - 4);
+        assert_winding(&data);
+    }
+
+    #[test]
+    fn blocks_at_slice_are_capped() {
+        let mut terrain = terrain_with(&[
+            (IVec3::new(4, 2, 4), Block::Stone),
+            (IVec3::new(4, 3, 4), Block::Stone),
+        ]);
+        terrain.slice = 3;
+        let data = mesh_chunk(&terrain, IVec3::ZERO);
+
+        // the upper block is sliced off, the lower one gets its top back
+        assert_eq!(face_count(&data), 6);
+        assert!(data.positions.iter().all(|p| p[1] <= 3.));
+    }
+
+    #[test]
+    fn shapes_wind_outwards() {
+        let terrain = terrain_with(&[
+            (IVec3::new(2, 2, 2), Block::Slab),
+            (IVec3::new(4, 2, 2), Block::Ramp(Facing::East)),
+            (IVec3::new(6, 2, 2), Block::Stairs(Facing::North)),
+            (IVec3::new(8, 2, 2), Block::Ladder(Facing::West)),
+        ]);
+        let data = mesh_chunk(&terrain, IVec3::ZERO);
+        assert!(!data.indicies.is_empty());
+        assert_winding(&data);
+    }
+
+    #[test]
+    fn merged_faces_light_their_corners_like_single_ones() {
+        // a roof with a hole in it, dimming the floor away from the hole
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE));
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                terrain.set_at(IVec3::new(x, 0, z), Block::Stone);
+                if (x, z) != (3, 3) {
+                    terrain.set_at(IVec3::new(x, 4, z), Block::Stone);
+                }
+            }
+        }
+        terrain.set_at(IVec3::new(6, 1, 5), Block::Dirt);
+        let mut light = LightMap::default();
+        light.compute(&terrain);
+        let view = ChunkView::new(&terrain, IVec3::ZERO).with_light(|pos| light.get(pos));
+
+        let mut simple = TerrainMeshData::default();
+        mesh_simple(&view, &mut simple);
+        let darkness = |data: &TerrainMeshData, i: usize| {
+            let key = (
+                data.positions[i].map(|p| p as i32),
+                data.normals[i].map(|n| n as i32),
+            );
+            (key, data.packed[i] >> DARKNESS_SHIFT)
+        };
+        let expected: HashMap<_, _> = (0..simple.packed.len())
+            .map(|i| darkness(&simple, i))
+            .collect();
+        assert!(expected.values().any(|d| *d > 0));
+        assert!(expected.values().any(|d| *d == 0));
+
+        let mut binary = TerrainMeshData::default();
+        mesh_binary(&view, &mut binary);
+        for i in 0..binary.packed.len() {
+            let (key, dark) = darkness(&binary, i);
+            assert_eq!(expected[&key], dark, "at {:?}", key);
+        }
+    }
+
+    #[test]
+    fn closed_map_culls_faces_against_its_edges() {
+        let mut terrain = terrain_with(&[(IVec3::ZERO, Block::Stone)]);
+        terrain.cull_oob = true;
+
+        // only the faces towards the map and the top are left
+        for mesher in [Mesher::Simple, Mesher::Binary] {
+            let mut data = TerrainMeshData::default();
+            mesher.mesh_into(&terrain, IVec3::ZERO, &mut data);
+            assert_eq!(face_count(&data), 3, "{:?}", mesher);
+            assert_winding(&data);
+        }
+    }
+}
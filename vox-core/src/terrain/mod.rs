@@ -0,0 +1,1481 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster},
+    prelude::*,
+    render::{
+        mesh::{Indices, MeshVertexAttribute, MeshVertexBufferLayout},
+        primitives::Aabb,
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, PrimitiveTopology, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError, VertexFormat,
+        },
+        texture::{ImageLoaderSettings, ImageSampler},
+    },
+    tasks::ComputeTaskPool,
+    utils::Instant,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    light::{self, LightDebug, LightMap},
+    menu::AppState,
+    worldgen::{Landform, WorldGenPipeline, WorldGenSettings},
+};
+
+mod binary;
+mod block;
+mod codec;
+mod collision;
+mod debug;
+mod designation;
+#[cfg(test)]
+pub(crate) mod fixtures;
+#[cfg(feature = "gpu-meshing")]
+mod gpu;
+mod mesher;
+mod occlusion;
+mod pulling;
+mod region;
+mod registry;
+mod relief;
+mod shading;
+mod shapes;
+mod smooth;
+mod stats;
+mod storage;
+mod surface;
+mod view;
+mod water;
+
+pub use binary::mesh_chunk_binary_into;
+pub use block::{Block, BlockDef, BlockMaterial, BlockShape, FaceDir, Facing, Orientation};
+pub use collision::SweepResult;
+pub use debug::{ChunkDebug, ChunkDebugPlugin, ChunkState};
+pub use designation::{Designation, Designations};
+#[cfg(feature = "gpu-meshing")]
+pub use gpu::GpuMeshingPlugin;
+pub use mesher::{mesh_chunk, mesh_chunk_into, Mesher, TerrainMeshData};
+pub use region::{RegionSet, FLOOD_FILL_LIMIT};
+pub use registry::{
+    builtin_blocks, check_block_names, find_builtin, find_modded, modded_blocks, register_block,
+    set_overrides, BlockId, BlockOverride, MODDED_ID_BASE,
+};
+pub use shapes::LIQUID_LEVEL;
+pub use smooth::mesh_chunk_smooth_into;
+pub use stats::TerrainStats;
+pub use view::ChunkView;
+pub use water::WaterMaterial;
+
+use binary::pull_faces_into;
+use designation::{placeholder_designations, update_designations};
+use occlusion::{placeholder_volume, update_occlusion};
+use pulling::{stand_in_mesh, PulledChunk, PulledTerrainMaterial, VertexPullingPlugin};
+use relief::{load_normal_map, pack_normal, update_normal_map};
+use shading::{dimming_params, outline_params, update_shading};
+use stats::{update_terrain_stats, MeshStats};
+use storage::PalettedChunk;
+use water::{update_water_chunk, WaterMeshData};
+
+/// The voxel data, its events and world generation. Runs headless.
+#[derive(Default)]
+pub struct TerrainPlugin {
+    pub config: TerrainConfig,
+}
+
+impl TerrainPlugin {
+    pub fn new(config: TerrainConfig) -> Self {
+        Self { config }
+    }
+}
+
+/// The map the app starts with, until the menu or a save replaces it.
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainConfig {
+    pub size: IVec3,
+    /// Height the map is sliced at, a little above halfway up if None.
+    pub slice: Option<u16>,
+    pub seed: u64,
+    pub generator: Landform,
+    /// Raindrops to erode the generated land with.
+    pub erosion_droplets: u32,
+    /// Passes of slumping the generated land's steepest slopes.
+    pub talus_passes: u32,
+    pub mesher: Mesher,
+    /// Draw chunks of plain cubes from a storage buffer of their faces
+    /// instead of vertex buffers. Not with the smooth mesher.
+    pub vertex_pulling: bool,
+    /// Leave out the faces against the sides and bottom of the map, which
+    /// can't be seen on a closed map.
+    pub cull_oob: bool,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            size: IVec3::new(MAP_SIZE_X as i32, MAP_SIZE_Y as i32, MAP_SIZE_Z as i32),
+            slice: None,
+            seed: WorldGenSettings::default().seed,
+            generator: Landform::Sphere,
+            erosion_droplets: 0,
+            talus_passes: 0,
+            mesher: Mesher::default(),
+            vertex_pulling: false,
+            cull_oob: false,
+        }
+    }
+}
+
+/// Rendering options players can change, from the settings menu.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// Darken corners and the ground under overhangs, at the cost of an
+    /// upload of the whole map after every edit.
+    pub ambient_occlusion: bool,
+    /// Fade the distance into fog, from halfway out to the render distance.
+    pub fog: bool,
+    /// How far from the camera anything is drawn, in blocks.
+    pub render_distance: f32,
+    /// Bumpy light across the faces, from the terrain's normal map if the
+    /// assets have one.
+    pub normal_maps: bool,
+    /// Dark edges where the slice cuts through the blocks, so the layout of
+    /// rooms reads from above.
+    pub slice_outlines: bool,
+    /// Fade unlit faces deep under the slice to black, so caves no light
+    /// reaches read as voids.
+    pub interior_dimming: bool,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            ambient_occlusion: true,
+            fog: true,
+            render_distance: 1000.,
+            normal_maps: true,
+            slice_outlines: true,
+            interior_dimming: true,
+        }
+    }
+}
+
+/// Meshes the terrain into chunks and keeps them up to date.
+pub struct TerrainMeshPlugin;
+
+/// Map dimensions used unless the `TerrainConfig` asks for others.
+pub const MAP_SIZE_X: u16 = 32;
+pub const MAP_SIZE_Z: u16 = 32;
+pub const MAP_SIZE_Y: u16 = 32;
+
+/// Largest size accepted along any axis. The occupancy and designation
+/// volumes hold a texel per block, and 3D textures stop at 2048 a side.
+const MAX_SIZE: i32 = 2048;
+
+/// Edge length of the cubes the terrain is meshed in.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Offsets to the 26 blocks around a block, x slowest then y then z.
+pub const NEIGHBORHOOD: [IVec3; 26] = neighborhood();
+
+/// Tiles per row of the terrain atlas.
+pub const TEXTURE_COUNT: u32 = 8;
+
+/// Chunks remeshed and uploaded per frame at most, the rest wait their turn.
+const MESH_BUDGET: usize = 32;
+
+/// Number of crack overlays shown while a block is being mined.
+pub const DAMAGE_STAGES: u32 = 4;
+
+/// Sent after the terrain or the slice changed. The mesh itself follows the
+/// dirty chunks `Terrain` tracks, this tells everything else to catch up.
+#[derive(Event)]
+pub struct TerrainModifiedEvent;
+
+/// Sent once per changed voxel after the frame's edits are flushed.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct BlockChangedEvent {
+    pub pos: IVec3,
+    pub block: Block,
+    pub previous: Block,
+}
+
+#[derive(Resource, Clone)]
+pub struct Terrain {
+    pub slice: u16,
+    /// Whether the outside of the map hides the faces against it, all but
+    /// the open top. Follows `TerrainConfig::cull_oob`.
+    pub cull_oob: bool,
+    /// Extent of the map in blocks.
+    size: IVec3,
+    /// Chunks needed to cover the map along each axis.
+    chunk_count: IVec3,
+    /// Blocks of each chunk, x-major then z, each column of chunks bottom
+    /// to top.
+    storage: Vec<PalettedChunk>,
+    /// Topmost full block of each column, x-major then z, -1 for none.
+    surface: Vec<i16>,
+    /// Positions touched by `set` since the last flush, with the block they held.
+    changes: Vec<(IVec3, Block)>,
+    /// Mining progress of partly broken blocks, from 0 to 1.
+    damage: HashMap<IVec3, f32>,
+    /// Chunks whose mesh no longer matches the blocks.
+    dirty: HashSet<IVec3>,
+    /// Chunks whose blocks or slice changed since the occupancy volume last
+    /// caught up with them.
+    changed_chunks: HashSet<IVec3>,
+    /// Whether a block was set since the world was made, other than by the
+    /// world changing on its own.
+    edited: bool,
+}
+
+/// Sparse layer of entities attached to individual voxels, for blocks that
+/// need state or visuals beyond their `Block` value (doors, containers, ...).
+/// An entry is despawned as soon as the block at its position changes.
+#[derive(Resource, Default)]
+pub struct BlockEntities {
+    entities: HashMap<IVec3, Entity>,
+}
+
+impl BlockEntities {
+    pub fn get(&self, pos: IVec3) -> Option<Entity> {
+        self.entities.get(&pos).copied()
+    }
+
+    pub fn insert(&mut self, pos: IVec3, entity: Entity) {
+        self.entities.insert(pos, entity);
+    }
+
+    pub fn remove(&mut self, pos: IVec3) -> Option<Entity> {
+        self.entities.remove(&pos)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit {
+    pub pos: IVec3,
+    pub normal: IVec3,
+}
+
+/// One chunk's mesh in the scene.
+#[derive(Component)]
+struct TerrainChunk;
+
+#[derive(Resource)]
+pub struct TerrainMesh {
+    chunks: HashMap<IVec3, Entity>,
+    /// Chunks waiting for a remesh, oldest first.
+    pending: VecDeque<IVec3>,
+    /// The same chunks as `pending`, to tell if one is already queued.
+    queued: HashSet<IVec3>,
+    material: Handle<TerrainMaterial>,
+    pub texture: Handle<Image>,
+    /// Water surfaces of the chunks that have any.
+    water: HashMap<IVec3, Entity>,
+    water_material: Handle<WaterMaterial>,
+    /// Which blocks are filled, for the ambient occlusion.
+    occlusion: Handle<Image>,
+    /// Which blocks are designated for work, for tinting them.
+    designations: Handle<Image>,
+    /// Chunks whose last mesh came out empty.
+    empty: HashSet<IVec3>,
+    /// Size of the last mesh of each chunk.
+    mesh_stats: HashMap<IVec3, MeshStats>,
+    /// Normal map of the atlas while it loads, None without one.
+    normal_map: Option<Handle<Image>>,
+}
+
+impl TerrainMesh {
+    /// Share of the chunks with an up to date mesh, from 0 to 1.
+    pub fn meshed(&self) -> f32 {
+        if self.chunks.is_empty() {
+            return 1.;
+        }
+        1. - self.pending.len() as f32 / self.chunks.len() as f32
+    }
+
+    /// Queues a remesh of `coord` unless one already is.
+    fn queue_remesh(&mut self, coord: IVec3) {
+        if self.queued.insert(coord) {
+            self.pending.push_back(coord);
+        }
+    }
+
+    /// Takes up to `count` of the oldest queued chunks off the queue.
+    fn take_pending(&mut self, count: usize) -> Vec<IVec3> {
+        let count = self.pending.len().min(count);
+        let batch: Vec<_> = self.pending.drain(..count).collect();
+        for coord in &batch {
+            self.queued.remove(coord);
+        }
+        batch
+    }
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::new(TerrainConfig::default().size)
+    }
+}
+
+impl Terrain {
+    /// An empty map of the given size, sliced a little above halfway up.
+    pub fn new(size: IVec3) -> Self {
+        let chunk_count = (size + IVec3::splat(CHUNK_SIZE - 1)) / CHUNK_SIZE;
+        let volume = (chunk_count.x * chunk_count.y * chunk_count.z) as usize;
+
+        let mut terrain = Self {
+            chunk_count,
+            storage: vec![PalettedChunk::filled(Block::Empty); volume],
+            surface: vec![-1; (size.x * size.z) as usize],
+            size,
+            slice: (size.y * 9 / 16) as u16,
+            cull_oob: false,
+            changes: vec![],
+            edited: false,
+            damage: HashMap::new(),
+            dirty: HashSet::new(),
+            changed_chunks: HashSet::new(),
+        };
+        // a new map is drawn into the volumes from scratch
+        terrain.changed_chunks = terrain.chunks().collect();
+        terrain
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    /// Whether `neighbor` hides the face of the block next to it, its side
+    /// `face` being the one against that block.
+    pub fn covers(&self, neighbor: Block, face: FaceDir) -> bool {
+        covers(self.cull_oob, neighbor, face)
+    }
+
+    /// Chunk `coord` packed for saves and the network, None outside the map.
+    pub fn encode_chunk(&self, coord: IVec3) -> Option<Vec<u8>> {
+        let chunk = self.chunk_index(coord)?;
+        Some(codec::encode(&self.storage[chunk]))
+    }
+
+    /// Replaces chunk `coord` with one packed by `encode_chunk`. Like the
+    /// world generator, this doesn't journal the change block by block.
+    pub fn decode_chunk(&mut self, coord: IVec3, bytes: &[u8]) -> Result<(), String> {
+        let chunk = self
+            .chunk_index(coord)
+            .ok_or_else(|| format!("chunk {} is outside the map", coord))?;
+
+        self.storage[chunk] = codec::decode(bytes)?;
+        self.refresh_chunk_surface(coord);
+        self.dirty.insert(coord);
+        self.changed_chunks.insert(coord);
+        Ok(())
+    }
+
+    /// Like `decode_chunk`, renumbering block ids with `remap` on the way.
+    pub fn decode_chunk_with(
+        &mut self,
+        coord: IVec3,
+        bytes: &[u8],
+        remap: impl Fn(u16) -> u16,
+    ) -> Result<(), String> {
+        let chunk = self
+            .chunk_index(coord)
+            .ok_or_else(|| format!("chunk {} is outside the map", coord))?;
+
+        self.storage[chunk] = codec::decode_with(bytes, remap)?;
+        self.refresh_chunk_surface(coord);
+        self.dirty.insert(coord);
+        self.changed_chunks.insert(coord);
+        Ok(())
+    }
+
+    /// Like `decode_chunk_with`, for a chunk packed while chunks were
+    /// `chunk_size` on a side. Its cells are spread over whichever chunks
+    /// hold them now, and any past the edge of the map are dropped.
+    pub fn decode_resized_chunk(
+        &mut self,
+        coord: IVec3,
+        chunk_size: i32,
+        bytes: &[u8],
+        remap: impl Fn(u16) -> u16,
+    ) -> Result<(), String> {
+        if !(1..=CHUNK_SIZE * 4).contains(&chunk_size) {
+            return Err(format!("bad chunk size {}", chunk_size));
+        }
+        let size = chunk_size as usize;
+        let origin = coord * chunk_size;
+
+        let mut runs = vec![];
+        let mut metas = vec![];
+        codec::decode_runs(
+            bytes,
+            size * size * size,
+            remap,
+            |cells, block| runs.push((cells, block)),
+            |cells, meta| metas.push((cells, meta)),
+        )?;
+
+        // x, z then y, the order the old chunk was packed in
+        let cell_pos = |i: usize| {
+            origin
+                + IVec3::new(
+                    (i / (size * size)) as i32,
+                    (i % size) as i32,
+                    (i / size % size) as i32,
+                )
+        };
+        for (cells, block) in runs {
+            for pos in cells.map(cell_pos) {
+                if pos.cmpge(self.size).any() {
+                    continue;
+                }
+                let (chunk, cell) = self.locate(pos.x as i16, pos.y as i16, pos.z as i16);
+                self.storage[chunk].set(cell, block);
+                self.dirty.insert(Terrain::chunk_of(pos));
+                self.changed_chunks.insert(Terrain::chunk_of(pos));
+            }
+        }
+        for (cells, meta) in metas {
+            for pos in cells.map(cell_pos) {
+                if pos.cmpge(self.size).any() {
+                    continue;
+                }
+                let (chunk, cell) = self.locate(pos.x as i16, pos.y as i16, pos.z as i16);
+                self.storage[chunk].set_meta(cell, meta);
+            }
+        }
+
+        let max = (origin + IVec3::splat(chunk_size)).min(self.size);
+        for x in origin.x..max.x {
+            for z in origin.z..max.z {
+                self.refresh_surface(x, z, origin.y, max.y);
+            }
+        }
+        Ok(())
+    }
+
+    fn chunk_index(&self, coord: IVec3) -> Option<usize> {
+        let count = self.chunk_count;
+        let in_bounds = coord.cmpge(IVec3::ZERO).all() && coord.cmplt(count).all();
+        in_bounds.then(|| ((coord.x * count.z + coord.z) * count.y + coord.y) as usize)
+    }
+
+    /// Chunk holding a cell and the cell's index within it.
+    fn locate(&self, x: i16, y: i16, z: i16) -> (usize, usize) {
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        let (size, count) = (CHUNK_SIZE as usize, self.chunk_count);
+
+        let chunk = ((x / size) * count.z as usize + z / size) * count.y as usize + y / size;
+        let cell = ((x % size) * size + z % size) * size + y % size;
+        (chunk, cell)
+    }
+
+    pub fn get(&self, x: i16, y: i16, z: i16) -> Block {
+        if self.is_pos_oob(x, y, z) {
+            return Block::Oob;
+        }
+
+        let (chunk, cell) = self.locate(x, y, z);
+        self.storage[chunk].get(cell)
+    }
+
+    pub fn get_at(&self, pos: IVec3) -> Block {
+        self.get(pos.x as i16, pos.y as i16, pos.z as i16)
+    }
+
+    /// Id of the block at `pos`, what the chunks store, `BlockId::OOB`
+    /// outside the map.
+    pub fn get_id(&self, pos: IVec3) -> BlockId {
+        if self.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16) {
+            return BlockId::OOB;
+        }
+
+        let (chunk, cell) = self.locate(pos.x as i16, pos.y as i16, pos.z as i16);
+        self.storage[chunk].get_id(cell)
+    }
+
+    pub fn set(&mut self, x: i16, y: i16, z: i16, block: Block) {
+        if self.is_pos_oob(x, y, z) {
+            return;
+        }
+
+        let pos = IVec3::new(x as i32, y as i32, z as i32);
+        let (chunk, cell) = self.locate(x, y, z);
+        let previous = self.storage[chunk].get(cell);
+        if previous != block {
+            self.changes.push((pos, previous));
+            self.edited = true;
+            self.damage.remove(&pos);
+            self.storage[chunk].set(cell, block);
+            self.storage[chunk].set_meta(cell, 0);
+            self.refresh_surface(pos.x, pos.z, pos.y, pos.y + 1);
+            self.mark_dirty(pos);
+            self.changed_chunks.insert(Terrain::chunk_of(pos));
+        }
+    }
+
+    /// Metadata byte of the block at `pos`, zero outside the map. What it
+    /// means is up to the block: a level, a stage, a count.
+    pub fn get_meta(&self, pos: IVec3) -> u8 {
+        if self.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16) {
+            return 0;
+        }
+
+        let (chunk, cell) = self.locate(pos.x as i16, pos.y as i16, pos.z as i16);
+        self.storage[chunk].get_meta(cell)
+    }
+
+    /// Sets the metadata of the block at `pos`. It's reset to zero whenever
+    /// the block changes, and isn't journaled or remeshed like the block.
+    pub fn set_meta(&mut self, pos: IVec3, meta: u8) {
+        if self.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16) {
+            return;
+        }
+
+        let (chunk, cell) = self.locate(pos.x as i16, pos.y as i16, pos.z as i16);
+        self.storage[chunk].set_meta(cell, meta);
+    }
+
+    /// Moves the slice, remeshing the layers between the old and new height.
+    pub fn set_slice(&mut self, slice: u16) {
+        let low = self.slice.min(slice) as i32 - 1;
+        let high = self.slice.max(slice) as i32;
+        self.slice = slice;
+
+        for chunk in self.chunks().collect::<Vec<_>>() {
+            let bottom = chunk.y * CHUNK_SIZE;
+            if bottom <= high && bottom + CHUNK_SIZE > low {
+                self.dirty.insert(chunk);
+                self.changed_chunks.insert(chunk);
+            }
+        }
+    }
+
+    /// Coordinates of every chunk in the map. Chunks on the far edges may
+    /// hang over the end of a map that isn't a multiple of `CHUNK_SIZE`.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec3> {
+        let count = self.chunk_count;
+
+        (0..count.x).flat_map(move |x| {
+            (0..count.y).flat_map(move |y| (0..count.z).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Every block in the map with its position.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, Block)> + '_ {
+        self.iter_region(IVec3::ZERO, self.size)
+    }
+
+    /// Blocks from `min` up to but not including `max`, with their positions.
+    /// The part of the region outside the map is skipped.
+    pub fn iter_region(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, Block)> + '_ {
+        let min = min.max(IVec3::ZERO);
+        let max = max.min(self.size);
+
+        // x, z then y, the order blocks are stored in
+        (min.x..max.x)
+            .flat_map(move |x| {
+                (min.z..max.z).flat_map(move |z| (min.y..max.y).map(move |y| IVec3::new(x, y, z)))
+            })
+            .map(|pos| (pos, self.get_at(pos)))
+    }
+
+    /// Number of full cubes in the map.
+    pub fn count_filled(&self) -> usize {
+        self.iter().filter(|(_, block)| block.is_filled()).count()
+    }
+
+    pub fn chunk_of(pos: IVec3) -> IVec3 {
+        pos.div_euclid(IVec3::splat(CHUNK_SIZE))
+    }
+
+    /// Flags the chunk holding `pos` for remeshing, along with every neighbor
+    /// chunk that has it in the border of its `ChunkView`.
+    pub fn mark_dirty(&mut self, pos: IVec3) {
+        let low = Terrain::chunk_of(pos - IVec3::splat(view::BORDER)).max(IVec3::ZERO);
+        let high = Terrain::chunk_of(pos + IVec3::splat(view::BORDER)).min(self.chunk_count - 1);
+
+        for x in low.x..=high.x {
+            for y in low.y..=high.y {
+                for z in low.z..=high.z {
+                    self.dirty.insert(IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Flags every chunk for remeshing, for when blocks change how they look.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.extend(self.chunks());
+    }
+
+    /// Drains the chunks whose blocks or slice changed since the last call.
+    pub fn take_changed_chunks(&mut self) -> Vec<IVec3> {
+        self.changed_chunks.drain().collect()
+    }
+
+    /// Drains the chunks waiting to be remeshed.
+    pub fn take_dirty_chunks(&mut self) -> Vec<IVec3> {
+        self.dirty.drain().collect()
+    }
+
+    /// Crack overlay to draw at `pos`, zero for an intact block.
+    pub fn damage_stage(&self, pos: IVec3) -> u32 {
+        match self.damage.get(&pos) {
+            Some(damage) => 1 + ((damage * DAMAGE_STAGES as f32) as u32).min(DAMAGE_STAGES - 1),
+            None => 0,
+        }
+    }
+
+    /// Mining progress of the block at `pos`, from 0 for intact to 1.
+    pub fn damage(&self, pos: IVec3) -> f32 {
+        self.damage.get(&pos).copied().unwrap_or(0.)
+    }
+
+    /// Puts the mining progress of the block at `pos` back to what `damage`
+    /// returned, without breaking it.
+    pub fn set_damage(&mut self, pos: IVec3, damage: f32) {
+        let stage = self.damage_stage(pos);
+        if damage > 0. {
+            self.damage.insert(pos, damage);
+        } else {
+            self.damage.remove(&pos);
+        }
+
+        if self.damage_stage(pos) != stage {
+            self.mark_dirty(pos);
+        }
+    }
+
+    /// Applies `work` seconds of mining to the block at `pos`, breaking it once
+    /// its hardness is used up. Returns whether the block broke.
+    pub fn add_damage(&mut self, pos: IVec3, work: f32) -> bool {
+        let block = self.get_at(pos);
+        if !block.is_minable() {
+            return false;
+        }
+
+        let stage = self.damage_stage(pos);
+        let damage = self.damage.entry(pos).or_insert(0.);
+        *damage += work / block.def().hardness;
+
+        if *damage >= 1. {
+            self.set_at(pos, Block::Empty);
+            return true;
+        }
+
+        if self.damage_stage(pos) != stage {
+            self.mark_dirty(pos);
+        }
+
+        false
+    }
+
+    /// Drains the positions changed since the last call.
+    pub fn take_changes(&mut self) -> Vec<(IVec3, Block)> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Whether any block was set since the world was made or last marked
+    /// unedited, leaving out the ones set in `naturally`.
+    pub fn is_edited(&self) -> bool {
+        self.edited
+    }
+
+    /// Marks the blocks as the world was made, once it's generated or loaded.
+    pub fn mark_unedited(&mut self) {
+        self.edited = false;
+    }
+
+    /// Runs `f` as the world changing on its own, like grass spreading or
+    /// lava flowing, so the blocks it sets don't count as edits.
+    pub fn naturally<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let edited = self.edited;
+        let result = f(self);
+        self.edited = edited;
+        result
+    }
+
+    pub fn set_at(&mut self, pos: IVec3, block: Block) {
+        self.set(pos.x as i16, pos.y as i16, pos.z as i16, block);
+    }
+
+    /// Walks the voxel grid along a ray (Amanatides & Woo) and returns the first
+    /// solid block hit. Blocks at or above the current slice are not rendered,
+    /// so they are skipped.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_dist: f32) -> Option<RayHit> {
+        let dir = direction.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+
+        let mut pos = origin.floor().as_ivec3();
+        let step = dir.signum().as_ivec3();
+        let delta = (Vec3::ONE / dir).abs();
+        let next_boundary = pos.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        let mut t_max = Vec3::new(
+            if dir.x != 0. {
+                (next_boundary.x - origin.x) / dir.x
+            } else {
+                f32::INFINITY
+            },
+            if dir.y != 0. {
+                (next_boundary.y - origin.y) / dir.y
+            } else {
+                f32::INFINITY
+            },
+            if dir.z != 0. {
+                (next_boundary.z - origin.z) / dir.z
+            } else {
+                f32::INFINITY
+            },
+        );
+        let mut normal = IVec3::ZERO;
+        let mut dist = 0.;
+
+        while dist <= max_dist {
+            if self.get_at(pos).is_solid() && pos.y < self.slice as i32 {
+                return Some(RayHit { pos, normal });
+            }
+
+            if t_max.x < t_max.y && t_max.x < t_max.z {
+                pos.x += step.x;
+                dist = t_max.x;
+                t_max.x += delta.x;
+                normal = IVec3::new(-step.x, 0, 0);
+            } else if t_max.y < t_max.z {
+                pos.y += step.y;
+                dist = t_max.y;
+                t_max.y += delta.y;
+                normal = IVec3::new(0, -step.y, 0);
+            } else {
+                pos.z += step.z;
+                dist = t_max.z;
+                t_max.z += delta.z;
+                normal = IVec3::new(0, 0, -step.z);
+            }
+        }
+
+        None
+    }
+
+    /// Whether a line between the centers of `a` and `b` gets through without
+    /// touching a filled block. The blocks at either end don't count, only
+    /// the ones the line passes on its way.
+    pub fn line_of_sight(&self, a: IVec3, b: IVec3) -> bool {
+        let offset = b - a;
+        let cells = offset.abs().to_array().iter().sum::<i32>();
+        let step = offset.signum();
+        // share of the line between crossings into the next cell on each
+        // axis, the first being half a cell out from the center
+        let delta = (Vec3::ONE / offset.as_vec3()).abs();
+        let mut t_max = delta * 0.5;
+        let mut pos = a;
+
+        for _ in 1..cells {
+            if t_max.x < t_max.y && t_max.x < t_max.z {
+                pos.x += step.x;
+                t_max.x += delta.x;
+            } else if t_max.y < t_max.z {
+                pos.y += step.y;
+                t_max.y += delta.y;
+            } else {
+                pos.z += step.z;
+                t_max.z += delta.z;
+            }
+
+            if self.get_at(pos).is_filled() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Whether `pos` is inside the map, without the truncation to i16 the
+    /// block getters do.
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(IVec3::ZERO).all() && pos.cmplt(self.size).all()
+    }
+
+    pub fn is_pos_oob(&self, x: i16, y: i16, z: i16) -> bool {
+        x < 0
+            || y < 0
+            || z < 0
+            || x as i32 >= self.size.x
+            || y as i32 >= self.size.y
+            || z as i32 >= self.size.z
+    }
+
+    pub fn get_neighbors_immediate(&self, x: i16, y: i16, z: i16) -> [Block; 6] {
+        [
+            self.get(x, y + 1, z), // above
+            self.get(x, y, z - 1), // front
+            self.get(x + 1, y, z), // right
+            self.get(x, y, z + 1), // behind
+            self.get(x - 1, y, z), // left
+            self.get(x, y - 1, z), // below
+        ]
+    }
+
+    /// Every block touching `pos` by a face, edge or corner, in the order of
+    /// `NEIGHBORHOOD`.
+    pub fn get_neighbors_full(&self, pos: IVec3) -> [Block; 26] {
+        NEIGHBORHOOD.map(|offset| self.get_at(pos + offset))
+    }
+
+    /// The blocks in front of side `face` of `pos` that meet at one of the
+    /// side's corners: the two across its edges, then the one diagonally
+    /// across. Bit 0 of `corner` picks the positive end of the first of
+    /// `FaceDir::tangents`, bit 1 that of the second.
+    pub fn get_corner_neighbors(&self, pos: IVec3, face: FaceDir, corner: usize) -> [Block; 3] {
+        let (u, v) = face.tangents();
+        let u = if corner & 1 == 0 { -u } else { u };
+        let v = if corner & 2 == 0 { -v } else { v };
+        let front = pos + face.normal();
+
+        [
+            self.get_at(front + u),
+            self.get_at(front + v),
+            self.get_at(front + u + v),
+        ]
+    }
+}
+
+const fn neighborhood() -> [IVec3; 26] {
+    let mut offsets = [IVec3::ZERO; 26];
+    let mut i = 0;
+    let mut n = 0;
+    while i < 27 {
+        // 13 is the block itself
+        if i != 13 {
+            offsets[n] = IVec3::new(i / 9 - 1, i / 3 % 3 - 1, i % 3 - 1);
+            n += 1;
+        }
+        i += 1;
+    }
+    offsets
+}
+
+/// Whether `neighbor` hides the face against it on its side `face`, the
+/// outside of the map doing so only when `cull_oob` is set.
+fn covers(cull_oob: bool, neighbor: Block, face: FaceDir) -> bool {
+    if neighbor == Block::Oob {
+        // the map stays open above
+        return cull_oob && face != FaceDir::NegY;
+    }
+    neighbor.covers(face)
+}
+
+/// Reads a map size written XxYxZ with Y up, e.g. 128x64x128.
+pub fn parse_size(text: &str) -> Result<IVec3, String> {
+    let parts = text
+        .split('x')
+        .map(|part| part.trim().parse::<i32>().map_err(|err| err.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [x, y, z] = parts[..] else {
+        return Err(format!("expected XxYxZ, got `{}`", text));
+    };
+
+    let size = IVec3::new(x, y, z);
+    check_size(size)?;
+    Ok(size)
+}
+
+/// Fails for sizes with a side under 1 or over `MAX_SIZE`, so maps read from
+/// files or the network can't ask for more than a map can hold.
+pub fn check_size(size: IVec3) -> Result<(), String> {
+    if size.cmplt(IVec3::ONE).any() || size.cmpgt(IVec3::splat(MAX_SIZE)).any() {
+        return Err(format!(
+            "bad map size {}, each side must be between 1 and {}",
+            size, MAX_SIZE
+        ));
+    }
+    Ok(())
+}
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        let config = &self.config;
+        let mut terrain = Terrain::new(config.size);
+        if let Some(slice) = config.slice {
+            terrain.slice = slice.min(config.size.y as u16);
+        }
+        terrain.cull_oob = config.cull_oob;
+
+        app.insert_resource(config.clone())
+            .insert_resource(terrain)
+            .insert_resource(WorldGenSettings {
+                seed: config.seed,
+                landform: config.generator,
+                erosion_droplets: config.erosion_droplets,
+                talus_passes: config.talus_passes,
+                ..default()
+            })
+            .init_resource::<BlockEntities>()
+            .init_resource::<WorldGenPipeline>()
+            .init_resource::<TerrainStats>()
+            .add_event::<TerrainModifiedEvent>()
+            .add_event::<BlockChangedEvent>()
+            .add_systems(
+                PostUpdate,
+                (flush_block_changes, update_terrain_stats).chain(),
+            )
+            .add_systems(OnExit(AppState::InGame), clear_block_entities);
+    }
+}
+
+impl Plugin for TerrainMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<TerrainMaterial>::default())
+            .add_plugins(MaterialPlugin::<WaterMaterial>::default())
+            .add_plugins(VertexPullingPlugin)
+            .init_resource::<GraphicsSettings>()
+            .init_resource::<Designations>()
+            .add_systems(OnEnter(AppState::InGame), setup_terrain_mesh)
+            .add_systems(OnExit(AppState::InGame), despawn_terrain_mesh)
+            .add_systems(PreUpdate, sync_cull_oob)
+            .add_systems(
+                Update,
+                (
+                    update_occlusion,
+                    update_normal_map,
+                    update_shading,
+                    update_designations,
+                    show_light_debug,
+                    update_terrain,
+                )
+                    .chain()
+                    .after(light::update_light)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Publishes the frame's voxel edits and drops block entities whose block was
+/// replaced.
+fn flush_block_changes(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut ev_block_changed: EventWriter<BlockChangedEvent>,
+) {
+    let mut seen = HashSet::new();
+
+    // only the first entry per position holds what the block was before the frame
+    for (pos, previous) in terrain.take_changes() {
+        let block = terrain.get_at(pos);
+        if !seen.insert(pos) || block == previous {
+            continue;
+        }
+
+        // entities can span several voxels, only the first change despawns them
+        if let Some(entity) = block_entities.remove(pos) {
+            if let Some(entity) = commands.get_entity(entity) {
+                entity.despawn_recursive();
+            }
+        }
+
+        ev_block_changed.send(BlockChangedEvent {
+            pos,
+            block,
+            previous,
+        });
+    }
+}
+
+/// Despawns the block entities of a world being left.
+fn clear_block_entities(mut commands: Commands, mut block_entities: ResMut<BlockEntities>) {
+    let entities: HashSet<_> = block_entities.entities.drain().map(|(_, e)| e).collect();
+    for entity in entities {
+        if let Some(entity) = commands.get_entity(entity) {
+            entity.despawn_recursive();
+        }
+    }
+}
+
+/// Spawns an entity for every chunk, their meshes follow over the next
+/// frames. The material and atlas of an earlier world are kept, mods have
+/// already drawn their tiles into it.
+#[allow(clippy::too_many_arguments)]
+fn setup_terrain_mesh(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    existing: Option<Res<TerrainMesh>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut water_materials: ResMut<Assets<WaterMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let slice = terrain.slice;
+    let (material, terrain_texture, water_material, occlusion, designations, normal_map) =
+        match existing {
+            Some(existing) => {
+                if let Some(material) = materials.get_mut(&existing.material) {
+                    material.terrain_slice_y = slice as u32;
+                }
+                (
+                    existing.material.clone(),
+                    existing.texture.clone(),
+                    existing.water_material.clone(),
+                    existing.occlusion.clone(),
+                    existing.designations.clone(),
+                    existing.normal_map.clone(),
+                )
+            }
+            None => {
+                let settings = |s: &mut ImageLoaderSettings| s.sampler = ImageSampler::nearest();
+                let texture: Handle<Image> =
+                    asset_server.load_with_settings("terrain.png", settings);
+                let occlusion = images.add(placeholder_volume());
+                let designations = images.add(placeholder_designations());
+                let material = materials.add(TerrainMaterial {
+                    color: Color::YELLOW_GREEN,
+                    texture: texture.clone(),
+                    texture_count: TEXTURE_COUNT,
+                    terrain_slice_y: slice as u32,
+                    occlusion: occlusion.clone(),
+                    occlusion_strength: 0.,
+                    light_debug: 0,
+                    designations: designations.clone(),
+                    normal_map: None,
+                    normal_strength: 0.,
+                    outline: outline_params(false),
+                    dimming: dimming_params(false),
+                });
+                let water_material = water_materials.add(WaterMaterial::default());
+                let normal_map = load_normal_map(&asset_server);
+                (
+                    material,
+                    texture,
+                    water_material,
+                    occlusion,
+                    designations,
+                    normal_map,
+                )
+            }
+        };
+
+    let empty = meshes.add(MeshScratch::default().build());
+    let mut chunks = HashMap::new();
+    for coord in terrain.chunks() {
+        // positions are chunk-local, and too packed for the wireframe and
+        // shadow passes to read
+        let entity = commands.spawn((
+            MaterialMeshBundle {
+                mesh: empty.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation((coord * CHUNK_SIZE).as_vec3()),
+                ..default()
+            },
+            Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_SIZE as f32)),
+            NotShadowCaster,
+            TerrainChunk,
+        ));
+        chunks.insert(coord, entity.id());
+    }
+    terrain.take_dirty_chunks();
+
+    let terrain_mesh = TerrainMesh {
+        pending: terrain.chunks().collect(),
+        queued: terrain.chunks().collect(),
+        chunks,
+        material,
+        texture: terrain_texture,
+        water: HashMap::new(),
+        water_material,
+        occlusion,
+        designations,
+        empty: HashSet::new(),
+        mesh_stats: HashMap::new(),
+        normal_map,
+    };
+    commands.insert_resource(terrain_mesh);
+}
+
+/// Carries `TerrainConfig::cull_oob` over to a terrain that was replaced, by
+/// a new or loaded world, and remeshes everything when it changes.
+fn sync_cull_oob(config: Res<TerrainConfig>, mut terrain: ResMut<Terrain>) {
+    if terrain.cull_oob != config.cull_oob {
+        terrain.cull_oob = config.cull_oob;
+        terrain.mark_all_dirty();
+    }
+}
+
+/// Drops the chunk meshes of a world being left.
+fn despawn_terrain_mesh(
+    mut commands: Commands,
+    mut terrain_mesh: ResMut<TerrainMesh>,
+    chunks: Query<Entity, With<TerrainChunk>>,
+) {
+    for entity in chunks.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (_, entity) in terrain_mesh.water.drain() {
+        commands.entity(entity).despawn();
+    }
+    terrain_mesh.chunks.clear();
+    terrain_mesh.pending.clear();
+    terrain_mesh.queued.clear();
+    terrain_mesh.empty.clear();
+}
+
+/// Buffers a remesh is built in, kept between chunks and frames so editing
+/// doesn't allocate a fresh set for every rebuild.
+#[derive(Default)]
+struct MeshScratch {
+    data: TerrainMeshData,
+    positions: Vec<u32>,
+    /// Packed normals, for the smooth mesher only.
+    normals: Vec<u32>,
+    faces: Vec<u32>,
+    water: WaterMeshData,
+}
+
+/// What a chunk is drawn from after a remesh.
+enum ChunkGeometry {
+    Vertices(Mesh),
+    /// Packed faces for vertex pulling.
+    Faces(Vec<u32>),
+}
+
+impl MeshScratch {
+    /// Meshes the chunk of `view` into the scratch buffers.
+    fn mesh(&mut self, mesher: Mesher, view: &ChunkView) {
+        mesher.mesh_view_into(view, &mut self.data);
+
+        let origin = (view.chunk * CHUNK_SIZE).as_vec3();
+        self.positions.clear();
+        self.positions.extend(
+            self.data
+                .positions
+                .iter()
+                .map(|p| pack_position(Vec3::from(*p) - origin)),
+        );
+        self.normals.clear();
+        if mesher == Mesher::Smooth {
+            self.normals.extend(
+                self.data
+                    .normals
+                    .iter()
+                    .map(|n| pack_normal(Vec3::from(*n))),
+            );
+        }
+    }
+
+    /// Packs the chunk of `view` as faces to pull if it's all cubes and
+    /// `pulling` is on, meshes it otherwise.
+    fn geometry(&mut self, mesher: Mesher, pulling: bool, view: &ChunkView) -> ChunkGeometry {
+        if pulling && pull_faces_into(view, &mut self.faces) && !self.faces.is_empty() {
+            return ChunkGeometry::Faces(self.faces.clone());
+        }
+        self.mesh(mesher, view);
+        ChunkGeometry::Vertices(self.build())
+    }
+
+    /// A new mesh holding a copy of the scratch geometry.
+    fn build(&self) -> Mesh {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(ATTRIBUTE_PACKED_POSITION, self.positions.clone())
+        .with_inserted_attribute(ATTRIBUTE_PACKED_BLOCK, self.data.packed.clone())
+        .with_inserted_indices(chunk_indices(&self.data.indicies, self.positions.len()));
+        if !self.normals.is_empty() {
+            mesh.insert_attribute(ATTRIBUTE_PACKED_NORMAL, self.normals.clone());
+        }
+        mesh
+    }
+}
+
+/// Narrows the indices to 16 bits when every vertex can be addressed with
+/// them, which most chunks can, halving what gets uploaded.
+fn chunk_indices(indices: &[u32], vertex_count: usize) -> Indices {
+    if vertex_count <= u16::MAX as usize + 1 {
+        Indices::U16(indices.iter().map(|i| *i as u16).collect())
+    } else {
+        Indices::U32(indices.to_vec())
+    }
+}
+
+/// Packs a chunk-local position into 10 bits per axis, counted in
+/// `POSITION_STEPS` per block. Every shape's corners lie on that grid, smooth
+/// terrain is rounded to it.
+fn pack_position(local: Vec3) -> u32 {
+    let steps = (local * POSITION_STEPS)
+        .round()
+        .as_uvec3()
+        .min(UVec3::splat(1023));
+    steps.x | (steps.y << 10) | (steps.z << 20)
+}
+
+/// Average color of the opaque texels in an atlas tile.
+pub fn tile_color(image: &Image, texture_id: u32) -> Option<Color> {
+    let width = image.texture_descriptor.size.width;
+    let tile = width / TEXTURE_COUNT;
+    let ox = (texture_id % TEXTURE_COUNT) * tile;
+    let oy = (texture_id / TEXTURE_COUNT) * tile;
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+
+    for y in oy..oy + tile {
+        for x in ox..ox + tile {
+            let i = ((y * width + x) * 4) as usize;
+            let texel = image.data.get(i..i + 4)?;
+            if texel[3] >= 128 {
+                sum[0] += texel[0] as u32;
+                sum[1] += texel[1] as u32;
+                sum[2] += texel[2] as u32;
+                count += 1;
+            }
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(Color::rgb_u8(
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    ))
+}
+
+/// Switches the terrain between its lighting and the light debug views,
+/// remeshing it with the light map channel the view shows.
+fn show_light_debug(
+    debug: Option<Res<LightDebug>>,
+    mut terrain: ResMut<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    let Some(debug) = debug.filter(|debug| debug.is_changed()) else {
+        return;
+    };
+    let Some(material) = materials.get_mut(&terrain_mesh.material) else {
+        return;
+    };
+
+    material.light_debug = match *debug {
+        LightDebug::Sky => 1,
+        LightDebug::Block => 2,
+        LightDebug::Off | LightDebug::Probe => 0,
+    };
+    terrain.mark_all_dirty();
+}
+
+/// Queues the chunks edited since the last frame and remeshes the oldest
+/// `MESH_BUDGET` of them in parallel, each task with its own scratch buffers.
+#[allow(clippy::too_many_arguments)]
+fn update_terrain(
+    mut commands: Commands,
+    mut pool: Local<Vec<MeshScratch>>,
+    config: Res<TerrainConfig>,
+    mut terrain: ResMut<Terrain>,
+    mut terrain_mesh: ResMut<TerrainMesh>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut pulled_materials: ResMut<Assets<PulledTerrainMaterial>>,
+    mut chunks: Query<(&mut Handle<Mesh>, Has<PulledChunk>), With<TerrainChunk>>,
+    light: Option<Res<LightMap>>,
+    debug: Option<Res<LightDebug>>,
+    time: Res<Time<Real>>,
+) {
+    for coord in terrain.take_dirty_chunks() {
+        terrain_mesh.queue_remesh(coord);
+    }
+
+    let batch = terrain_mesh.take_pending(MESH_BUDGET);
+    let terrain = &*terrain;
+    let light = light.as_deref();
+    let debug = debug.map_or(LightDebug::Off, |debug| *debug);
+    let mesher = config.mesher;
+    let pulling = config.vertex_pulling && mesher != Mesher::Smooth;
+
+    let results = ComputeTaskPool::get().scope(|scope| {
+        for coord in batch {
+            let mut scratch = pool.pop().unwrap_or_default();
+            scope.spawn(async move {
+                let start = Instant::now();
+                let mut view = ChunkView::new(terrain, coord);
+                if let Some(light) = light {
+                    view = view.with_light(|pos| debug.level(light, pos));
+                }
+                let geometry = scratch.geometry(mesher, pulling, &view);
+                let took = start.elapsed();
+                scratch.water.mesh(terrain, coord);
+                let water = scratch.water.build();
+                (coord, geometry, took, water, scratch)
+            });
+        }
+    });
+
+    for (coord, geometry, took, water, scratch) in results {
+        pool.push(scratch);
+        update_water_chunk(&mut commands, &mut terrain_mesh, &mut meshes, coord, water);
+
+        let Some(&entity) = terrain_mesh.chunks.get(&coord) else {
+            continue;
+        };
+        let Ok((mut handle, pulled)) = chunks.get_mut(entity) else {
+            continue;
+        };
+
+        // the chunk keeps drawing its old mesh until the new one is in, never
+        // one that's half rewritten. The old one goes with its last handle.
+        let is_empty =
+            matches!(&geometry, ChunkGeometry::Vertices(mesh) if mesh.count_vertices() == 0);
+        if is_empty {
+            terrain_mesh.empty.insert(coord);
+        } else {
+            terrain_mesh.empty.remove(&coord);
+        }
+        let stats = MeshStats {
+            took,
+            meshed_at: time.elapsed(),
+            ..MeshStats::of(&geometry)
+        };
+        terrain_mesh.mesh_stats.insert(coord, stats);
+
+        match geometry {
+            ChunkGeometry::Vertices(mesh) => {
+                *handle = meshes.add(mesh);
+                if pulled {
+                    commands
+                        .entity(entity)
+                        .remove::<(PulledChunk, Handle<PulledTerrainMaterial>)>()
+                        .insert(terrain_mesh.material.clone());
+                }
+            }
+            ChunkGeometry::Faces(faces) => {
+                if !pulled {
+                    *handle = meshes.add(stand_in_mesh());
+                }
+                let base = materials.get(&terrain_mesh.material).unwrap();
+                let count = faces.len() as u32 / 2;
+                let material = pulled_materials.add(PulledTerrainMaterial::new(base, faces));
+                commands
+                    .entity(entity)
+                    .remove::<Handle<TerrainMaterial>>()
+                    .insert((
+                        material,
+                        PulledChunk {
+                            mesh: handle.id(),
+                            faces: count,
+                        },
+                    ));
+            }
+        }
+    }
+
+    let slice = terrain.slice as u32;
+    if materials
+        .get(&terrain_mesh.material)
+        .unwrap()
+        .terrain_slice_y
+        != slice
+    {
+        let mat = materials.get_mut(&terrain_mesh.material).unwrap();
+        mat.terrain_slice_y = slice;
+    }
+}
+
+/// Flags the terrain materials as changed after one of their images was
+/// replaced, since their bind groups hold on to the old texture until then.
+fn refresh_terrain_materials(
+    terrain_mesh: &TerrainMesh,
+    materials: &mut Assets<TerrainMaterial>,
+    pulled_materials: &mut Assets<PulledTerrainMaterial>,
+) {
+    materials.get_mut(&terrain_mesh.material);
+    for _ in pulled_materials.iter_mut() {}
+}
+
+/// Subdivisions of a block that packed vertex positions snap to.
+const POSITION_STEPS: f32 = 16.;
+
+// a terrain vertex is these two words, 8 bytes against the 28 that float
+// positions and normals took
+const ATTRIBUTE_PACKED_POSITION: MeshVertexAttribute =
+    MeshVertexAttribute::new("PackedPosition", 9985136797, VertexFormat::Uint32);
+const ATTRIBUTE_PACKED_BLOCK: MeshVertexAttribute =
+    MeshVertexAttribute::new("PackedBlock", 9985136798, VertexFormat::Uint32);
+const ATTRIBUTE_PACKED_NORMAL: MeshVertexAttribute =
+    MeshVertexAttribute::new("PackedNormal", 9985136799, VertexFormat::Uint32);
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TerrainMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    texture: Handle<Image>,
+    #[uniform[2]]
+    color: Color,
+    #[uniform[3]]
+    texture_count: u32,
+    #[uniform[4]]
+    terrain_slice_y: u32,
+    #[texture(6, dimension = "3d")]
+    #[sampler(7)]
+    occlusion: Handle<Image>,
+    /// 0 while ambient occlusion is off.
+    #[uniform(8)]
+    occlusion_strength: f32,
+    /// 1 or 2 to color the terrain by its sky or block light instead.
+    #[uniform(9)]
+    light_debug: u32,
+    #[texture(10, dimension = "3d")]
+    designations: Handle<Image>,
+    /// Set once the normal map is loaded and split into its tiles.
+    #[texture(11, dimension = "2d_array")]
+    #[sampler(12)]
+    normal_map: Option<Handle<Image>>,
+    /// 0 while the relief is off or there's no normal map.
+    #[uniform(13)]
+    normal_strength: f32,
+    /// Built by `outline_params`, 0 strength while the slice outlines are
+    /// off.
+    #[uniform(14)]
+    outline: Vec4,
+    /// Built by `dimming_params`, 0 strength while the dimming is off.
+    #[uniform(15)]
+    dimming: Vec4,
+}
+
+impl Material for TerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let mut attributes = vec![
+            ATTRIBUTE_PACKED_POSITION.at_shader_location(0),
+            ATTRIBUTE_PACKED_BLOCK.at_shader_location(1),
+        ];
+        // sloped smooth terrain brings its own normals
+        if layout.contains(ATTRIBUTE_PACKED_NORMAL) {
+            attributes.push(ATTRIBUTE_PACKED_NORMAL.at_shader_location(2));
+            descriptor.vertex.shader_defs.push("PACKED_NORMAL".into());
+            if let Some(fragment) = descriptor.fragment.as_mut() {
+                fragment.shader_defs.push("PACKED_NORMAL".into());
+            }
+        }
+        descriptor.vertex.buffers = vec![layout.get_layout(&attributes)?];
+        Ok(())
+    }
+}
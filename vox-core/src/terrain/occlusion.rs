@@ -0,0 +1,141 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+};
+
+use super::{
+    pulling::PulledTerrainMaterial, refresh_terrain_materials, GraphicsSettings, Terrain,
+    TerrainMaterial, TerrainMesh, CHUNK_SIZE,
+};
+
+/// How dark the fully enclosed corners get, from 0 for not at all to 1 for
+/// black.
+pub(super) const OCCLUSION_STRENGTH: f32 = 0.6;
+
+/// One texel per block of the map, full where the block is a full cube. The
+/// terrain shader samples it filtered just in front of each face, so faces
+/// in corners and under overhangs come out darker, and reads it per block
+/// to outline the slice's cut where it meets open space. Blocks above the
+/// slice count as air since they aren't drawn.
+pub(super) fn occupancy_volume(terrain: &Terrain) -> Image {
+    let size = terrain.size();
+    let mut data = vec![0; (size.x * size.y * size.z) as usize];
+    write_occupancy(terrain, &mut data, IVec3::ZERO, size);
+
+    let mut image = Image::new(
+        Extent3d {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth_or_array_layers: size.z as u32,
+        },
+        TextureDimension::D3,
+        data,
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::linear();
+    image
+}
+
+/// Rewrites the texels of the blocks from `min` up to but not including
+/// `max` in the occupancy volume `data` of the whole map.
+fn write_occupancy(terrain: &Terrain, data: &mut [u8], min: IVec3, max: IVec3) {
+    let size = terrain.size();
+    let slice = terrain.slice as i32;
+
+    for (pos, block) in terrain.iter_region(min, max) {
+        let full = block.is_filled() && pos.y < slice;
+        data[(pos.x + (pos.y + pos.z * size.y) * size.x) as usize] = if full { u8::MAX } else { 0 };
+    }
+}
+
+/// An empty volume for the material to hold until the first real one.
+pub(super) fn placeholder_volume() -> Image {
+    occupancy_volume(&Terrain::new(IVec3::ONE))
+}
+
+/// Keeps the occupancy volume up to date while ambient occlusion or the
+/// slice outlines are on, rewriting the chunks whose blocks or slice
+/// changed.
+pub(super) fn update_occlusion(
+    settings: Res<GraphicsSettings>,
+    mut terrain: ResMut<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut pulled_materials: ResMut<Assets<PulledTerrainMaterial>>,
+) {
+    let changed = terrain.take_changed_chunks();
+
+    if !settings.ambient_occlusion && !settings.slice_outlines {
+        return;
+    }
+
+    let size = images
+        .get(&terrain_mesh.occlusion)
+        .map(|image| image.texture_descriptor.size);
+    let is_stale = size.is_none_or(|size| {
+        IVec3::new(
+            size.width as i32,
+            size.height as i32,
+            size.depth_or_array_layers as i32,
+        ) != terrain.size()
+    });
+    if is_stale || settings.is_changed() {
+        images.insert(&terrain_mesh.occlusion, occupancy_volume(&terrain));
+    } else if !changed.is_empty() {
+        let Some(image) = images.get_mut(&terrain_mesh.occlusion) else {
+            return;
+        };
+        for coord in changed {
+            let min = coord * CHUNK_SIZE;
+            write_occupancy(&terrain, &mut image.data, min, min + CHUNK_SIZE);
+        }
+    } else {
+        return;
+    }
+    refresh_terrain_materials(&terrain_mesh, &mut materials, &mut pulled_materials);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{fixtures, Block};
+
+    #[test]
+    fn blocks_above_the_slice_are_left_out() {
+        let mut terrain = Terrain::new(IVec3::new(4, 8, 4));
+        terrain.slice = 4;
+        terrain.set_at(IVec3::new(1, 2, 3), Block::Stone);
+        terrain.set_at(IVec3::new(1, 6, 3), Block::Stone);
+
+        let image = occupancy_volume(&terrain);
+        let filled: Vec<usize> = (0..image.data.len())
+            .filter(|&i| image.data[i] > 0)
+            .collect();
+        assert_eq!(filled, vec![1 + (2 + 3 * 8) * 4]);
+    }
+
+    #[test]
+    fn rewriting_changed_chunks_matches_a_rebuild() {
+        let mut terrain = fixtures::ground(IVec3::new(40, 24, 20), 8, Block::Stone);
+        terrain.take_changed_chunks();
+        let mut image = occupancy_volume(&terrain);
+
+        terrain.set_at(IVec3::new(3, 7, 3), Block::Empty);
+        terrain.set_at(IVec3::new(35, 10, 18), Block::Dirt);
+        terrain.set_slice(6);
+        let changed = terrain.take_changed_chunks();
+        assert!(changed.len() < terrain.chunks().count());
+
+        for coord in changed {
+            let min = coord * CHUNK_SIZE;
+            write_occupancy(&terrain, &mut image.data, min, min + CHUNK_SIZE);
+        }
+        assert!(image.data == occupancy_volume(&terrain).data);
+    }
+}
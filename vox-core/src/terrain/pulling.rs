@@ -0,0 +1,141 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        mesh::MeshVertexBufferLayout,
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            AsBindGroup, PrimitiveTopology, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError,
+        },
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{TerrainMaterial, ATTRIBUTE_PACKED_BLOCK};
+
+/// Draws chunks straight from their packed faces: the vertex shader finds
+/// each quad's corners from `vertex_index`, six vertices to a face, with no
+/// vertex or index buffer.
+pub(super) struct VertexPullingPlugin;
+
+/// `TerrainMaterial` with the faces of one chunk, two words each as
+/// `binary::pull_faces_into` packs them.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub(super) struct PulledTerrainMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    texture: Handle<Image>,
+    #[uniform[2]]
+    color: Color,
+    #[uniform[3]]
+    texture_count: u32,
+    #[uniform[4]]
+    terrain_slice_y: u32,
+    #[storage(5, read_only)]
+    faces: Vec<u32>,
+    #[texture(6, dimension = "3d")]
+    #[sampler(7)]
+    occlusion: Handle<Image>,
+    #[uniform(8)]
+    occlusion_strength: f32,
+    #[uniform(9)]
+    light_debug: u32,
+    #[texture(10, dimension = "3d")]
+    designations: Handle<Image>,
+    #[texture(11, dimension = "2d_array")]
+    #[sampler(12)]
+    normal_map: Option<Handle<Image>>,
+    #[uniform(13)]
+    normal_strength: f32,
+    #[uniform(14)]
+    outline: Vec4,
+    #[uniform(15)]
+    dimming: Vec4,
+}
+
+impl PulledTerrainMaterial {
+    pub(super) fn new(base: &TerrainMaterial, faces: Vec<u32>) -> Self {
+        Self {
+            texture: base.texture.clone(),
+            color: base.color,
+            texture_count: base.texture_count,
+            terrain_slice_y: base.terrain_slice_y,
+            faces,
+            occlusion: base.occlusion.clone(),
+            occlusion_strength: base.occlusion_strength,
+            light_debug: base.light_debug,
+            designations: base.designations.clone(),
+            normal_map: base.normal_map.clone(),
+            normal_strength: base.normal_strength,
+            outline: base.outline,
+            dimming: base.dimming,
+        }
+    }
+}
+
+/// On a chunk drawn by vertex pulling. Its mesh only stands in for the draw
+/// call, the render world sets how many vertices it draws.
+#[derive(Component, Clone, ExtractComponent)]
+pub(super) struct PulledChunk {
+    pub mesh: AssetId<Mesh>,
+    pub faces: u32,
+}
+
+impl Plugin for VertexPullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<PulledTerrainMaterial>::default())
+            .add_plugins(ExtractComponentPlugin::<PulledChunk>::default());
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_systems(
+                Render,
+                count_pulled_vertices.in_set(RenderSet::PrepareResources),
+            );
+        }
+    }
+}
+
+impl Material for PulledTerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers.clear();
+        descriptor.vertex.shader_defs.push("VERTEX_PULLING".into());
+        Ok(())
+    }
+}
+
+/// The mesh a pulled chunk is drawn with. Its single vertex is never read,
+/// it's only there so the mesh gets a buffer to bind.
+pub(super) fn stand_in_mesh() -> Mesh {
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(ATTRIBUTE_PACKED_BLOCK, vec![0u32])
+}
+
+/// Stretches the stand-in meshes of pulled chunks over all their faces.
+fn count_pulled_vertices(
+    chunks: Query<&PulledChunk>,
+    mut render_meshes: ResMut<RenderAssets<Mesh>>,
+) {
+    for chunk in chunks.iter() {
+        if let Some(mesh) = render_meshes.get_mut(chunk.mesh) {
+            mesh.vertex_count = chunk.faces * 6;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::math::IVec3;
+
+use super::{Block, Terrain};
+
+/// Cells a flood fill visits at most unless asked for a different limit,
+/// about a 40 block cube.
+pub const FLOOD_FILL_LIMIT: usize = 1 << 16;
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Blocks joined to each other face to face, as found by a flood fill.
+#[derive(Debug, Default, Clone)]
+pub struct RegionSet {
+    pub cells: HashSet<IVec3>,
+    /// The fill ran into its limit with cells still left to visit.
+    pub truncated: bool,
+    /// Some cell of the region lies against the side of the map.
+    pub reaches_edge: bool,
+}
+
+impl RegionSet {
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        self.cells.contains(&pos)
+    }
+
+    /// Whether the region was filled completely without leaking out to the
+    /// side of the map, like the air of a sealed room.
+    pub fn is_enclosed(&self) -> bool {
+        !self.truncated && !self.reaches_edge
+    }
+}
+
+impl Terrain {
+    /// Every block reachable from `start` through blocks `predicate` accepts,
+    /// visiting at most `FLOOD_FILL_LIMIT` of them. Empty when `start` itself
+    /// isn't accepted.
+    pub fn flood_fill(
+        &self,
+        start: IVec3,
+        predicate: impl FnMut(IVec3, Block) -> bool,
+    ) -> RegionSet {
+        self.flood_fill_limited(start, FLOOD_FILL_LIMIT, predicate)
+    }
+
+    /// `flood_fill` stopping after `limit` blocks.
+    pub fn flood_fill_limited(
+        &self,
+        start: IVec3,
+        limit: usize,
+        mut predicate: impl FnMut(IVec3, Block) -> bool,
+    ) -> RegionSet {
+        let mut region = RegionSet::default();
+        let block = self.get_at(start);
+        if block == Block::Oob || !predicate(start, block) {
+            return region;
+        }
+
+        region.cells.insert(start);
+        let mut open = VecDeque::from([start]);
+
+        while let Some(pos) = open.pop_front() {
+            for offset in NEIGHBORS {
+                let next = pos + offset;
+                if region.cells.contains(&next) {
+                    continue;
+                }
+
+                let block = self.get_at(next);
+                if block == Block::Oob {
+                    region.reaches_edge = true;
+                    continue;
+                }
+                if !predicate(next, block) {
+                    continue;
+                }
+
+                if region.cells.len() >= limit {
+                    region.truncated = true;
+                    return region;
+                }
+                region.cells.insert(next);
+                open.push_back(next);
+            }
+        }
+
+        region
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::fixtures::room;
+
+    fn is_air(_: IVec3, block: Block) -> bool {
+        block == Block::Empty
+    }
+
+    #[test]
+    fn sealed_room_is_enclosed() {
+        let region = room().flood_fill(IVec3::splat(6), is_air);
+        assert_eq!(region.len(), 27);
+        assert!(region.is_enclosed());
+    }
+
+    #[test]
+    fn open_room_leaks_to_the_edge() {
+        let mut terrain = room();
+        terrain.set_at(IVec3::new(6, 8, 6), Block::Empty);
+
+        let region = terrain.flood_fill(IVec3::splat(6), is_air);
+        assert!(region.reaches_edge);
+        assert!(region.contains(IVec3::ZERO));
+    }
+
+    #[test]
+    fn fill_stops_at_its_limit() {
+        let terrain = Terrain::new(IVec3::splat(16));
+        let region = terrain.flood_fill_limited(IVec3::ZERO, 100, is_air);
+        assert_eq!(region.len(), 100);
+        assert!(region.truncated);
+        assert!(!region.is_enclosed());
+    }
+}
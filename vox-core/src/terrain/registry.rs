@@ -0,0 +1,198 @@
+//! The block registry: ids for the built-in blocks, definitions of the
+//! blocks mods add, and the overrides `blocks.ron` makes to the built-in
+//! ones.
+//!
+//! It is one registry for the whole process rather than a `BlockRegistry`
+//! resource. `Block` stays the typed handle the rest of the game works
+//! with, and `Block::def` has to resolve a block anywhere one turns up,
+//! including the meshing tasks and serde, where no `World` is at hand.
+//! Chunks store `BlockId`s, so moving the registry into a resource later
+//! only changes how ids resolve, not what's saved.
+//!
+//! Everything sharing the process shares the blocks: two apps in one
+//! process see each other's modded blocks and overrides, and so do tests.
+//! Modded blocks are only ever added, so a test registering one gives it a
+//! name no other test uses, and tests leave the overrides alone.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+
+use serde::Deserialize;
+
+use super::{Block, BlockDef, BlockMaterial};
+use crate::light::MAX_LIGHT;
+
+/// Ids from here up are modded blocks, numbered in the order they were
+/// registered.
+pub const MODDED_ID_BASE: u16 = 0x8000;
+
+/// Number a block is stored under in the terrain, as `Block::id` gives it.
+/// Chunks hold these rather than `Block`s, so blocks mods add at runtime
+/// take no different path than the built-in ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    pub const OOB: BlockId = BlockId(0x0000);
+    pub const EMPTY: BlockId = BlockId(0x0100);
+
+    /// The block this stands for, None for a number no block gives, like
+    /// that of a mod no longer installed.
+    pub fn block(self) -> Option<Block> {
+        Block::from_id(self.0)
+    }
+}
+
+impl From<Block> for BlockId {
+    fn from(block: Block) -> Self {
+        BlockId(block.id())
+    }
+}
+
+/// Definitions of the blocks mod packs add, indexed by `Block::Modded`.
+/// Filled while the app starts, before any terrain exists.
+static MODDED_BLOCKS: RwLock<Vec<BlockDef>> = RwLock::new(Vec::new());
+
+/// Changes to a built-in block's definition, any field left out keeps the
+/// block's own.
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BlockOverride {
+    pub texture_id: Option<u32>,
+    pub end_texture_id: Option<u32>,
+    pub hardness: Option<f32>,
+    pub material: Option<BlockMaterial>,
+    pub flammable: Option<bool>,
+    pub light: Option<u8>,
+}
+
+/// Overrides of the built-in blocks, indexed by the high byte of their id.
+static OVERRIDES: RwLock<Vec<Option<BlockOverride>>> = RwLock::new(Vec::new());
+/// Lets `Block::def` skip the lock while nothing is overridden.
+static HAS_OVERRIDES: AtomicBool = AtomicBool::new(false);
+
+/// Replaces every override with `overrides`, keyed by block name as in
+/// `"Stone"` or `"Log"`. Fails without changing anything on a name no
+/// built-in block has.
+pub fn set_overrides(overrides: HashMap<String, BlockOverride>) -> Result<(), String> {
+    let kinds = (MODDED_ID_BASE >> 8) as usize;
+    let mut table = vec![None; kinds];
+
+    for (name, o) in overrides {
+        let kind = find_builtin(&name).ok_or_else(|| format!("no block called {}", name))?;
+        table[(kind.id() >> 8) as usize] = Some(o);
+    }
+
+    let any = table.iter().any(Option::is_some);
+    *OVERRIDES.write().unwrap() = table;
+    HAS_OVERRIDES.store(any, Ordering::Release);
+    Ok(())
+}
+
+/// One block of every built-in kind, in id order. Each stands in for all
+/// the facings or orientations of its kind, which share a definition.
+pub fn builtin_blocks() -> Vec<Block> {
+    (0..MODDED_ID_BASE >> 8)
+        .filter_map(|kind| (0..=0xff).find_map(|state| Block::from_id(kind << 8 | state)))
+        .collect()
+}
+
+/// A block of the built-in kind called `name`.
+pub fn find_builtin(name: &str) -> Option<Block> {
+    builtin_blocks()
+        .into_iter()
+        .find(|block| block.def().name == name)
+}
+
+/// `def` with the overrides for `block` applied.
+pub(super) fn overridden(block: Block, mut def: BlockDef) -> BlockDef {
+    if !HAS_OVERRIDES.load(Ordering::Acquire) {
+        return def;
+    }
+
+    let kind = (block.id() >> 8) as usize;
+    if let Some(Some(o)) = OVERRIDES.read().unwrap().get(kind) {
+        def.texture_id = o.texture_id.unwrap_or(def.texture_id);
+        def.end_texture_id = o.end_texture_id.or(def.end_texture_id);
+        def.hardness = o.hardness.unwrap_or(def.hardness);
+        def.material = o.material.unwrap_or(def.material);
+        def.flammable = o.flammable.unwrap_or(def.flammable);
+        def.light = o.light.map_or(def.light, |light| light.min(MAX_LIGHT));
+    }
+    def
+}
+
+/// Adds a block, failing if one of that name is already registered.
+pub fn register_block(def: BlockDef) -> Result<Block, String> {
+    let mut blocks = MODDED_BLOCKS.write().unwrap();
+    check_names(&blocks, &[def.name])?;
+
+    blocks.push(def);
+    Ok(Block::Modded((blocks.len() - 1) as u16))
+}
+
+/// Fails if blocks called `names` couldn't all be registered, for checking
+/// a set of blocks before registering any of them.
+pub fn check_block_names(names: &[&str]) -> Result<(), String> {
+    check_names(&MODDED_BLOCKS.read().unwrap(), names)
+}
+
+fn check_names(blocks: &[BlockDef], names: &[&str]) -> Result<(), String> {
+    for (i, name) in names.iter().enumerate() {
+        if blocks.iter().any(|b| b.name == *name) || names[..i].contains(name) {
+            return Err(format!("block {} is already registered", name));
+        }
+        if name.len() > u8::MAX as usize {
+            return Err(format!("block name {} is too long", name));
+        }
+    }
+    if blocks.len() + names.len() > (u16::MAX - MODDED_ID_BASE) as usize {
+        return Err("too many modded blocks".to_string());
+    }
+    Ok(())
+}
+
+pub fn modded_def(index: u16) -> Option<BlockDef> {
+    MODDED_BLOCKS.read().unwrap().get(index as usize).copied()
+}
+
+/// The modded block called `name`.
+pub fn find_modded(name: &str) -> Option<Block> {
+    let blocks = MODDED_BLOCKS.read().unwrap();
+    let index = blocks.iter().position(|b| b.name == name)?;
+    Some(Block::Modded(index as u16))
+}
+
+/// Every modded block, in id order.
+pub fn modded_blocks() -> Vec<Block> {
+    let count = MODDED_BLOCKS.read().unwrap().len();
+    (0..count as u16).map(Block::Modded).collect()
+}
+
+/// Writes modded blocks by name, as in `Modded("Marble")`, since their
+/// numbers change with the mods installed.
+pub(super) mod by_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::{find_modded, modded_def};
+    use crate::terrain::Block;
+
+    pub fn serialize<S: Serializer>(index: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        let def = modded_def(*index)
+            .ok_or_else(|| serde::ser::Error::custom(format!("no modded block {}", index)))?;
+        serializer.serialize_str(def.name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match find_modded(&name) {
+            Some(Block::Modded(index)) => Ok(index),
+            _ => Err(D::Error::custom(format!("no modded block {}", name))),
+        }
+    }
+}
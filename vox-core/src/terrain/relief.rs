@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    render::texture::{ImageLoaderSettings, ImageSampler},
+};
+
+use super::{TerrainMaterial, TerrainMesh, TEXTURE_COUNT};
+
+/// Normal map of the atlas tiles, a column of tiles one per texture id in
+/// the order of the atlas. Optional, the faces stay flat without it.
+pub(super) const NORMAL_MAP: &str = "terrain_normal.png";
+
+/// How far the normal map bends the light across a face.
+pub(super) const RELIEF_STRENGTH: f32 = 1.;
+
+/// Packs a unit normal into 10 bits an axis, for the smooth mesher's sloped
+/// triangles. The cube meshers leave it out, their face says it already.
+pub(super) fn pack_normal(normal: Vec3) -> u32 {
+    let steps = ((normal.clamp(Vec3::NEG_ONE, Vec3::ONE) + 1.) * 511.5)
+        .round()
+        .as_uvec3();
+    steps.x | (steps.y << 10) | (steps.z << 20)
+}
+
+/// Starts loading the normal map if the assets have one. Its texels are
+/// directions rather than colors, so it's read as they are.
+pub(super) fn load_normal_map(asset_server: &AssetServer) -> Option<Handle<Image>> {
+    let settings = |s: &mut ImageLoaderSettings| {
+        s.is_srgb = false;
+        s.sampler = ImageSampler::nearest();
+    };
+    Path::new("assets")
+        .join(NORMAL_MAP)
+        .is_file()
+        .then(|| asset_server.load_with_settings(NORMAL_MAP, settings))
+}
+
+/// Hands the normal map to the terrain once it's loaded and stacked into
+/// layers. `update_shading` turns the relief on once it's there.
+pub(super) fn update_normal_map(
+    mut terrain_mesh: ResMut<TerrainMesh>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    let Some(handle) = terrain_mesh.normal_map.clone() else {
+        return;
+    };
+    let Some(image) = images.get(&handle) else {
+        return;
+    };
+
+    let layers = TEXTURE_COUNT * TEXTURE_COUNT;
+    if image.texture_descriptor.size.depth_or_array_layers == 1 {
+        if image.height() % layers != 0 || image.height() / layers != image.width() {
+            println!(
+                "{} should be a column of {} square tiles, it's {}x{}",
+                NORMAL_MAP,
+                layers,
+                image.width(),
+                image.height()
+            );
+            terrain_mesh.normal_map = None;
+            return;
+        }
+        images
+            .get_mut(&handle)
+            .unwrap()
+            .reinterpret_stacked_2d_as_array(layers);
+    }
+
+    let Some(material) = materials.get(&terrain_mesh.material) else {
+        return;
+    };
+    if material.normal_map.is_none() {
+        materials
+            .get_mut(&terrain_mesh.material)
+            .unwrap()
+            .normal_map = Some(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normals_pack_to_within_a_step() {
+        for normal in [
+            Vec3::X,
+            Vec3::NEG_Y,
+            Vec3::new(0.6, -0.8, 0.),
+            Vec3::ONE.normalize(),
+        ] {
+            let packed = pack_normal(normal);
+            let steps = UVec3::new(packed & 1023, (packed >> 10) & 1023, (packed >> 20) & 1023);
+            let unpacked = steps.as_vec3() / 511.5 - 1.;
+            assert!(unpacked.distance(normal) < 0.005, "{} {}", normal, unpacked);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+
+use super::{
+    occlusion::OCCLUSION_STRENGTH, relief::RELIEF_STRENGTH, GraphicsSettings, Terrain,
+    TerrainMaterial, TerrainMesh,
+};
+
+/// Unlit faces this many blocks under the slice start fading out, and are
+/// black by `DIM_END`, so caves no light reaches read as voids.
+const DIM_START: f32 = 4.;
+const DIM_END: f32 = 24.;
+/// Light levels up to this one count as partly unlit.
+const DIM_LIGHT: f32 = 4.;
+
+/// How dark the edges of the slice's cut through the blocks are drawn.
+const OUTLINE_STRENGTH: f32 = 0.7;
+/// Width of the slice outlines, in pixels.
+const OUTLINE_PIXELS: f32 = 1.5;
+/// How much of the outlines' darkness the seams between two cut blocks get.
+const SEAM_STRENGTH: f32 = 0.25;
+
+/// The terrain shader's `dimming`: the depths the fading starts and ends
+/// at, the light it stops at, and its strength, 0 while turned off.
+pub(super) fn dimming_params(enabled: bool) -> Vec4 {
+    let strength = if enabled { 1. } else { 0. };
+    Vec4::new(DIM_START, DIM_END, DIM_LIGHT, strength)
+}
+
+/// The terrain shader's `outline`: how dark the outlines are, 0 while
+/// turned off, how wide, and how dark the seams are next to them.
+pub(super) fn outline_params(enabled: bool) -> Vec4 {
+    let strength = if enabled { OUTLINE_STRENGTH } else { 0. };
+    Vec4::new(strength, OUTLINE_PIXELS, SEAM_STRENGTH, 0.)
+}
+
+/// Writes every uniform the graphics settings pick into the terrain
+/// material, remeshing once if any of them changed since pulled chunks copy
+/// the material when they're meshed. The relief stays off until the normal
+/// map is in.
+pub(super) fn update_shading(
+    settings: Res<GraphicsSettings>,
+    mut terrain: ResMut<Terrain>,
+    terrain_mesh: Res<TerrainMesh>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    let Some(material) = materials.get(&terrain_mesh.material) else {
+        return;
+    };
+
+    let occlusion_strength = if settings.ambient_occlusion {
+        OCCLUSION_STRENGTH
+    } else {
+        0.
+    };
+    let normal_strength = if settings.normal_maps && material.normal_map.is_some() {
+        RELIEF_STRENGTH
+    } else {
+        0.
+    };
+    let outline = outline_params(settings.slice_outlines);
+    let dimming = dimming_params(settings.interior_dimming);
+
+    if material.occlusion_strength == occlusion_strength
+        && material.normal_strength == normal_strength
+        && material.outline == outline
+        && material.dimming == dimming
+    {
+        return;
+    }
+
+    let material = materials.get_mut(&terrain_mesh.material).unwrap();
+    material.occlusion_strength = occlusion_strength;
+    material.normal_strength = normal_strength;
+    material.outline = outline;
+    material.dimming = dimming;
+    terrain.mark_all_dirty();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::MAX_LIGHT;
+
+    /// What's left of the brightness of a face `depth` blocks under the slice
+    /// with `light`, a copy of `interior_dimming` in the terrain shader.
+    fn interior_dimming(params: Vec4, depth: f32, light: f32) -> f32 {
+        let depth = smoothstep(params.x, params.y, depth);
+        let unlit = 1. - smoothstep(0., params.z, light);
+        1. - params.w * depth * unlit
+    }
+
+    /// How much of an outline covers the point `inside` a cut block, with
+    /// `pixel` how far the point moves a pixel over and `open` whether the
+    /// block past its low x, low z, high x and high z edge is open. A copy of
+    /// `slice_outline` in the terrain shader.
+    fn slice_outline(params: Vec4, inside: Vec2, pixel: Vec2, open: [bool; 4]) -> f32 {
+        let pixel = pixel.max(Vec2::splat(1e-5));
+        let low = inside / pixel;
+        let high = (Vec2::ONE - inside) / pixel;
+        let near = [low.x, low.y, high.x, high.y].map(|d| 1. - smoothstep(0., params.y, d));
+
+        let mut edge = params.z * near.iter().copied().fold(0., f32::max);
+        for (near, open) in near.into_iter().zip(open) {
+            if open {
+                edge = edge.max(near);
+            }
+        }
+        edge
+    }
+
+    /// WGSL's `smoothstep`.
+    fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+        let t = ((x - low) / (high - low)).clamp(0., 1.);
+        t * t * (3. - 2. * t)
+    }
+
+    #[test]
+    fn only_unlit_faces_deep_under_the_slice_dim() {
+        let params = dimming_params(true);
+        assert!(0. < params.x && params.x < params.y);
+        assert!(0. < params.z && params.z < MAX_LIGHT as f32);
+
+        // near the cut, or with light enough, faces keep their brightness
+        assert_eq!(interior_dimming(params, DIM_START, 0.), 1.);
+        assert_eq!(interior_dimming(params, 100., DIM_LIGHT), 1.);
+        assert_eq!(interior_dimming(params, 100., MAX_LIGHT as f32), 1.);
+        // the dark well under it goes black
+        assert_eq!(interior_dimming(params, DIM_END, 0.), 0.);
+
+        // and in between, deeper and darker both dim more
+        let middle = (DIM_START + DIM_END) / 2.;
+        let half = interior_dimming(params, middle, 0.);
+        assert!(0. < half && half < 1.);
+        assert!(interior_dimming(params, middle + 1., 0.) < half);
+        assert!(interior_dimming(params, middle, 1.) > half);
+
+        let off = dimming_params(false);
+        assert_eq!(interior_dimming(off, DIM_END, 0.), 1.);
+    }
+
+    #[test]
+    fn outlines_darken_edges_against_open_blocks() {
+        let params = outline_params(true);
+        let pixel = Vec2::splat(0.01);
+        let center = Vec2::splat(0.5);
+        let edge = Vec2::new(0., 0.5);
+
+        assert_eq!(slice_outline(params, center, pixel, [true; 4]), 0.);
+        assert_eq!(
+            slice_outline(params, edge, pixel, [true, false, false, false]),
+            1.
+        );
+        // a seam against another cut block is fainter
+        assert_eq!(
+            slice_outline(params, edge, pixel, [false; 4]),
+            SEAM_STRENGTH
+        );
+        // the open side only darkens its own edge
+        assert_eq!(
+            slice_outline(params, edge, pixel, [false, false, true, false]),
+            SEAM_STRENGTH
+        );
+        // and the lines stay as wide on screen however far out the camera is
+        let far = Vec2::new(OUTLINE_PIXELS * 0.05, 0.5);
+        let near = slice_outline(params, far / 10., pixel, [true; 4]);
+        assert!(near > 0. && near < 1.);
+        assert!((slice_outline(params, far, Vec2::splat(0.1), [true; 4]) - near).abs() < 1e-4);
+
+        assert_eq!(outline_params(false).x, 0.);
+        assert_eq!(outline_params(true).x, OUTLINE_STRENGTH);
+    }
+}
@@ -0,0 +1,285 @@
+use bevy::math::{IVec3, Vec3};
+
+use super::{mesher::pack_block, Block, BlockShape, ChunkView, FaceDir, Facing, TerrainMeshData};
+
+/// Gap between a ladder and the wall it hangs on.
+const LADDER_INSET: f32 = 1. / 16.;
+/// Height of a liquid's surface when it isn't topped by more of itself.
+pub const LIQUID_LEVEL: f32 = 0.875;
+
+/// Emits geometry for blocks that aren't full cubes.
+pub(super) fn mesh_shape(data: &mut TerrainMeshData, view: &ChunkView, pos: IVec3, block: Block) {
+    match block.def().shape {
+        BlockShape::None | BlockShape::Cube | BlockShape::Custom => {}
+        BlockShape::Slab => {
+            push_box(
+                data,
+                view,
+                pos,
+                block,
+                Facing::South,
+                (Vec3::ZERO, Vec3::new(1., 0.5, 1.)),
+                &[],
+            );
+        }
+        BlockShape::Stairs(facing) => {
+            push_box(
+                data,
+                view,
+                pos,
+                block,
+                facing,
+                (Vec3::ZERO, Vec3::new(1., 0.5, 0.5)),
+                &[Vec3::Z],
+            );
+            push_box(
+                data,
+                view,
+                pos,
+                block,
+                facing,
+                (Vec3::new(0., 0., 0.5), Vec3::ONE),
+                &[],
+            );
+        }
+        BlockShape::Ramp(facing) => mesh_ramp(data, view, pos, block, facing),
+        BlockShape::Ladder(facing) => {
+            let origin = pos.as_vec3();
+            let corner = |x: f32, y: f32, z: f32| origin + facing.rotate(Vec3::new(x, y, z));
+            let z = 1. - LADDER_INSET;
+            push_quad(
+                data,
+                [
+                    corner(0., 0., z),
+                    corner(1., 0., z),
+                    corner(1., 1., z),
+                    corner(0., 1., z),
+                ],
+                -facing.offset().as_vec3(),
+                pack_block(block, FaceDir::from_normal(-facing.offset())),
+            );
+        }
+        BlockShape::Cross => mesh_cross(data, pos, block),
+        // only its surface is drawn, with a material of its own
+        BlockShape::Liquid if block == Block::Water => {}
+        BlockShape::Liquid => {
+            let is_topped = view.get_at(pos + IVec3::Y) == block;
+            let skip: Vec<Vec3> = [
+                Vec3::X,
+                Vec3::NEG_X,
+                Vec3::Y,
+                Vec3::NEG_Y,
+                Vec3::Z,
+                Vec3::NEG_Z,
+            ]
+            .into_iter()
+            .filter(|dir| view.get_at(pos + dir.as_ivec3()) == block)
+            .collect();
+            let level = if is_topped { 1. } else { LIQUID_LEVEL };
+
+            push_box(
+                data,
+                view,
+                pos,
+                block,
+                Facing::South,
+                (Vec3::ZERO, Vec3::new(1., level, 1.)),
+                &skip,
+            );
+        }
+    }
+}
+
+/// Whether the neighbor in direction `offset` hides a face pointing at it.
+fn is_face_hidden(view: &ChunkView, pos: IVec3, offset: IVec3) -> bool {
+    view.get_at(pos + offset)
+        .covers(FaceDir::from_normal(-offset))
+}
+
+/// Rotates a block-local direction the same way `Facing::rotate` moves points.
+fn rotate_dir(facing: Facing, dir: Vec3) -> Vec3 {
+    facing.rotate(dir + 0.5) - 0.5
+}
+
+/// Pushes a triangle, ordering it counter-clockwise when seen from `normal`.
+fn push_tri(data: &mut TerrainMeshData, corners: [Vec3; 3], normal: Vec3, packed: u32) {
+    let idx = data.positions.len() as u32;
+    let facing = (corners[1] - corners[0])
+        .cross(corners[2] - corners[0])
+        .dot(normal);
+
+    for corner in corners {
+        data.positions.push(corner.to_array());
+        data.normals.push(normal.to_array());
+        data.packed.push(packed);
+    }
+
+    if facing >= 0. {
+        data.indicies.extend([idx, idx + 1, idx + 2]);
+    } else {
+        data.indicies.extend([idx, idx + 2, idx + 1]);
+    }
+}
+
+/// Pushes a planar quad given its corners in order around the edge.
+fn push_quad(data: &mut TerrainMeshData, corners: [Vec3; 4], normal: Vec3, packed: u32) {
+    push_tri(data, [corners[0], corners[1], corners[2]], normal, packed);
+    push_tri(data, [corners[2], corners[3], corners[0]], normal, packed);
+}
+
+/// Pushes an axis-aligned box given in block-local space authored facing south.
+/// Faces lying on the cell boundary are culled against covering neighbors, and
+/// faces whose local normal is listed in `skip` are never emitted.
+fn push_box(
+    data: &mut TerrainMeshData,
+    view: &ChunkView,
+    pos: IVec3,
+    block: Block,
+    facing: Facing,
+    (min, max): (Vec3, Vec3),
+    skip: &[Vec3],
+) {
+    let origin = pos.as_vec3();
+    let faces = [
+        (Vec3::X, max.x == 1.),
+        (Vec3::NEG_X, min.x == 0.),
+        (Vec3::Y, max.y == 1.),
+        (Vec3::NEG_Y, min.y == 0.),
+        (Vec3::Z, max.z == 1.),
+        (Vec3::NEG_Z, min.z == 0.),
+    ];
+
+    for (local_normal, on_boundary) in faces {
+        if skip.contains(&local_normal) {
+            continue;
+        }
+
+        let normal = rotate_dir(facing, local_normal);
+        let offset = normal.round().as_ivec3();
+
+        if on_boundary && is_face_hidden(view, pos, offset) {
+            continue;
+        }
+
+        // pick the plane of the face, then walk its rectangle
+        let plane = if local_normal.max_element() > 0. {
+            max
+        } else {
+            min
+        };
+        let corners = if local_normal.x != 0. {
+            [
+                Vec3::new(plane.x, min.y, min.z),
+                Vec3::new(plane.x, max.y, min.z),
+                Vec3::new(plane.x, max.y, max.z),
+                Vec3::new(plane.x, min.y, max.z),
+            ]
+        } else if local_normal.y != 0. {
+            [
+                Vec3::new(min.x, plane.y, min.z),
+                Vec3::new(max.x, plane.y, min.z),
+                Vec3::new(max.x, plane.y, max.z),
+                Vec3::new(min.x, plane.y, max.z),
+            ]
+        } else {
+            [
+                Vec3::new(min.x, min.y, plane.z),
+                Vec3::new(max.x, min.y, plane.z),
+                Vec3::new(max.x, max.y, plane.z),
+                Vec3::new(min.x, max.y, plane.z),
+            ]
+        };
+
+        push_quad(
+            data,
+            corners.map(|c| origin + facing.rotate(c)),
+            normal,
+            pack_block(block, FaceDir::from_normal(offset)),
+        );
+    }
+}
+
+fn mesh_ramp(
+    data: &mut TerrainMeshData,
+    view: &ChunkView,
+    pos: IVec3,
+    block: Block,
+    facing: Facing,
+) {
+    let origin = pos.as_vec3();
+    let corner = |x: f32, y: f32, z: f32| origin + facing.rotate(Vec3::new(x, y, z));
+    let high_side = facing.offset().as_vec3();
+
+    // slope, rising from the low edge to the high edge on the facing side
+    push_quad(
+        data,
+        [
+            corner(0., 0., 0.),
+            corner(1., 0., 0.),
+            corner(1., 1., 1.),
+            corner(0., 1., 1.),
+        ],
+        (Vec3::Y - high_side).normalize(),
+        pack_block(block, FaceDir::PosY),
+    );
+
+    if !is_face_hidden(view, pos, facing.offset()) {
+        push_quad(
+            data,
+            [
+                corner(0., 0., 1.),
+                corner(1., 0., 1.),
+                corner(1., 1., 1.),
+                corner(0., 1., 1.),
+            ],
+            high_side,
+            pack_block(block, FaceDir::from_normal(facing.offset())),
+        );
+    }
+
+    for (x, side) in [(0., Vec3::NEG_X), (1., Vec3::X)] {
+        let normal = rotate_dir(facing, side);
+        let offset = normal.round().as_ivec3();
+
+        if !is_face_hidden(view, pos, offset) {
+            push_tri(
+                data,
+                [corner(x, 0., 0.), corner(x, 0., 1.), corner(x, 1., 1.)],
+                normal,
+                pack_block(block, FaceDir::from_normal(offset)),
+            );
+        }
+    }
+
+    if !is_face_hidden(view, pos, IVec3::NEG_Y) {
+        push_quad(
+            data,
+            [
+                corner(0., 0., 0.),
+                corner(1., 0., 0.),
+                corner(1., 0., 1.),
+                corner(0., 0., 1.),
+            ],
+            Vec3::NEG_Y,
+            pack_block(block, FaceDir::NegY),
+        );
+    }
+}
+
+/// Two diagonal panels, each pushed once per side so they show from anywhere.
+fn mesh_cross(data: &mut TerrainMeshData, pos: IVec3, block: Block) {
+    let origin = pos.as_vec3();
+
+    for (start, end) in [(Vec3::ZERO, Vec3::new(1., 0., 1.)), (Vec3::X, Vec3::Z)] {
+        let corners = [
+            origin + start,
+            origin + end,
+            origin + end + Vec3::Y,
+            origin + start + Vec3::Y,
+        ];
+        let normal = (end - start).cross(Vec3::Y).normalize();
+
+        push_quad(data, corners, normal, pack_block(block, FaceDir::PosX));
+        push_quad(data, corners, -normal, pack_block(block, FaceDir::NegX));
+    }
+}
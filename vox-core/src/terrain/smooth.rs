@@ -0,0 +1,366 @@
+use std::sync::OnceLock;
+
+use bevy::math::{IVec3, Vec3};
+
+use crate::light::MAX_LIGHT;
+
+use super::{
+    mesher::{mark_damage, mark_darkness, pack_block, DARKNESS_SHIFT},
+    shapes, Block, ChunkView, FaceDir, Terrain, TerrainMeshData, CHUNK_SIZE,
+};
+
+/// Density the surface is drawn at.
+const ISO: f32 = 0.5;
+/// How far a block's density is kept from `ISO`. Blurring moves the surface
+/// around but never adds or removes a block from the terrain.
+const MARGIN: f32 = 0.1;
+
+/// Cell edges as pairs of corners, corner `i` sitting at `(i & 1, i >> 1 & 1,
+/// i >> 2 & 1)`. The x edges come first, then y, then z.
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Meshes a chunk with marching cubes. Cells run between block centers, the
+/// density at each being how much of its neighborhood is filled, so slopes
+/// and steps come out as rolling hills. Partial shapes are meshed the same
+/// way as with the cube meshers.
+pub fn mesh_chunk_smooth_into(terrain: &Terrain, chunk: IVec3, data: &mut TerrainMeshData) {
+    mesh_smooth(&ChunkView::new(terrain, chunk), data);
+}
+
+pub(super) fn mesh_smooth(view: &ChunkView, data: &mut TerrainMeshData) {
+    data.clear();
+
+    let min = view.chunk * CHUNK_SIZE;
+    let max = min + IVec3::splat(CHUNK_SIZE);
+    let slice = view.slice as i32;
+    let bounds = view
+        .size()
+        .as_vec3()
+        .min(Vec3::new(f32::MAX, slice as f32, f32::MAX));
+
+    // the first chunk along an axis also takes the cells reaching in from
+    // outside the map, so its sides get closed
+    let start = IVec3::select(min.cmpeq(IVec3::ZERO), min - IVec3::ONE, min);
+    let filled = |pos: IVec3| {
+        let block = view.get_at(pos);
+        // the outside of a closed map is solid, so no surface runs along it
+        pos.y < slice && (block.is_filled() || view.cull_oob && block == Block::Oob)
+    };
+
+    // densities of every corner the chunk's cells touch
+    let dims = max - start + IVec3::ONE;
+    let index = |pos: IVec3| {
+        let local = pos - start;
+        (local.x + (local.y + local.z * dims.y) * dims.x) as usize
+    };
+    let mut density = vec![0.; (dims.x * dims.y * dims.z) as usize];
+    for x in start.x..=max.x {
+        for y in start.y..=max.y {
+            for z in start.z..=max.z {
+                let pos = IVec3::new(x, y, z);
+                density[index(pos)] = block_density(pos, filled);
+            }
+        }
+    }
+
+    for x in start.x..max.x {
+        for y in start.y..max.y {
+            for z in start.z..max.z {
+                let cell = IVec3::new(x, y, z);
+                let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| cell + corner(i));
+                let values = corners.map(|pos| density[index(pos)]);
+
+                let case = (0..8)
+                    .filter(|i| values[*i] > ISO)
+                    .fold(0, |case, i| case | 1 << i);
+
+                for tri in triangles(case) {
+                    let points = tri.map(|edge| {
+                        let (a, b) = EDGES[edge as usize];
+                        let t = (ISO - values[a]) / (values[b] - values[a]);
+                        let from = corners[a].as_vec3() + 0.5;
+                        let to = corners[b].as_vec3() + 0.5;
+                        from.lerp(to, t).clamp(Vec3::ZERO, bounds)
+                    });
+                    // each point lit by the open block at its edge's end
+                    let darkness = tri.map(|edge| {
+                        let (a, b) = EDGES[edge as usize];
+                        let open = if values[a] > ISO {
+                            corners[b]
+                        } else {
+                            corners[a]
+                        };
+                        let light = if open.y >= slice {
+                            MAX_LIGHT
+                        } else {
+                            view.light_at(open)
+                        };
+                        (MAX_LIGHT - light) as u32
+                    });
+                    push_tri(data, view, points, darkness, &corners, &values);
+                }
+            }
+        }
+    }
+
+    for x in min.x..max.x {
+        for z in min.z..max.z {
+            for y in min.y..max.y.min(slice) {
+                let pos = IVec3::new(x, y, z);
+                let block = view.get_at(pos);
+                if block.is_solid() && !block.is_filled() {
+                    let start = data.packed.len();
+                    shapes::mesh_shape(data, view, pos, block);
+                    mark_damage(data, start, view.damage_stage(pos));
+                    mark_darkness(data, start, view.light_at(pos));
+                }
+            }
+        }
+    }
+}
+
+/// Share of the 3x3x3 blocks around `pos` that are filled, kept on the side
+/// of `ISO` the block itself is on.
+fn block_density(pos: IVec3, filled: impl Fn(IVec3) -> bool) -> f32 {
+    let mut count = 0;
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                if filled(pos + IVec3::new(x, y, z)) {
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    let blurred = count as f32 / 27.;
+    if filled(pos) {
+        blurred.max(ISO + MARGIN)
+    } else {
+        blurred.min(ISO - MARGIN)
+    }
+}
+
+/// Pushes a flat shaded triangle, textured as the filled corner block nearest
+/// to it and shaded as the face its normal leans towards most.
+fn push_tri(
+    data: &mut TerrainMeshData,
+    view: &ChunkView,
+    points: [Vec3; 3],
+    darkness: [u32; 3],
+    corners: &[IVec3; 8],
+    values: &[f32; 8],
+) {
+    let normal = (points[1] - points[0]).cross(points[2] - points[0]);
+    let Some(normal) = normal.try_normalize() else {
+        // collapsed where the surface was clamped to the map
+        return;
+    };
+
+    let center = (points[0] + points[1] + points[2]) / 3.;
+    let Some(pos) = (0..8)
+        .filter(|i| values[*i] > ISO)
+        .map(|i| corners[i])
+        .min_by(|a, b| {
+            let a = (a.as_vec3() + 0.5).distance_squared(center);
+            let b = (b.as_vec3() + 0.5).distance_squared(center);
+            a.total_cmp(&b)
+        })
+    else {
+        return;
+    };
+
+    let dir = dominant_face(normal);
+    let packed = pack_block(view.get_at(pos), dir) | view.damage_stage(pos) << 12;
+
+    let idx = data.positions.len() as u32;
+    for (point, darkness) in points.into_iter().zip(darkness) {
+        data.positions.push(point.to_array());
+        data.normals.push(normal.to_array());
+        data.packed.push(packed | darkness << DARKNESS_SHIFT);
+    }
+    data.indicies.extend([idx, idx + 1, idx + 2]);
+}
+
+/// The cube face pointing closest to `normal`.
+fn dominant_face(normal: Vec3) -> FaceDir {
+    let abs = normal.abs();
+    if abs.y >= abs.x && abs.y >= abs.z {
+        if normal.y > 0. {
+            FaceDir::PosY
+        } else {
+            FaceDir::NegY
+        }
+    } else if abs.x >= abs.z {
+        if normal.x > 0. {
+            FaceDir::PosX
+        } else {
+            FaceDir::NegX
+        }
+    } else if normal.z > 0. {
+        FaceDir::PosZ
+    } else {
+        FaceDir::NegZ
+    }
+}
+
+fn corner(i: usize) -> IVec3 {
+    IVec3::new(i as i32 & 1, i as i32 >> 1 & 1, i as i32 >> 2 & 1)
+}
+
+fn edge(a: usize, b: usize) -> usize {
+    let pair = (a.min(b), a.max(b));
+    EDGES.iter().position(|e| *e == pair).unwrap()
+}
+
+/// Triangles for one of the 256 ways a cell's corners can be inside, as
+/// triples of edges. Built once on first use rather than carried as the
+/// usual hand written table.
+fn triangles(case: usize) -> &'static [[u8; 3]] {
+    static TABLE: OnceLock<Vec<Vec<[u8; 3]>>> = OnceLock::new();
+    &TABLE.get_or_init(|| (0..256).map(triangulate).collect())[case]
+}
+
+/// Walks each face of the cell counter-clockwise from outside, linking every
+/// edge the surface leaves the face through to the edge it comes back in
+/// by. The links close into loops around the inside corners, which are then
+/// fanned into triangles facing out. Diagonal corners on a face are kept
+/// apart when outside, so neighboring cells always agree on the split.
+fn triangulate(case: usize) -> Vec<[u8; 3]> {
+    let inside = |c: usize| case & 1 << c != 0;
+    let mut next = [None; 12];
+
+    for axis in 0..3 {
+        let (u, v) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        for side in 0..2 {
+            let mut ring =
+                [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(i, j)| side << axis | i << u | j << v);
+            // the ring runs counter-clockwise around u x v, which is -y for
+            // the y faces
+            let around_positive = axis != 1;
+            if (side == 1) != around_positive {
+                ring.reverse();
+            }
+
+            for k in 0..4 {
+                let (a, b) = (ring[k], ring[(k + 1) % 4]);
+                if !inside(a) || inside(b) {
+                    continue;
+                }
+                let mut m = (k + 1) % 4;
+                while inside(ring[m]) || !inside(ring[(m + 1) % 4]) {
+                    m = (m + 1) % 4;
+                }
+                next[edge(a, b)] = Some(edge(ring[m], ring[(m + 1) % 4]));
+            }
+        }
+    }
+
+    let mut tris = Vec::new();
+    let mut seen = [false; 12];
+    for first in 0..12 {
+        if next[first].is_none() || seen[first] {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut e = first;
+        while !seen[e] {
+            seen[e] = true;
+            ring.push(e as u8);
+            e = next[e].unwrap();
+        }
+        for i in 1..ring.len() - 1 {
+            tris.push([ring[0], ring[i + 1], ring[i]]);
+        }
+    }
+    tris
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Block;
+
+    fn mesh(terrain: &Terrain) -> TerrainMeshData {
+        let mut data = TerrainMeshData::default();
+        mesh_chunk_smooth_into(terrain, IVec3::ZERO, &mut data);
+        data
+    }
+
+    #[test]
+    fn every_case_closes_its_loops() {
+        for case in 0..256 {
+            let edges = (0..12)
+                .filter(|e| {
+                    let (a, b) = EDGES[*e];
+                    (case & 1 << a != 0) != (case & 1 << b != 0)
+                })
+                .count();
+            let used: std::collections::HashSet<_> =
+                triangles(case).iter().flatten().copied().collect();
+            assert_eq!(used.len(), edges, "case {}", case);
+        }
+    }
+
+    #[test]
+    fn empty_chunk_has_no_triangles() {
+        let data = mesh(&Terrain::new(IVec3::splat(CHUNK_SIZE)));
+        assert!(data.positions.is_empty());
+    }
+
+    #[test]
+    fn lone_block_faces_outwards() {
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE));
+        let pos = IVec3::new(4, 2, 4);
+        terrain.set_at(pos, Block::Stone);
+
+        let data = mesh(&terrain);
+        assert!(!data.indicies.is_empty());
+        let center = pos.as_vec3() + 0.5;
+        for tri in data.indicies.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(data.positions[tri[i] as usize]));
+            let facing = (b - a).cross(c - a).dot((a + b + c) / 3. - center);
+            assert!(facing > 0., "triangle {:?} faces inwards", tri);
+        }
+    }
+
+    #[test]
+    fn level_ground_stays_level() {
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE));
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..3 {
+                    terrain.set_at(IVec3::new(x, y, z), Block::Dirt);
+                }
+            }
+        }
+
+        // away from the sides, and above the floor the map closes at y = 0
+        let data = mesh(&terrain);
+        for (position, normal) in data.positions.iter().zip(&data.normals) {
+            let [x, y, z] = *position;
+            if x > 2. && x < 12. && z > 2. && z < 12. && y > 0. {
+                assert_eq!(position[1], 3.);
+                assert_eq!(*normal, [0., 1., 0.]);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fmt, time::Duration};
+
+use bevy::{prelude::*, render::mesh::Indices};
+
+use super::{ChunkGeometry, Terrain, TerrainMesh};
+
+/// Totals over the whole map, for judging how tightly the blocks and meshes
+/// pack. Updated every frame, shown in the chunk debug view and printed by
+/// the console's `stats`.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq)]
+pub struct TerrainStats {
+    /// Cells holding anything but `Block::Empty`.
+    pub filled_blocks: usize,
+    pub chunks: usize,
+    /// Chunks whose last mesh drew anything.
+    pub meshed_chunks: usize,
+    pub mesh_vertices: usize,
+    pub mesh_indices: usize,
+    /// Bytes of vertex and index buffers, or of packed faces for chunks drawn
+    /// by vertex pulling.
+    pub mesh_bytes: usize,
+    /// Estimated bytes the blocks take in memory, palettes included.
+    pub storage_bytes: usize,
+}
+
+/// Size of the last mesh of a chunk, and when it was made.
+#[derive(Debug, Default, Copy, Clone)]
+pub(super) struct MeshStats {
+    pub vertices: usize,
+    pub indices: usize,
+    pub bytes: usize,
+    /// Time spent meshing the chunk.
+    pub took: Duration,
+    /// Real time since startup when it was meshed.
+    pub meshed_at: Duration,
+}
+
+impl MeshStats {
+    pub(super) fn of(geometry: &ChunkGeometry) -> Self {
+        match geometry {
+            ChunkGeometry::Vertices(mesh) => {
+                let vertices = mesh.count_vertices();
+                let (indices, index_bytes) = match mesh.indices() {
+                    Some(Indices::U16(indices)) => (indices.len(), indices.len() * 2),
+                    Some(Indices::U32(indices)) => (indices.len(), indices.len() * 4),
+                    None => (0, 0),
+                };
+                let vertex_bytes: usize = mesh
+                    .attributes()
+                    .map(|(_, values)| values.get_bytes().len())
+                    .sum();
+                Self {
+                    vertices,
+                    indices,
+                    bytes: vertex_bytes + index_bytes,
+                    ..default()
+                }
+            }
+            // two words a face, drawn as two triangles without indices
+            ChunkGeometry::Faces(faces) => Self {
+                vertices: faces.len() / 2 * 6,
+                indices: 0,
+                bytes: faces.len() * 4,
+                ..default()
+            },
+        }
+    }
+}
+
+impl Terrain {
+    /// Cells in the map holding anything but `Block::Empty`.
+    pub fn filled_blocks(&self) -> usize {
+        self.storage.iter().map(|chunk| chunk.filled_cells()).sum()
+    }
+
+    /// Estimated bytes the blocks take in memory.
+    pub fn storage_bytes(&self) -> usize {
+        self.storage.iter().map(|chunk| chunk.memory_bytes()).sum()
+    }
+}
+
+impl fmt::Display for TerrainStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Filled blocks: {}", self.filled_blocks)?;
+        writeln!(f, "Chunks: {} ({} meshed)", self.chunks, self.meshed_chunks)?;
+        writeln!(
+            f,
+            "Mesh: {} vertices, {} indices, {}",
+            self.mesh_vertices,
+            self.mesh_indices,
+            format_bytes(self.mesh_bytes)
+        )?;
+        write!(f, "Block storage: {}", format_bytes(self.storage_bytes))
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1 << 10 {
+        format!("{} B", bytes)
+    } else if bytes < 1 << 20 {
+        format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    }
+}
+
+pub(super) fn update_terrain_stats(
+    mut stats: ResMut<TerrainStats>,
+    terrain: Res<Terrain>,
+    terrain_mesh: Option<Res<TerrainMesh>>,
+) {
+    let mut next = TerrainStats {
+        filled_blocks: terrain.filled_blocks(),
+        chunks: terrain.storage.len(),
+        storage_bytes: terrain.storage_bytes(),
+        ..default()
+    };
+
+    if let Some(terrain_mesh) = terrain_mesh {
+        for mesh in terrain_mesh.mesh_stats.values() {
+            next.meshed_chunks += (mesh.vertices > 0) as usize;
+            next.mesh_vertices += mesh.vertices;
+            next.mesh_indices += mesh.indices;
+            next.mesh_bytes += mesh.bytes;
+        }
+    }
+
+    // only touched on a change, so whatever shows them only updates then
+    stats.set_if_neq(next);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Block;
+
+    #[test]
+    fn filled_blocks_follow_edits_and_loads() {
+        let mut terrain = Terrain::new(IVec3::splat(20));
+        assert_eq!(terrain.filled_blocks(), 0);
+        let empty_bytes = terrain.storage_bytes();
+
+        terrain.set(1, 2, 3, Block::Stone);
+        terrain.set(1, 2, 3, Block::Dirt);
+        terrain.set(18, 18, 18, Block::Stone);
+        terrain.set(4, 4, 4, Block::Empty);
+        assert_eq!(terrain.filled_blocks(), 2);
+        assert!(terrain.storage_bytes() > empty_bytes);
+
+        let bytes = terrain.encode_chunk(IVec3::ZERO).unwrap();
+        terrain.set(1, 2, 3, Block::Empty);
+        assert_eq!(terrain.filled_blocks(), 1);
+        terrain.decode_chunk(IVec3::ZERO, &bytes).unwrap();
+        assert_eq!(terrain.filled_blocks(), 2);
+    }
+}
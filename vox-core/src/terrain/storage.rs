@@ -0,0 +1,264 @@
+use super::{Block, BlockId, CHUNK_SIZE};
+
+/// Cells in a chunk.
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Widths an index can take, each divides a word evenly. The widest covers
+/// a palette as large as the chunk.
+const INDEX_BITS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// Blocks of one chunk, stored as indices into a palette of the block ids it
+/// holds, with a byte of metadata per cell packed the same way. Indices take
+/// as few bits as the palette needs, and a chunk of a single block, like
+/// open air or solid rock, stores none at all.
+#[derive(Clone)]
+pub struct PalettedChunk {
+    blocks: PackedCells<BlockId>,
+    /// The block each palette entry stands for, looked up in the registry
+    /// once rather than on every read.
+    resolved: Vec<Block>,
+    /// State a block needs beyond its `Block`, like how far along it is.
+    /// Zero in almost every cell, so it usually costs nothing.
+    meta: PackedCells<u8>,
+    /// Cells holding anything but `Block::Empty`.
+    filled: u16,
+}
+
+impl PalettedChunk {
+    /// A chunk holding `block` in every cell, with no metadata.
+    pub fn filled(block: Block) -> Self {
+        Self {
+            blocks: PackedCells::filled(block.into()),
+            resolved: vec![block],
+            meta: PackedCells::filled(0),
+            filled: if block == Block::Empty {
+                0
+            } else {
+                CHUNK_VOLUME as u16
+            },
+        }
+    }
+
+    /// Cells holding anything but `Block::Empty`.
+    pub fn filled_cells(&self) -> usize {
+        self.filled as usize
+    }
+
+    /// Bytes the chunk takes in memory, its palettes and indices included.
+    pub fn memory_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.blocks.heap_bytes()
+            + self.resolved.capacity() * size_of::<Block>()
+            + self.meta.heap_bytes()
+    }
+
+    /// Block in cell `i`, cells run x-major then z, each column bottom to top.
+    pub fn get(&self, i: usize) -> Block {
+        self.resolved[self.blocks.index(i)]
+    }
+
+    /// Id of the block in cell `i`.
+    pub fn get_id(&self, i: usize) -> BlockId {
+        self.blocks.get(i)
+    }
+
+    pub fn set(&mut self, i: usize, block: Block) {
+        let id = BlockId::from(block);
+        let previous = self.get_id(i);
+        if previous == id {
+            return;
+        }
+        if previous == BlockId::EMPTY {
+            self.filled += 1;
+        } else if id == BlockId::EMPTY {
+            self.filled -= 1;
+        }
+
+        if self.blocks.palette.contains(&id) {
+            self.blocks.set(i, id);
+            return;
+        }
+
+        // adding to the palette can compact it too, reordering the entries
+        let before: Vec<(BlockId, Block)> = self
+            .blocks
+            .palette
+            .iter()
+            .copied()
+            .zip(self.resolved.iter().copied())
+            .collect();
+        self.blocks.set(i, id);
+        self.resolved = self
+            .blocks
+            .palette
+            .iter()
+            .map(|entry| match before.iter().find(|(old, _)| old == entry) {
+                Some((_, resolved)) => *resolved,
+                None => block,
+            })
+            .collect();
+    }
+
+    /// Metadata byte of cell `i`.
+    pub fn get_meta(&self, i: usize) -> u8 {
+        self.meta.get(i)
+    }
+
+    pub fn set_meta(&mut self, i: usize, meta: u8) {
+        if self.get_meta(i) != meta {
+            self.meta.set(i, meta);
+        }
+    }
+}
+
+/// A value per cell of a chunk, stored as indices into a palette of the
+/// values it holds.
+#[derive(Clone)]
+struct PackedCells<T> {
+    palette: Vec<T>,
+    /// Width of an index, zero while the palette has one entry.
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl<T: Copy + PartialEq> PackedCells<T> {
+    fn filled(value: T) -> Self {
+        Self {
+            palette: vec![value],
+            bits: 0,
+            words: vec![],
+        }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.palette.capacity() * size_of::<T>() + self.words.capacity() * size_of::<u64>()
+    }
+
+    fn get(&self, i: usize) -> T {
+        self.palette[self.index(i)]
+    }
+
+    fn set(&mut self, i: usize, value: T) {
+        let index = match self.palette.iter().position(|v| *v == value) {
+            Some(index) => index,
+            None => self.insert(value),
+        };
+
+        if self.bits > 0 {
+            self.set_index(i, index);
+        }
+    }
+
+    fn index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = (1 << self.bits) - 1;
+        ((self.words[i / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_index(&mut self, i: usize, value: usize) {
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = ((1 << self.bits) - 1) << shift;
+        let word = &mut self.words[i / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    /// Adds `value` to the palette, widening the indices if it no longer
+    /// fits. A full palette first drops the values no cell uses anymore, so a
+    /// chunk that has been dug through doesn't keep widening.
+    fn insert(&mut self, value: T) -> usize {
+        if self.bits > 0 && self.palette.len() == 1 << self.bits {
+            self.compact();
+        }
+
+        self.palette.push(value);
+        let bits = bits_for(self.palette.len());
+        if bits != self.bits {
+            let indices = self.indices();
+            self.pack(bits, &indices);
+        }
+
+        self.palette.len() - 1
+    }
+
+    /// Drops palette entries no cell points at and packs what's left.
+    fn compact(&mut self) {
+        let indices = self.indices();
+        let mut used = vec![false; self.palette.len()];
+        for index in &indices {
+            used[*index] = true;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = vec![];
+        for (old, value) in self.palette.iter().enumerate() {
+            if used[old] {
+                remap[old] = palette.len();
+                palette.push(*value);
+            }
+        }
+
+        self.palette = palette;
+        let indices: Vec<_> = indices.iter().map(|index| remap[*index]).collect();
+        self.pack(bits_for(self.palette.len()), &indices);
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        (0..CHUNK_VOLUME).map(|i| self.index(i)).collect()
+    }
+
+    fn pack(&mut self, bits: u32, indices: &[usize]) {
+        self.bits = bits;
+        if bits == 0 {
+            self.words = vec![];
+            return;
+        }
+
+        self.words = vec![0; CHUNK_VOLUME / (64 / bits) as usize];
+        for (i, index) in indices.iter().enumerate() {
+            self.set_index(i, *index);
+        }
+    }
+}
+
+/// Narrowest index width that can address `count` palette entries. A full
+/// palette is compacted before it grows, so it never outgrows the widest.
+fn bits_for(count: usize) -> u32 {
+    if count <= 1 {
+        return 0;
+    }
+
+    INDEX_BITS
+        .into_iter()
+        .find(|bits| count <= 1 << bits)
+        .expect("palette wider than the widest index")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_palettes_keep_every_value() {
+        let mut cells = PackedCells::filled(0u16);
+        for i in 0..300 {
+            cells.set(i * 7, 1000 + i as u16);
+        }
+        assert_eq!(cells.bits, 16);
+        for i in 0..300 {
+            assert_eq!(cells.get(i * 7), 1000 + i as u16);
+        }
+        assert_eq!(cells.get(1), 0);
+
+        // the indices stay wide while the palette still holds every value
+        for i in 0..300 {
+            cells.set(i * 7, 0);
+        }
+        assert!((0..CHUNK_VOLUME).all(|i| cells.get(i) == 0));
+    }
+}
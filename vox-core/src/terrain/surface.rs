@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+
+use super::{Terrain, CHUNK_SIZE};
+
+impl Terrain {
+    /// Topmost full block of the column at `x`, `z`, None for an empty
+    /// column or one outside the map. Kept up to date as blocks change, so
+    /// this doesn't scan the column.
+    pub fn surface_height(&self, x: i32, z: i32) -> Option<i32> {
+        let i = self.column_index(x, z)?;
+        let y = self.surface[i];
+        (y >= 0).then_some(y as i32)
+    }
+
+    fn column_index(&self, x: i32, z: i32) -> Option<usize> {
+        let in_bounds = (0..self.size.x).contains(&x) && (0..self.size.z).contains(&z);
+        in_bounds.then(|| (x * self.size.z + z) as usize)
+    }
+
+    /// Catches the surface of a column up after the blocks from `low` up to
+    /// but not including `high` changed. Only scans below them when the
+    /// surface was among them and is gone.
+    pub(super) fn refresh_surface(&mut self, x: i32, z: i32, low: i32, high: i32) {
+        let Some(i) = self.column_index(x, z) else {
+            return;
+        };
+        let surface = self.surface[i] as i32;
+        if surface >= high {
+            return;
+        }
+
+        let is_filled = |y: &i32| self.get_at(IVec3::new(x, *y, z)).is_filled();
+        let top = (low..high).rev().find(is_filled);
+        let top = match top {
+            Some(y) => y,
+            None if surface < low => return,
+            None => (0..low).rev().find(is_filled).unwrap_or(-1),
+        };
+        self.surface[i] = top as i16;
+    }
+
+    /// `refresh_surface` over every column of chunk `coord`, after the
+    /// whole chunk was replaced.
+    pub(super) fn refresh_chunk_surface(&mut self, coord: IVec3) {
+        let min = coord * CHUNK_SIZE;
+        let max = (min + IVec3::splat(CHUNK_SIZE)).min(self.size);
+
+        for x in min.x..max.x {
+            for z in min.z..max.z {
+                self.refresh_surface(x, z, min.y, max.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Block;
+
+    #[test]
+    fn surface_follows_edits_and_loads() {
+        let mut terrain = Terrain::new(IVec3::new(8, 40, 8));
+        assert_eq!(terrain.surface_height(2, 3), None);
+        assert_eq!(terrain.surface_height(-1, 3), None);
+
+        terrain.set_at(IVec3::new(2, 4, 3), Block::Stone);
+        terrain.set_at(IVec3::new(2, 30, 3), Block::Dirt);
+        // not a full block, so not the surface
+        terrain.set_at(IVec3::new(2, 35, 3), Block::Slab);
+        assert_eq!(terrain.surface_height(2, 3), Some(30));
+
+        terrain.set_at(IVec3::new(2, 30, 3), Block::Empty);
+        assert_eq!(terrain.surface_height(2, 3), Some(4));
+        terrain.set_at(IVec3::new(2, 4, 3), Block::Water);
+        assert_eq!(terrain.surface_height(2, 3), None);
+
+        // a chunk loaded over the top of the column
+        let coord = Terrain::chunk_of(IVec3::new(2, 30, 3));
+        let mut other = Terrain::new(terrain.size());
+        other.set_at(IVec3::new(2, 20, 3), Block::Stone);
+        let bytes = other.encode_chunk(coord).unwrap();
+        terrain.set_at(IVec3::new(2, 2, 3), Block::Stone);
+        terrain.decode_chunk(coord, &bytes).unwrap();
+        assert_eq!(terrain.surface_height(2, 3), Some(20));
+
+        let empty = Terrain::new(terrain.size()).encode_chunk(coord).unwrap();
+        terrain.decode_chunk(coord, &empty).unwrap();
+        assert_eq!(terrain.surface_height(2, 3), Some(2));
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::math::IVec3;
+
+use super::{Block, FaceDir, Terrain, CHUNK_SIZE};
+use crate::light::MAX_LIGHT;
+
+/// Blocks copied from the neighboring chunks on each side. The cube meshers
+/// look one block out, the smooth mesher's blurred corners two.
+pub const BORDER: i32 = 2;
+/// Cells along each side of a view.
+const SPAN: i32 = CHUNK_SIZE + 2 * BORDER;
+
+/// A chunk's blocks together with a border of its neighbors', copied out of
+/// the terrain so a chunk can be meshed on its own and still agree with the
+/// chunks around it on which faces along the seam are drawn.
+pub struct ChunkView {
+    pub chunk: IVec3,
+    pub slice: u16,
+    pub cull_oob: bool,
+    /// Extent of the map the chunk came from.
+    size: IVec3,
+    /// Corner of the border, in world coordinates.
+    origin: IVec3,
+    /// Blocks by x, then y, then z, `SPAN` to a side.
+    blocks: Vec<Block>,
+    /// Crack stages of the damaged blocks in the view.
+    damage: Vec<(IVec3, u32)>,
+    /// Light levels laid out like `blocks`, empty for a view taken without
+    /// them where everything is fully lit.
+    light: Vec<u8>,
+}
+
+impl ChunkView {
+    /// Copies `chunk` and its border out of `terrain`.
+    pub fn new(terrain: &Terrain, chunk: IVec3) -> Self {
+        let origin = chunk * CHUNK_SIZE - IVec3::splat(BORDER);
+        let end = origin + IVec3::splat(SPAN);
+
+        let mut blocks = Vec::with_capacity((SPAN * SPAN * SPAN) as usize);
+        for z in origin.z..end.z {
+            for y in origin.y..end.y {
+                for x in origin.x..end.x {
+                    blocks.push(terrain.get_at(IVec3::new(x, y, z)));
+                }
+            }
+        }
+
+        let damage = terrain
+            .damage
+            .keys()
+            .filter(|pos| pos.cmpge(origin).all() && pos.cmplt(end).all())
+            .map(|pos| (*pos, terrain.damage_stage(*pos)))
+            .collect();
+
+        Self {
+            chunk,
+            slice: terrain.slice,
+            cull_oob: terrain.cull_oob,
+            size: terrain.size(),
+            origin,
+            blocks,
+            damage,
+            light: Vec::new(),
+        }
+    }
+
+    /// Takes the light level of each of the view's cells from `level` too.
+    pub fn with_light(mut self, level: impl Fn(IVec3) -> u8) -> Self {
+        let end = self.origin + IVec3::splat(SPAN);
+
+        self.light.clear();
+        self.light.reserve((SPAN * SPAN * SPAN) as usize);
+        for z in self.origin.z..end.z {
+            for y in self.origin.y..end.y {
+                for x in self.origin.x..end.x {
+                    self.light.push(level(IVec3::new(x, y, z)));
+                }
+            }
+        }
+        self
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    /// Block at `pos`, which has to lie in the chunk or its border.
+    pub fn get_at(&self, pos: IVec3) -> Block {
+        let local = pos - self.origin;
+        debug_assert!(
+            local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(SPAN)).all(),
+            "{} is outside the view of chunk {}",
+            pos,
+            self.chunk
+        );
+        self.blocks[Self::index(local)]
+    }
+
+    /// Light level at `pos`, which has to lie in the chunk or its border.
+    pub fn light_at(&self, pos: IVec3) -> u8 {
+        if self.light.is_empty() {
+            return MAX_LIGHT;
+        }
+        self.light[Self::index(pos - self.origin)]
+    }
+
+    fn index(local: IVec3) -> usize {
+        (local.x + (local.y + local.z * SPAN) * SPAN) as usize
+    }
+
+    pub fn get(&self, x: i16, y: i16, z: i16) -> Block {
+        self.get_at(IVec3::new(x as i32, y as i32, z as i32))
+    }
+
+    /// Same as `Terrain::covers`.
+    pub fn covers(&self, neighbor: Block, face: FaceDir) -> bool {
+        super::covers(self.cull_oob, neighbor, face)
+    }
+
+    /// Same as `Terrain::damage_stage`.
+    pub fn damage_stage(&self, pos: IVec3) -> u32 {
+        self.damage
+            .iter()
+            .find(|(damaged, _)| *damaged == pos)
+            .map_or(0, |(_, stage)| *stage)
+    }
+
+    /// Same as `Terrain::get_neighbors_immediate`.
+    pub fn get_neighbors_immediate(&self, x: i16, y: i16, z: i16) -> [Block; 6] {
+        [
+            self.get(x, y + 1, z), // above
+            self.get(x, y, z - 1), // front
+            self.get(x + 1, y, z), // right
+            self.get(x, y, z + 1), // behind
+            self.get(x - 1, y, z), // left
+            self.get(x, y - 1, z), // below
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_sees_into_the_neighbors() {
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE * 2));
+        let pos = IVec3::new(CHUNK_SIZE + 1, 3, 3);
+        terrain.set_at(pos, Block::Stone);
+
+        let view = ChunkView::new(&terrain, IVec3::ZERO);
+        assert_eq!(view.get_at(pos), Block::Stone);
+        assert_eq!(view.get_at(IVec3::splat(-1)), Block::Oob);
+    }
+
+    #[test]
+    fn edit_on_a_seam_dirties_both_chunks() {
+        let mut terrain = Terrain::new(IVec3::splat(CHUNK_SIZE * 2));
+        terrain.take_dirty_chunks();
+
+        terrain.set_at(IVec3::new(CHUNK_SIZE, 3, 3), Block::Stone);
+        let mut dirty = terrain.take_dirty_chunks();
+        dirty.sort_by_key(|chunk| chunk.x);
+        assert_eq!(dirty, [IVec3::ZERO, IVec3::X]);
+    }
+}
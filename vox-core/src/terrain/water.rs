@@ -0,0 +1,229 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster},
+    prelude::*,
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        primitives::Aabb,
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, PrimitiveTopology, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError, VertexFormat,
+        },
+    },
+};
+
+use super::{
+    chunk_indices, pack_position, Block, Terrain, TerrainMesh, ATTRIBUTE_PACKED_POSITION,
+    CHUNK_SIZE, LIQUID_LEVEL,
+};
+
+/// Blocks of water under the surface counted for its depth, deeper looks
+/// the same.
+const MAX_DEPTH: i32 = 8;
+
+// blocks of water under each surface vertex, fading the shallows out
+const ATTRIBUTE_WATER_DEPTH: MeshVertexAttribute =
+    MeshVertexAttribute::new("WaterDepth", 9985136799, VertexFormat::Float32);
+
+/// The surface of the water in one chunk, drawn over the terrain with its
+/// own material.
+#[derive(Component)]
+pub(super) struct WaterChunk;
+
+/// Translucent water with ripples, deeper water darker and more opaque.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct WaterMaterial {
+    #[uniform(0)]
+    pub shallow: Color,
+    #[uniform(1)]
+    pub deep: Color,
+}
+
+impl Default for WaterMaterial {
+    fn default() -> Self {
+        Self {
+            shallow: Color::rgba(0.25, 0.6, 0.75, 0.35),
+            deep: Color::rgba(0.05, 0.15, 0.35, 0.9),
+        }
+    }
+}
+
+impl Material for WaterMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/water.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/water.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            ATTRIBUTE_PACKED_POSITION.at_shader_location(0),
+            ATTRIBUTE_WATER_DEPTH.at_shader_location(1),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        // seen from underneath too
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// Buffers the water surface of a chunk is built in, chunk-local.
+#[derive(Default)]
+pub(super) struct WaterMeshData {
+    positions: Vec<u32>,
+    depths: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl WaterMeshData {
+    /// Meshes the top of the water in `chunk` wherever it meets the air,
+    /// cut off at the slice. Reads the terrain rather than a `ChunkView`
+    /// since the depth can run down through the chunks below.
+    pub(super) fn mesh(&mut self, terrain: &Terrain, chunk: IVec3) {
+        self.positions.clear();
+        self.depths.clear();
+        self.indices.clear();
+
+        let min = chunk * CHUNK_SIZE;
+        let max = (min + IVec3::splat(CHUNK_SIZE)).min(terrain.size());
+        let slice = terrain.slice as i32;
+
+        for x in min.x..max.x {
+            for z in min.z..max.z {
+                for y in min.y..max.y.min(slice) {
+                    let pos = IVec3::new(x, y, z);
+                    if !is_surface(terrain, pos) {
+                        continue;
+                    }
+
+                    let idx = self.positions.len() as u32;
+                    // counter-clockwise seen from above
+                    for (dx, dz) in [(0, 0), (0, 1), (1, 1), (1, 0)] {
+                        let corner = pos + IVec3::new(dx, 0, dz);
+                        let local = (corner - min).as_vec3() + Vec3::Y * LIQUID_LEVEL;
+                        self.positions.push(pack_position(local));
+                        self.depths.push(corner_depth(terrain, corner));
+                    }
+                    self.indices
+                        .extend([idx, idx + 1, idx + 2, idx + 2, idx + 3, idx]);
+                }
+            }
+        }
+    }
+
+    /// A new mesh of the surface, None if the chunk has none.
+    pub(super) fn build(&self) -> Option<Mesh> {
+        if self.positions.is_empty() {
+            return None;
+        }
+
+        let mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(ATTRIBUTE_PACKED_POSITION, self.positions.clone())
+        .with_inserted_attribute(ATTRIBUTE_WATER_DEPTH, self.depths.clone())
+        .with_inserted_indices(chunk_indices(&self.indices, self.positions.len()));
+        Some(mesh)
+    }
+}
+
+/// Water with open air, or the slice, right above it.
+fn is_surface(terrain: &Terrain, pos: IVec3) -> bool {
+    let above = pos + IVec3::Y;
+    terrain.get_at(pos) == Block::Water
+        && (above.y >= terrain.slice as i32
+            || matches!(terrain.get_at(above), Block::Empty | Block::Oob))
+}
+
+/// Water in the column from `pos` down, up to `MAX_DEPTH`. Nothing where
+/// `pos` isn't part of a surface.
+fn depth(terrain: &Terrain, pos: IVec3) -> i32 {
+    if !is_surface(terrain, pos) {
+        return 0;
+    }
+    (0..MAX_DEPTH)
+        .take_while(|dy| terrain.get_at(pos - IVec3::Y * *dy) == Block::Water)
+        .count() as i32
+}
+
+/// Depth at a corner of the surface, the average of the four cells around
+/// it so it fades smoothly out to the shore.
+fn corner_depth(terrain: &Terrain, corner: IVec3) -> f32 {
+    let total: i32 = [(-1, -1), (-1, 0), (0, -1), (0, 0)]
+        .into_iter()
+        .map(|(dx, dz)| depth(terrain, corner + IVec3::new(dx, 0, dz)))
+        .sum();
+    total as f32 / 4.
+}
+
+/// Points the water entity of `chunk` at `mesh`, spawning it the first time
+/// the chunk has water and despawning it once it has none.
+pub(super) fn update_water_chunk(
+    commands: &mut Commands,
+    terrain_mesh: &mut TerrainMesh,
+    meshes: &mut Assets<Mesh>,
+    chunk: IVec3,
+    mesh: Option<Mesh>,
+) {
+    match (mesh, terrain_mesh.water.get(&chunk)) {
+        (Some(mesh), Some(&entity)) => {
+            commands.entity(entity).insert(meshes.add(mesh));
+        }
+        (Some(mesh), None) => {
+            let entity = commands.spawn((
+                MaterialMeshBundle {
+                    mesh: meshes.add(mesh),
+                    material: terrain_mesh.water_material.clone(),
+                    transform: Transform::from_translation((chunk * CHUNK_SIZE).as_vec3()),
+                    ..default()
+                },
+                Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_SIZE as f32)),
+                NotShadowCaster,
+                WaterChunk,
+            ));
+            terrain_mesh.water.insert(chunk, entity.id());
+        }
+        (None, Some(_)) => {
+            if let Some(entity) = terrain_mesh.water.remove(&chunk) {
+                commands.entity(entity).despawn();
+            }
+        }
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_top_of_a_pool_is_meshed() {
+        let mut terrain = Terrain::new(IVec3::splat(16));
+        for (pos, _) in terrain
+            .iter_region(IVec3::new(4, 2, 4), IVec3::new(8, 5, 8))
+            .collect::<Vec<_>>()
+        {
+            terrain.set_at(pos, Block::Water);
+        }
+
+        let mut data = WaterMeshData::default();
+        data.mesh(&terrain, IVec3::ZERO);
+        // a quad for each of the 4x4 cells on top
+        assert_eq!(data.positions.len(), 16 * 4);
+        assert_eq!(corner_depth(&terrain, IVec3::new(6, 4, 6)), 3.);
+        // half out over the shore
+        assert_eq!(corner_depth(&terrain, IVec3::new(4, 4, 6)), 1.5);
+    }
+}
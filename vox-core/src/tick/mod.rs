@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    menu::AppState,
+    net::is_authority,
+    terrain::{Block, Terrain},
+};
+
+/// The simulation clock. Gameplay runs on `FixedUpdate` at a steady
+/// `TICKS_PER_SECOND` whatever the frame rate, a slow machine catching up by
+/// running several ticks in a frame. `SimTick` counts them from the moment
+/// the world was entered, and the speed controls, replays and periodic
+/// systems all go by it.
+pub struct SimTickPlugin;
+
+pub struct RandomTickPlugin;
+
+/// Simulation ticks in a second of game time.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// Voxels picked each simulation tick. Slow processes like growth hang off
+/// these, so their cost stays flat however big the world is.
+const RANDOM_TICKS_PER_TICK: usize = 48;
+
+/// Ticks simulated since the world was entered.
+#[derive(Resource, Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SimTick(pub u64);
+
+/// Sent at the start of every simulation tick.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct SimTickEvent {
+    pub tick: u64,
+}
+
+/// A randomly chosen voxel getting its turn to update.
+#[derive(Event, Debug, Copy, Clone)]
+pub struct RandomTickEvent {
+    pub pos: IVec3,
+    pub block: Block,
+}
+
+/// Runs a `FixedUpdate` system once every `period` of simulation time, on
+/// ticks that are a multiple of it.
+pub fn every(period: Duration) -> impl FnMut(Res<SimTick>) -> bool + Clone {
+    let ticks = (period.as_secs_f64() * TICKS_PER_SECOND as f64)
+        .round()
+        .max(1.) as u64;
+    move |tick: Res<SimTick>| tick.0.is_multiple_of(ticks)
+}
+
+impl Plugin for SimTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICKS_PER_SECOND as f64))
+            .init_resource::<SimTick>()
+            .add_event::<SimTickEvent>()
+            .add_systems(OnEnter(AppState::InGame), reset_sim_tick)
+            .add_systems(
+                FixedFirst,
+                advance_sim_tick.run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+impl Plugin for RandomTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RandomTickEvent>().add_systems(
+            FixedUpdate,
+            random_ticks.run_if(in_state(AppState::InGame).and_then(is_authority)),
+        );
+    }
+}
+
+fn reset_sim_tick(mut tick: ResMut<SimTick>) {
+    tick.0 = 0;
+}
+
+fn advance_sim_tick(mut tick: ResMut<SimTick>, mut ev_tick: EventWriter<SimTickEvent>) {
+    tick.0 += 1;
+    ev_tick.send(SimTickEvent { tick: tick.0 });
+}
+
+fn random_ticks(terrain: Res<Terrain>, mut ev_tick: EventWriter<RandomTickEvent>) {
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..RANDOM_TICKS_PER_TICK {
+        let pos = IVec3::new(
+            rng.gen_range(0..terrain.size().x),
+            rng.gen_range(0..terrain.size().y),
+            rng.gen_range(0..terrain.size().z),
+        );
+        let block = terrain.get_at(pos);
+
+        if block != Block::Empty {
+            ev_tick.send(RandomTickEvent { pos, block });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_systems_run_on_multiples_of_their_period() {
+        let mut world = World::new();
+        world.init_resource::<SimTick>();
+        let mut condition = IntoSystem::into_system(every(Duration::from_millis(500)));
+        condition.initialize(&mut world);
+
+        let ran: Vec<u64> = (0..=90)
+            .filter(|tick| {
+                world.resource_mut::<SimTick>().0 = *tick;
+                condition.run((), &mut world)
+            })
+            .collect();
+        assert_eq!(ran, vec![0, 30, 60, 90]);
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng};
+
+use crate::terrain::{Block, Terrain};
+
+/// Steps a droplet takes before it soaks away.
+const DROPLET_LIFETIME: u32 = 48;
+/// How much a droplet keeps to its heading instead of turning downhill.
+const INERTIA: f32 = 0.1;
+/// Sediment a droplet can carry per unit of speed, water and slope.
+const CAPACITY: f32 = 4.;
+/// Slope used for the capacity on flat ground, so droplets still carry a bit.
+const MIN_SLOPE: f32 = 0.01;
+/// Share of the spare capacity a droplet picks up each step.
+const EROSION: f32 = 0.3;
+/// Share of the excess sediment a droplet drops each step.
+const DEPOSITION: f32 = 0.3;
+/// Share of a droplet's water lost each step.
+const EVAPORATION: f32 = 0.02;
+const GRAVITY: f32 = 4.;
+/// Steepest drop in blocks between neighboring columns that holds up under
+/// thermal erosion.
+const TALUS: f32 = 1.5;
+/// Share of the excess over the talus that slides per pass.
+const SLUMP: f32 = 0.25;
+
+/// Height of the ground in every column, as a fraction of blocks so small
+/// amounts of erosion add up.
+struct Heights {
+    size: IVec2,
+    /// The top of each column, or None where there's no ground at all.
+    tops: Vec<Option<f32>>,
+}
+
+impl Heights {
+    fn new(terrain: &Terrain) -> Self {
+        let size = terrain.size().xz();
+        let mut tops = Vec::with_capacity((size.x * size.y) as usize);
+        for z in 0..size.y {
+            for x in 0..size.x {
+                tops.push(terrain.surface_height(x, z).map(|y| (y + 1) as f32));
+            }
+        }
+        Self { size, tops }
+    }
+
+    fn index(&self, x: i32, z: i32) -> Option<usize> {
+        let inside = x >= 0 && z >= 0 && x < self.size.x && z < self.size.y;
+        inside.then(|| (x + z * self.size.x) as usize)
+    }
+
+    fn get(&self, x: i32, z: i32) -> Option<f32> {
+        self.index(x, z).and_then(|i| self.tops[i])
+    }
+
+    /// The four columns around `pos` with how much each counts towards it.
+    fn corners(&self, pos: Vec2) -> Option<[(usize, f32); 4]> {
+        let cell = pos.floor();
+        let t = pos - cell;
+        let (x, z) = (cell.x as i32, cell.y as i32);
+
+        let corner = |dx: i32, dz: i32, weight: f32| {
+            let i = self.index(x + dx, z + dz)?;
+            self.tops[i].map(|_| (i, weight))
+        };
+        Some([
+            corner(0, 0, (1. - t.x) * (1. - t.y))?,
+            corner(1, 0, t.x * (1. - t.y))?,
+            corner(0, 1, (1. - t.x) * t.y)?,
+            corner(1, 1, t.x * t.y)?,
+        ])
+    }
+
+    /// Height at `pos` blended from the columns around it, and which way
+    /// it rises. None off the edge of the ground.
+    fn sample(&self, pos: Vec2) -> Option<(f32, Vec2)> {
+        let corners = self.corners(pos)?;
+        let [a, b, c, d] = corners.map(|(i, _)| self.tops[i].unwrap_or(0.));
+        let t = pos - pos.floor();
+
+        let height = corners
+            .iter()
+            .map(|(i, weight)| self.tops[*i].unwrap_or(0.) * weight)
+            .sum();
+        let gradient = Vec2::new(
+            (b - a) * (1. - t.y) + (d - c) * t.y,
+            (c - a) * (1. - t.x) + (d - b) * t.x,
+        );
+        Some((height, gradient))
+    }
+
+    /// Spreads `amount` over the columns around `pos`, negative to take it
+    /// away.
+    fn add(&mut self, pos: Vec2, amount: f32) {
+        let Some(corners) = self.corners(pos) else {
+            return;
+        };
+        for (i, weight) in corners {
+            if let Some(top) = &mut self.tops[i] {
+                *top += amount * weight;
+            }
+        }
+    }
+}
+
+/// Wears the land down the way rain and gravity would: droplets carve
+/// valleys and leave sediment where they slow, then slopes too steep to
+/// stand slump onto their neighbors. Columns are cut down or built up in the
+/// block on top of them, so this belongs before the ground is layered.
+pub fn erode(terrain: &mut Terrain, droplets: u32, talus_passes: u32, rng: &mut StdRng) {
+    if droplets == 0 && talus_passes == 0 {
+        return;
+    }
+
+    let mut heights = Heights::new(terrain);
+    for _ in 0..droplets {
+        run_droplet(&mut heights, rng);
+    }
+    for _ in 0..talus_passes {
+        slump(&mut heights);
+    }
+
+    for z in 0..heights.size.y {
+        for x in 0..heights.size.x {
+            let (Some(old), Some(new)) = (terrain.surface_height(x, z), heights.get(x, z)) else {
+                continue;
+            };
+            let block = terrain.get_at(IVec3::new(x, old, z));
+            // the bottom block always stays, a column never erodes away
+            let new = (new.round() as i32 - 1).clamp(0, terrain.size().y - 1);
+
+            for y in new + 1..=old {
+                terrain.set_at(IVec3::new(x, y, z), Block::Empty);
+            }
+            for y in old + 1..=new {
+                terrain.set_at(IVec3::new(x, y, z), block);
+            }
+        }
+    }
+}
+
+/// Rolls one raindrop downhill from a random column, picking up ground where
+/// it speeds up and dropping it where it slows or climbs.
+fn run_droplet(heights: &mut Heights, rng: &mut StdRng) {
+    let size = heights.size.as_vec2() - Vec2::ONE;
+    let mut pos = Vec2::new(rng.gen_range(0.0..size.x), rng.gen_range(0.0..size.y));
+    let mut dir = Vec2::ZERO;
+    let mut speed = 1.;
+    let mut water = 1.;
+    let mut sediment = 0.;
+
+    for _ in 0..DROPLET_LIFETIME {
+        let Some((height, gradient)) = heights.sample(pos) else {
+            // started off the ground
+            return;
+        };
+        dir = (dir * INERTIA - gradient * (1. - INERTIA)).normalize_or_zero();
+        if dir == Vec2::ZERO {
+            break;
+        }
+
+        let next = pos + dir;
+        let Some((next_height, _)) = heights.sample(next) else {
+            // ran off the ground, the sediment piles up at the brink
+            break;
+        };
+        let drop = next_height - height;
+
+        let capacity = (-drop).max(MIN_SLOPE) * speed * water * CAPACITY;
+        if drop > 0. || sediment > capacity {
+            // fill the hollow it's climbing out of, or shed the excess
+            let deposit = if drop > 0. {
+                drop.min(sediment)
+            } else {
+                (sediment - capacity) * DEPOSITION
+            };
+            sediment -= deposit;
+            heights.add(pos, deposit);
+        } else {
+            // never dig deeper than the drop, that would leave a pit
+            let taken = ((capacity - sediment) * EROSION).min(-drop);
+            sediment += taken;
+            heights.add(pos, -taken);
+        }
+
+        speed = (speed * speed - drop * GRAVITY).max(0.).sqrt();
+        water *= 1. - EVAPORATION;
+        pos = next;
+    }
+
+    heights.add(pos, sediment);
+}
+
+/// Moves ground from every column to the lower neighbors it towers over by
+/// more than the talus.
+fn slump(heights: &mut Heights) {
+    let mut moved = vec![0.; heights.tops.len()];
+
+    for z in 0..heights.size.y {
+        for x in 0..heights.size.x {
+            let Some(top) = heights.get(x, z) else {
+                continue;
+            };
+            let i = heights.index(x, z).unwrap();
+
+            for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let Some(neighbor) = heights.get(x + dx, z + dz) else {
+                    continue;
+                };
+                let excess = top - neighbor - TALUS;
+                if excess > 0. {
+                    let amount = excess * SLUMP / 2.;
+                    moved[i] -= amount;
+                    moved[heights.index(x + dx, z + dz).unwrap()] += amount;
+                }
+            }
+        }
+    }
+
+    for (top, amount) in heights.tops.iter_mut().zip(moved) {
+        if let Some(top) = top {
+            *top += amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Flat ground three blocks deep with a pillar of stone in the middle.
+    fn spire() -> Terrain {
+        let mut terrain = Terrain::new(IVec3::new(16, 16, 16));
+        for (pos, _) in terrain
+            .iter_region(IVec3::ZERO, IVec3::new(16, 3, 16))
+            .collect::<Vec<_>>()
+        {
+            terrain.set_at(pos, Block::Stone);
+        }
+        for y in 3..12 {
+            terrain.set_at(IVec3::new(8, y, 8), Block::Stone);
+        }
+        terrain
+    }
+
+    #[test]
+    fn thermal_erosion_slumps_a_spire() {
+        let mut terrain = spire();
+        let before = terrain.count_filled();
+
+        erode(&mut terrain, 0, 32, &mut StdRng::seed_from_u64(0));
+        assert!(terrain.surface_height(8, 8).unwrap() < 11);
+        assert!(terrain.surface_height(9, 8).unwrap() > 2);
+        // the spire spreads out rather than vanishing
+        assert!(terrain.count_filled().abs_diff(before) < 4);
+    }
+
+    #[test]
+    fn rain_carves_the_same_way_for_the_same_seed() {
+        let mut a = spire();
+        let mut b = spire();
+        erode(&mut a, 200, 0, &mut StdRng::seed_from_u64(7));
+        erode(&mut b, 200, 0, &mut StdRng::seed_from_u64(7));
+
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| a == b));
+        assert!(a.surface_height(8, 8).unwrap() < 11);
+    }
+}
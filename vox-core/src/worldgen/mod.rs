@@ -0,0 +1,348 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blueprint::BLUEPRINT_DIR,
+    terrain::{Block, Orientation, Terrain},
+};
+
+mod erosion;
+mod noise;
+mod stages;
+
+const TRUNK_HEIGHT: (i32, i32) = (4, 6);
+/// Blocks the leaves of a grown tree reach out from the top of its trunk.
+pub const CANOPY_RADIUS: i32 = 2;
+
+/// Overall shape of the land.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Landform {
+    /// A ball of ground floating in the middle of the map.
+    Sphere,
+    /// Level ground across the whole map, halfway up.
+    Flat,
+}
+
+/// Knobs for the world generator.
+#[derive(Resource, Debug, Clone)]
+pub struct WorldGenSettings {
+    pub seed: u64,
+    pub landform: Landform,
+    /// Hollows in the ground fill with water up to this height, but no higher.
+    pub water_table: i32,
+    pub river_count: u32,
+    /// Raindrops the erosion stage rolls down the land, none to skip them.
+    pub erosion_droplets: u32,
+    /// Times the erosion stage lets slopes too steep to stand slump.
+    pub talus_passes: u32,
+    pub strata: Vec<Stratum>,
+    pub ores: Vec<OreVein>,
+    /// Folders the structures stage reads blueprints from, in order.
+    pub blueprint_dirs: Vec<PathBuf>,
+}
+
+/// A layer of the ground, listed from the surface down. The last stratum
+/// runs all the way to the bottom whatever its thickness.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct Stratum {
+    pub block: Block,
+    /// Blocks from the top of the layer to the top of the next.
+    pub thickness: i32,
+}
+
+/// An ore and where it turns up.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct OreVein {
+    pub block: Block,
+    /// Lowest and highest level the ore forms at.
+    pub depth: (i32, i32),
+    /// How common the ore is, from 0 for none to 1 for solid ore. Noise only
+    /// rarely peaks, so the share of stone that turns is well below this.
+    pub frequency: f32,
+    /// Size of the blobs in blocks, bigger spreads the same ore over fewer
+    /// and larger veins.
+    pub scale: f32,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            seed: 1337,
+            landform: Landform::Sphere,
+            water_table: 26,
+            river_count: 3,
+            erosion_droplets: 0,
+            talus_passes: 0,
+            strata: vec![
+                Stratum {
+                    block: Block::Dirt,
+                    thickness: 3,
+                },
+                Stratum {
+                    block: Block::Clay,
+                    thickness: 2,
+                },
+                Stratum {
+                    block: Block::Sandstone,
+                    thickness: 5,
+                },
+                Stratum {
+                    block: Block::Stone,
+                    thickness: 6,
+                },
+                Stratum {
+                    block: Block::Basalt,
+                    thickness: 0,
+                },
+            ],
+            ores: vec![
+                OreVein {
+                    block: Block::Coal,
+                    depth: (6, 16),
+                    frequency: 0.16,
+                    scale: 3.,
+                },
+                OreVein {
+                    block: Block::Iron,
+                    depth: (2, 12),
+                    frequency: 0.14,
+                    scale: 2.5,
+                },
+                OreVein {
+                    block: Block::Gold,
+                    depth: (0, 6),
+                    frequency: 0.12,
+                    scale: 2.,
+                },
+            ],
+            blueprint_dirs: vec![PathBuf::from(BLUEPRINT_DIR)],
+        }
+    }
+}
+
+/// Broad climate of a column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Biome {
+    Tundra,
+    Temperate,
+    Desert,
+}
+
+impl Biome {
+    /// Average surface air temperature, in degrees Celsius.
+    pub fn base_temperature(&self) -> f32 {
+        match self {
+            Biome::Tundra => -6.,
+            Biome::Temperate => 14.,
+            Biome::Desert => 30.,
+        }
+    }
+
+    /// How far the surface swings either side of the base between midnight
+    /// and noon.
+    pub fn daily_swing(&self) -> f32 {
+        match self {
+            Biome::Tundra => 4.,
+            Biome::Temperate => 6.,
+            Biome::Desert => 12.,
+        }
+    }
+}
+
+/// Biomes run in wavy bands along the z axis of a map `size` big, cold to the
+/// north.
+pub fn biome_at(size: IVec3, x: i32, z: i32) -> Biome {
+    let band = z as f32 + (x as f32 * 0.3).sin() * 3.;
+    let third = size.z as f32 / 3.;
+
+    if band < third {
+        Biome::Tundra
+    } else if band < third * 2. {
+        Biome::Temperate
+    } else {
+        Biome::Desert
+    }
+}
+
+/// A step of world generation. Stages run in order over the same terrain,
+/// each building on what the ones before it left.
+pub trait WorldGenStage: Send + Sync + 'static {
+    /// Identifies the stage when inserting others around it.
+    fn name(&self) -> &'static str;
+
+    /// Rough cost of the stage next to the others, for the loading screen
+    /// to tell how far along the generation is.
+    fn weight(&self) -> f32 {
+        1.
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, rng: &mut StdRng);
+}
+
+/// A generation under way, run a stage at a time so the loading screen can
+/// show its progress between them.
+pub struct WorldGenRun {
+    rng: StdRng,
+    /// Stages run so far.
+    done: usize,
+}
+
+/// The ordered stages the loading screen runs to fill a new map. Plugins can
+/// reach in to add their own stages or drop and reorder the built-in ones.
+#[derive(Resource)]
+pub struct WorldGenPipeline {
+    stages: Vec<Box<dyn WorldGenStage>>,
+}
+
+impl Default for WorldGenPipeline {
+    fn default() -> Self {
+        let mut pipeline = Self { stages: vec![] };
+        pipeline
+            .add_stage(stages::Heightmap)
+            .add_stage(stages::Erosion)
+            .add_stage(stages::Strata)
+            .add_stage(stages::Caves)
+            .add_stage(stages::Ores)
+            .add_stage(stages::Water)
+            .add_stage(stages::Surface)
+            .add_stage(stages::Structures)
+            .add_stage(stages::Trees);
+        pipeline
+    }
+}
+
+// hooks for plugins, the game itself only runs the default order
+#[allow(dead_code)]
+impl WorldGenPipeline {
+    pub fn add_stage(&mut self, stage: impl WorldGenStage) -> &mut Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Inserts `stage` right before the stage called `name`, or at the end
+    /// if there's none.
+    pub fn insert_before(&mut self, name: &str, stage: impl WorldGenStage) -> &mut Self {
+        let index = self.position(name).unwrap_or(self.stages.len());
+        self.stages.insert(index, Box::new(stage));
+        self
+    }
+
+    /// Inserts `stage` right after the stage called `name`, or at the end if
+    /// there's none.
+    pub fn insert_after(&mut self, name: &str, stage: impl WorldGenStage) -> &mut Self {
+        let index = self.position(name).map_or(self.stages.len(), |i| i + 1);
+        self.stages.insert(index, Box::new(stage));
+        self
+    }
+
+    pub fn remove_stage(&mut self, name: &str) -> Option<Box<dyn WorldGenStage>> {
+        self.position(name).map(|i| self.stages.remove(i))
+    }
+
+    /// Moves the stage called `name` to `index`, clamped to the end.
+    pub fn move_stage(&mut self, name: &str, index: usize) -> &mut Self {
+        if let Some(stage) = self.remove_stage(name) {
+            let index = index.min(self.stages.len());
+            self.stages.insert(index, stage);
+        }
+        self
+    }
+
+    pub fn stage_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.stages.iter().map(|stage| stage.name())
+    }
+
+    /// The stage called `name`, to run on its own.
+    pub fn stage(&self, name: &str) -> Option<&dyn WorldGenStage> {
+        self.position(name).map(|i| self.stages[i].as_ref())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name() == name)
+    }
+
+    /// Runs every stage in order with one generator seeded from the settings,
+    /// so the same seed and stages always give the same map.
+    pub fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings) {
+        let mut run = self.start(settings);
+        while self.step(&mut run, terrain, settings).is_some() {}
+    }
+
+    /// A generation that hasn't run any stage yet.
+    pub fn start(&self, settings: &WorldGenSettings) -> WorldGenRun {
+        WorldGenRun {
+            rng: StdRng::seed_from_u64(settings.seed),
+            done: 0,
+        }
+    }
+
+    /// Runs the next stage of `run`, returning its name, or None once every
+    /// stage has run.
+    pub fn step(
+        &self,
+        run: &mut WorldGenRun,
+        terrain: &mut Terrain,
+        settings: &WorldGenSettings,
+    ) -> Option<&'static str> {
+        let stage = self.stages.get(run.done)?;
+        stage.generate(terrain, settings, &mut run.rng);
+        run.done += 1;
+        Some(stage.name())
+    }
+
+    /// Share of the work `run` has done, by the weight of its stages, from 0
+    /// to 1.
+    pub fn progress(&self, run: &WorldGenRun) -> f32 {
+        let total: f32 = self.stages.iter().map(|stage| stage.weight()).sum();
+        let done: f32 = self.stages[..run.done.min(self.stages.len())]
+            .iter()
+            .map(|stage| stage.weight())
+            .sum();
+        if total > 0. {
+            done / total
+        } else {
+            1.
+        }
+    }
+}
+
+/// Grows a trunk up from `base` topped with a blob of leaves. Leaves only go
+/// into empty cells, the trunk needs its whole height clear.
+pub fn grow_tree(terrain: &mut Terrain, base: IVec3, rng: &mut impl Rng) -> bool {
+    let height = rng.gen_range(TRUNK_HEIGHT.0..=TRUNK_HEIGHT.1);
+
+    let is_clear = (0..height).all(|y| {
+        let block = terrain.get_at(base + IVec3::Y * y);
+        block == Block::Empty || block == Block::Sapling
+    });
+    if !is_clear {
+        return false;
+    }
+
+    let top = base + IVec3::Y * (height - 1);
+    for dx in -CANOPY_RADIUS..=CANOPY_RADIUS {
+        for dy in -1..=CANOPY_RADIUS {
+            for dz in -CANOPY_RADIUS..=CANOPY_RADIUS {
+                let offset = IVec3::new(dx, dy, dz);
+                // trim the corners off so the canopy reads as round
+                if offset.length_squared() > CANOPY_RADIUS * CANOPY_RADIUS + 1 {
+                    continue;
+                }
+
+                let pos = top + offset;
+                if terrain.get_at(pos) == Block::Empty {
+                    terrain.set_at(pos, Block::Leaves);
+                }
+            }
+        }
+    }
+
+    for y in 0..height {
+        terrain.set_at(base + IVec3::Y * y, Block::Log(Orientation::Up));
+    }
+
+    true
+}
@@ -0,0 +1,34 @@
+use bevy::math::{IVec3, Vec3};
+
+/// Smooth 3d value noise in 0..1, the same for the same seed and point.
+/// Random values sit on the integer lattice and are blended between.
+pub fn value_noise(seed: u64, point: Vec3) -> f32 {
+    let cell = point.floor();
+    let t = point - cell;
+    // smoothstep hides the lattice
+    let t = t * t * (Vec3::splat(3.) - 2. * t);
+    let base = cell.as_ivec3();
+
+    let corner = |x: i32, y: i32, z: i32| lattice(seed, base + IVec3::new(x, y, z));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), t.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), t.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), t.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), t.x);
+
+    lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+}
+
+/// Hashes a lattice point to 0..1.
+fn lattice(seed: u64, pos: IVec3) -> f32 {
+    let mut h = seed
+        ^ (pos.x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (pos.y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (pos.z as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -0,0 +1,556 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng};
+
+use super::{erosion, grow_tree, noise, Landform, WorldGenSettings, WorldGenStage};
+use crate::{
+    blueprint::{Blueprint, Placement},
+    terrain::{Block, BlockMaterial, Facing, Terrain},
+};
+
+/// Longest a river runs before it gives up looking for lower ground.
+const MAX_RIVER_LENGTH: usize = 64;
+/// Radius of the pond dug where a river ends in a hollow.
+const POND_RADIUS: i32 = 2;
+/// Spots tried per blueprint copy before giving up on it.
+const PLACEMENT_ATTEMPTS: u32 = 64;
+/// Chance of a tree on any given patch of surface grass.
+const TREE_CHANCE: f64 = 0.04;
+/// Noise level above which rock is hollowed into cave.
+const CAVE_THRESHOLD: f32 = 0.78;
+/// Size of the cave pockets in blocks.
+const CAVE_SCALE: f32 = 5.;
+/// Caves keep this much ground over their heads.
+const CAVE_ROOF: i32 = 4;
+
+/// The base shape of the land in plain stone, per the settings' landform.
+pub struct Heightmap;
+
+impl WorldGenStage for Heightmap {
+    fn name(&self) -> &'static str {
+        "heightmap"
+    }
+
+    fn weight(&self) -> f32 {
+        // noise for every column, and a fill of the whole map
+        2.
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, _: &mut StdRng) {
+        match settings.landform {
+            Landform::Sphere => fill_sphere(terrain),
+            Landform::Flat => fill_flat(terrain),
+        }
+    }
+}
+
+/// Weathers the bare heightmap with rain and slumping slopes, as many times
+/// as the settings ask. Does nothing by default.
+pub struct Erosion;
+
+impl WorldGenStage for Erosion {
+    fn name(&self) -> &'static str {
+        "erosion"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, rng: &mut StdRng) {
+        erosion::erode(
+            terrain,
+            settings.erosion_droplets,
+            settings.talus_passes,
+            rng,
+        );
+    }
+}
+
+/// Swaps the stone for the layers in the settings' strata table.
+pub struct Strata;
+
+impl WorldGenStage for Strata {
+    fn name(&self) -> &'static str {
+        "strata"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, _: &mut StdRng) {
+        lay_strata(terrain, settings);
+    }
+}
+
+/// Hollows pockets out of the rock, well below the surface.
+pub struct Caves;
+
+impl WorldGenStage for Caves {
+    fn name(&self) -> &'static str {
+        "caves"
+    }
+
+    fn weight(&self) -> f32 {
+        2.
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, _: &mut StdRng) {
+        carve_caves(terrain, settings);
+    }
+}
+
+pub struct Ores;
+
+impl WorldGenStage for Ores {
+    fn name(&self) -> &'static str {
+        "ores"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, _: &mut StdRng) {
+        place_ores(terrain, settings);
+    }
+}
+
+/// Rivers, then ponds and lakes in whatever hollows are left.
+pub struct Water;
+
+impl WorldGenStage for Water {
+    fn name(&self) -> &'static str {
+        "water"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, rng: &mut StdRng) {
+        for _ in 0..settings.river_count {
+            carve_river(terrain, rng);
+        }
+        fill_depressions(terrain, settings.water_table);
+    }
+}
+
+/// Ramps up the steps and grass on the open ground.
+pub struct Surface;
+
+impl WorldGenStage for Surface {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, _: &WorldGenSettings, _: &mut StdRng) {
+        place_ramps(terrain);
+        cover_grass(terrain);
+    }
+}
+
+/// Prefab ruins and rooms from the blueprint folders.
+pub struct Structures;
+
+impl WorldGenStage for Structures {
+    fn name(&self) -> &'static str {
+        "structures"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, settings: &WorldGenSettings, rng: &mut StdRng) {
+        let blueprints: Vec<_> = settings
+            .blueprint_dirs
+            .iter()
+            .flat_map(Blueprint::load_dir)
+            .collect();
+        stamp_structures(terrain, &blueprints, rng);
+    }
+}
+
+pub struct Trees;
+
+impl WorldGenStage for Trees {
+    fn name(&self) -> &'static str {
+        "trees"
+    }
+
+    fn generate(&self, terrain: &mut Terrain, _: &WorldGenSettings, rng: &mut StdRng) {
+        for x in 0..terrain.size().x {
+            for z in 0..terrain.size().z {
+                let Some(y) = terrain.surface_height(x, z) else {
+                    continue;
+                };
+                let ground = IVec3::new(x, y, z);
+
+                if terrain.get_at(ground) == Block::Grass && rng.gen_bool(TREE_CHANCE) {
+                    grow_tree(terrain, ground + IVec3::Y, rng);
+                }
+            }
+        }
+    }
+}
+
+fn fill_sphere(terrain: &mut Terrain) {
+    let size = terrain.size();
+    let rad = size.min_element() as f32 / 2.;
+    let center = size.as_vec3() / 2.;
+
+    for x in 0..size.x {
+        for z in 0..size.z {
+            for y in 0..size.y {
+                let pos = IVec3::new(x, y, z);
+
+                if pos.as_vec3().distance(center) < rad {
+                    terrain.set_at(pos, Block::Stone);
+                }
+            }
+        }
+    }
+}
+
+fn fill_flat(terrain: &mut Terrain) {
+    let size = terrain.size();
+
+    for x in 0..size.x {
+        for z in 0..size.z {
+            for y in 0..size.y / 2 {
+                terrain.set_at(IVec3::new(x, y, z), Block::Stone);
+            }
+        }
+    }
+}
+
+/// Puts a ramp in front of every one-block step on the surface so agents can
+/// walk between levels.
+fn place_ramps(terrain: &mut Terrain) {
+    for x in 0..terrain.size().x {
+        for z in 0..terrain.size().z {
+            for y in 1..terrain.size().y {
+                let pos = IVec3::new(x, y, z);
+                let block = terrain.get_at(pos);
+
+                if block != Block::Empty || !terrain.get_at(pos - IVec3::Y).is_filled() {
+                    continue;
+                }
+
+                let facing = Facing::ALL.into_iter().find(|f| {
+                    let step = pos + f.offset();
+                    terrain.get_at(step).is_filled()
+                        && terrain.get_at(step + IVec3::Y) == Block::Empty
+                });
+
+                if let Some(facing) = facing {
+                    terrain.set_at(pos, Block::Ramp(facing));
+                }
+            }
+        }
+    }
+}
+
+/// Blocks the boundaries between strata wander up and down.
+const STRATA_WARP: f32 = 1.5;
+/// Horizontal size of the bumps in the strata boundaries.
+const STRATA_WARP_SCALE: f32 = 8.;
+
+/// Replaces the ground with the strata table by depth below each column's
+/// surface. Every boundary is bent by its own noise so layers pinch and swell.
+fn lay_strata(terrain: &mut Terrain, settings: &WorldGenSettings) {
+    let Some(last) = settings.strata.last() else {
+        return;
+    };
+
+    for x in 0..terrain.size().x {
+        for z in 0..terrain.size().z {
+            let Some(top) = terrain.surface_height(x, z) else {
+                continue;
+            };
+
+            // depth of the bottom of each stratum at this column
+            let mut bottom = 0.;
+            let boundaries: Vec<(f32, Block)> = settings
+                .strata
+                .iter()
+                .enumerate()
+                .map(|(i, stratum)| {
+                    let point = Vec3::new(
+                        x as f32 / STRATA_WARP_SCALE,
+                        i as f32 * 10.,
+                        z as f32 / STRATA_WARP_SCALE,
+                    );
+                    let warp = (noise::value_noise(settings.seed, point) - 0.5) * 2. * STRATA_WARP;
+                    bottom += stratum.thickness as f32;
+                    ((bottom + warp).max(0.), stratum.block)
+                })
+                .collect();
+
+            for y in 0..=top {
+                let pos = IVec3::new(x, y, z);
+                if !terrain.get_at(pos).is_filled() {
+                    continue;
+                }
+
+                let depth = (top - y) as f32;
+                let block = boundaries
+                    .iter()
+                    .find(|(bottom, _)| depth < *bottom)
+                    .map_or(last.block, |(_, block)| *block);
+                terrain.set_at(pos, block);
+            }
+        }
+    }
+}
+
+/// Empties rock wherever a noise field peaks, leaving the top few blocks of
+/// each column and the bottom of the map alone.
+fn carve_caves(terrain: &mut Terrain, settings: &WorldGenSettings) {
+    let seed = settings.seed.wrapping_mul(31);
+
+    for x in 0..terrain.size().x {
+        for z in 0..terrain.size().z {
+            let Some(top) = terrain.surface_height(x, z) else {
+                continue;
+            };
+
+            for y in 1..=top - CAVE_ROOF {
+                let pos = IVec3::new(x, y, z);
+                if noise::value_noise(seed, pos.as_vec3() / CAVE_SCALE) > CAVE_THRESHOLD {
+                    terrain.set_at(pos, Block::Empty);
+                }
+            }
+        }
+    }
+}
+
+/// Swaps rock for ore where each ore's noise field peaks. Every ore samples
+/// its own field, the first to claim a block keeps it.
+fn place_ores(terrain: &mut Terrain, settings: &WorldGenSettings) {
+    for (i, vein) in settings.ores.iter().enumerate() {
+        let seed = settings.seed.wrapping_add(i as u64 + 1);
+
+        for x in 0..terrain.size().x {
+            for z in 0..terrain.size().z {
+                for y in vein.depth.0.max(0)..=vein.depth.1.min(terrain.size().y - 1) {
+                    let pos = IVec3::new(x, y, z);
+                    let block = terrain.get_at(pos);
+                    let is_ore = settings.ores.iter().any(|ore| ore.block == block);
+                    if block.def().material != BlockMaterial::Stone || is_ore {
+                        continue;
+                    }
+
+                    let value = noise::value_noise(seed, pos.as_vec3() / vein.scale);
+                    if value > 1. - vein.frequency {
+                        terrain.set_at(pos, vein.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Walks downhill from a random spot on the surface, turning the ground along
+/// the way into a water channel. A river that runs into a hollow digs a pond
+/// there for `fill_depressions` to fill.
+fn carve_river(terrain: &mut Terrain, rng: &mut impl Rng) {
+    let mut x = rng.gen_range(0..terrain.size().x);
+    let mut z = rng.gen_range(0..terrain.size().z);
+    let mut visited = HashSet::new();
+
+    for _ in 0..MAX_RIVER_LENGTH {
+        let Some(y) = terrain.surface_height(x, z) else {
+            return;
+        };
+        visited.insert((x, z));
+        terrain.set_at(IVec3::new(x, y, z), Block::Water);
+
+        // prefer the steepest way down, level ground only if there's no slope
+        let next = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(dx, dz)| (x + dx, z + dz))
+            .filter(|column| !visited.contains(column))
+            .filter_map(|(nx, nz)| terrain.surface_height(nx, nz).map(|ny| (ny, nx, nz)))
+            .filter(|(ny, _, _)| *ny <= y)
+            .min_by_key(|(ny, _, _)| *ny);
+
+        match next {
+            Some((_, nx, nz)) => {
+                x = nx;
+                z = nz;
+            }
+            None => {
+                dig_pond(terrain, IVec3::new(x, y, z));
+                return;
+            }
+        }
+    }
+}
+
+fn dig_pond(terrain: &mut Terrain, center: IVec3) {
+    for dx in -POND_RADIUS..=POND_RADIUS {
+        for dz in -POND_RADIUS..=POND_RADIUS {
+            if dx * dx + dz * dz > POND_RADIUS * POND_RADIUS {
+                continue;
+            }
+
+            for dy in -1..=0 {
+                let pos = center + IVec3::new(dx, dy, dz);
+                if terrain.get_at(pos).is_filled() {
+                    terrain.set_at(pos, Block::Empty);
+                }
+            }
+        }
+    }
+}
+
+/// Floods every hollow in the ground up to the height it would spill over,
+/// capped at the water table. Water leaves the map wherever a column has no
+/// ground at all.
+fn fill_depressions(terrain: &mut Terrain, water_table: i32) {
+    let size_x = terrain.size().x;
+    let size_z = terrain.size().z;
+    let index = |x: i32, z: i32| (x * size_z + z) as usize;
+
+    let mut ground = vec![-1; (size_x * size_z) as usize];
+    for x in 0..size_x {
+        for z in 0..size_z {
+            ground[index(x, z)] = terrain.surface_height(x, z).unwrap_or(-1);
+        }
+    }
+
+    // priority flood: grow inward from the edges, lowest rim first, so each
+    // column learns the lowest level water there could spill out at
+    let mut spill = vec![i32::MAX; ground.len()];
+    let mut open = BinaryHeap::new();
+    for x in 0..size_x {
+        for z in 0..size_z {
+            let is_edge = x == 0 || z == 0 || x == size_x - 1 || z == size_z - 1;
+            if is_edge || ground[index(x, z)] < 0 {
+                spill[index(x, z)] = ground[index(x, z)];
+                open.push(Reverse((ground[index(x, z)], x, z)));
+            }
+        }
+    }
+
+    while let Some(Reverse((level, x, z))) = open.pop() {
+        for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0 || nz < 0 || nx >= size_x || nz >= size_z {
+                continue;
+            }
+
+            let i = index(nx, nz);
+            if spill[i] == i32::MAX {
+                spill[i] = level.max(ground[i]);
+                open.push(Reverse((spill[i], nx, nz)));
+            }
+        }
+    }
+
+    for x in 0..size_x {
+        for z in 0..size_z {
+            let i = index(x, z);
+            let top = spill[i].min(water_table);
+
+            for y in ground[i] + 1..=top {
+                let pos = IVec3::new(x, y, z);
+                if terrain.get_at(pos) == Block::Empty {
+                    terrain.set_at(pos, Block::Water);
+                }
+            }
+        }
+    }
+}
+
+fn cover_grass(terrain: &mut Terrain) {
+    for x in 0..terrain.size().x {
+        for z in 0..terrain.size().z {
+            let Some(y) = terrain.surface_height(x, z) else {
+                continue;
+            };
+            let pos = IVec3::new(x, y, z);
+
+            // the top of the map counts as open sky, water doesn't
+            if terrain.get_at(pos) == Block::Dirt && !terrain.get_at(pos + IVec3::Y).is_solid() {
+                terrain.set_at(pos, Block::Grass);
+            }
+        }
+    }
+}
+
+/// Places prefab blueprints at random spots that pass `fits`. Placement only
+/// draws from the seeded generator, so a seed always yields the same layout.
+fn stamp_structures(terrain: &mut Terrain, blueprints: &[Blueprint], rng: &mut impl Rng) {
+    for blueprint in blueprints {
+        let size = blueprint.size();
+        if size.x > terrain.size().x || size.z > terrain.size().z {
+            continue;
+        }
+
+        let mut placed = 0;
+        for _ in 0..blueprint.count * PLACEMENT_ATTEMPTS {
+            if placed == blueprint.count {
+                break;
+            }
+
+            let x = rng.gen_range(0..=terrain.size().x - size.x);
+            let z = rng.gen_range(0..=terrain.size().z - size.z);
+            let Some(origin) = pick_origin(terrain, blueprint, x, z, rng) else {
+                continue;
+            };
+
+            if fits(terrain, blueprint, origin) {
+                for (offset, block) in blueprint.cells() {
+                    terrain.set_at(origin + offset, block);
+                }
+                placed += 1;
+            }
+        }
+
+        println!("Placed {}/{} {}", placed, blueprint.count, blueprint.name);
+    }
+}
+
+/// Ground under each column of a blueprint's footprint placed at `x, z`.
+fn footprint_ground(terrain: &Terrain, size: IVec3, x: i32, z: i32) -> Option<Vec<i32>> {
+    (0..size.x)
+        .flat_map(|dx| (0..size.z).map(move |dz| (x + dx, z + dz)))
+        .map(|(cx, cz)| terrain.surface_height(cx, cz))
+        .collect()
+}
+
+/// Surface blueprints sit with their bottom layer replacing the top of the
+/// ground, buried ones go at a random depth well under the lowest column.
+fn pick_origin(
+    terrain: &Terrain,
+    blueprint: &Blueprint,
+    x: i32,
+    z: i32,
+    rng: &mut impl Rng,
+) -> Option<IVec3> {
+    let size = blueprint.size();
+    let ground = footprint_ground(terrain, size, x, z)?;
+    let lowest = *ground.iter().min()?;
+    let highest = *ground.iter().max()?;
+
+    match blueprint.placement {
+        Placement::Surface => (highest - lowest <= 1).then_some(IVec3::new(x, highest, z)),
+        Placement::Buried => {
+            let top = lowest - size.y - 2;
+            (top > 1).then(|| IVec3::new(x, rng.gen_range(1..top), z))
+        }
+    }
+}
+
+/// Surface blueprints need open air above their bottom layer and no water in
+/// the way. Buried ones must be wrapped in solid ground on every side so they
+/// never break into a river or the open.
+fn fits(terrain: &Terrain, blueprint: &Blueprint, origin: IVec3) -> bool {
+    let size = blueprint.size();
+    let in_map = |pos: IVec3| !terrain.is_pos_oob(pos.x as i16, pos.y as i16, pos.z as i16);
+
+    match blueprint.placement {
+        Placement::Surface => (0..size.x).all(|x| {
+            (0..size.y).all(|y| {
+                (0..size.z).all(|z| {
+                    let pos = origin + IVec3::new(x, y, z);
+                    let block = terrain.get_at(pos);
+                    in_map(pos) && block != Block::Water && (y == 0 || block == Block::Empty)
+                })
+            })
+        }),
+        Placement::Buried => (-1..=size.x).all(|x| {
+            (-1..=size.y).all(|y| {
+                (-1..=size.z).all(|z| terrain.get_at(origin + IVec3::new(x, y, z)).is_filled())
+            })
+        }),
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages},
+};
+
+use crate::{menu::AppState, pathfinding::is_walkable, terraform::Selection, terrain::Terrain};
+
+/// Areas of floor set aside for something, painted over the selection from
+/// the console: `zone <kind>` paints a new zone, `unzone` erases whatever
+/// zones the selection covers.
+pub struct ZonePlugin;
+
+/// Draws the zones as a tint over the floor they cover.
+pub struct ZoneOverlayPlugin;
+
+/// Height of the tint above the floor it covers.
+const TINT_OFFSET: f32 = 0.02;
+
+/// Identifies a zone. Ids start at 1, the grids hold 0 where nothing is
+/// painted.
+pub type ZoneId = u16;
+
+/// What a zone is for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ZoneKind {
+    /// Where hauled items are put down.
+    Stockpile,
+    /// Where idle agents gather.
+    MeetingArea,
+    /// Where tired agents go to sleep.
+    Bedroom,
+}
+
+impl ZoneKind {
+    pub const ALL: [ZoneKind; 3] = [
+        ZoneKind::Stockpile,
+        ZoneKind::MeetingArea,
+        ZoneKind::Bedroom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ZoneKind::Stockpile => "stockpile",
+            ZoneKind::MeetingArea => "meeting",
+            ZoneKind::Bedroom => "bedroom",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ZoneKind> {
+        ZoneKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Number the kind is saved as.
+    pub fn id(self) -> u8 {
+        match self {
+            ZoneKind::Stockpile => 0,
+            ZoneKind::MeetingArea => 1,
+            ZoneKind::Bedroom => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<ZoneKind> {
+        ZoneKind::ALL.into_iter().find(|kind| kind.id() == id)
+    }
+
+    fn color(self) -> Color {
+        match self {
+            ZoneKind::Stockpile => Color::rgba(1., 0.8, 0.2, 0.35),
+            ZoneKind::MeetingArea => Color::rgba(0.3, 0.6, 1., 0.35),
+            ZoneKind::Bedroom => Color::rgba(0.7, 0.4, 1., 0.35),
+        }
+    }
+}
+
+/// Every zone of the map. Each level has a grid of the zone painted on each
+/// of its columns, made the first time anything is painted on the level. A
+/// cell is zoned for the open space in it, standing on the block below.
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct Zones {
+    size: IVec3,
+    kinds: BTreeMap<ZoneId, ZoneKind>,
+    levels: Vec<Vec<ZoneId>>,
+}
+
+impl Zones {
+    pub fn new(size: IVec3) -> Self {
+        Self {
+            size,
+            kinds: BTreeMap::new(),
+            levels: vec![vec![]; size.y.max(0) as usize],
+        }
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    fn index(&self, pos: IVec3) -> Option<(usize, usize)> {
+        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(self.size).any() {
+            return None;
+        }
+        Some((pos.y as usize, (pos.x + pos.z * self.size.x) as usize))
+    }
+
+    /// Zone painted on the cell at `pos`, if any.
+    pub fn zone_at(&self, pos: IVec3) -> Option<ZoneId> {
+        let (level, i) = self.index(pos)?;
+        let id = *self.levels[level].get(i)?;
+        (id != 0).then_some(id)
+    }
+
+    pub fn kind(&self, id: ZoneId) -> Option<ZoneKind> {
+        self.kinds.get(&id).copied()
+    }
+
+    pub fn kind_at(&self, pos: IVec3) -> Option<ZoneKind> {
+        self.kind(self.zone_at(pos)?)
+    }
+
+    /// Every zone with its kind, by id.
+    pub fn zones(&self) -> impl Iterator<Item = (ZoneId, ZoneKind)> + '_ {
+        self.kinds.iter().map(|(id, kind)| (*id, *kind))
+    }
+
+    /// Every zoned cell with its zone, level by level.
+    pub fn cells(&self) -> impl Iterator<Item = (IVec3, ZoneId)> + '_ {
+        let width = self.size.x.max(1) as usize;
+        self.levels.iter().enumerate().flat_map(move |(y, grid)| {
+            grid.iter()
+                .enumerate()
+                .filter(|(_, id)| **id != 0)
+                .map(move |(i, id)| {
+                    let pos = IVec3::new((i % width) as i32, y as i32, (i / width) as i32);
+                    (pos, *id)
+                })
+        })
+    }
+
+    /// Cells of every zone of `kind`.
+    pub fn cells_of(&self, kind: ZoneKind) -> impl Iterator<Item = IVec3> + '_ {
+        self.cells()
+            .filter(move |(_, id)| self.kind(*id) == Some(kind))
+            .map(|(pos, _)| pos)
+    }
+
+    /// The cell of a zone of `kind` closest to `from`, by blocks walked
+    /// along each axis.
+    pub fn nearest(&self, kind: ZoneKind, from: IVec3) -> Option<IVec3> {
+        self.cells_of(kind).min_by_key(|pos| {
+            let offset = (*pos - from).abs();
+            offset.x + offset.y + offset.z
+        })
+    }
+
+    /// Starts a zone of `kind` with nothing painted yet, None once every id
+    /// is taken.
+    pub fn add(&mut self, kind: ZoneKind) -> Option<ZoneId> {
+        let id = (1..=ZoneId::MAX).find(|id| !self.kinds.contains_key(id))?;
+        self.kinds.insert(id, kind);
+        Some(id)
+    }
+
+    /// Paints `zone` on the cell at `pos`, or clears it for None.
+    pub fn paint(&mut self, pos: IVec3, zone: Option<ZoneId>) {
+        let Some((level, i)) = self.index(pos) else {
+            return;
+        };
+        let grid = &mut self.levels[level];
+        if grid.is_empty() {
+            if zone.is_none() {
+                return;
+            }
+            *grid = vec![0; (self.size.x * self.size.z) as usize];
+        }
+        grid[i] = zone.unwrap_or(0);
+    }
+
+    /// Forgets zones painted over everywhere.
+    fn prune(&mut self) {
+        let mut used = vec![false; ZoneId::MAX as usize + 1];
+        for grid in &self.levels {
+            for id in grid {
+                used[*id as usize] = true;
+            }
+        }
+        self.kinds.retain(|id, _| used[*id as usize]);
+    }
+
+    /// Paints a new zone of `kind` on the floor of the blocks from `min` up
+    /// to but not including `max`, and on the cells standing on the top of
+    /// them, so selecting the ground zones the floor over it. Returns the
+    /// number of cells painted.
+    pub fn paint_region(
+        &mut self,
+        terrain: &Terrain,
+        min: IVec3,
+        max: IVec3,
+        kind: ZoneKind,
+    ) -> usize {
+        let Some(id) = self.add(kind) else {
+            return 0;
+        };
+        let count = self.paint_floor(terrain, min, max, Some(id));
+        self.prune();
+        count
+    }
+
+    /// Clears every zone from the cells `paint_region` would paint, whether
+    /// they can still be stood in or not.
+    pub fn erase_region(&mut self, terrain: &Terrain, min: IVec3, max: IVec3) -> usize {
+        let count = self.paint_floor(terrain, min, max, None);
+        self.prune();
+        count
+    }
+
+    fn paint_floor(
+        &mut self,
+        terrain: &Terrain,
+        min: IVec3,
+        max: IVec3,
+        zone: Option<ZoneId>,
+    ) -> usize {
+        let mut count = 0;
+        for y in min.y..=max.y {
+            for z in min.z..max.z {
+                for x in min.x..max.x {
+                    let pos = IVec3::new(x, y, z);
+                    let is_painted = match zone {
+                        Some(_) => is_walkable(terrain, pos),
+                        None => self.zone_at(pos).is_some(),
+                    };
+                    if is_painted {
+                        self.paint(pos, zone);
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    /// The grid of `level`, empty if nothing was ever painted on it.
+    pub(crate) fn level(&self, level: usize) -> &[ZoneId] {
+        &self.levels[level]
+    }
+
+    /// Puts back a zone read from a save.
+    pub(crate) fn insert_zone(&mut self, id: ZoneId, kind: ZoneKind) {
+        self.kinds.insert(id, kind);
+    }
+
+    /// Puts back a level's grid read from a save.
+    pub(crate) fn insert_level(&mut self, level: usize, grid: Vec<ZoneId>) {
+        self.levels[level] = grid;
+    }
+}
+
+/// Sent by the console to paint the selection.
+#[derive(Event, Debug, Copy, Clone)]
+pub enum ZoneEvent {
+    Paint(ZoneKind),
+    Erase,
+}
+
+impl Plugin for ZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Zones>()
+            .init_resource::<Selection>()
+            .add_event::<ZoneEvent>()
+            .add_systems(Update, paint_zones.run_if(in_state(AppState::InGame)));
+    }
+}
+
+impl Plugin for ZoneOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), spawn_zone_tint)
+            .add_systems(OnExit(AppState::InGame), despawn_zone_tint)
+            .add_systems(Update, update_zone_tint.run_if(in_state(AppState::InGame)));
+    }
+}
+
+fn paint_zones(
+    terrain: Res<Terrain>,
+    selection: Res<Selection>,
+    mut zones: ResMut<Zones>,
+    mut ev_zone: EventReader<ZoneEvent>,
+) {
+    for ev in ev_zone.read() {
+        let Some((min, max)) = selection.bounds() else {
+            println!("Select two corners with [ and ] first");
+            continue;
+        };
+        if zones.size() != terrain.size() {
+            *zones = Zones::new(terrain.size());
+        }
+
+        match *ev {
+            ZoneEvent::Paint(kind) => {
+                let count = zones.paint_region(&terrain, min, max, kind);
+                println!("Zoned {} cells as {}", count, kind.name());
+            }
+            ZoneEvent::Erase => {
+                let count = zones.erase_region(&terrain, min, max);
+                println!("Unzoned {} cells", count);
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct ZoneTint;
+
+fn spawn_zone_tint(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(tint_mesh(&Zones::default(), 0)),
+            material: materials.add(StandardMaterial {
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            ..default()
+        },
+        NotShadowCaster,
+        ZoneTint,
+    ));
+}
+
+fn despawn_zone_tint(mut commands: Commands, tints: Query<Entity, With<ZoneTint>>) {
+    for entity in tints.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// A square over the floor of every zoned cell up to the slice, in the
+/// color of its zone's kind. Cells above the slice stand on hidden blocks,
+/// so they're left out.
+fn tint_mesh(zones: &Zones, slice: i32) -> Mesh {
+    let mut positions = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+
+    for (pos, id) in zones.cells().filter(|(pos, _)| pos.y <= slice) {
+        let Some(kind) = zones.kind(id) else {
+            continue;
+        };
+        let idx = positions.len() as u32;
+        // counter-clockwise seen from above
+        for (dx, dz) in [(0., 0.), (0., 1.), (1., 1.), (1., 0.)] {
+            positions.push(pos.as_vec3() + Vec3::new(dx, TINT_OFFSET, dz));
+            colors.push(kind.color().as_linear_rgba_f32());
+        }
+        indices.extend([idx, idx + 1, idx + 2, idx + 2, idx + 3, idx]);
+    }
+
+    let normals = vec![Vec3::Y; positions.len()];
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+    .with_inserted_indices(bevy::render::mesh::Indices::U32(indices))
+}
+
+/// Rebuilds the tint when the zones or the slice change.
+fn update_zone_tint(
+    zones: Res<Zones>,
+    terrain: Res<Terrain>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tints: Query<&Handle<Mesh>, With<ZoneTint>>,
+    mut last_slice: Local<Option<u16>>,
+) {
+    if !zones.is_changed() && *last_slice == Some(terrain.slice) {
+        return;
+    }
+    *last_slice = Some(terrain.slice);
+
+    for handle in tints.iter() {
+        meshes.insert(handle, tint_mesh(&zones, terrain.slice as i32));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{fixtures::ground, Block};
+
+    fn floor() -> Terrain {
+        ground(IVec3::splat(8), 2, Block::Dirt)
+    }
+
+    #[test]
+    fn zones_cover_the_floor_over_the_selection() {
+        let terrain = floor();
+        let mut zones = Zones::new(terrain.size());
+        // the ground blocks, as picked with the selection tool
+        let count = zones.paint_region(
+            &terrain,
+            IVec3::new(1, 1, 1),
+            IVec3::new(3, 2, 4),
+            ZoneKind::Stockpile,
+        );
+
+        assert_eq!(count, 6);
+        assert_eq!(
+            zones.kind_at(IVec3::new(2, 2, 3)),
+            Some(ZoneKind::Stockpile)
+        );
+        assert_eq!(zones.zone_at(IVec3::new(2, 1, 3)), None);
+        assert_eq!(zones.zone_at(IVec3::new(3, 2, 3)), None);
+        assert_eq!(
+            zones.nearest(ZoneKind::Stockpile, IVec3::new(7, 2, 0)),
+            Some(IVec3::new(2, 2, 1))
+        );
+        assert_eq!(zones.nearest(ZoneKind::MeetingArea, IVec3::ZERO), None);
+    }
+
+    #[test]
+    fn zones_painted_over_are_forgotten() {
+        let terrain = floor();
+        let mut zones = Zones::new(terrain.size());
+        let (min, max) = (IVec3::new(0, 1, 0), IVec3::new(2, 2, 2));
+        zones.paint_region(&terrain, min, max, ZoneKind::Stockpile);
+        zones.paint_region(&terrain, min, max, ZoneKind::MeetingArea);
+
+        assert_eq!(
+            zones.zones().collect::<Vec<_>>(),
+            vec![(2, ZoneKind::MeetingArea)]
+        );
+
+        assert_eq!(zones.erase_region(&terrain, min, max), 4);
+        assert_eq!(zones.zones().count(), 0);
+        assert_eq!(zones.cells().count(), 0);
+    }
+}
@@ -0,0 +1,199 @@
+//! Invariants every terrain mesher has to keep, checked on random small maps
+//! of full cubes. They only look at the emitted geometry, so merged faces
+//! pass as long as they cover the same surface.
+
+use std::collections::HashSet;
+
+use bevy::math::{IVec3, Vec3};
+use proptest::prelude::*;
+use vox_core::terrain::{Block, FaceDir, Mesher, Terrain, TerrainMeshData};
+
+const DIRS: [FaceDir; 6] = [
+    FaceDir::PosX,
+    FaceDir::NegX,
+    FaceDir::PosY,
+    FaceDir::NegY,
+    FaceDir::PosZ,
+    FaceDir::NegZ,
+];
+
+/// Off-center point on a unit face, away from any diagonal a quad is split on.
+const PROBE: [f32; 2] = [0.3141, 0.7182];
+
+/// A map small enough to read back when a case fails.
+#[derive(Debug, Clone)]
+struct Map {
+    size: IVec3,
+    cells: Vec<Block>,
+    slice: u16,
+}
+
+impl Map {
+    fn terrain(&self) -> Terrain {
+        let mut terrain = Terrain::new(self.size);
+        for (i, block) in self.cells.iter().enumerate() {
+            let i = i as i32;
+            let size = self.size;
+            let pos = IVec3::new(i % size.x, i / size.x % size.y, i / (size.x * size.y));
+            terrain.set_at(pos, *block);
+        }
+        terrain.slice = self.slice;
+        terrain
+    }
+}
+
+fn map_strategy() -> impl Strategy<Value = Map> {
+    (1..20i32, 1..20i32, 1..20i32)
+        .prop_flat_map(|(x, y, z)| {
+            let volume = (x * y * z) as usize;
+            let cells = prop::collection::vec(
+                prop_oneof![Just(Block::Empty), Just(Block::Stone), Just(Block::Dirt)],
+                volume,
+            );
+            (Just(IVec3::new(x, y, z)), cells, 0..=y as u16)
+        })
+        .prop_map(|(size, cells, slice)| Map { size, cells, slice })
+}
+
+fn mesher_strategy() -> impl Strategy<Value = Mesher> {
+    prop_oneof![Just(Mesher::Simple), Just(Mesher::Binary)]
+}
+
+/// Meshes every chunk into one buffer, offsetting the indices to match.
+fn mesh_all(terrain: &Terrain, mesher: Mesher) -> TerrainMeshData {
+    let mut all = TerrainMeshData::default();
+    let mut data = TerrainMeshData::default();
+    for coord in terrain.chunks() {
+        mesher.mesh_into(terrain, coord, &mut data);
+        let base = all.positions.len() as u32;
+        all.positions.extend(&data.positions);
+        all.normals.extend(&data.normals);
+        all.packed.extend(&data.packed);
+        all.indicies.extend(data.indicies.iter().map(|i| i + base));
+    }
+    all
+}
+
+fn is_shown(terrain: &Terrain, pos: IVec3) -> bool {
+    pos.y < terrain.slice as i32 && terrain.get_at(pos).is_filled()
+}
+
+/// Unit faces that should be drawn: a shown cube next to open air, the map
+/// edge, or the cut made by the slice.
+fn exposed_faces(terrain: &Terrain) -> HashSet<(IVec3, u32)> {
+    let size = terrain.size();
+    let mut faces = HashSet::new();
+
+    for (pos, _) in terrain.iter() {
+        if !is_shown(terrain, pos) {
+            continue;
+        }
+        for dir in DIRS {
+            let next = pos + dir.normal();
+            let outside = next.cmplt(IVec3::ZERO).any() || next.cmpge(size).any();
+            if outside || !is_shown(terrain, next) {
+                faces.insert((pos, dir.bit()));
+            }
+        }
+    }
+    faces
+}
+
+fn triangles(data: &TerrainMeshData) -> impl Iterator<Item = ([Vec3; 3], Vec3, u32)> + '_ {
+    data.indicies.chunks(3).map(|tri| {
+        let corners = [0, 1, 2].map(|i| Vec3::from(data.positions[tri[i] as usize]));
+        let normal = Vec3::from(data.normals[tri[0] as usize]);
+        (corners, normal, data.packed[tri[0] as usize])
+    })
+}
+
+/// Whether `point` lies on the triangle.
+fn contains(corners: &[Vec3; 3], normal: Vec3, point: Vec3) -> bool {
+    let in_plane = (point - corners[0]).dot(normal).abs() < 1e-4;
+    in_plane
+        && (0..3).all(|i| {
+            let a = corners[i];
+            let b = corners[(i + 1) % 3];
+            (b - a).cross(point - a).dot(normal) > 0.
+        })
+}
+
+/// A point on the face of `pos` pointing along `dir`.
+fn probe(pos: IVec3, dir: FaceDir) -> Vec3 {
+    let normal = dir.normal().as_vec3();
+    let center = pos.as_vec3() + Vec3::splat(0.5) + normal * 0.5;
+    let (u, v) = normal.any_orthonormal_pair();
+    center + u * (PROBE[0] - 0.5) + v * (PROBE[1] - 0.5)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(96))]
+
+    #[test]
+    fn buffers_are_consistent(map in map_strategy(), mesher in mesher_strategy()) {
+        let terrain = map.terrain();
+        let data = mesh_all(&terrain, mesher);
+        let count = data.positions.len();
+
+        prop_assert_eq!(data.normals.len(), count);
+        prop_assert_eq!(data.packed.len(), count);
+        prop_assert_eq!(data.indicies.len() % 3, 0);
+        prop_assert!(data.indicies.iter().all(|&i| (i as usize) < count));
+    }
+
+    #[test]
+    fn faces_wind_along_their_direction(map in map_strategy(), mesher in mesher_strategy()) {
+        let terrain = map.terrain();
+        let data = mesh_all(&terrain, mesher);
+
+        for (corners, normal, packed) in triangles(&data) {
+            let dir = FaceDir::from_normal(normal.as_ivec3());
+            prop_assert_eq!(dir.normal().as_vec3(), normal);
+            prop_assert_eq!((packed >> 6) & 7, dir.bit());
+
+            let facing = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            prop_assert!(facing.dot(normal) > 0., "{:?} winds away from {}", corners, normal);
+        }
+    }
+
+    #[test]
+    fn no_faces_between_filled_blocks(map in map_strategy(), mesher in mesher_strategy()) {
+        let terrain = map.terrain();
+        let data = mesh_all(&terrain, mesher);
+
+        for (corners, normal, _) in triangles(&data) {
+            let centroid = (corners[0] + corners[1] + corners[2]) / 3.;
+            let behind = (centroid - normal * 0.5).floor().as_ivec3();
+            let ahead = (centroid + normal * 0.5).floor().as_ivec3();
+
+            prop_assert!(is_shown(&terrain, behind), "face at {} has nothing behind it", centroid);
+            prop_assert!(!is_shown(&terrain, ahead), "face at {} is buried", centroid);
+        }
+    }
+
+    #[test]
+    fn exposed_faces_are_emitted_once(map in map_strategy(), mesher in mesher_strategy()) {
+        let terrain = map.terrain();
+        let data = mesh_all(&terrain, mesher);
+        let tris: Vec<_> = triangles(&data).collect();
+        let exposed = exposed_faces(&terrain);
+
+        for &(pos, bit) in &exposed {
+            let dir = DIRS[bit as usize];
+            let point = probe(pos, dir);
+            let normal = dir.normal().as_vec3();
+            let hits = tris
+                .iter()
+                .filter(|(corners, n, _)| *n == normal && contains(corners, normal, point))
+                .count();
+            prop_assert_eq!(hits, 1, "face {:?} of {} drawn {} times", dir, pos, hits);
+        }
+
+        // every emitted bit of surface belongs to one of the exposed faces
+        let area: f32 = tris
+            .iter()
+            .map(|(c, _, _)| (c[1] - c[0]).cross(c[2] - c[0]).length() / 2.)
+            .sum();
+        prop_assert!((area - exposed.len() as f32).abs() < 1e-3);
+    }
+}